@@ -3,6 +3,13 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The headless build needs neither GTK nor compiled resources
+    if env::var_os("CARGO_FEATURE_GUI").is_none() {
+        return;
+    }
+
     if cfg!(target_os = "windows") {
         // Tell cargo to look for GTK4 in the MSYS2 installation directory
         println!("cargo:rustc-link-search=C:/msys64/mingw64/lib");
@@ -30,7 +37,7 @@ fn main() {
 
     // Compile the GResource file
     let status = Command::new("glib-compile-resources")
-        .args(&[
+        .args([
             "--target=resources.gresource",
             "resources.gresource.xml",
         ])
@@ -0,0 +1,738 @@
+//! Command-line interface
+//!
+//! `eventghost <subcommand> [args]`. Without a subcommand, or with the
+//! legacy single-dash switches (`-event`, `-file`, `-hide`, `-restart`), it behaves like
+//! `run`. Subcommands that act on a running instance talk to it over IPC;
+//! `event` and `macro run` start a short-lived local instance if none is
+//! running.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde_json::Value;
+use crate::core::config::{ConfigError, FileConfigStore};
+use crate::core::dispatch;
+use crate::core::event::{EventPayload, EventType, NamedEvent};
+use crate::core::instance::{self, InstanceError, InstanceRole, LaunchArgs, LaunchRequest, PrimaryInstance};
+use crate::core::ipc::{default_transport, IpcClient, IpcEndpoint, IpcError, ItemRef};
+use crate::core::logging::{self, ExportFormat, ExportOptions, LogEntry, LogQuery, LogStore};
+use crate::eg::tree::TreeFile;
+
+/// The command succeeded
+pub const EXIT_OK: i32 = 0;
+/// The command ran but failed, e.g. a file did not validate
+pub const EXIT_FAILED: i32 = 1;
+
+#[derive(Debug, Parser)]
+#[command(name = "eventghost", version, about = "Automation driven by events from devices, programs and the network")]
+pub struct Cli {
+    /// Control endpoint of the running instance (socket path or pipe name)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub endpoint: Option<PathBuf>,
+
+    /// Use this config directory
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+
+    /// Log at this level instead of global.log_level, e.g. debug
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+
+    #[command(subcommand)]
+    pub command: CliCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CliCommand {
+    /// Start EventGhost, or hand the arguments to the running instance
+    Run(RunArgs),
+    /// Trigger an event in the running instance
+    Event {
+        /// Event name, e.g. Remote.Play
+        name: String,
+        /// Payload as JSON; anything else is sent as a string
+        payload: Option<String>,
+    },
+    /// Check a settings file or a tree file
+    Validate {
+        file: PathBuf,
+    },
+    /// Convert a legacy .egtree file to the JSON or TOML tree format
+    Convert {
+        input: PathBuf,
+        /// Output file; the extension selects .json or .toml
+        output: PathBuf,
+    },
+    /// Inspect plugins
+    Plugins {
+        #[command(subcommand)]
+        command: PluginsCommand,
+    },
+    /// Work with macros in the running instance
+    Macro {
+        #[command(subcommand)]
+        command: MacroCommand,
+    },
+    /// Inspect the settings
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Work with the log of the running instance
+    Log {
+        #[command(subcommand)]
+        command: LogCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PluginsCommand {
+    /// Loaded plugins and their states, or the configured plugins if
+    /// EventGhost is not running
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MacroCommand {
+    /// Run a macro by path (Folder/Macro) or ID
+    Run {
+        item: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective settings of all layers, with secrets masked
+    Show,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LogCommand {
+    /// Save recent entries as HTML, CSV or text
+    Export {
+        /// Output file; the extension selects the format unless --format is given
+        output: PathBuf,
+        /// html, csv or text
+        #[arg(long)]
+        format: Option<ExportFormat>,
+        /// Least severe level to include, e.g. warn
+        #[arg(long)]
+        level: Option<log::Level>,
+        /// Only this target and those below it, e.g. plugin::MQTT; repeatable
+        #[arg(long = "target", value_name = "TARGET")]
+        targets: Vec<String>,
+        /// Only the newest entries
+        #[arg(long)]
+        limit: Option<usize>,
+        /// chrono format of the timestamps, e.g. "%H:%M:%S"; empty leaves them out
+        #[arg(long, value_name = "FORMAT")]
+        time_format: Option<String>,
+        /// Show timestamps in UTC instead of local time
+        #[arg(long)]
+        utc: bool,
+    },
+}
+
+#[derive(Debug, Default, Args)]
+pub struct RunArgs {
+    /// Run without the GUI
+    #[arg(long)]
+    pub headless: bool,
+    /// Start with the main window hidden
+    #[arg(long)]
+    pub hide: bool,
+    /// Allow a separate instance; requires --config-dir
+    #[arg(long)]
+    pub multi: bool,
+    /// Stop the running instance and take its place
+    #[arg(long)]
+    pub restart: bool,
+    /// Trigger this event once running
+    #[arg(long = "event", value_name = "NAME")]
+    pub events: Vec<String>,
+    /// Tree files to open
+    pub files: Vec<PathBuf>,
+}
+
+impl RunArgs {
+    pub fn launch_args(&self) -> LaunchArgs {
+        let mut requests: Vec<LaunchRequest> = self.files.iter().cloned().map(LaunchRequest::OpenFile).collect();
+        requests.extend(self.events.iter().map(|name| LaunchRequest::TriggerEvent {
+            name: name.clone(),
+            payload: Value::Null,
+        }));
+        LaunchArgs {
+            requests,
+            hide: self.hide,
+            multi: self.multi,
+            restart: self.restart,
+            ..Default::default()
+        }
+    }
+}
+
+/// What `main` should do
+#[derive(Debug)]
+pub enum Invocation {
+    /// Start, or forward to the running instance
+    Run { launch: LaunchArgs, headless: bool },
+    /// Run a one-shot command against the instance `launch` addresses
+    Command { command: CliCommand, launch: LaunchArgs },
+}
+
+/// Error type for argument parsing
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Clap(#[from] clap::Error),
+    #[error(transparent)]
+    Legacy(#[from] InstanceError),
+}
+
+impl CliError {
+    /// Print the error or help text and exit
+    pub fn exit(self) -> ! {
+        match self {
+            CliError::Clap(e) => e.exit(),
+            CliError::Legacy(e) => {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        }
+    }
+}
+
+/// Parse the full argument list, including the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Invocation, CliError> {
+    let args: Vec<String> = args.into_iter().collect();
+    if is_legacy(args.get(1)) {
+        let launch = LaunchArgs::parse(args.into_iter().skip(1))?;
+        return Ok(Invocation::Run { launch, headless: false });
+    }
+
+    let cli = Cli::try_parse_from(args)?;
+    let (mut launch, headless) = match &cli.command {
+        CliCommand::Run(run) => (run.launch_args(), run.headless),
+        // Commands never bring up the window of the running instance
+        _ => (LaunchArgs { hide: true, ..Default::default() }, false),
+    };
+    launch.endpoint = cli.endpoint;
+    launch.config_dir = cli.config_dir;
+    launch.log_level = cli.log_level;
+    Ok(match cli.command {
+        CliCommand::Run(_) => Invocation::Run { launch, headless },
+        command => Invocation::Command { command, launch },
+    })
+}
+
+/// No arguments, single-dash words such as `-event`, or a bare file path
+fn is_legacy(first: Option<&String>) -> bool {
+    let Some(first) = first else {
+        return true;
+    };
+    let single_dash_word = first.len() > 2 && first.starts_with('-') && !first.starts_with("--");
+    let subcommand = first == "help" || Cli::command().find_subcommand(first).is_some();
+    single_dash_word || (!first.starts_with('-') && !subcommand)
+}
+
+/// Run a one-shot command against the instance `launch` addresses and
+/// return the process exit status
+///
+/// `run` is not a one-shot command; see [`run_headless`].
+///
+/// The endpoint is only resolved for commands that talk to an instance, so
+/// offline ones such as `validate` work with any `launch`.
+pub async fn execute(command: CliCommand, launch: LaunchArgs) -> i32 {
+    let result = match command {
+        CliCommand::Run(_) => {
+            eprintln!("run starts EventGhost and cannot be executed as a command");
+            return instance::EXIT_USAGE;
+        }
+        CliCommand::Event { name, payload } => {
+            let payload = payload
+                .map(|raw| serde_json::from_str(&raw).unwrap_or(Value::String(raw)))
+                .unwrap_or(Value::Null);
+            return trigger_event(launch, name, payload).await;
+        }
+        CliCommand::Validate { file } => return validate(&file),
+        CliCommand::Convert { input, output } => return convert(&input, &output),
+        CliCommand::Plugins { command: PluginsCommand::List } => match launch.endpoint() {
+            Ok(endpoint) => return list_plugins(&endpoint, &launch).await,
+            Err(e) => Err(e),
+        },
+        CliCommand::Config { command: ConfigCommand::Show } => return show_config(&launch),
+        CliCommand::Log { command: LogCommand::Export { output, format, level, targets, limit, time_format, utc } } => {
+            let query = LogQuery { level, targets, limit, ..Default::default() };
+            let format = format.or_else(|| ExportFormat::from_path(&output)).unwrap_or(ExportFormat::Text);
+            let mut options = ExportOptions::new(format);
+            if let Some(time_format) = time_format {
+                options.time_format = Some(time_format).filter(|format| !format.is_empty());
+            }
+            options.utc = utc;
+            match launch.endpoint() {
+                Ok(endpoint) => return export_log(&endpoint, query, &options, &output).await,
+                Err(e) => Err(e),
+            }
+        }
+        CliCommand::Macro { command: MacroCommand::Run { item } } => {
+            let endpoint = match launch.endpoint() {
+                Ok(endpoint) => endpoint,
+                Err(e) => return status(Err(e)),
+            };
+            let item: ItemRef = item.parse().unwrap_or_else(|never| match never {});
+            let request = item.clone();
+            match remote(&endpoint, "run the macro", |mut client| async move { client.run_macro(request).await }).await {
+                Err(InstanceError::Unreachable(_)) => return run_macro_locally(launch, item).await,
+                result => result,
+            }
+        }
+    };
+    status(result)
+}
+
+/// Exit status for the outcome of a remote command, printing the error
+fn status(result: Result<(), InstanceError>) -> i32 {
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+/// Forward the event to the running instance, or deliver it in a local one
+async fn trigger_event(mut launch: LaunchArgs, name: String, payload: Value) -> i32 {
+    launch.requests = vec![LaunchRequest::TriggerEvent { name: name.clone(), payload: payload.clone() }];
+    let primary = match local_instance(&launch).await {
+        Ok(Some(primary)) => primary,
+        Ok(None) => return EXIT_OK,
+        Err(status) => return status,
+    };
+    let event = NamedEvent::new(&name, EventType::User, EventPayload::from_json(payload));
+    dispatch::dispatch(&primary.document, &primary.registry, &event).await;
+    primary.shutdown().await;
+    EXIT_OK
+}
+
+/// Run the macro in a local instance, or in the running one if it was
+/// started in the meantime
+async fn run_macro_locally(launch: LaunchArgs, item: ItemRef) -> i32 {
+    let primary = match local_instance(&launch).await {
+        Ok(Some(primary)) => primary,
+        Ok(None) => {
+            let endpoint = launch.endpoint().unwrap_or_default();
+            return status(remote(&endpoint, "run the macro", |mut client| async move { client.run_macro(item).await }).await);
+        }
+        Err(status) => return status,
+    };
+    let result = primary.handler().run_macro(&item).await;
+    primary.shutdown().await;
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("Failed to run {}: {}", item, e);
+            EXIT_FAILED
+        }
+    }
+}
+
+/// Start a short-lived primary instance with its plugins if none is
+/// running; `None` if one is and `launch` was forwarded to it
+///
+/// Errors are printed and returned as the exit status.
+async fn local_instance(launch: &LaunchArgs) -> Result<Option<PrimaryInstance>, i32> {
+    let listener = match instance::acquire(default_transport().as_ref(), launch).await {
+        Ok(InstanceRole::Primary(listener)) => listener,
+        Ok(InstanceRole::Forwarded) => return Ok(None),
+        Err(e) => {
+            eprintln!("{}", e);
+            return Err(e.exit_code());
+        }
+    };
+    log::info!("EventGhost is not running; running locally");
+    match PrimaryInstance::start(listener, launch, None, Arc::new(LogStore::default())) {
+        Ok(primary) => {
+            primary.start_plugins().await;
+            Ok(Some(primary))
+        }
+        Err(e) => {
+            eprintln!("Failed to start: {}", e);
+            Err(EXIT_FAILED)
+        }
+    }
+}
+
+/// Connect to the running instance and run `f`
+async fn remote<F, Fut, T>(endpoint: &IpcEndpoint, request: &str, f: F) -> Result<T, InstanceError>
+where
+    F: FnOnce(IpcClient) -> Fut,
+    Fut: std::future::Future<Output = Result<T, IpcError>>,
+{
+    let client = IpcClient::connect(endpoint).await.map_err(|e| {
+        InstanceError::Unreachable(IpcError::Protocol(format!("EventGhost is not running at {} ({})", endpoint, e)))
+    })?;
+    f(client).await.map_err(|error| match error {
+        IpcError::Remote(_) => InstanceError::Rejected { request: request.to_string(), error },
+        error => InstanceError::Ipc(error),
+    })
+}
+
+fn validate(file: &Path) -> i32 {
+    let result = if TreeFile::is_legacy_path(file) || is_tree_file(file) {
+        TreeFile::load(file).map(|tree| format!("{} tree nodes", tree.node_count()))
+    } else {
+        std::fs::read_to_string(file)
+            .map_err(crate::core::Error::from)
+            .and_then(|text| {
+                let (_, migrated_from) = FileConfigStore::parse(&text)?;
+                Ok(match migrated_from {
+                    Some(version) => format!("settings, will be upgraded from schema version {}", version),
+                    None => "settings".to_string(),
+                })
+            })
+    };
+    match result {
+        Ok(summary) => {
+            println!("{}: OK ({})", file.display(), summary);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}: {}", file.display(), e);
+            EXIT_FAILED
+        }
+    }
+}
+
+/// Tree files have a top-level `items` list, settings files do not
+fn is_tree_file(file: &Path) -> bool {
+    let Ok(text) = std::fs::read_to_string(file) else {
+        return false;
+    };
+    let value: Option<Value> = if file.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&text).ok()
+    } else {
+        toml::from_str(&text).ok()
+    };
+    value.is_some_and(|value| value.get("items").is_some())
+}
+
+fn convert(input: &Path, output: &Path) -> i32 {
+    match TreeFile::load(input).and_then(|tree| tree.save(output).map(|()| tree.node_count())) {
+        Ok(count) => {
+            println!("Converted {} nodes from {} to {}", count, input.display(), output.display());
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Failed to convert {}: {}", input.display(), e);
+            EXIT_FAILED
+        }
+    }
+}
+
+async fn list_plugins(endpoint: &IpcEndpoint, launch: &LaunchArgs) -> i32 {
+    if let Ok(mut client) = IpcClient::connect(endpoint).await {
+        return match client.list_plugins().await {
+            Ok(plugins) => {
+                for plugin in plugins {
+                    println!("{:<24} {:<10} {:?}", plugin.name, plugin.version, plugin.state);
+                }
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                EXIT_FAILED
+            }
+        };
+    }
+
+    match launch.config_loader().load() {
+        Ok(layered) => {
+            println!("EventGhost is not running; configured plugins:");
+            for plugin in &layered.config().plugins {
+                let state = if plugin.enabled { "enabled" } else { "disabled" };
+                println!("{:<24} {}", plugin.id, state);
+            }
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Failed to load settings: {}", e);
+            EXIT_FAILED
+        }
+    }
+}
+
+async fn export_log(endpoint: &IpcEndpoint, query: LogQuery, options: &ExportOptions, output: &Path) -> i32 {
+    let entries = match remote(endpoint, "send its log", |mut client| async move { client.tail_log(query).await }).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("{}", e);
+            return e.exit_code();
+        }
+    };
+    let entries: Vec<LogEntry> = entries.into_iter().map(|stored| stored.entry).collect();
+    match logging::export::export_to_file(&entries, options, output) {
+        Ok(count) => {
+            println!("Exported {} entries to {}", count, output.display());
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("Failed to export the log to {}: {}", output.display(), e);
+            EXIT_FAILED
+        }
+    }
+}
+
+fn show_config(launch: &LaunchArgs) -> i32 {
+    let shown = launch
+        .config_loader()
+        .load()
+        .map_err(|e| format!("Failed to load settings: {}", e))
+        .and_then(|layered| toml::to_string_pretty(&layered.config().masked()).map_err(|e| e.to_string()));
+    match shown {
+        Ok(text) => {
+            print!("{}", text);
+            EXIT_OK
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            EXIT_FAILED
+        }
+    }
+}
+
+/// Log to the file and console configured for `launch`, at the merged
+/// `global.log_level` unless `[global.log]` sets its own levels; only to the
+/// console if the log file cannot be opened
+///
+/// Returns the store of recent entries remote clients follow. Fails if the
+/// settings are invalid, so they are not silently replaced by defaults.
+pub fn start_logging(launch: &LaunchArgs) -> Result<Arc<LogStore>, ConfigError> {
+    let layered = launch.config_loader().load()?;
+    match logging::init(layered.config().global.log.clone()) {
+        Ok(logger) => return Ok(logger.store()),
+        Err(e) => eprintln!("Logging to the console only: {}", e),
+    }
+    let store = Arc::new(LogStore::default());
+    let _ = logging::init_console(store.clone());
+    Ok(store)
+}
+
+/// Run as the primary instance without a GUI until interrupted or asked to
+/// exit by a client; remote
+/// clients follow the log in `log_store`
+pub async fn run_headless(launch: LaunchArgs, log_store: Arc<LogStore>) -> i32 {
+    let listener = match instance::acquire(default_transport().as_ref(), &launch).await {
+        Ok(InstanceRole::Primary(listener)) => listener,
+        Ok(InstanceRole::Forwarded) => return instance::EXIT_FORWARDED,
+        Err(e) => {
+            eprintln!("{}", e);
+            return e.exit_code();
+        }
+    };
+
+    let primary = match PrimaryInstance::start(listener, &launch, None, log_store) {
+        Ok(primary) => primary,
+        Err(e) => {
+            eprintln!("Failed to start: {}", e);
+            return EXIT_FAILED;
+        }
+    };
+    primary.start_plugins().await;
+    if let Err(e) = primary.handle(&launch.requests) {
+        eprintln!("{}", e);
+    }
+    log::info!("EventGhost running headless, listening on {}", primary.endpoint());
+
+    let status = tokio::select! {
+        interrupted = tokio::signal::ctrl_c() => match interrupted {
+            Ok(()) => EXIT_OK,
+            Err(e) => {
+                eprintln!("Failed to wait for Ctrl+C: {}", e);
+                EXIT_FAILED
+            }
+        },
+        () = primary.shutdown_requested() => {
+            log::info!("Exiting at the request of a client");
+            EXIT_OK
+        }
+    };
+    primary.shutdown().await;
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn parse_args(args: &[&str]) -> Invocation {
+        parse(std::iter::once("eventghost").chain(args.iter().copied()).map(String::from)).unwrap()
+    }
+
+    #[test]
+    fn test_subcommands_and_legacy_switches() {
+        match parse_args(&["event", "Remote.Play", "3"]) {
+            Invocation::Command { command: CliCommand::Event { name, payload }, .. } => {
+                assert_eq!(name, "Remote.Play");
+                assert_eq!(payload.as_deref(), Some("3"));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match parse_args(&["--endpoint", "eg.sock", "run", "--headless", "--event", "Started", "tree.json", "--config-dir", "cfg"]) {
+            Invocation::Run { launch, headless } => {
+                assert!(headless);
+                assert_eq!(launch.requests.len(), 2);
+                assert_eq!(launch.endpoint().unwrap(), IpcEndpoint::at("eg.sock"));
+                assert_eq!(launch.config_dir.as_deref(), Some(Path::new("cfg")));
+                assert_eq!(launch.log_level, None);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        match parse_args(&["-event", "Remote.Play", "-hide"]) {
+            Invocation::Run { launch, headless } => {
+                assert!(!headless && launch.hide);
+                assert_eq!(launch.requests.len(), 1);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        assert!(matches!(parse_args(&["-restart"]), Invocation::Run { launch, .. } if launch.restart));
+        assert!(matches!(
+            parse_args(&["run", "--log-level", "debug"]),
+            Invocation::Run { launch, .. } if launch.log_level.as_deref() == Some("debug")
+        ));
+        assert!(matches!(parse_args(&["run", "--restart"]), Invocation::Run { launch, .. } if launch.restart));
+        assert!(matches!(parse_args(&[]), Invocation::Run { .. }));
+        assert!(matches!(parse_args(&["tree.egtree"]), Invocation::Run { .. }));
+        assert!(matches!(
+            parse_args(&["macro", "run", "Lights/Dim"]),
+            Invocation::Command { command: CliCommand::Macro { .. }, .. }
+        ));
+        match parse_args(&["log", "export", "log.csv", "--level", "warn", "--target", "plugin::MQTT", "--utc"]) {
+            Invocation::Command { command: CliCommand::Log { command: LogCommand::Export { format, level, targets, time_format, utc, .. } }, .. } => {
+                assert_eq!((format, level), (None, Some(log::Level::Warn)));
+                assert_eq!(targets, ["plugin::MQTT"]);
+                assert_eq!((time_format, utc), (None, true));
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(parse(["eventghost", "log", "export", "x", "--format", "pdf"].map(String::from)).is_err());
+        assert!(matches!(
+            parse_args(&["config", "show"]),
+            Invocation::Command { command: CliCommand::Config { command: ConfigCommand::Show }, .. }
+        ));
+        assert!(parse(["eventghost", "plugins", "bogus"].map(String::from)).is_err());
+    }
+
+    #[test]
+    fn test_validate_and_convert() {
+        let dir = tempdir().unwrap();
+        let legacy = dir.path().join("tree.egtree");
+        std::fs::write(&legacy, r#"<EventGhost Version="0.5"><Macro Name="M"><Event Name="E"/></Macro></EventGhost>"#).unwrap();
+        let converted = dir.path().join("tree.toml");
+        assert_eq!(convert(&legacy, &converted), EXIT_OK);
+        assert_eq!(validate(&converted), EXIT_OK);
+
+        let settings = dir.path().join("config.toml");
+        let text = |level: &str| {
+            format!("version = 1\nplugins = []\n[global]\nplugin_dir = \"plugins\"\nlog_level = \"{}\"\ntheme = \"x\"\n", level)
+        };
+        std::fs::write(&settings, text("loud")).unwrap();
+        assert_eq!(validate(&settings), EXIT_FAILED);
+        std::fs::write(&settings, text("debug")).unwrap();
+        assert_eq!(validate(&settings), EXIT_OK);
+    }
+
+    #[tokio::test]
+    async fn test_offline_commands_need_no_endpoint() {
+        let dir = tempdir().unwrap();
+        let legacy = dir.path().join("tree.egtree");
+        std::fs::write(&legacy, r#"<EventGhost Version="0.5"><Macro Name="M"/></EventGhost>"#).unwrap();
+        // --multi without --config-dir has no endpoint
+        let launch = || LaunchArgs { multi: true, ..Default::default() };
+
+        let converted = dir.path().join("tree.toml");
+        let command = CliCommand::Convert { input: legacy, output: converted.clone() };
+        assert_eq!(execute(command, launch()).await, EXIT_OK);
+        assert_eq!(execute(CliCommand::Validate { file: converted }, launch()).await, EXIT_OK);
+        let command = CliCommand::Plugins { command: PluginsCommand::List };
+        assert_eq!(execute(command, launch()).await, instance::EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_reported() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "version = 1\nplugins = []\n[global]\nplugin_dir = \"plugins\"\nlog_level = \"loud\"\ntheme = \"x\"\n",
+        )
+        .unwrap();
+        let launch = LaunchArgs { config_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+        let err = start_logging(&launch).err().unwrap();
+        assert!(err.to_string().contains("global.log_level"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_commands_without_instance_run_locally() {
+        let dir = tempdir().unwrap();
+        let launch = || LaunchArgs {
+            config_dir: Some(dir.path().to_path_buf()),
+            endpoint: Some(dir.path().join("eg.sock")),
+            ..Default::default()
+        };
+        let command = CliCommand::Event { name: "Remote.Play".into(), payload: None };
+        assert_eq!(execute(command, launch()).await, EXIT_OK);
+        let command = CliCommand::Macro { command: MacroCommand::Run { item: "Lights/Dim".into() } };
+        assert_eq!(execute(command, launch()).await, EXIT_FAILED);
+
+        assert_eq!(execute(CliCommand::Run(RunArgs::default()), launch()).await, instance::EXIT_USAGE);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_log_export() {
+        use std::sync::RwLock;
+        use crate::core::event::EventBus;
+        use crate::core::ipc::{InstanceHandler, IpcServer};
+        use crate::core::PluginRegistry;
+        use crate::eg::globals::Globals;
+        use crate::eg::tree::Document;
+
+        let dir = tempdir().unwrap();
+        let store = Arc::new(LogStore::default());
+        for (level, message) in [(log::Level::Info, "started"), (log::Level::Warn, "disk almost full")] {
+            let args = format_args!("{}", message);
+            store.push(LogEntry::from_record(&log::Record::builder().args(args).level(level).target("core").build()));
+        }
+        let handler = InstanceHandler::new(
+            Arc::new(RwLock::new(Document::new())),
+            Arc::new(PluginRegistry::new(dir.path().to_path_buf()).unwrap()),
+            Arc::new(tokio::sync::Mutex::new(Globals::new())),
+            EventBus::default(),
+        )
+        .with_log_store(store);
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+        let listener = default_transport().bind(&endpoint).await.unwrap();
+        let _server = IpcServer::serve(listener, Arc::new(handler));
+
+        let output = dir.path().join("log.csv");
+        let command = LogCommand::Export {
+            output: output.clone(),
+            format: None,
+            level: Some(log::Level::Warn),
+            targets: Vec::new(),
+            limit: None,
+            time_format: Some("[%Y UTC]".to_string()),
+            utc: true,
+        };
+        let launch = LaunchArgs { endpoint: Some(dir.path().join("eg.sock")), ..Default::default() };
+        assert_eq!(execute(CliCommand::Log { command }, launch).await, EXIT_OK);
+        let csv = std::fs::read_to_string(&output).unwrap();
+        assert_eq!(csv.lines().count(), 2, "{}", csv);
+        assert!(csv.contains("disk almost full"));
+        assert!(csv.contains(&chrono::Utc::now().format("[%Y UTC]").to_string()), "{}", csv);
+    }
+}
@@ -0,0 +1,100 @@
+//! WebSocket feed of live events and log entries
+//!
+//! Each text message is one JSON object:
+//!
+//! ```text
+//! {"type":"event","name":"Remote.Play","source":"ipc","payload":null,"timestamp":"..."}
+//! {"type":"log","entry":{"seq":42,"level":"INFO","target":"...","message":"...",...}}
+//! ```
+//!
+//! Messages from the client are ignored, apart from close frames.
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use crate::core::event::Event;
+use crate::core::logging::StoredLogEntry;
+use super::ApiState;
+
+/// One feed message
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedMessage {
+    Event {
+        name: String,
+        source: Option<String>,
+        payload: Value,
+        timestamp: DateTime<Local>,
+    },
+    Log { entry: StoredLogEntry },
+}
+
+impl FeedMessage {
+    pub fn event(event: &dyn Event) -> Self {
+        FeedMessage::Event {
+            name: event.get_id().to_string(),
+            source: event.get_source().map(str::to_string),
+            payload: event.get_payload().to_json(),
+            timestamp: event.get_timestamp(),
+        }
+    }
+}
+
+/// `GET /api/v1/feed`
+pub async fn subscribe(State(state): State<ApiState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream(socket, state))
+}
+
+/// Forward events and log entries until either side goes away
+async fn stream(mut socket: WebSocket, state: ApiState) {
+    let mut events = state.events.subscribe();
+    let mut entries = state.handler.log_store().map(|store| store.subscribe());
+
+    loop {
+        let message = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => FeedMessage::event(event.as_ref()),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            },
+            entry = next_entry(&mut entries) => match entry {
+                Some(entry) => FeedMessage::Log { entry },
+                None => {
+                    entries = None;
+                    continue;
+                }
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+
+        let text = match serde_json::to_string(&message) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Next log entry; pending forever without a log store, `None` once it is gone
+async fn next_entry(entries: &mut Option<broadcast::Receiver<StoredLogEntry>>) -> Option<StoredLogEntry> {
+    let Some(receiver) = entries else {
+        return std::future::pending().await;
+    };
+    loop {
+        match receiver.recv().await {
+            Ok(entry) => return Some(entry),
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! HTTP REST and WebSocket control API
+//!
+//! Successor of the legacy `Webserver` plugin: dashboards and scripts get
+//! the commands of the IPC protocol over HTTP, answered by the same
+//! [`CommandHandler`].
+//!
+//! | Method        | Path                        |                                        |
+//! |---------------|-----------------------------|----------------------------------------|
+//! | `POST`        | `/api/v1/events/{name}`     | trigger an event; a JSON body is its payload |
+//! | `POST`        | `/api/v1/macros/{*item}`    | run a macro by path or ID              |
+//! | `GET`         | `/api/v1/tree`              | outline of the configuration tree      |
+//! | `GET`         | `/api/v1/plugins`           | plugins and their states               |
+//! | `GET`         | `/api/v1/variables`         | all global variables                   |
+//! | `GET`, `PUT`  | `/api/v1/variables/{name}`  | read or set one variable               |
+//! | `GET`         | `/api/v1/feed`              | WebSocket of live events and log entries |
+//!
+//! Every request must carry the configured token, as
+//! `Authorization: Bearer <token>` or, for browsers opening the WebSocket,
+//! as a `token` query parameter. Failures answer with the IPC error object,
+//! `{"error": {"code": -32001, "message": "..."}}`.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use axum::extract::{Query, Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use crate::core::config::{ApiConfig, SecretString};
+use crate::core::event::EventBus;
+use crate::core::ipc::{CommandHandler, RpcError};
+
+pub mod feed;
+pub mod routes;
+
+/// Error type for starting the API server
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("No API token is configured")]
+    MissingToken,
+}
+
+/// What request handlers share
+#[derive(Clone)]
+pub struct ApiState {
+    pub handler: Arc<dyn CommandHandler>,
+    pub events: EventBus,
+    token: Arc<SecretString>,
+}
+
+impl ApiState {
+    pub fn new(handler: Arc<dyn CommandHandler>, events: EventBus, token: SecretString) -> Self {
+        Self { handler, events, token: Arc::new(token) }
+    }
+}
+
+/// Serves the API until dropped
+pub struct ApiServer {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ApiServer {
+    /// Bind the configured address and serve clients presenting `token`,
+    /// the revealed `config.token`; must be called inside a Tokio runtime
+    pub fn start(
+        config: &ApiConfig,
+        token: SecretString,
+        handler: Arc<dyn CommandHandler>,
+        events: EventBus,
+    ) -> Result<Self, ApiError> {
+        if token.expose().is_empty() {
+            return Err(ApiError::MissingToken);
+        }
+        let listener = std::net::TcpListener::bind((config.address, config.port))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let local_addr = listener.local_addr()?;
+
+        let app = router(ApiState::new(handler, events, token));
+        let task = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("API server stopped: {}", e);
+            }
+        });
+        Ok(Self { local_addr, task })
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// All API routes, behind token authentication
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/api/v1/events/{name}", post(routes::trigger_event))
+        .route("/api/v1/macros/{*item}", post(routes::run_macro))
+        .route("/api/v1/tree", get(routes::list_tree))
+        .route("/api/v1/plugins", get(routes::list_plugins))
+        .route("/api/v1/variables", get(routes::list_variables))
+        .route("/api/v1/variables/{name}", get(routes::get_variable).put(routes::set_variable))
+        .route("/api/v1/feed", get(feed::subscribe))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+/// Reject requests without the configured token
+async fn authorize(State(state): State<ApiState>, request: Request, next: Next) -> Response {
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string);
+    let presented = bearer.or_else(|| {
+        Query::<TokenQuery>::try_from_uri(request.uri())
+            .ok()
+            .and_then(|query| query.0.token)
+    });
+
+    match presented {
+        Some(token) if same_token(token.trim(), state.token.expose()) => next.run(request).await,
+        _ => {
+            let error = RpcError::new(RpcError::INVALID_REQUEST, "Missing or wrong API token");
+            (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, "Bearer")], error_body(&error)).into_response()
+        }
+    }
+}
+
+/// Compare without returning early, so timing does not reveal a prefix
+pub(crate) fn same_token(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn error_body(error: &RpcError) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "error": error }))
+}
+
+impl IntoResponse for RpcError {
+    fn into_response(self) -> Response {
+        let status = match self.code {
+            RpcError::PARSE_ERROR | RpcError::INVALID_REQUEST => StatusCode::BAD_REQUEST,
+            RpcError::NOT_FOUND => StatusCode::NOT_FOUND,
+            RpcError::UNSUPPORTED => StatusCode::NOT_IMPLEMENTED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, error_body(&self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock;
+    use axum::body::{to_bytes, Body};
+    use axum::http::Method;
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+    use tokio_tungstenite::tungstenite::Message;
+    use tower::ServiceExt;
+    use crate::core::ipc::InstanceHandler;
+    use crate::core::logging::{LogEntry, LogStore};
+    use crate::core::config::SecretRef;
+    use crate::core::PluginRegistry;
+    use crate::eg::globals::Globals;
+    use crate::eg::tree::{Document, Folder, Macro_};
+
+    const TOKEN: &str = "test-token";
+
+    fn state(dir: &std::path::Path, store: Arc<LogStore>) -> ApiState {
+        let document = Document::new();
+        let mut folder = Folder::new("Lights");
+        folder.add_child(Arc::new(RwLock::new(Macro_::new("Dim"))));
+        document.get_root().write().unwrap().add_child(Arc::new(RwLock::new(folder)));
+
+        let events = EventBus::default();
+        let handler = InstanceHandler::new(
+            Arc::new(RwLock::new(document)),
+            Arc::new(PluginRegistry::new(dir.to_path_buf()).unwrap()),
+            Arc::new(tokio::sync::Mutex::new(Globals::new())),
+            events.clone(),
+        )
+        .with_log_store(store);
+        ApiState::new(Arc::new(handler), events, TOKEN.into())
+    }
+
+    async fn call(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_rest_endpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = state(dir.path(), Arc::new(LogStore::default()));
+        let mut received = state.events.subscribe();
+        let app = router(state);
+
+        let unauthorized = Request::get("/api/v1/tree").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(unauthorized).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let wrong = Request::get("/api/v1/tree?token=guess").body(Body::empty()).unwrap();
+        assert_eq!(app.clone().oneshot(wrong).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+        let (status, _) = call(&app, Method::POST, "/api/v1/events/Remote.Play", Some(json!({"volume": 3}))).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let event = received.recv().await.unwrap();
+        assert_eq!(event.get_id(), "Remote.Play");
+        assert_eq!(event.get_payload().to_json(), json!({"volume": 3}));
+
+        let (status, tree) = call(&app, Method::GET, "/api/v1/tree", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tree[0]["name"], "Lights");
+        assert_eq!(tree[0]["children"][0]["kind"], "macro");
+
+        assert_eq!(call(&app, Method::POST, "/api/v1/macros/Lights/Dim", None).await.0, StatusCode::NO_CONTENT);
+        let (status, body) = call(&app, Method::POST, "/api/v1/macros/Lights/Bright", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], RpcError::NOT_FOUND);
+
+        assert_eq!(call(&app, Method::PUT, "/api/v1/variables/mode", Some(json!("away"))).await.0, StatusCode::NO_CONTENT);
+        assert_eq!(call(&app, Method::GET, "/api/v1/variables/mode", None).await.1, json!("away"));
+        assert_eq!(call(&app, Method::GET, "/api/v1/variables", None).await.1, json!({"mode": "away"}));
+        assert_eq!(call(&app, Method::GET, "/api/v1/variables/other", None).await.0, StatusCode::NOT_FOUND);
+
+        let (status, plugins) = call(&app, Method::GET, "/api/v1/plugins", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(plugins, json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_feed() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(LogStore::default());
+        let state = state(dir.path(), store.clone());
+        let config = ApiConfig { enabled: true, port: 0, token: Some(SecretRef::new("api")), ..Default::default() };
+        let server = ApiServer::start(&config, TOKEN.into(), state.handler.clone(), state.events.clone()).unwrap();
+
+        let url = format!("ws://{}/api/v1/feed?token={}", server.local_addr(), TOKEN);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        // The subscription exists once the upgrade has completed; a ping round trip ensures that
+        socket.send(Message::Ping(Vec::new().into())).await.unwrap();
+        while !matches!(socket.next().await.unwrap().unwrap(), Message::Pong(_)) {}
+
+        state.handler.trigger_event("Remote.Play", json!(1)).await.unwrap();
+        let message = socket.next().await.unwrap().unwrap();
+        let message: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(message["type"], "event");
+        assert_eq!(message["name"], "Remote.Play");
+        assert_eq!(message["payload"], 1);
+
+        store.push(LogEntry::from_record(
+            &log::Record::builder().args(format_args!("hello")).level(log::Level::Info).target("test").build(),
+        ));
+        let message = socket.next().await.unwrap().unwrap();
+        let message: Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(message["type"], "log");
+        assert_eq!(message["entry"]["message"], "hello");
+
+        assert!(matches!(
+            ApiServer::start(&config, "".into(), state.handler.clone(), state.events.clone()),
+            Err(ApiError::MissingToken)
+        ));
+    }
+}
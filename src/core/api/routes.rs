@@ -0,0 +1,67 @@
+//! REST endpoints
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde_json::{Map, Value};
+use crate::core::ipc::{ItemRef, PluginStatus, RpcError, TreeEntry};
+use super::ApiState;
+
+/// `POST /api/v1/events/{name}`; an empty body means no payload
+pub async fn trigger_event(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, RpcError> {
+    state.handler.trigger_event(&name, json_body(&body)?).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /api/v1/macros/{*item}`
+pub async fn run_macro(State(state): State<ApiState>, Path(item): Path<String>) -> Result<StatusCode, RpcError> {
+    let item: ItemRef = item.parse().unwrap_or_else(|never| match never {});
+    state.handler.run_macro(&item).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /api/v1/tree`
+pub async fn list_tree(State(state): State<ApiState>) -> Result<Json<Vec<TreeEntry>>, RpcError> {
+    state.handler.list_tree().await.map(Json)
+}
+
+/// `GET /api/v1/plugins`
+pub async fn list_plugins(State(state): State<ApiState>) -> Result<Json<Vec<PluginStatus>>, RpcError> {
+    state.handler.list_plugins().await.map(Json)
+}
+
+/// `GET /api/v1/variables`
+pub async fn list_variables(State(state): State<ApiState>) -> Result<Json<Map<String, Value>>, RpcError> {
+    state.handler.list_variables().await.map(Json)
+}
+
+/// `GET /api/v1/variables/{name}`
+pub async fn get_variable(State(state): State<ApiState>, Path(name): Path<String>) -> Result<Json<Value>, RpcError> {
+    match state.handler.get_variable(&name).await? {
+        Some(value) => Ok(Json(value)),
+        None => Err(RpcError::not_found(name)),
+    }
+}
+
+/// `PUT /api/v1/variables/{name}` with the JSON value as body
+pub async fn set_variable(
+    State(state): State<ApiState>,
+    Path(name): Path<String>,
+    body: Bytes,
+) -> Result<StatusCode, RpcError> {
+    state.handler.set_variable(&name, json_body(&body)?).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Parse a JSON body whatever its content type, so `curl -d` works; empty is `null`
+fn json_body(body: &[u8]) -> Result<Value, RpcError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Value::Null);
+    }
+    serde_json::from_slice(body).map_err(|e| RpcError::new(RpcError::PARSE_ERROR, e.to_string()))
+}
@@ -0,0 +1,604 @@
+//! Layered configuration
+//!
+//! The effective configuration is built by merging, from lowest to highest
+//! precedence:
+//!
+//! 1. compiled defaults
+//! 2. the system-wide settings file
+//! 3. the per-user settings file under `utils::get_config_dir()`
+//! 4. `EVENTGHOST_*` environment variables
+//! 5. command-line overrides
+//!
+//! Layer files may be partial. Tables are merged key by key, and the
+//! `plugins` list is merged by plugin `id`. Every leaf value remembers the
+//! layer it came from so the GUI and CLI can explain where a setting is set.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use serde_json::{Map, Value};
+use crate::core::utils;
+use super::{migration, store, Config, ConfigError, ConfigStore, Keystore};
+
+/// Prefix of environment variables that override settings
+pub const ENV_PREFIX: &str = "EVENTGHOST_";
+
+/// Source of a configuration value, in increasing precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ConfigLayer {
+    /// Compiled-in defaults
+    Defaults,
+    /// System-wide settings file
+    System,
+    /// Per-user settings file
+    User,
+    /// `EVENTGHOST_*` environment variables
+    Environment,
+    /// Command-line flags
+    CommandLine,
+}
+
+impl fmt::Display for ConfigLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConfigLayer::Defaults => "defaults",
+            ConfigLayer::System => "system",
+            ConfigLayer::User => "user",
+            ConfigLayer::Environment => "environment",
+            ConfigLayer::CommandLine => "command line",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Effective configuration together with the origin of each value
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    config: Config,
+    sources: BTreeMap<String, ConfigLayer>,
+    user_path: Option<PathBuf>,
+}
+
+impl LayeredConfig {
+    /// The merged configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Consume the wrapper and return the merged configuration
+    pub fn into_config(self) -> Config {
+        self.config
+    }
+
+    /// Layer that supplied the value at `key`, e.g. `global.log_level` or
+    /// `plugins[Mouse].enabled`.
+    ///
+    /// For a table such as `global`, the highest layer of any value inside
+    /// it is returned.
+    pub fn source(&self, key: &str) -> Option<ConfigLayer> {
+        if let Some(layer) = self.sources.get(key) {
+            return Some(*layer);
+        }
+        self.sources
+            .iter()
+            .filter(|(path, _)| is_within(path, key))
+            .map(|(_, layer)| *layer)
+            .max()
+    }
+
+    /// Origin of every leaf value, keyed by path
+    pub fn sources(&self) -> &BTreeMap<String, ConfigLayer> {
+        &self.sources
+    }
+
+    /// Per-user settings file, which is where changes are saved
+    pub fn user_path(&self) -> Option<&Path> {
+        self.user_path.as_deref()
+    }
+}
+
+/// Resolves the effective configuration from all layers
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLoader {
+    system_path: Option<PathBuf>,
+    user_path: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    overrides: Vec<(String, String)>,
+}
+
+impl ConfigLoader {
+    /// Loader using the standard file locations and the process environment
+    pub fn new() -> Self {
+        Self::default()
+            .with_system_file(utils::get_system_config_path())
+            .with_user_file_opt(utils::get_user_config_path().ok())
+            .with_env(std::env::vars())
+    }
+
+    /// Compiled defaults, with the plugin directory and log file resolved for
+    /// this user
+    pub fn defaults() -> Config {
+        let mut config = Config::default();
+        if let Ok(dir) = utils::get_user_plugin_dir() {
+            config.global.plugin_dir = dir;
+        }
+        if let Ok(path) = utils::get_log_path() {
+            config.global.log.file_path = path;
+        }
+        config
+    }
+
+    /// Use `path` as the system-wide settings file
+    pub fn with_system_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.system_path = Some(path.into());
+        self
+    }
+
+    /// Use `path` as the per-user settings file
+    pub fn with_user_file(self, path: impl Into<PathBuf>) -> Self {
+        self.with_user_file_opt(Some(path.into()))
+    }
+
+    fn with_user_file_opt(mut self, path: Option<PathBuf>) -> Self {
+        self.user_path = path;
+        self
+    }
+
+    /// Replace the environment the loader reads `EVENTGHOST_*` variables from
+    pub fn with_env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = vars
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .filter(|(k, _)| k.starts_with(ENV_PREFIX))
+            .collect();
+        self
+    }
+
+    /// Add a command-line override for a dotted key such as `global.log_level`
+    pub fn with_override(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.overrides.push((key.into(), value.into()));
+        self
+    }
+
+    /// Per-user settings file, if any
+    pub fn user_path(&self) -> Option<&Path> {
+        self.user_path.as_deref()
+    }
+
+    /// Move clear-text secrets in the per-user settings file into `keystore`
+    ///
+    /// Only that file is rewritten, and it keeps holding only what it set.
+    /// Returns true if it changed.
+    pub fn seal_user_file(&self, keystore: &mut Keystore) -> Result<bool, ConfigError> {
+        let Some(path) = &self.user_path else {
+            return Ok(false);
+        };
+        let Some(mut value) = read_layer_file(path)? else {
+            return Ok(false);
+        };
+        if !keystore.seal_layer(&mut value)? {
+            return Ok(false);
+        }
+        let text = toml::to_string_pretty(&value)
+            .map_err(|e| ConfigError::Other(format!("Failed to serialize settings: {}", e)))?;
+        store::write_file(path, &text)?;
+        Ok(true)
+    }
+
+    /// Settings files this loader reads, whether or not they exist yet
+    pub fn watched_paths(&self) -> Vec<PathBuf> {
+        self.system_path.iter().chain(self.user_path.iter()).cloned().collect()
+    }
+
+    /// Merge all layers and validate the result
+    pub fn load(&self) -> Result<LayeredConfig, ConfigError> {
+        let mut merged = serde_json::to_value(Self::defaults())
+            .map_err(|e| ConfigError::Other(e.to_string()))?;
+        let mut sources = BTreeMap::new();
+        record_leaves(&merged, "", ConfigLayer::Defaults, &mut sources);
+
+        for (layer, path) in [
+            (ConfigLayer::System, &self.system_path),
+            (ConfigLayer::User, &self.user_path),
+        ] {
+            if let Some(path) = path {
+                if let Some(value) = read_layer_file(path)? {
+                    merge(&mut merged, value, "", layer, &mut sources)?;
+                }
+            }
+        }
+
+        for (name, raw) in &self.env {
+            let key = env_key(name);
+            if lookup(&merged, &key).is_none() {
+                log::debug!("Ignoring {}: no setting named {}", name, key);
+                continue;
+            }
+            apply_override(&mut merged, &key, raw, ConfigLayer::Environment, &mut sources)?;
+        }
+
+        for (key, raw) in &self.overrides {
+            apply_override(&mut merged, key, raw, ConfigLayer::CommandLine, &mut sources)?;
+        }
+
+        apply_log_level(&mut merged, &mut sources);
+        sources.remove("version");
+        Ok(LayeredConfig {
+            config: Config::from_value(merged)?,
+            sources,
+            user_path: self.user_path.clone(),
+        })
+    }
+}
+
+/// The merged settings of every layer
+///
+/// Saving is refused: each file holds only its own layer, so changes are
+/// written to a single file rather than as a whole configuration.
+impl ConfigStore for ConfigLoader {
+    fn load(&self) -> Result<Config, ConfigError> {
+        ConfigLoader::load(self).map(LayeredConfig::into_config)
+    }
+
+    fn save(&self, _config: &Config) -> Result<(), ConfigError> {
+        Err(ConfigError::Other("Layered settings cannot be saved as a whole".to_string()))
+    }
+}
+
+/// Read and migrate one layer file; a missing file is not an error
+///
+/// An upgraded file is backed up and rewritten, still holding only what
+/// it set. Layers the user cannot write, such as the system file, are
+/// upgraded in memory only.
+fn read_layer_file(path: &Path) -> Result<Option<Value>, ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let in_file = |msg: String| ConfigError::Invalid(format!("{}: {}", path.display(), msg));
+    let mut value: Value = toml::from_str(&text).map_err(|e| in_file(ConfigError::parse(&text, &e).to_string()))?;
+    if let Some(version) = migration::migrate(&mut value).map_err(|e| in_file(e.to_string()))? {
+        let rewritten = toml::to_string_pretty(&value)
+            .map_err(|e| ConfigError::Other(e.to_string()))
+            .and_then(|text| store::back_up(path, version).and_then(|()| store::write_file(path, &text)));
+        if let Err(e) = rewritten {
+            log::warn!("Keeping {} at schema version {}: {}", path.display(), version, e);
+        }
+    }
+    Ok(Some(value))
+}
+
+/// `EVENTGHOST_LOG_LEVEL` -> `global.log_level`, `EVENTGHOST_API__PORT` -> `global.api.port`
+fn env_key(name: &str) -> String {
+    let suffix = name[ENV_PREFIX.len()..].to_ascii_lowercase();
+    format!("global.{}", suffix.replace("__", "."))
+}
+
+fn join(parent: &str, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn is_within(path: &str, prefix: &str) -> bool {
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.starts_with('.') || rest.starts_with('['))
+}
+
+fn record_leaves(value: &Value, path: &str, layer: ConfigLayer, sources: &mut BTreeMap<String, ConfigLayer>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                record_leaves(child, &join(path, key), layer, sources);
+            }
+        }
+        Value::Array(items) if path == "plugins" => {
+            for item in items {
+                if let Some(id) = item.get("id").and_then(Value::as_str) {
+                    record_leaves(item, &format!("plugins[{}]", id), layer, sources);
+                }
+            }
+        }
+        _ => {
+            sources.insert(path.to_string(), layer);
+        }
+    }
+}
+
+fn replace(base: &mut Value, overlay: Value, path: &str, layer: ConfigLayer, sources: &mut BTreeMap<String, ConfigLayer>) {
+    sources.retain(|key, _| key != path && !is_within(key, path));
+    record_leaves(&overlay, path, layer, sources);
+    *base = overlay;
+}
+
+fn merge(
+    base: &mut Value,
+    overlay: Value,
+    path: &str,
+    layer: ConfigLayer,
+    sources: &mut BTreeMap<String, ConfigLayer>,
+) -> Result<(), ConfigError> {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                let child = join(path, &key);
+                match base.get_mut(&key) {
+                    Some(existing) if child == "plugins" => merge_plugins(existing, value, layer, sources)?,
+                    Some(existing) => merge(existing, value, &child, layer, sources)?,
+                    None => {
+                        record_leaves(&value, &child, layer, sources);
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => replace(base, overlay, path, layer, sources),
+    }
+    Ok(())
+}
+
+fn merge_plugins(
+    base: &mut Value,
+    overlay: Value,
+    layer: ConfigLayer,
+    sources: &mut BTreeMap<String, ConfigLayer>,
+) -> Result<(), ConfigError> {
+    let (Value::Array(base), Value::Array(overlay)) = (base, overlay) else {
+        return Err(ConfigError::Invalid("plugins: expected a list".to_string()));
+    };
+
+    for (index, entry) in overlay.into_iter().enumerate() {
+        let id = entry
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| ConfigError::Invalid(format!("plugins[{}].id: missing", index)))?
+            .to_string();
+        let path = format!("plugins[{}]", id);
+        match base.iter_mut().find(|p| p.get("id").and_then(Value::as_str) == Some(id.as_str())) {
+            Some(existing) => merge(existing, entry, &path, layer, sources)?,
+            None => {
+                record_leaves(&entry, &path, layer, sources);
+                base.push(entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Let `global.log_level` set the console and file levels of `[global.log]`
+/// unless they are set in the same or a higher layer
+fn apply_log_level(merged: &mut Value, sources: &mut BTreeMap<String, ConfigLayer>) {
+    let Some(&layer) = sources.get("global.log_level") else {
+        return;
+    };
+    // An invalid level is reported when the merged value is parsed
+    let Some(level) = lookup(merged, "global.log_level")
+        .and_then(Value::as_str)
+        .and_then(|level| level.parse::<log::LevelFilter>().ok())
+    else {
+        return;
+    };
+    // Outputs have no `off` level; errors are still logged
+    let level = serde_json::to_value(level.to_level().unwrap_or(log::Level::Error)).unwrap_or_default();
+    let Some(log) = merged.pointer_mut("/global/log").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for key in ["console_level", "file_level"] {
+        let path = format!("global.log.{}", key);
+        if sources.get(&path).is_some_and(|set_in| *set_in < layer) {
+            log.insert(key.to_string(), level.clone());
+            sources.insert(path, layer);
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |current, part| current.get(part))
+}
+
+/// Set a dotted key from a string, keeping strings as-is and parsing
+/// anything else (numbers, booleans, lists) as JSON.
+fn apply_override(
+    root: &mut Value,
+    key: &str,
+    raw: &str,
+    layer: ConfigLayer,
+    sources: &mut BTreeMap<String, ConfigLayer>,
+) -> Result<(), ConfigError> {
+    let parsed = match lookup(root, key) {
+        Some(Value::String(_)) | None => Value::String(raw.to_string()),
+        Some(_) => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    };
+
+    let mut parts = key.split('.').peekable();
+    let mut current = root;
+    while let Some(part) = parts.next() {
+        let map: &mut Map<String, Value> = current
+            .as_object_mut()
+            .ok_or_else(|| ConfigError::Invalid(format!("{}: not a table", key)))?;
+        if parts.peek().is_none() {
+            let slot = map.entry(part.to_string()).or_insert(Value::Null);
+            replace(slot, parsed, key, layer, sources);
+            return Ok(());
+        }
+        current = map
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    Err(ConfigError::Invalid(format!("{}: empty key", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::{ConfigManager, MasterKey, SecretRef, CONFIG_SCHEMA_VERSION};
+
+    fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = write(
+            dir.path(),
+            "system.toml",
+            "[global]\ntheme = \"dark\"\nlog_level = \"warn\"\n\n[[plugins]]\nid = \"Mouse\"\nenabled = true\nsettings = { speed = 3, accel = 1 }\n",
+        );
+        let user = write(
+            dir.path(),
+            "user.toml",
+            "[global]\nlog_level = \"debug\"\n\n[[plugins]]\nid = \"Mouse\"\nsettings = { speed = 5 }\n",
+        );
+
+        let layered = ConfigLoader::default()
+            .with_system_file(system)
+            .with_user_file(user)
+            .with_env([("EVENTGHOST_THEME", "light"), ("PATH", "/bin")])
+            .with_override("global.log_level", "trace")
+            .load()
+            .unwrap();
+
+        let config = layered.config();
+        assert_eq!(config.global.theme, "light");
+        assert_eq!(config.global.log_level, "trace");
+        assert_eq!(config.plugins[0].settings["speed"], 5);
+        assert_eq!(config.plugins[0].settings["accel"], 1);
+
+        assert_eq!(layered.source("global.plugin_dir"), Some(ConfigLayer::Defaults));
+        assert_eq!(layered.source("global.theme"), Some(ConfigLayer::Environment));
+        assert_eq!(layered.source("global.log_level"), Some(ConfigLayer::CommandLine));
+        assert_eq!(layered.source("plugins[Mouse].enabled"), Some(ConfigLayer::System));
+        assert_eq!(layered.source("plugins[Mouse].settings.speed"), Some(ConfigLayer::User));
+        assert_eq!(layered.source("plugins[Mouse]"), Some(ConfigLayer::User));
+    }
+
+    #[test]
+    fn test_old_layer_file_is_backed_up_and_upgraded() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy = "[global]\nlog_level = \"debug\"\n";
+        let user = write(dir.path(), "user.toml", legacy);
+
+        let layered = ConfigLoader::default().with_user_file(&user).load().unwrap();
+        assert_eq!(layered.config().global.log_level, "debug");
+        assert_eq!(fs::read_to_string(store::backup_path(&user, 0)).unwrap(), legacy);
+
+        // Still partial, so the other layers keep showing through
+        let upgraded: Value = toml::from_str(&fs::read_to_string(&user).unwrap()).unwrap();
+        assert_eq!(upgraded["version"], CONFIG_SCHEMA_VERSION);
+        assert_eq!(upgraded["global"].as_object().unwrap().len(), 1);
+        assert_eq!(ConfigLoader::default().with_user_file(&user).load().unwrap().config(), layered.config());
+    }
+
+    #[test]
+    fn test_sealing_rewrites_only_the_user_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let system = write(dir.path(), "system.toml", "[global]\ntheme = \"dark\"\n");
+        let user = write(
+            dir.path(),
+            "user.toml",
+            &format!(
+                "version = {}\n\n[global.api]\ntoken = {{ \"$secret\" = \"\", value = \"t0ken\" }}\n\n\
+                 [[plugins]]\nid = \"MQTT\"\nenabled = true\nsettings = {{ password = {{ \"$secret\" = \"\", value = \"pw\" }} }}\n",
+                CONFIG_SCHEMA_VERSION
+            ),
+        );
+        let loader = ConfigLoader::default().with_system_file(&system).with_user_file(&user);
+        let mut keystore =
+            Keystore::open(dir.path().join("secrets.json"), MasterKey::KeyFile(dir.path().join("key"))).unwrap();
+
+        assert!(loader.seal_user_file(&mut keystore).unwrap());
+        assert!(!loader.seal_user_file(&mut keystore).unwrap());
+
+        let text = fs::read_to_string(&user).unwrap();
+        assert!(!text.contains("t0ken") && !text.contains("pw"), "{}", text);
+        let sealed: Value = toml::from_str(&text).unwrap();
+        assert_eq!(sealed["global"].as_object().unwrap().len(), 1);
+        assert!(sealed["global"].get("theme").is_none());
+
+        let manager = ConfigManager::new(Box::new(loader)).unwrap().with_keystore_as_is(keystore);
+        let config = manager.get_config();
+        assert_eq!(config.global.theme, "dark");
+        let token = config.global.api.token.as_ref().unwrap();
+        assert_eq!(manager.reveal_secret(token).unwrap().expose(), "t0ken");
+        let password: SecretRef = serde_json::from_value(config.plugins[0].settings["password"].clone()).unwrap();
+        assert_eq!(manager.reveal_secret(&password).unwrap().expose(), "pw");
+    }
+
+    #[test]
+    fn test_log_level_sets_unset_output_levels() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = write(dir.path(), "user.toml", "[global]\nlog_level = \"debug\"\n");
+        let layered = ConfigLoader::default().with_user_file(&user).load().unwrap();
+        let log = &layered.config().global.log;
+        assert_eq!((log.console_level, log.file_level), (log::Level::Debug, log::Level::Debug));
+        assert_eq!(layered.source("global.log.console_level"), Some(ConfigLayer::User));
+
+        let user = write(
+            dir.path(),
+            "user.toml",
+            "[global]\nlog_level = \"debug\"\n\n[global.log]\nfile_level = \"warn\"\n",
+        );
+        let loader = ConfigLoader::default().with_user_file(&user);
+        let log = loader.load().unwrap().into_config().global.log;
+        assert_eq!((log.console_level, log.file_level), (log::Level::Debug, log::Level::Warn));
+
+        let log = loader.with_override("global.log_level", "trace").load().unwrap().into_config().global.log;
+        assert_eq!((log.console_level, log.file_level), (log::Level::Trace, log::Level::Trace));
+    }
+
+    #[test]
+    fn test_missing_files_fall_back_to_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let layered = ConfigLoader::default()
+            .with_system_file(dir.path().join("missing.toml"))
+            .load()
+            .unwrap();
+
+        assert_eq!(layered.config(), &ConfigLoader::defaults());
+        assert_eq!(layered.source("global"), Some(ConfigLayer::Defaults));
+    }
+
+    #[test]
+    fn test_invalid_layer_value_names_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = write(dir.path(), "user.toml", "[global]\nlog_level = \"loud\"\n");
+        let err = ConfigLoader::default().with_user_file(user).load().unwrap_err();
+        assert!(err.to_string().contains("global.log_level"), "{}", err);
+    }
+
+    #[test]
+    fn test_api_section_from_environment() {
+        let loader = ConfigLoader::default()
+            .with_env([("EVENTGHOST_API__ENABLED", "true"), ("EVENTGHOST_API__PORT", "9000")]);
+        let err = loader.load().unwrap_err();
+        assert!(err.to_string().contains("global.api.token"), "{}", err);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "config.toml",
+            "[global.api]\ntoken = { \"$secret\" = \"api\" }\n\n\
+             [[plugins]]\nid = \"MQTT\"\nenabled = true\nsettings = { password = { \"$secret\" = \"mqtt\" } }\n",
+        );
+        let layered = loader.with_user_file(&path).load().unwrap();
+        let api = &layered.config().global.api;
+        assert!(api.enabled);
+        assert_eq!((api.port, api.token.as_ref()), (9000, Some(&SecretRef::new("api"))));
+        let masked = layered.config().masked();
+        assert_eq!(masked.plugins[0].settings["password"], crate::core::config::secrets::SECRET_MASK);
+    }
+}
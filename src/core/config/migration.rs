@@ -0,0 +1,124 @@
+//! Schema versioning for the settings file
+//!
+//! Every settings file carries a `version` key. Files written before the
+//! key existed are treated as version 0. On load, the raw document is
+//! upgraded one step at a time through [`MIGRATIONS`] until it reaches
+//! [`CONFIG_SCHEMA_VERSION`], and only then deserialized into `Config`.
+
+use serde_json::{Map, Value};
+use super::ConfigError;
+
+/// Schema version written by this build
+pub const CONFIG_SCHEMA_VERSION: u32 = 1;
+
+/// A single upgrade step from `from` to `from + 1`
+pub struct Migration {
+    /// Schema version this step upgrades from
+    pub from: u32,
+    /// Short human readable summary, used in logs
+    pub description: &'static str,
+    /// Transform the raw document in place
+    pub apply: fn(&mut Map<String, Value>) -> Result<(), ConfigError>,
+}
+
+/// Ordered migration chain; entry `n` upgrades version `n` to `n + 1`
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        description: "introduce schema version field",
+        apply: migrate_v0_to_v1,
+    },
+];
+
+/// Read the schema version of a raw settings document
+pub fn schema_version(value: &Value) -> Result<u32, ConfigError> {
+    let root = value
+        .as_object()
+        .ok_or_else(|| ConfigError::Invalid("<root>: expected a table".to_string()))?;
+
+    match root.get("version") {
+        None => Ok(0),
+        Some(Value::Number(n)) => n
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| ConfigError::Invalid(format!("version: invalid schema version {}", n))),
+        Some(other) => Err(ConfigError::Invalid(format!(
+            "version: expected an integer, found {}",
+            other
+        ))),
+    }
+}
+
+/// Upgrade a raw settings document to the current schema version.
+///
+/// Returns the version the document had before migrating, or `None` if it
+/// was already current.
+pub fn migrate(value: &mut Value) -> Result<Option<u32>, ConfigError> {
+    let original = schema_version(value)?;
+    if original == CONFIG_SCHEMA_VERSION {
+        return Ok(None);
+    }
+    if original > CONFIG_SCHEMA_VERSION {
+        return Err(ConfigError::Invalid(format!(
+            "version: schema version {} is newer than the supported version {}",
+            original, CONFIG_SCHEMA_VERSION
+        )));
+    }
+
+    let root = value
+        .as_object_mut()
+        .ok_or_else(|| ConfigError::Invalid("<root>: expected a table".to_string()))?;
+
+    for step in &MIGRATIONS[original as usize..] {
+        log::info!(
+            "Migrating settings from schema version {}: {}",
+            step.from,
+            step.description
+        );
+        (step.apply)(root)?;
+        root.insert("version".to_string(), Value::from(step.from + 1));
+    }
+
+    Ok(Some(original))
+}
+
+/// Version 0 files predate the `version` key; their layout is otherwise
+/// identical, so only a missing plugin list is filled in. Layer files may
+/// be partial, so no section is required here.
+fn migrate_v0_to_v1(root: &mut Map<String, Value>) -> Result<(), ConfigError> {
+    root.entry("plugins").or_insert_with(|| Value::Array(Vec::new()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migration_chain_is_contiguous() {
+        assert_eq!(MIGRATIONS.len(), CONFIG_SCHEMA_VERSION as usize);
+        for (index, step) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(step.from as usize, index);
+        }
+    }
+
+    #[test]
+    fn test_migrate_unversioned_document() {
+        let mut value = json!({
+            "global": { "plugin_dir": "plugins", "log_level": "info", "theme": "default" }
+        });
+
+        assert_eq!(migrate(&mut value).unwrap(), Some(0));
+        assert_eq!(value["version"], json!(CONFIG_SCHEMA_VERSION));
+        assert_eq!(value["plugins"], json!([]));
+        assert_eq!(migrate(&mut value).unwrap(), None);
+    }
+
+    #[test]
+    fn test_newer_version_is_rejected() {
+        let mut value = json!({ "version": CONFIG_SCHEMA_VERSION + 1, "global": {} });
+        let err = migrate(&mut value).unwrap_err();
+        assert!(err.to_string().contains("version:"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+// ,
+use serde::{Serialize, Deserialize};
+// use crate::core::Error;
+// use thiserror::Error;
+use std::fmt::Debug;
+use crate::core::constants::DEFAULT_API_PORT;
+use crate::core::logging::LogConfig;
+
+pub mod layers;
+pub mod migration;
+pub mod reload;
+pub mod secrets;
+pub mod store;
+
+pub use layers::{ConfigLayer, ConfigLoader, LayeredConfig};
+pub use migration::CONFIG_SCHEMA_VERSION;
+pub use reload::{ConfigDiff, ConfigEvent, ConfigWatcher, PluginFactory, ReloadTargets};
+pub use secrets::{Keystore, MasterKey, SecretRef, SecretString};
+pub use store::FileConfigStore;
+
+/// Error type for configuration operations
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+    #[error("Missing field: {0}")]
+    MissingField(String),
+    #[error("line {line}, column {column}: {message}")]
+    Parse { line: usize, column: usize, message: String },
+    #[error("{0}")]
+    Other(String),
+}
+
+impl ConfigError {
+    /// A TOML syntax or type error in `text`, keeping where it happened
+    pub fn parse(text: &str, e: &toml::de::Error) -> Self {
+        let offset = e.span().map_or(0, |span| span.start).min(text.len());
+        let before = text.get(..offset).unwrap_or(text);
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or_default().chars().count() + 1;
+        ConfigError::Parse { line, column, message: e.message().to_string() }
+    }
+}
+
+impl From<String> for ConfigError {
+    fn from(s: String) -> Self {
+        ConfigError::Other(s)
+    }
+}
+
+impl From<&str> for ConfigError {
+    fn from(s: &str) -> Self {
+        ConfigError::Other(s.to_string())
+    }
+}
+
+/// Base trait for configuration storage
+pub trait ConfigStore: Send + Sync + Debug {
+    /// Load configuration from storage
+    fn load(&self) -> Result<Config, ConfigError>;
+    /// Save configuration to storage
+    fn save(&self, config: &Config) -> Result<(), ConfigError>;
+}
+
+/// Configuration data structure
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// Schema version of the settings file
+    #[serde(default)]
+    pub version: u32,
+    /// Global settings
+    pub global: GlobalConfig,
+    /// Plugin-specific settings
+    pub plugins: Vec<PluginConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_SCHEMA_VERSION,
+            global: GlobalConfig::default(),
+            plugins: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Build a configuration from an already migrated JSON value.
+    ///
+    /// Deserialization errors name the key path that failed, e.g.
+    /// `global.log_level: invalid type: integer, expected a string`.
+    pub fn from_value(value: serde_json::Value) -> Result<Self, ConfigError> {
+        let config: Config = serde_path_to_error::deserialize(value).map_err(|e| {
+            ConfigError::Invalid(format!("{}: {}", e.path(), e.inner()))
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Copy that is safe to log or export, with secret values masked
+    pub fn masked(&self) -> Config {
+        let mut masked = self.clone();
+        for plugin in &mut masked.plugins {
+            plugin.settings = secrets::mask_secrets(&plugin.settings);
+        }
+        masked
+    }
+
+    /// Check semantic constraints serde cannot express
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.version != CONFIG_SCHEMA_VERSION {
+            return Err(ConfigError::Invalid(format!(
+                "version: expected schema version {}, found {}",
+                CONFIG_SCHEMA_VERSION, self.version
+            )));
+        }
+
+        if self.global.log_level.parse::<log::LevelFilter>().is_err() {
+            return Err(ConfigError::Invalid(format!(
+                "global.log_level: unknown level '{}'",
+                self.global.log_level
+            )));
+        }
+
+        if self.global.api.enabled && self.global.api.token.is_none() {
+            return Err(ConfigError::Invalid(
+                "global.api.token: required when the API is enabled".to_string(),
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        for (index, plugin) in self.plugins.iter().enumerate() {
+            if plugin.id.is_empty() {
+                return Err(ConfigError::Invalid(format!("plugins[{}].id: must not be empty", index)));
+            }
+            if !seen.insert(plugin.id.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "plugins[{}].id: duplicate plugin id '{}'",
+                    index, plugin.id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Global configuration settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalConfig {
+    /// Plugin directory path
+    pub plugin_dir: PathBuf,
+    /// Log level
+    pub log_level: String,
+    /// UI theme
+    pub theme: String,
+    /// HTTP control API
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// Log file and levels
+    #[serde(default)]
+    pub log: LogConfig,
+    /// Tree file loaded at startup, relative to the config directory
+    #[serde(default)]
+    pub document: Option<PathBuf>,
+}
+
+impl Default for GlobalConfig {
+    fn default() -> Self {
+        Self {
+            plugin_dir: PathBuf::from("plugins"),
+            log_level: "info".to_string(),
+            theme: "default".to_string(),
+            api: ApiConfig::default(),
+            log: LogConfig::default(),
+            document: None,
+        }
+    }
+}
+
+/// HTTP REST and WebSocket control API settings (`[global.api]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Start the API server
+    pub enabled: bool,
+    /// Address to listen on; anything but loopback exposes the API to the network
+    pub address: IpAddr,
+    /// TCP port
+    pub port: u16,
+    /// Bearer token clients must present, kept in the keystore; required
+    /// when enabled
+    pub token: Option<SecretRef>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_API_PORT,
+            token: None,
+        }
+    }
+}
+
+/// Plugin-specific configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Plugin ID
+    pub id: String,
+    /// Plugin settings
+    pub settings: serde_json::Value,
+    /// Plugin enabled state
+    pub enabled: bool,
+}
+
+/// Configuration manager
+pub struct ConfigManager {
+    store: Box<dyn ConfigStore>,
+    config: Config,
+    keystore: Option<Keystore>,
+}
+
+impl ConfigManager {
+    /// Create a new configuration manager
+    pub fn new(store: Box<dyn ConfigStore>) -> Result<Self, ConfigError> {
+        let config = store.load()?;
+        Ok(Self { store, config, keystore: None })
+    }
+
+    /// Keep secret plugin settings in `keystore`.
+    ///
+    /// Any clear-text secrets already in the settings are moved into the
+    /// keystore and the settings are saved without them.
+    pub fn with_keystore(mut self, mut keystore: Keystore) -> Result<Self, ConfigError> {
+        let mut config = self.config.clone();
+        if keystore.seal(&mut config)? {
+            self.store.save(&config)?;
+            self.config = config;
+        }
+        self.keystore = Some(keystore);
+        Ok(self)
+    }
+
+    /// Reveal secrets from `keystore`, leaving the settings as they are.
+    ///
+    /// For stores whose files were sealed on their own, such as the layers
+    /// of a [`ConfigLoader`].
+    pub fn with_keystore_as_is(mut self, keystore: Keystore) -> Self {
+        self.keystore = Some(keystore);
+        self
+    }
+
+    /// Keystore holding secret plugin settings, if one is configured
+    pub fn keystore(&self) -> Option<&Keystore> {
+        self.keystore.as_ref()
+    }
+
+    /// Decrypt a secret referenced from plugin settings
+    pub fn reveal_secret(&self, secret: &SecretRef) -> Result<SecretString, ConfigError> {
+        self.keystore
+            .as_ref()
+            .ok_or_else(|| ConfigError::Other("No keystore configured for secret values".to_string()))?
+            .reveal(secret)
+    }
+
+    /// Get the current configuration
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Update the configuration
+    pub fn update_config(&mut self, mut config: Config) -> Result<(), ConfigError> {
+        if let Some(keystore) = &mut self.keystore {
+            keystore.seal(&mut config)?;
+        }
+        config.validate()?;
+        self.store.save(&config)?;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Get plugin configuration
+    pub fn get_plugin_config(&self, id: &str) -> Option<&PluginConfig> {
+        self.config.plugins.iter().find(|p| p.id == id)
+    }
+
+    /// Update plugin configuration
+    pub fn update_plugin_config(&mut self, config: PluginConfig) -> Result<(), ConfigError> {
+        let mut updated = self.config.clone();
+        match updated.plugins.iter_mut().find(|p| p.id == config.id) {
+            Some(existing) => *existing = config,
+            None => updated.plugins.push(config),
+        }
+        self.update_config(updated)
+    }
+}
+
+// #[cfg(test)]
+// mod tests {
+//     use super::*;
+
+//     struct MockConfigStore;
+
+//     impl ConfigStore for MockConfigStore {
+//         fn load(&self) -> Result<Config, ConfigError> {
+//             // TODO: Implement mock load
+//             unimplemented!()
+//         }
+
+//         fn save(&self, _config: &Config) -> Result<(), ConfigError> {
+//             // TODO: Implement mock save
+//             unimplemented!()
+//         }
+//     }
+
+//     #[test]
+//     fn test_config_manager() {
+//         // TODO: Implement config manager tests
+//         unimplemented!()
+//     }
+// }
@@ -0,0 +1,480 @@
+//! Hot reload of the settings files
+//!
+//! `ConfigWatcher` watches every file a `ConfigLoader` reads. After an edit
+//! settles, the layers are merged and validated again and the result is
+//! diffed against the active configuration. Accepted changes are broadcast
+//! as `ConfigEvent::Reloaded`; invalid edits are broadcast as
+//! `ConfigEvent::Rejected` and the previous configuration stays active.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use notify::{Watcher, RecursiveMode, Event as NotifyEvent};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio::task::JoinHandle;
+use crate::core::{Error, Plugin, PluginRegistry};
+use crate::core::logging::Logger;
+use super::{Config, ConfigError, ConfigLoader};
+
+/// Quiet period after the last file event before reloading
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Builds the plugins of the enabled entries in a configuration
+pub type PluginFactory = Arc<dyn Fn(&Config) -> Vec<Box<dyn Plugin>> + Send + Sync>;
+
+/// The parts of the running instance reloaded settings are applied to
+#[derive(Clone)]
+pub struct ReloadTargets {
+    registry: Arc<PluginRegistry>,
+    logger: Option<Logger>,
+    plugins: Option<PluginFactory>,
+}
+
+impl ReloadTargets {
+    /// Apply plugin settings to `registry`
+    pub fn new(registry: Arc<PluginRegistry>) -> Self {
+        Self { registry, logger: None, plugins: None }
+    }
+
+    /// Reconfigure `logger` when `global.log` or `global.log_level` change
+    pub fn with_logger(mut self, logger: Logger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    /// Build newly added or enabled plugins with `factory`
+    pub fn with_plugin_factory(mut self, factory: PluginFactory) -> Self {
+        self.plugins = Some(factory);
+        self
+    }
+}
+
+/// Differences between two configurations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigDiff {
+    /// Changed keys in the `global` section, e.g. `log_level`
+    pub global: Vec<String>,
+    /// Plugins whose settings or enabled state changed
+    pub plugins_changed: Vec<String>,
+    /// Plugins that gained a configuration entry
+    pub plugins_added: Vec<String>,
+    /// Plugins whose configuration entry was removed
+    pub plugins_removed: Vec<String>,
+}
+
+impl ConfigDiff {
+    /// Compare `old` against `new`
+    pub fn between(old: &Config, new: &Config) -> Self {
+        let mut diff = ConfigDiff::default();
+
+        let old_global = serde_json::to_value(&old.global).unwrap_or_default();
+        let new_global = serde_json::to_value(&new.global).unwrap_or_default();
+        if let (Some(old_global), Some(new_global)) = (old_global.as_object(), new_global.as_object()) {
+            for (key, value) in new_global {
+                if old_global.get(key) != Some(value) {
+                    diff.global.push(key.clone());
+                }
+            }
+            for key in old_global.keys() {
+                if !new_global.contains_key(key) {
+                    diff.global.push(key.clone());
+                }
+            }
+        }
+
+        for plugin in &new.plugins {
+            match old.plugins.iter().find(|p| p.id == plugin.id) {
+                Some(previous) if previous != plugin => diff.plugins_changed.push(plugin.id.clone()),
+                Some(_) => {}
+                None => diff.plugins_added.push(plugin.id.clone()),
+            }
+        }
+        for plugin in &old.plugins {
+            if !new.plugins.iter().any(|p| p.id == plugin.id) {
+                diff.plugins_removed.push(plugin.id.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// True if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty()
+            && self.plugins_changed.is_empty()
+            && self.plugins_added.is_empty()
+            && self.plugins_removed.is_empty()
+    }
+
+    /// Ids of every plugin whose configuration entry changed in any way
+    pub fn affected_plugins(&self) -> impl Iterator<Item = &str> {
+        self.plugins_changed
+            .iter()
+            .chain(&self.plugins_added)
+            .chain(&self.plugins_removed)
+            .map(String::as_str)
+    }
+}
+
+/// Outcome of a reload attempt
+#[derive(Debug, Clone)]
+pub enum ConfigEvent {
+    /// The settings changed and were accepted
+    Reloaded {
+        config: Arc<Config>,
+        diff: ConfigDiff,
+    },
+    /// The edited settings were invalid; the previous configuration stays active
+    Rejected {
+        error: String,
+    },
+}
+
+struct Shared {
+    loader: ConfigLoader,
+    current: RwLock<Arc<Config>>,
+    events: broadcast::Sender<ConfigEvent>,
+}
+
+impl Shared {
+    async fn reload(&self) -> Option<ConfigEvent> {
+        let event = match self.loader.load() {
+            Ok(layered) => {
+                let config = layered.into_config();
+                let mut current = self.current.write().await;
+                let diff = ConfigDiff::between(&current, &config);
+                if diff.is_empty() {
+                    return None;
+                }
+                log::info!("Settings reloaded: {:?}", diff);
+                *current = Arc::new(config);
+                ConfigEvent::Reloaded {
+                    config: current.clone(),
+                    diff,
+                }
+            }
+            Err(e) => {
+                log::warn!("Rejected edited settings, keeping the previous configuration: {}", e);
+                ConfigEvent::Rejected { error: e.to_string() }
+            }
+        };
+
+        // No subscribers is fine
+        let _ = self.events.send(event.clone());
+        Some(event)
+    }
+}
+
+/// Watches the settings files and reloads them when they change
+pub struct ConfigWatcher {
+    shared: Arc<Shared>,
+    _watcher: notify::RecommendedWatcher,
+    task: JoinHandle<()>,
+}
+
+impl ConfigWatcher {
+    /// Start watching the files read by `loader`, with `initial` as the
+    /// active configuration. Must be called from within a tokio runtime.
+    pub fn start(loader: ConfigLoader, initial: Config) -> Result<Self, ConfigError> {
+        // Editors usually replace files by renaming, so watch the parent
+        // directories and filter on the file names.
+        let mut watched = Vec::new();
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for path in loader.watched_paths() {
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+            let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir.to_path_buf() };
+            let Ok(dir) = dir.canonicalize() else {
+                log::debug!("Not watching {}: directory does not exist", path.display());
+                continue;
+            };
+            watched.push(dir.join(name));
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: Result<NotifyEvent, _>| {
+            if let Ok(event) = res {
+                if event.kind.is_access() {
+                    return;
+                }
+                if event.paths.iter().any(|p| watched.contains(p)) {
+                    let _ = tx.send(());
+                }
+            }
+        })
+        .map_err(|e| ConfigError::Other(format!("Failed to watch settings: {}", e)))?;
+
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| ConfigError::Other(format!("Failed to watch {}: {}", dir.display(), e)))?;
+        }
+
+        let (events, _) = broadcast::channel(16);
+        let shared = Arc::new(Shared {
+            loader,
+            current: RwLock::new(Arc::new(initial)),
+            events,
+        });
+
+        let reloader = shared.clone();
+        let task = tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                // Let bursts of events from a single save settle
+                tokio::time::sleep(DEBOUNCE).await;
+                while rx.try_recv().is_ok() {}
+                reloader.reload().await;
+            }
+        });
+
+        Ok(Self {
+            shared,
+            _watcher: watcher,
+            task,
+        })
+    }
+
+    /// Receive future reload outcomes
+    pub fn subscribe(&self) -> broadcast::Receiver<ConfigEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// The active configuration
+    pub async fn current(&self) -> Arc<Config> {
+        self.shared.current.read().await.clone()
+    }
+
+    /// Reload immediately; returns `None` if nothing changed
+    pub async fn reload(&self) -> Option<ConfigEvent> {
+        self.shared.reload().await
+    }
+
+    /// Apply every accepted reload to `targets` until the watcher is dropped
+    pub fn spawn_applier(&self, targets: ReloadTargets) -> JoinHandle<()> {
+        let mut events = self.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(ConfigEvent::Reloaded { config, diff }) => {
+                        if let Err(e) = apply_changes(&targets, &config, &diff).await {
+                            log::error!("Failed to apply reloaded settings: {}", e);
+                        }
+                    }
+                    Ok(ConfigEvent::Rejected { .. }) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Missed {} settings reloads", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+}
+
+impl Drop for ConfigWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Apply a reloaded configuration to the running instance.
+///
+/// Logging and `global.plugin_dir` take effect immediately. Plugins whose
+/// entry was removed or disabled are stopped and unregistered, newly added
+/// or enabled ones are built and started, and every other plugin whose
+/// entry changed receives `Plugin::update_config`. Changes to `global.api`
+/// only take effect after a restart, which is logged. Every change is
+/// attempted even if an earlier one fails; the first failure is returned.
+pub async fn apply_changes(targets: &ReloadTargets, config: &Config, diff: &ConfigDiff) -> Result<(), Error> {
+    let registry = &targets.registry;
+    let mut first_error: Option<Error> = None;
+    let changed = |key: &str| diff.global.iter().any(|changed| changed == key);
+
+    if changed("log") || changed("log_level") {
+        match &targets.logger {
+            Some(logger) => {
+                if let Err(e) = logger.update_config(config.global.log.clone()).await {
+                    first_error.get_or_insert(e.into());
+                }
+            }
+            None => {
+                if let Ok(level) = config.global.log_level.parse::<log::LevelFilter>() {
+                    log::set_max_level(level);
+                }
+            }
+        }
+    }
+    if changed("plugin_dir") {
+        if let Err(e) = registry.set_plugin_dir(config.global.plugin_dir.clone()).await {
+            first_error.get_or_insert(e.into());
+        }
+    }
+    if changed("api") {
+        log::warn!("Changes to global.api take effect after EventGhost is restarted");
+    }
+
+    let mut stopped: Vec<&str> = diff.plugins_removed.iter().map(String::as_str).collect();
+    let mut started = Vec::new();
+    let mut updated = Vec::new();
+    for id in diff.plugins_added.iter().chain(&diff.plugins_changed) {
+        let enabled = config.plugins.iter().any(|entry| entry.id == *id && entry.enabled);
+        match (enabled, registry.contains(id).await) {
+            (true, true) => updated.push(id.clone()),
+            (true, false) => started.push(id.as_str()),
+            (false, true) => stopped.push(id),
+            (false, false) => {}
+        }
+    }
+
+    for id in stopped {
+        log::info!("Stopping {}", id);
+        if let Err(e) = registry.remove(id).await {
+            first_error.get_or_insert(e.into());
+        }
+    }
+    if !started.is_empty() {
+        match &targets.plugins {
+            Some(factory) => {
+                let entries = Config {
+                    plugins: config.plugins.iter().filter(|entry| started.contains(&entry.id.as_str())).cloned().collect(),
+                    ..config.clone()
+                };
+                for plugin in factory(&entries) {
+                    log::info!("Starting {}", plugin.get_name());
+                    let result = match registry.register(plugin).await {
+                        Ok(uuid) => registry.start_plugin(uuid).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        log::warn!("{}", e);
+                        first_error.get_or_insert(e.into());
+                    }
+                }
+            }
+            None => log::warn!("{} will start after EventGhost is restarted", started.join(", ")),
+        }
+    }
+    if !updated.is_empty() {
+        if let Err(e) = registry.apply_config(config, &updated).await {
+            first_error.get_or_insert(e.into());
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::PluginConfig;
+    use crate::testing::mocks::MockPlugin;
+    use std::fs;
+
+    fn plugin(id: &str, enabled: bool) -> PluginConfig {
+        PluginConfig {
+            id: id.to_string(),
+            settings: serde_json::json!({}),
+            enabled,
+        }
+    }
+
+    #[test]
+    fn test_diff_between_configs() {
+        let old = Config {
+            plugins: vec![plugin("Mouse", true), plugin("Keyboard", true)],
+            ..Config::default()
+        };
+        let mut new = old.clone();
+        new.global.log_level = "debug".to_string();
+        new.plugins = vec![plugin("Mouse", false), plugin("Timer", true)];
+
+        let diff = ConfigDiff::between(&old, &new);
+        assert_eq!(diff.global, vec!["log_level".to_string()]);
+        assert_eq!(diff.plugins_changed, vec!["Mouse".to_string()]);
+        assert_eq!(diff.plugins_added, vec!["Timer".to_string()]);
+        assert_eq!(diff.plugins_removed, vec!["Keyboard".to_string()]);
+        assert!(ConfigDiff::between(&new, &new).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watcher_reloads_and_rejects_invalid_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "[global]\nlog_level = \"info\"\n").unwrap();
+
+        let loader = ConfigLoader::default().with_user_file(&path);
+        let initial = loader.load().unwrap().into_config();
+        let watcher = ConfigWatcher::start(loader, initial).unwrap();
+        let mut events = watcher.subscribe();
+
+        fs::write(&path, "[global]\nlog_level = \"debug\"\n").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        match event {
+            ConfigEvent::Reloaded { config, diff } => {
+                assert_eq!(config.global.log_level, "debug");
+                assert_eq!(config.global.log.console_level, log::Level::Debug);
+                assert_eq!(diff.global, vec!["log".to_string(), "log_level".to_string()]);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        fs::write(&path, "[global]\nlog_level = \"loud\"\n").unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        assert!(matches!(event, ConfigEvent::Rejected { .. }));
+        assert_eq!(watcher.current().await.global.log_level, "debug");
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_updates_affected_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(PluginRegistry::new(dir.path().to_path_buf()).unwrap());
+        let id = registry.register(Box::new(MockPlugin::new())).await.unwrap();
+
+        let old = Config::default();
+        let mut new = old.clone();
+        new.plugins.push(plugin("Mock Plugin", true));
+
+        let targets = ReloadTargets::new(registry.clone());
+        apply_changes(&targets, &new, &ConfigDiff::between(&old, &new)).await.unwrap();
+
+        let plugin = registry.get_plugin(id).await.unwrap();
+        assert_eq!(plugin.read().await.get_config(), Some(&new));
+        assert_eq!(registry.get_plugin_config(id).await.unwrap(), new);
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes_starts_and_stops_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(PluginRegistry::new(dir.path().to_path_buf()).unwrap());
+        let factory: PluginFactory = Arc::new(|config: &Config| {
+            config
+                .plugins
+                .iter()
+                .filter(|entry| entry.enabled && entry.id == "Mock Plugin")
+                .map(|_| Box::new(MockPlugin::new()) as Box<dyn Plugin>)
+                .collect()
+        });
+        let targets = ReloadTargets::new(registry.clone()).with_plugin_factory(factory);
+
+        let old = Config::default();
+        let mut enabled = old.clone();
+        enabled.plugins.push(plugin("Mock Plugin", true));
+        apply_changes(&targets, &enabled, &ConfigDiff::between(&old, &enabled)).await.unwrap();
+        let states = registry.get_plugin_states().await;
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].1, crate::core::plugin::PluginState::Running);
+
+        let mut disabled = enabled.clone();
+        disabled.plugins[0].enabled = false;
+        apply_changes(&targets, &disabled, &ConfigDiff::between(&enabled, &disabled)).await.unwrap();
+        assert!(!registry.contains("Mock Plugin").await);
+
+        apply_changes(&targets, &enabled, &ConfigDiff::between(&disabled, &enabled)).await.unwrap();
+        assert!(registry.contains("Mock Plugin").await);
+        apply_changes(&targets, &old, &ConfigDiff::between(&enabled, &old)).await.unwrap();
+        assert!(registry.get_plugins().await.is_empty());
+    }
+}
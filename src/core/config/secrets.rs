@@ -0,0 +1,482 @@
+//! Encrypted secret values for plugin settings
+//!
+//! Passwords and tokens never appear in the settings file. A plugin setting
+//! holding a secret is stored as a reference, `{ "$secret" = "<name>" }`,
+//! and the value itself lives encrypted in a separate keystore file.
+//!
+//! A hand-edited reference may carry the clear text as
+//! `{ "$secret" = "<name>", value = "..." }`; [`Keystore::seal`] and
+//! [`Keystore::seal_layer`] move such values into the keystore and strip
+//! them from the settings.
+//!
+//! Entries are encrypted with ChaCha20-Poly1305. The key is either derived
+//! from a passphrase with Argon2id or read from a 32-byte master key file.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use zeroize::Zeroize;
+use super::{Config, ConfigError};
+
+/// Settings key marking an object as a secret reference
+pub const SECRET_KEY: &str = "$secret";
+
+/// Replacement text for secrets in logs, exports and the GUI
+pub const SECRET_MASK: &str = "********";
+
+/// Current keystore file format
+const KEYSTORE_VERSION: u32 = 1;
+
+/// Known plaintext used to verify the master key on open
+const CHECK_PLAINTEXT: &[u8] = b"eventghost-keystore";
+
+/// A secret held in memory; masked when formatted and wiped on drop
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretString {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    /// Access the clear text
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretString({})", SECRET_MASK)
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(SECRET_MASK)
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Secret field type for plugin settings structs
+///
+/// Serializes as `{ "$secret" = "<name>" }`. Resolve it with
+/// [`Keystore::reveal`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SecretRef {
+    #[serde(rename = "$secret")]
+    pub name: String,
+}
+
+impl SecretRef {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+}
+
+/// Source of the keystore encryption key
+#[derive(Debug, Clone)]
+pub enum MasterKey {
+    /// Derive the key from a passphrase
+    Passphrase(SecretString),
+    /// Read a raw 32-byte key from a file, creating it on first use
+    KeyFile(PathBuf),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedSecret {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeystoreFile {
+    version: u32,
+    /// Base64 Argon2 salt, present for passphrase-protected stores
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    check: SealedSecret,
+    #[serde(default)]
+    entries: BTreeMap<String, SealedSecret>,
+}
+
+/// Encrypted store for secret values
+pub struct Keystore {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    salt: Option<String>,
+    check: SealedSecret,
+    entries: BTreeMap<String, SealedSecret>,
+}
+
+impl fmt::Debug for Keystore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keystore")
+            .field("path", &self.path)
+            .field("entries", &self.entries.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keystore {
+    /// Open the keystore at `path`, creating it if it does not exist.
+    ///
+    /// Fails with `ConfigError::Invalid` if `master` does not match the key
+    /// the store was created with.
+    pub fn open(path: impl Into<PathBuf>, master: MasterKey) -> Result<Self, ConfigError> {
+        let path = path.into();
+
+        if path.exists() {
+            let text = fs::read_to_string(&path)?;
+            let file: KeystoreFile = serde_json::from_str(&text)
+                .map_err(|e| ConfigError::Invalid(format!("{}: {}", path.display(), e)))?;
+            if file.version != KEYSTORE_VERSION {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: unsupported keystore version {}",
+                    path.display(),
+                    file.version
+                )));
+            }
+
+            let cipher = Self::cipher(&master, file.salt.as_deref())?;
+            let store = Self {
+                path,
+                cipher,
+                salt: file.salt,
+                check: file.check,
+                entries: file.entries,
+            };
+            store.decrypt("", &store.check).map_err(|_| {
+                ConfigError::Invalid(format!("{}: wrong passphrase or master key", store.path.display()))
+            })?;
+            return Ok(store);
+        }
+
+        let salt = match master {
+            MasterKey::Passphrase(_) => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                Some(BASE64.encode(salt))
+            }
+            MasterKey::KeyFile(_) => None,
+        };
+        let cipher = Self::cipher(&master, salt.as_deref())?;
+        let check = Self::encrypt_with(&cipher, "", CHECK_PLAINTEXT)?;
+        let store = Self {
+            path,
+            cipher,
+            salt,
+            check,
+            entries: BTreeMap::new(),
+        };
+        store.save()?;
+        Ok(store)
+    }
+
+    fn cipher(master: &MasterKey, salt: Option<&str>) -> Result<ChaCha20Poly1305, ConfigError> {
+        let mut key = [0u8; 32];
+        match master {
+            MasterKey::Passphrase(passphrase) => {
+                let salt = salt
+                    .ok_or_else(|| ConfigError::Invalid("keystore: not protected by a passphrase".to_string()))?;
+                let salt = BASE64
+                    .decode(salt)
+                    .map_err(|e| ConfigError::Invalid(format!("keystore salt: {}", e)))?;
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.expose().as_bytes(), &salt, &mut key)
+                    .map_err(|e| ConfigError::Other(format!("Key derivation failed: {}", e)))?;
+            }
+            MasterKey::KeyFile(path) => {
+                if salt.is_some() {
+                    return Err(ConfigError::Invalid("keystore: protected by a passphrase".to_string()));
+                }
+                key = Self::read_or_create_key_file(path)?;
+            }
+        }
+
+        let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+        key.zeroize();
+        Ok(cipher)
+    }
+
+    fn read_or_create_key_file(path: &Path) -> Result<[u8; 32], ConfigError> {
+        if path.exists() {
+            let bytes = fs::read(path)?;
+            return bytes.as_slice().try_into().map_err(|_| {
+                ConfigError::Invalid(format!("{}: master key must be exactly 32 bytes", path.display()))
+            });
+        }
+
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        write_private(path, &key)?;
+        Ok(key)
+    }
+
+    fn encrypt_with(cipher: &ChaCha20Poly1305, name: &str, plaintext: &[u8]) -> Result<SealedSecret, ConfigError> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        // The entry name is authenticated so ciphertexts can't be swapped
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: name.as_bytes() })
+            .map_err(|_| ConfigError::Other(format!("Failed to encrypt secret '{}'", name)))?;
+        Ok(SealedSecret {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    fn decrypt(&self, name: &str, sealed: &SealedSecret) -> Result<Vec<u8>, ConfigError> {
+        let invalid = || ConfigError::Invalid(format!("secret '{}': corrupt or tampered entry", name));
+        let nonce = BASE64.decode(&sealed.nonce).map_err(|_| invalid())?;
+        if nonce.len() != 12 {
+            return Err(invalid());
+        }
+        let ciphertext = BASE64.decode(&sealed.ciphertext).map_err(|_| invalid())?;
+        self.cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: name.as_bytes() })
+            .map_err(|_| invalid())
+    }
+
+    fn save(&self) -> Result<(), ConfigError> {
+        let file = KeystoreFile {
+            version: KEYSTORE_VERSION,
+            salt: self.salt.clone(),
+            check: self.check.clone(),
+            entries: self.entries.clone(),
+        };
+        let text = serde_json::to_string_pretty(&file)
+            .map_err(|e| ConfigError::Other(format!("Failed to serialize keystore: {}", e)))?;
+        write_private(&self.path, text.as_bytes())
+    }
+
+    /// Path of the keystore file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// True if a secret named `name` is stored
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Encrypt and store a secret, returning the reference to put in settings
+    pub fn store(&mut self, name: &str, value: &SecretString) -> Result<SecretRef, ConfigError> {
+        let sealed = Self::encrypt_with(&self.cipher, name, value.expose().as_bytes())?;
+        self.entries.insert(name.to_string(), sealed);
+        self.save()?;
+        Ok(SecretRef::new(name))
+    }
+
+    /// Decrypt the secret a reference points to
+    pub fn reveal(&self, secret: &SecretRef) -> Result<SecretString, ConfigError> {
+        let sealed = self
+            .entries
+            .get(&secret.name)
+            .ok_or_else(|| ConfigError::MissingField(format!("secret '{}'", secret.name)))?;
+        let bytes = self.decrypt(&secret.name, sealed)?;
+        String::from_utf8(bytes)
+            .map(SecretString)
+            .map_err(|_| ConfigError::Invalid(format!("secret '{}': not valid UTF-8", secret.name)))
+    }
+
+    /// Delete a stored secret
+    pub fn remove(&mut self, name: &str) -> Result<bool, ConfigError> {
+        let removed = self.entries.remove(name).is_some();
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    /// Move clear-text secret values out of every plugin's settings.
+    ///
+    /// References without a name are named `<plugin id>.<key path>`.
+    /// Returns true if the configuration changed and should be saved.
+    pub fn seal(&mut self, config: &mut Config) -> Result<bool, ConfigError> {
+        let mut changed = false;
+        for plugin in &mut config.plugins {
+            changed |= self.seal_value(&mut plugin.settings, &plugin.id)?;
+        }
+        Ok(changed)
+    }
+
+    /// Move clear-text secret values out of one settings file as read,
+    /// before it is merged with the other layers.
+    ///
+    /// Seals plugin settings the way [`Keystore::seal`] does, and
+    /// `global.api.token`. Returns true if the layer changed and should be
+    /// written back.
+    pub fn seal_layer(&mut self, layer: &mut Value) -> Result<bool, ConfigError> {
+        let mut changed = false;
+        if let Some(token) = layer.pointer_mut("/global/api/token") {
+            changed |= self.seal_value(token, "global.api.token")?;
+        }
+        if let Some(plugins) = layer.get_mut("plugins").and_then(Value::as_array_mut) {
+            for plugin in plugins {
+                let id = plugin.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+                if let Some(settings) = plugin.get_mut("settings") {
+                    changed |= self.seal_value(settings, &id)?;
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    fn seal_value(&mut self, value: &mut Value, path: &str) -> Result<bool, ConfigError> {
+        match value {
+            Value::Object(map) if map.contains_key(SECRET_KEY) => {
+                let Some(plain) = map.remove("value") else {
+                    return Ok(false);
+                };
+                let plain = plain
+                    .as_str()
+                    .ok_or_else(|| ConfigError::Invalid(format!("{}.value: secret must be a string", path)))?;
+                let name = match map.get(SECRET_KEY).and_then(Value::as_str) {
+                    Some(name) if !name.is_empty() => name.to_string(),
+                    _ => path.to_string(),
+                };
+                self.store(&name, &SecretString::from(plain))?;
+                map.insert(SECRET_KEY.to_string(), Value::String(name));
+                Ok(true)
+            }
+            Value::Object(map) => {
+                let mut changed = false;
+                for (key, child) in map.iter_mut() {
+                    changed |= self.seal_value(child, &format!("{}.{}", path, key))?;
+                }
+                Ok(changed)
+            }
+            Value::Array(items) => {
+                let mut changed = false;
+                for (index, child) in items.iter_mut().enumerate() {
+                    changed |= self.seal_value(child, &format!("{}[{}]", path, index))?;
+                }
+                Ok(changed)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+/// Copy of `value` with every secret reference replaced by [`SECRET_MASK`]
+pub fn mask_secrets(value: &Value) -> Value {
+    match value {
+        Value::Object(map) if map.contains_key(SECRET_KEY) => Value::String(SECRET_MASK.to_string()),
+        Value::Object(map) => Value::Object(map.iter().map(|(k, v)| (k.clone(), mask_secrets(v))).collect()),
+        Value::Array(items) => Value::Array(items.iter().map(mask_secrets).collect()),
+        other => other.clone(),
+    }
+}
+
+/// True if `value` is a secret reference
+pub fn is_secret(value: &Value) -> bool {
+    value.as_object().is_some_and(|map| map.contains_key(SECRET_KEY))
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), ConfigError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    // NamedTempFile is created with owner-only permissions on Unix
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.persist(path).map_err(|e| ConfigError::IO(e.error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::PluginConfig;
+    use serde_json::json;
+
+    #[test]
+    fn test_key_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_file = dir.path().join("master.key");
+        let path = dir.path().join("secrets.json");
+
+        let mut store = Keystore::open(&path, MasterKey::KeyFile(key_file.clone())).unwrap();
+        let secret = store.store("smtp.password", &SecretString::from("hunter2")).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(!text.contains("hunter2"));
+
+        let reopened = Keystore::open(&path, MasterKey::KeyFile(key_file)).unwrap();
+        assert_eq!(reopened.reveal(&secret).unwrap().expose(), "hunter2");
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.json");
+
+        let mut store = Keystore::open(&path, MasterKey::Passphrase("correct horse".into())).unwrap();
+        let secret = store.store("mqtt.token", &SecretString::from("abc")).unwrap();
+        drop(store);
+
+        let err = Keystore::open(&path, MasterKey::Passphrase("wrong".into())).unwrap_err();
+        assert!(err.to_string().contains("wrong passphrase"));
+
+        let store = Keystore::open(&path, MasterKey::Passphrase("correct horse".into())).unwrap();
+        assert_eq!(store.reveal(&secret).unwrap().expose(), "abc");
+    }
+
+    #[test]
+    fn test_seal_moves_clear_text_into_keystore() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store =
+            Keystore::open(dir.path().join("secrets.json"), MasterKey::KeyFile(dir.path().join("key"))).unwrap();
+        let mut config = Config::default();
+        config.plugins.push(PluginConfig {
+            id: "E-mail".to_string(),
+            settings: json!({ "server": "smtp.example.org", "password": { "$secret": "", "value": "pw" } }),
+            enabled: true,
+        });
+
+        assert!(store.seal(&mut config).unwrap());
+        let settings = &config.plugins[0].settings;
+        assert_eq!(settings["password"], json!({ "$secret": "E-mail.password" }));
+
+        let secret: SecretRef = serde_json::from_value(settings["password"].clone()).unwrap();
+        assert_eq!(store.reveal(&secret).unwrap().expose(), "pw");
+        assert_eq!(mask_secrets(settings)["password"], json!(SECRET_MASK));
+        assert!(!store.seal(&mut config).unwrap());
+    }
+
+    #[test]
+    fn test_secret_string_is_masked() {
+        let secret = SecretString::from("hunter2");
+        assert_eq!(format!("{} {:?}", secret, secret), "******** SecretString(********)");
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use super::{migration, Config, ConfigError, ConfigStore};
+
+/// TOML settings file on disk
+///
+/// Loading runs the schema migrations. When a file had to be upgraded, the
+/// original is copied to `<file>.v<old version>.bak` before the migrated
+/// version is written back.
+#[derive(Debug, Clone)]
+pub struct FileConfigStore {
+    path: PathBuf,
+}
+
+impl FileConfigStore {
+    /// Create a store for the given settings file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Path of the settings file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path the pre-migration copy of a file at `version` is written to
+    pub fn backup_path(&self, version: u32) -> PathBuf {
+        backup_path(&self.path, version)
+    }
+
+    /// Parse settings text, migrating it in memory.
+    ///
+    /// Returns the config and the schema version it was migrated from, if any.
+    pub fn parse(text: &str) -> Result<(Config, Option<u32>), ConfigError> {
+        let mut value: serde_json::Value = toml::from_str(text).map_err(|e| ConfigError::parse(text, &e))?;
+        let migrated_from = migration::migrate(&mut value)?;
+        Ok((Config::from_value(value)?, migrated_from))
+    }
+}
+
+impl ConfigStore for FileConfigStore {
+    fn load(&self) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(&self.path)?;
+        let (config, migrated_from) = Self::parse(&text)?;
+
+        if let Some(version) = migrated_from {
+            back_up(&self.path, version)?;
+            self.save(&config)?;
+        }
+
+        Ok(config)
+    }
+
+    fn save(&self, config: &Config) -> Result<(), ConfigError> {
+        let text = toml::to_string_pretty(config)
+            .map_err(|e| ConfigError::Other(format!("Failed to serialize settings: {}", e)))?;
+        write_file(&self.path, &text)
+    }
+}
+
+/// `<file>.v<version>.bak` next to the settings file at `path`
+pub(super) fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".v{}.bak", version));
+    path.with_file_name(name)
+}
+
+/// Copy the settings file at `path`, still at schema `version`, to its
+/// backup path before it is rewritten
+pub(super) fn back_up(path: &Path, version: u32) -> Result<(), ConfigError> {
+    let backup = backup_path(path, version);
+    fs::copy(path, &backup)?;
+    log::info!(
+        "Settings upgraded from schema version {}; backup written to {}",
+        version,
+        backup.display()
+    );
+    Ok(())
+}
+
+/// Replace the file at `path` with `text`, creating its directory if needed
+pub(super) fn write_file(path: &Path, text: &str) -> Result<(), ConfigError> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+
+    // Write next to the target and rename so a crash never leaves a
+    // half-written settings file behind.
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(text.as_bytes())?;
+    file.persist(path).map_err(|e| ConfigError::IO(e.error))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::CONFIG_SCHEMA_VERSION;
+    use crate::core::logging::LogConfig;
+
+    const LEGACY_SETTINGS: &str = r#"
+[global]
+plugin_dir = "plugins"
+log_level = "debug"
+theme = "dark"
+
+[[plugins]]
+id = "Mouse"
+enabled = true
+
+[plugins.settings]
+speed = 3
+"#;
+
+    #[test]
+    fn test_load_migrates_and_writes_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, LEGACY_SETTINGS).unwrap();
+
+        let store = FileConfigStore::new(&path);
+        let config = store.load().unwrap();
+
+        assert_eq!(config.version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.global.log_level, "debug");
+        assert_eq!(config.plugins[0].settings["speed"], 3);
+        assert_eq!(fs::read_to_string(store.backup_path(0)).unwrap(), LEGACY_SETTINGS);
+
+        // The upgraded file loads without another migration
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert_eq!(FileConfigStore::parse(&rewritten).unwrap(), (config, None));
+    }
+
+    #[test]
+    fn test_invalid_value_names_key_path() {
+        let text = format!(
+            "version = {}\nplugins = []\n[global]\nplugin_dir = \"plugins\"\nlog_level = 5\ntheme = \"x\"\n",
+            CONFIG_SCHEMA_VERSION
+        );
+        let err = FileConfigStore::parse(&text).unwrap_err();
+        assert!(err.to_string().contains("global.log_level"), "{}", err);
+    }
+
+    #[test]
+    fn test_syntax_error_keeps_position() {
+        let text = "version = 1\n[global]\nlog_level = \"debug\nplugins = []\n";
+        match FileConfigStore::parse(text).unwrap_err() {
+            ConfigError::Parse { line, column, .. } => assert_eq!((line, column), (3, 19)),
+            other => panic!("unexpected error {}", other),
+        }
+    }
+
+    #[test]
+    fn test_unknown_log_level_is_rejected() {
+        let text = LEGACY_SETTINGS.replace("debug", "chatty");
+        let err = FileConfigStore::parse(&text).unwrap_err();
+        assert!(err.to_string().contains("global.log_level: unknown level 'chatty'"), "{}", err);
+    }
+
+    #[test]
+    fn test_log_section() {
+        let text = format!(
+            "version = {}\nplugins = []\n[global]\nplugin_dir = \"plugins\"\nlog_level = \"info\"\ntheme = \"x\"\n\
+             [global.log]\nfile_path = \"/var/log/eg.log\"\nfile_level = \"debug\"\n\
+             target_levels = {{ \"plugin::MQTT\" = \"trace\" }}\n",
+            CONFIG_SCHEMA_VERSION
+        );
+        let (config, _) = FileConfigStore::parse(&text).unwrap();
+        let log = &config.global.log;
+        assert_eq!(log.file_path, PathBuf::from("/var/log/eg.log"));
+        assert_eq!(log.file_level, log::Level::Debug);
+        assert_eq!(log.console_level, LogConfig::default().console_level);
+        assert_eq!(log.max_level(), log::LevelFilter::Trace);
+    }
+}
@@ -8,7 +8,15 @@ pub const CORE_PLUGIN_GUIDS: &[&str] = &[
 pub const DEFAULT_DEBUG_LEVEL: i32 = 0;
 pub const DEFAULT_ENCODING: &str = "utf-8";
 pub const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\EventGhost";
+/// Name of the control endpoint: a named pipe on Windows, a socket elsewhere
+pub const DEFAULT_IPC_NAME: &str = "EventGhost";
 pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+pub const DEFAULT_KEYSTORE_PATH: &str = "secrets.json";
+pub const DEFAULT_MASTER_KEY_PATH: &str = "master.key";
+/// Log file, in the per-user log directory
+pub const DEFAULT_LOG_PATH: &str = "eventghost.log";
+/// Port of the HTTP control API
+pub const DEFAULT_API_PORT: u16 = 8734;
 
 // Window message constants
 pub const WM_TRAY_NOTIFY: u32 = 0x0401;
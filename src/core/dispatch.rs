@@ -0,0 +1,189 @@
+//! Delivers events from the [`EventBus`] to plugins and the configuration tree
+//!
+//! Every published event goes to the running plugins first, then runs the
+//! enabled macros it triggers. This is the loop that keeps a headless
+//! instance working without a window.
+//!
+//! Action calls loaded from a tree file only name their action, e.g.
+//! `MQTT.Publish({...})`; [`bind_actions`] asks the registered plugins for
+//! the handlers before anything runs.
+
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use crate::core::event::{Event, EventBus};
+use crate::core::PluginRegistry;
+use crate::eg::tree::document::execute_all;
+use crate::eg::tree::{ActionCall, Document};
+
+/// Dispatch events published on `events` until the bus is dropped
+pub fn spawn(events: &EventBus, document: Arc<RwLock<Document>>, registry: Arc<PluginRegistry>) -> JoinHandle<()> {
+    let mut receiver = events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("Event dispatch fell behind; {} events were dropped", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+            dispatch(&document, &registry, event.as_ref()).await;
+        }
+    })
+}
+
+/// Deliver one event to the plugins and the tree
+///
+/// Macros run on the blocking pool since actions may wait on I/O; no
+/// document lock is held while they do.
+pub async fn dispatch(document: &RwLock<Document>, registry: &PluginRegistry, event: &dyn Event) {
+    registry.dispatch_event(event).await;
+    bind_actions(document, registry).await;
+
+    let triggered = match document.read() {
+        Ok(document) => document.triggered_by(event),
+        Err(_) => return log::error!("Document is poisoned; dropping {}", event.get_id()),
+    };
+    let items = match triggered {
+        Ok(items) if items.is_empty() => return,
+        Ok(items) => items,
+        Err(e) => return log::error!("Cannot run macros for {}: {}", event.get_id(), e),
+    };
+
+    let owned = event.clone_event();
+    let result = tokio::task::spawn_blocking(move || execute_all(&items, owned.as_ref())).await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("Macro triggered by {} failed: {}", event.get_id(), e),
+        Err(e) => log::error!("Macro triggered by {} panicked: {}", event.get_id(), e),
+    }
+}
+
+/// Attach plugin handlers to the tree's action calls that have none
+///
+/// Calls naming a plugin that is not loaded stay unbound and are retried on
+/// the next event. Returns how many calls were bound.
+pub async fn bind_actions(document: &RwLock<Document>, registry: &PluginRegistry) -> usize {
+    let unbound = match document.read() {
+        Ok(document) => document.unbound_actions(),
+        Err(_) => return 0,
+    };
+
+    let mut bound = 0;
+    for item in unbound {
+        let call = {
+            let Ok(item) = item.read() else { continue };
+            let Some(call) = item.as_any().downcast_ref::<ActionCall>() else { continue };
+            match (call.get_plugin(), call.get_action(), call.get_args()) {
+                (Some(plugin), Some(action), Ok(args)) => (plugin.to_string(), action.to_string(), args),
+                (_, _, Err(e)) => {
+                    log::debug!("Cannot bind {}: {}", call.get_call(), e);
+                    continue;
+                }
+                _ => {
+                    log::debug!("Cannot bind {}: not a plugin action", call.get_call());
+                    continue;
+                }
+            }
+        };
+
+        let (plugin, action, args) = call;
+        let handler = match registry.create_action(&plugin, &action, &args).await {
+            Ok(action) => action.handler(),
+            Err(e) => {
+                log::debug!("Cannot bind {}.{}: {}", plugin, action, e);
+                continue;
+            }
+        };
+        if let Ok(mut item) = item.write() {
+            if let Some(call) = item.as_any_mut().downcast_mut::<ActionCall>() {
+                call.set_handler(handler);
+                bound += 1;
+            }
+        }
+    }
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+    use crate::core::event::{EventPayload, EventType, NamedEvent};
+    use crate::core::plugin::PluginState;
+    use crate::eg::tree::{ActionCall, Macro_};
+    use crate::testing::MockPlugin;
+
+    #[tokio::test]
+    async fn test_events_start_macros_and_reach_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = Arc::new(PluginRegistry::new(dir.path().to_path_buf()).unwrap());
+        let id = registry.register(Box::new(MockPlugin::new())).await.unwrap();
+        registry.start_all().await.unwrap();
+        assert_eq!(registry.get_plugin(id).await.unwrap().read().await.get_state(), PluginState::Running);
+
+        let (ran, mut runs) = mpsc::unbounded_channel();
+        let mut macro_ = Macro_::new("Lights");
+        macro_.add_event("Timer.*");
+        macro_.add_action(Arc::new(RwLock::new(ActionCall::new("X10.On()").with_handler(move |event| {
+            let _ = ran.send(event.get_id().to_string());
+            Ok(())
+        }))));
+        let document = Arc::new(RwLock::new(Document::new()));
+        document.read().unwrap().get_root().write().unwrap().add_child(Arc::new(RwLock::new(macro_)));
+
+        let bus = EventBus::default();
+        let task = spawn(&bus, document, registry.clone());
+        bus.publish(NamedEvent::new("Remote.Play", EventType::User, EventPayload::None));
+        bus.publish(NamedEvent::new("Timer.Evening", EventType::Plugin, EventPayload::None));
+        assert_eq!(runs.recv().await.as_deref(), Some("Timer.Evening"));
+
+        drop(bus);
+        task.await.unwrap();
+        assert!(runs.try_recv().is_err());
+
+        registry.stop_all().await.unwrap();
+        assert_eq!(registry.get_plugin(id).await.unwrap().read().await.get_state(), PluginState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_loaded_actions_are_bound_to_plugins() {
+        use crate::plugins::scheduler::{Schedule, Scheduler, SchedulerSettings, TimerSettings};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.json");
+        std::fs::write(&path, r#"{"version": 1, "items": [
+            {"kind": "macro", "name": "Start polling", "children": [
+                {"kind": "event", "name": "Remote.Play"},
+                {"kind": "action", "content": "Scheduler.Start({\"timer\": \"Poll\"})"},
+                {"kind": "action", "content": "Winamp.Play()"}
+            ]}
+        ]}"#).unwrap();
+        let document = RwLock::new(Document::new());
+        document.write().unwrap().load(path).unwrap();
+
+        let scheduler = Scheduler::new(SchedulerSettings {
+            timers: vec![TimerSettings {
+                name: "Poll".into(),
+                schedule: Schedule::Interval { every_ms: 60_000, repeat: None },
+                autostart: false,
+            }],
+            ..Default::default()
+        });
+        let handle = scheduler.handle();
+        let registry = PluginRegistry::new(dir.path().to_path_buf()).unwrap();
+        registry.register(Box::new(scheduler)).await.unwrap();
+        registry.start_all().await.unwrap();
+
+        // Winamp is not loaded, so its call stays unbound
+        assert_eq!(bind_actions(&document, &registry).await, 1);
+        assert_eq!(document.read().unwrap().unbound_actions().len(), 1);
+        assert!(!handle.status()[0].running);
+
+        dispatch(&document, &registry, &NamedEvent::new("Remote.Play", EventType::User, EventPayload::None)).await;
+        assert!(handle.status()[0].running);
+        registry.stop_all().await.unwrap();
+    }
+}
@@ -20,6 +20,9 @@ pub enum Error {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     
+    #[error("Config error: {0}")]
+    Config(#[from] crate::core::config::ConfigError),
+    
     #[error("Other error: {0}")]
     Other(String),
 }
@@ -3,6 +3,8 @@ use std::collections::VecDeque;
 use crate::core::Error;
 use std::fmt::Debug;
 use std::any::Any;
+use std::sync::Arc;
+use tokio::sync::broadcast;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventType {
@@ -36,6 +38,37 @@ impl Clone for EventPayload {
     }
 }
 
+impl EventPayload {
+    /// Convert a JSON value; arrays and objects are kept as a `serde_json::Value` in `Custom`
+    pub fn from_json(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Self::None,
+            serde_json::Value::Bool(b) => Self::Boolean(b),
+            serde_json::Value::String(s) => Self::Text(s),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Self::Number(i),
+                None => Self::Float(n.as_f64().unwrap_or_default()),
+            },
+            other => Self::Custom(Box::new(other)),
+        }
+    }
+
+    /// JSON form of the payload; custom data is only kept if it is a `serde_json::Value`
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::None => serde_json::Value::Null,
+            Self::Text(s) => s.clone().into(),
+            Self::Number(n) => (*n).into(),
+            Self::Float(f) => (*f).into(),
+            Self::Boolean(b) => (*b).into(),
+            Self::Custom(data) => data
+                .downcast_ref::<serde_json::Value>()
+                .cloned()
+                .unwrap_or(serde_json::Value::Null),
+        }
+    }
+}
+
 pub trait Event: Any + Send + Sync + Debug {
     fn get_id(&self) -> &str;
     fn get_type(&self) -> EventType;
@@ -81,9 +114,144 @@ impl EventManager {
     }
 
 
+    /// Queue an event and deliver everything queued to the handlers that accept it
     pub fn process_event(&mut self, event: Box<dyn Event>) -> Result<(), Error> {
-        // TODO: Implement event processing
-        println!("Processing event: {:?}", event);
+        self.event_queue.push_back(event);
+        while let Some(event) = self.event_queue.pop_front() {
+            for handler in &mut self.handlers {
+                if handler.can_handle(event.get_type()) {
+                    handler.handle_event(event.as_ref())?;
+                }
+            }
+        }
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+impl Default for EventManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An event identified by its name, e.g. `MQTT.home/door` or `Timer.Morning`
+#[derive(Debug)]
+pub struct NamedEvent {
+    name: String,
+    event_type: EventType,
+    payload: EventPayload,
+    timestamp: DateTime<Local>,
+    source: Option<String>,
+}
+
+impl NamedEvent {
+    pub fn new(name: &str, event_type: EventType, payload: EventPayload) -> Self {
+        Self {
+            name: name.to_string(),
+            event_type,
+            payload,
+            timestamp: Local::now(),
+            source: None,
+        }
+    }
+
+    /// Set the plugin or client that produced the event
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+}
+
+impl Event for NamedEvent {
+    fn get_id(&self) -> &str {
+        &self.name
+    }
+
+    fn get_type(&self) -> EventType {
+        self.event_type
+    }
+
+    fn get_payload(&self) -> &EventPayload {
+        &self.payload
+    }
+
+    fn get_timestamp(&self) -> DateTime<Local> {
+        self.timestamp
+    }
+
+    fn get_source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn clone_event(&self) -> Box<dyn Event + Send + Sync> {
+        let payload = match &self.payload {
+            EventPayload::Custom(_) => EventPayload::from_json(self.payload.to_json()),
+            other => other.clone(),
+        };
+        Box::new(NamedEvent {
+            name: self.name.clone(),
+            event_type: self.event_type,
+            payload,
+            timestamp: self.timestamp,
+            source: self.source.clone(),
+        })
+    }
+}
+
+/// Fan-out of events from plugins and remote clients to every subscriber
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Arc<dyn Event>>,
+}
+
+impl EventBus {
+    /// Create a bus buffering up to `capacity` events per slow subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Deliver an event; returns the number of subscribers that will see it
+    pub fn publish(&self, event: impl Event) -> usize {
+        self.sender.send(Arc::new(event)).unwrap_or(0)
+    }
+
+    /// Receive every event published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<dyn Event>> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_event_bus_delivers_named_events() {
+        let bus = EventBus::default();
+        let mut events = bus.subscribe();
+
+        let payload = EventPayload::from_json(serde_json::json!({"door": "open"}));
+        assert_eq!(bus.publish(NamedEvent::new("MQTT.home", EventType::Plugin, payload).with_source("MQTT")), 1);
+
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.get_id(), "MQTT.home");
+        assert_eq!(event.get_source(), Some("MQTT"));
+        assert_eq!(event.get_payload().to_json()["door"], "open");
+        assert_eq!(event.clone_event().get_payload().to_json()["door"], "open");
+    }
+}
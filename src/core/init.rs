@@ -1,5 +1,6 @@
-use crate::core::Error;
-use crate::eg::Globals;
+use crate::core::config::{Config, ConfigLoader};
+use crate::core::{utils, Error};
+use crate::eg::globals::Globals;
 use std::path::PathBuf;
 
 pub struct InitOptions {
@@ -9,23 +10,49 @@ pub struct InitOptions {
     pub debug_mode: bool,
 }
 
-impl Default for InitOptions {
-    fn default() -> Self {
+impl InitOptions {
+    /// Derive startup options from a resolved configuration.
+    ///
+    /// Built-in plugins next to the executable are always searched first,
+    /// followed by the configured `plugin_dir`.
+    pub fn from_config(config: &Config, config_dir: PathBuf) -> Self {
+        let mut plugin_dirs = Vec::new();
+        if let Ok(dir) = utils::get_builtin_plugin_dir() {
+            plugin_dirs.push(dir);
+        }
+        if !plugin_dirs.contains(&config.global.plugin_dir) {
+            plugin_dirs.push(config.global.plugin_dir.clone());
+        }
+
         Self {
-            config_dir: PathBuf::from("config"),
-            plugin_dirs: vec![PathBuf::from("plugins")],
-            log_level: log::LevelFilter::Info,
+            config_dir,
+            plugin_dirs,
+            log_level: config.global.log_level.parse().unwrap_or(log::LevelFilter::Info),
             debug_mode: false,
         }
     }
 }
 
+impl Default for InitOptions {
+    fn default() -> Self {
+        let config_dir = utils::get_app_data_dir()
+            .map(|dir| dir.join("config"))
+            .unwrap_or_else(|_| PathBuf::from("config"));
+        Self::from_config(&ConfigLoader::defaults(), config_dir)
+    }
+}
+
 pub fn initialize(options: InitOptions) -> Result<Globals, Error> {
     // TODO: Implement initialization
-    Ok(Globals::new())
+    let mut globals = Globals::new();
+    globals.config_dir = options.config_dir.to_string_lossy().into_owned();
+    if let Some(plugin_dir) = options.plugin_dirs.last() {
+        globals.plugin_dir = plugin_dir.to_string_lossy().into_owned();
+    }
+    Ok(globals)
 }
 
 pub fn cleanup() -> Result<(), Error> {
     // TODO: Implement cleanup
     Ok(())
-} 
\ No newline at end of file
+}
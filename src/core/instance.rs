@@ -0,0 +1,634 @@
+//! Single-instance enforcement
+//!
+//! The first process to bind the control endpoint becomes the primary
+//! instance. A later launch finds the endpoint taken, forwards what its
+//! command line asked for (open a file, trigger an event, show the window)
+//! to the primary over IPC and exits. `--multi` gives an instance its own
+//! endpoint derived from its config directory, so separate instances can
+//! run side by side as long as they use different config directories.
+//! `-restart` asks the primary to exit and takes its place.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde_json::Value;
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use crate::core::api::ApiServer;
+use crate::core::config::{
+    ApiConfig, Config, ConfigError, ConfigLoader, ConfigManager, ConfigWatcher, Keystore, MasterKey,
+    PluginFactory, ReloadTargets, SecretString,
+};
+use crate::core::constants::{DEFAULT_CONFIG_PATH, DEFAULT_IPC_NAME, DEFAULT_KEYSTORE_PATH, DEFAULT_MASTER_KEY_PATH};
+use crate::core::dispatch;
+use crate::core::event::{EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::init::{self, InitOptions};
+use crate::core::ipc::{CommandHandler, InstanceHandler, IpcClient, IpcEndpoint, IpcError, IpcListener, IpcServer, Transport, UiRequest};
+use crate::core::logging::{self, LogStore};
+use crate::core::{Error, PluginRegistry};
+use crate::eg::globals::Globals;
+use crate::eg::tree::Document;
+use crate::plugins;
+
+/// Requests were forwarded to the primary instance
+pub const EXIT_FORWARDED: i32 = 0;
+/// The command line could not be parsed
+pub const EXIT_USAGE: i32 = 2;
+/// The primary instance refused a forwarded request
+pub const EXIT_REJECTED: i32 = 3;
+/// Another instance holds the endpoint but could not be reached
+pub const EXIT_UNREACHABLE: i32 = 4;
+
+/// How long `-restart` waits for the running instance to exit
+pub const RESTART_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Error type for instance startup
+#[derive(Debug, thiserror::Error)]
+pub enum InstanceError {
+    #[error("Invalid arguments: {0}")]
+    Usage(String),
+    #[error("Another instance is running but could not be reached: {0}")]
+    Unreachable(IpcError),
+    #[error("The running instance refused to {request}: {error}")]
+    Rejected { request: String, error: IpcError },
+    #[error("The running instance did not exit within {0:?}")]
+    StillRunning(Duration),
+    #[error("IPC error: {0}")]
+    Ipc(#[from] IpcError),
+}
+
+impl InstanceError {
+    /// Process exit status for this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InstanceError::Usage(_) => EXIT_USAGE,
+            InstanceError::Rejected { .. } => EXIT_REJECTED,
+            InstanceError::Unreachable(_) | InstanceError::StillRunning(_) | InstanceError::Ipc(_) => EXIT_UNREACHABLE,
+        }
+    }
+}
+
+/// Something the command line asks the running instance to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchRequest {
+    OpenFile(PathBuf),
+    TriggerEvent { name: String, payload: Value },
+    ShowWindow,
+}
+
+impl std::fmt::Display for LaunchRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LaunchRequest::OpenFile(path) => write!(f, "open {}", path.display()),
+            LaunchRequest::TriggerEvent { name, .. } => write!(f, "trigger {}", name),
+            LaunchRequest::ShowWindow => write!(f, "show the window"),
+        }
+    }
+}
+
+/// Startup switches, including the legacy `-file`, `-event`, `-hide` and `-restart`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LaunchArgs {
+    /// Requests in command-line order
+    pub requests: Vec<LaunchRequest>,
+    /// Start without showing the main window
+    pub hide: bool,
+    /// Run alongside other instances
+    pub multi: bool,
+    /// Replace the running instance instead of forwarding to it
+    pub restart: bool,
+    /// Use this config directory instead of the default
+    pub config_dir: Option<PathBuf>,
+    /// Use this control endpoint instead of the default
+    pub endpoint: Option<PathBuf>,
+    /// Log at this level instead of `global.log_level`
+    pub log_level: Option<String>,
+}
+
+impl LaunchArgs {
+    /// Parse arguments, without the program name
+    ///
+    /// Switches may start with `-` or `--`. A bare argument is a file to
+    /// open. The optional payload after `-event <name>` is parsed as JSON and
+    /// falls back to a plain string.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, InstanceError> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let switch = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-'));
+            match switch.map(str::to_ascii_lowercase).as_deref() {
+                Some("file") => {
+                    let path = args.next().ok_or_else(|| missing(&arg, "a file"))?;
+                    parsed.requests.push(LaunchRequest::OpenFile(PathBuf::from(path)));
+                }
+                Some("event" | "e") => {
+                    let name = args.next().ok_or_else(|| missing(&arg, "an event name"))?;
+                    let payload = match args.next_if(|next| !next.starts_with('-')) {
+                        Some(raw) => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+                        None => Value::Null,
+                    };
+                    parsed.requests.push(LaunchRequest::TriggerEvent { name, payload });
+                }
+                Some("show") => parsed.requests.push(LaunchRequest::ShowWindow),
+                Some("hide") => parsed.hide = true,
+                Some("multi") => parsed.multi = true,
+                Some("restart") => parsed.restart = true,
+                Some("config-dir" | "configdir") => {
+                    let dir = args.next().ok_or_else(|| missing(&arg, "a directory"))?;
+                    parsed.config_dir = Some(PathBuf::from(dir));
+                }
+                Some("log-level" | "loglevel") => {
+                    parsed.log_level = Some(args.next().ok_or_else(|| missing(&arg, "a level"))?);
+                }
+                Some(_) => return Err(InstanceError::Usage(format!("Unknown option {}", arg))),
+                None => parsed.requests.push(LaunchRequest::OpenFile(PathBuf::from(arg))),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Settings layers for this launch, with the user file in `config_dir`
+    /// if one was given and `log_level` as a command-line override
+    pub fn config_loader(&self) -> ConfigLoader {
+        let loader = match &self.config_dir {
+            Some(config_dir) => ConfigLoader::new().with_user_file(config_dir.join(DEFAULT_CONFIG_PATH)),
+            None => ConfigLoader::new(),
+        };
+        match &self.log_level {
+            Some(level) => loader.with_override("global.log_level", level),
+            None => loader,
+        }
+    }
+
+    /// Control endpoint for this launch
+    pub fn endpoint(&self) -> Result<IpcEndpoint, InstanceError> {
+        if let Some(endpoint) = &self.endpoint {
+            return Ok(IpcEndpoint::at(endpoint));
+        }
+        if !self.multi {
+            return Ok(IpcEndpoint::default());
+        }
+        let config_dir = self.config_dir.as_deref().ok_or_else(|| {
+            InstanceError::Usage("--multi needs --config-dir so instances do not share settings".into())
+        })?;
+        Ok(IpcEndpoint::named(&endpoint_name(config_dir)))
+    }
+
+    /// What a second launch asks of the primary; just showing the window if
+    /// nothing else was requested
+    pub fn forwarded_requests(&self) -> Vec<LaunchRequest> {
+        if self.requests.is_empty() && !self.hide {
+            vec![LaunchRequest::ShowWindow]
+        } else {
+            self.requests.clone()
+        }
+    }
+}
+
+fn missing(switch: &str, what: &str) -> InstanceError {
+    InstanceError::Usage(format!("{} needs {}", switch, what))
+}
+
+/// Stable per-directory endpoint name, e.g. `EventGhost-3f2a…`
+fn endpoint_name(config_dir: &Path) -> String {
+    let dir = std::path::absolute(config_dir).unwrap_or_else(|_| config_dir.to_path_buf());
+    // FNV-1a, so the name is the same for every build
+    let hash = dir
+        .to_string_lossy()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{}-{:016x}", DEFAULT_IPC_NAME, hash)
+}
+
+/// Outcome of [`acquire`]
+pub enum InstanceRole {
+    /// This process owns the endpoint and should serve it
+    Primary(Box<dyn IpcListener>),
+    /// Another instance took over the requests; exit with [`EXIT_FORWARDED`]
+    Forwarded,
+}
+
+/// Become the primary instance, or hand the requests to the existing one
+pub async fn acquire(transport: &dyn Transport, args: &LaunchArgs) -> Result<InstanceRole, InstanceError> {
+    let endpoint = args.endpoint()?;
+    match transport.bind(&endpoint).await {
+        Ok(listener) => Ok(InstanceRole::Primary(listener)),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse && args.restart => {
+            log::info!("Restarting the EventGhost instance at {}", endpoint);
+            let mut client = IpcClient::connect_with(transport, &endpoint)
+                .await
+                .map_err(InstanceError::Unreachable)?;
+            client
+                .shutdown()
+                .await
+                .map_err(|error| InstanceError::Rejected { request: "exit".to_string(), error })?;
+            drop(client);
+            take_over(transport, &endpoint).await.map(InstanceRole::Primary)
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            log::info!("EventGhost is already running, forwarding to {}", endpoint);
+            forward(transport, &endpoint, &args.forwarded_requests()).await?;
+            Ok(InstanceRole::Forwarded)
+        }
+        Err(e) => Err(IpcError::Io(e).into()),
+    }
+}
+
+/// Bind `endpoint` once the instance that held it has exited
+async fn take_over(transport: &dyn Transport, endpoint: &IpcEndpoint) -> Result<Box<dyn IpcListener>, InstanceError> {
+    let deadline = tokio::time::Instant::now() + RESTART_TIMEOUT;
+    loop {
+        match transport.bind(endpoint).await {
+            Ok(listener) => return Ok(listener),
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                if tokio::time::Instant::now() >= deadline {
+                    return Err(InstanceError::StillRunning(RESTART_TIMEOUT));
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(e) => return Err(IpcError::Io(e).into()),
+        }
+    }
+}
+
+/// Send `requests` to the instance listening on `endpoint`, stopping at the first failure
+pub async fn forward(
+    transport: &dyn Transport,
+    endpoint: &IpcEndpoint,
+    requests: &[LaunchRequest],
+) -> Result<(), InstanceError> {
+    let mut client = IpcClient::connect_with(transport, endpoint)
+        .await
+        .map_err(InstanceError::Unreachable)?;
+    for request in requests {
+        let result = match request {
+            LaunchRequest::OpenFile(path) => {
+                let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+                client.open_file(&path).await
+            }
+            LaunchRequest::TriggerEvent { name, payload } => client.trigger_event(name, payload.clone()).await,
+            LaunchRequest::ShowWindow => client.show_window().await,
+        };
+        result.map_err(|error| InstanceError::Rejected { request: request.to_string(), error })?;
+    }
+    Ok(())
+}
+
+/// State shared by the GUI, the IPC server and plugins of the primary instance
+pub struct PrimaryInstance {
+    pub document: Arc<RwLock<Document>>,
+    pub registry: Arc<PluginRegistry>,
+    pub events: EventBus,
+    pub config: Config,
+    secrets: Option<Arc<ConfigManager>>,
+    globals: Arc<tokio::sync::Mutex<Globals>>,
+    ui: Option<mpsc::UnboundedSender<UiRequest>>,
+    server: IpcServer,
+    api: Option<ApiServer>,
+    settings: Option<ConfigWatcher>,
+    handler: Arc<dyn CommandHandler>,
+    dispatcher: JoinHandle<()>,
+    applier: Option<JoinHandle<()>>,
+    shutdown: Arc<Notify>,
+}
+
+impl PrimaryInstance {
+    /// Initialize from `launch` and serve `listener`; must be called inside a Tokio runtime
+    ///
+    /// With `ui`, window and file requests are passed to the GUI; without
+    /// it the instance runs headless. Remote clients follow the log in `log_store`.
+    /// Fails if the settings do not load, rather than running with defaults.
+    pub fn start(
+        listener: Box<dyn IpcListener>,
+        launch: &LaunchArgs,
+        ui: Option<mpsc::UnboundedSender<UiRequest>>,
+        log_store: Arc<LogStore>,
+    ) -> Result<Self, Error> {
+        let loader = launch.config_loader();
+        let config_dir = launch.config_dir.clone().unwrap_or_else(|| InitOptions::default().config_dir);
+        let secrets = open_secrets(&loader, &config_dir)
+            .inspect_err(|e| log::warn!("Secret settings are unavailable: {}", e))
+            .ok()
+            .flatten()
+            .map(Arc::new);
+        // Loaded after sealing, so plugins only see references to secrets
+        let config = loader.clone().load()?.into_config();
+        let document_path = config.global.document.as_ref().map(|path| config_dir.join(path));
+        let options = InitOptions::from_config(&config, config_dir);
+        let plugin_dir = options.plugin_dirs.last().cloned().unwrap_or_else(|| PathBuf::from("plugins"));
+        let globals = Arc::new(tokio::sync::Mutex::new(init::initialize(options)?));
+        let registry = Arc::new(PluginRegistry::new(plugin_dir)?);
+        let settings = ConfigWatcher::start(loader, config.clone())
+            .inspect_err(|e| log::warn!("Settings will not reload on change: {}", e))
+            .ok();
+
+        let mut document = Document::new();
+        if let Some(path) = document_path {
+            if let Err(e) = document.load(path.clone()) {
+                log::warn!("Could not load {}: {}", path.display(), e);
+            }
+        }
+        let document = Arc::new(RwLock::new(document));
+        let shutdown = Arc::new(Notify::new());
+        let events = EventBus::default();
+        let dispatcher = dispatch::spawn(&events, document.clone(), registry.clone());
+        let applier = settings.as_ref().map(|watcher| {
+            let (events, secrets, globals) = (events.clone(), secrets.clone(), globals.clone());
+            let factory: PluginFactory =
+                Arc::new(move |config: &Config| plugins::builtin(config, &events, secrets.as_ref(), &globals));
+            let mut targets = ReloadTargets::new(registry.clone()).with_plugin_factory(factory);
+            if let Some(logger) = logging::installed() {
+                targets = targets.with_logger(logger);
+            }
+            watcher.spawn_applier(targets)
+        });
+        let mut handler = InstanceHandler::new(
+            document.clone(),
+            registry.clone(),
+            globals.clone(),
+            events.clone(),
+        )
+        .with_log_store(log_store)
+        .with_shutdown(shutdown.clone());
+        if let Some(ui) = &ui {
+            handler = handler.with_ui(ui.clone());
+        }
+        let handler: Arc<dyn CommandHandler> = Arc::new(handler);
+        let server = IpcServer::serve(listener, handler.clone());
+
+        let api = if config.global.api.enabled {
+            start_api(&config.global.api, secrets.as_deref(), handler.clone(), events.clone())
+        } else {
+            None
+        };
+
+        Ok(Self { document, registry, events, config, secrets, globals, ui, server, api, settings, handler, dispatcher, applier, shutdown })
+    }
+
+    /// Register the enabled built-in plugins and start all plugins; failures
+    /// are logged and do not stop the others
+    pub async fn start_plugins(&self) {
+        for plugin in plugins::builtin(&self.config, &self.events, self.secrets.as_ref(), &self.globals) {
+            let name = plugin.get_name().to_string();
+            if let Err(e) = self.registry.register(plugin).await {
+                log::warn!("Could not register {}: {}", name, e);
+            }
+        }
+        if let Err(e) = self.registry.start_all().await {
+            log::warn!("Not all plugins started: {}", e);
+        }
+    }
+
+    /// Stop the plugins before exiting
+    pub async fn shutdown(&self) {
+        if let Err(e) = self.registry.stop_all().await {
+            log::warn!("Not all plugins stopped cleanly: {}", e);
+        }
+    }
+
+    /// Carries out commands the way remote clients send them
+    pub fn handler(&self) -> Arc<dyn CommandHandler> {
+        self.handler.clone()
+    }
+
+    /// Wait until a client asks this headless instance to exit
+    pub async fn shutdown_requested(&self) {
+        self.shutdown.notified().await
+    }
+
+    /// Watcher of the settings files, if it could be started
+    pub fn settings(&self) -> Option<&ConfigWatcher> {
+        self.settings.as_ref()
+    }
+
+    /// Address of the HTTP API, if it is running
+    pub fn api_addr(&self) -> Option<std::net::SocketAddr> {
+        self.api.as_ref().map(ApiServer::local_addr)
+    }
+
+    /// Endpoint later launches forward to
+    pub fn endpoint(&self) -> &IpcEndpoint {
+        self.server.endpoint()
+    }
+
+    /// Carry out requests from this process's own command line
+    pub fn handle(&self, requests: &[LaunchRequest]) -> Result<(), Error> {
+        for request in requests {
+            match request {
+                LaunchRequest::OpenFile(path) => match &self.ui {
+                    Some(ui) => {
+                        let _ = ui.send(UiRequest::OpenFile(path.clone()));
+                    }
+                    None => self
+                        .document
+                        .write()
+                        .map_err(|e| Error::Other(e.to_string()))?
+                        .load(path.clone())?,
+                },
+                LaunchRequest::TriggerEvent { name, payload } => {
+                    self.events
+                        .publish(NamedEvent::new(name, EventType::User, EventPayload::from_json(payload.clone())));
+                }
+                LaunchRequest::ShowWindow => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keystore-backed manager of the settings `loader` reads, if there is a
+/// per-user settings file; clear-text secrets in that file are moved into
+/// the keystore and only that file is rewritten
+fn open_secrets(loader: &ConfigLoader, config_dir: &Path) -> Result<Option<ConfigManager>, ConfigError> {
+    if !loader.user_path().is_some_and(Path::exists) {
+        return Ok(None);
+    }
+    let master = MasterKey::KeyFile(config_dir.join(DEFAULT_MASTER_KEY_PATH));
+    let mut keystore = Keystore::open(config_dir.join(DEFAULT_KEYSTORE_PATH), master)?;
+    loader.seal_user_file(&mut keystore)?;
+    Ok(Some(ConfigManager::new(Box::new(loader.clone()))?.with_keystore_as_is(keystore)))
+}
+
+fn start_api(
+    config: &ApiConfig,
+    secrets: Option<&ConfigManager>,
+    handler: Arc<dyn CommandHandler>,
+    events: EventBus,
+) -> Option<ApiServer> {
+    let started = plugins::reveal(secrets, config.token.as_ref())
+        .and_then(|token| {
+            let token = token.unwrap_or_else(|| SecretString::new(""));
+            ApiServer::start(config, token, handler, events).map_err(|e| e.to_string())
+        });
+    match started {
+        Ok(api) => {
+            log::info!("HTTP API listening on {}", api.local_addr());
+            Some(api)
+        }
+        Err(e) => {
+            log::warn!("HTTP API not started: {}", e);
+            None
+        }
+    }
+}
+
+impl Drop for PrimaryInstance {
+    fn drop(&mut self) {
+        self.dispatcher.abort();
+        if let Some(applier) = &self.applier {
+            applier.abort();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use async_trait::async_trait;
+    use tempfile::tempdir;
+    use crate::core::ipc::{default_transport, CommandHandler, IpcServer, ItemRef, PluginStatus, RpcError};
+    use crate::core::logging::LogStore;
+
+    fn args(args: &[&str]) -> Result<LaunchArgs, InstanceError> {
+        LaunchArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_legacy_switches() {
+        let parsed = args(&["-event", "Remote.Play", "{\"volume\":3}", "-hide", "-event", "Bare", "tree.egtree"]).unwrap();
+        assert!(parsed.hide);
+        assert_eq!(
+            parsed.requests,
+            [
+                LaunchRequest::TriggerEvent { name: "Remote.Play".into(), payload: serde_json::json!({"volume": 3}) },
+                LaunchRequest::TriggerEvent { name: "Bare".into(), payload: Value::String("tree.egtree".into()) },
+            ]
+        );
+        assert_eq!(args(&["--file", "a.egtree"]).unwrap().requests, [LaunchRequest::OpenFile("a.egtree".into())]);
+        assert_eq!(args(&["-loglevel", "debug"]).unwrap().log_level.as_deref(), Some("debug"));
+        assert_eq!(args(&[]).unwrap().forwarded_requests(), [LaunchRequest::ShowWindow]);
+        assert_eq!(args(&["-event"]).unwrap_err().exit_code(), EXIT_USAGE);
+        assert_eq!(args(&["--bogus"]).unwrap_err().exit_code(), EXIT_USAGE);
+    }
+
+    #[test]
+    fn test_multi_endpoint_depends_on_config_dir() {
+        assert_eq!(args(&["--multi"]).unwrap().endpoint().unwrap_err().exit_code(), EXIT_USAGE);
+        let a = args(&["--multi", "--config-dir", "/tmp/a"]).unwrap().endpoint().unwrap();
+        let b = args(&["--multi", "--config-dir", "/tmp/b"]).unwrap().endpoint().unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a, args(&["--config-dir", "/tmp/a", "--multi"]).unwrap().endpoint().unwrap());
+        assert_ne!(a, IpcEndpoint::default());
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        received: Mutex<Vec<String>>,
+        stopped: Notify,
+    }
+
+    #[async_trait]
+    impl CommandHandler for Recorder {
+        async fn trigger_event(&self, name: &str, _payload: Value) -> Result<(), RpcError> {
+            self.received.lock().unwrap().push(format!("event {}", name));
+            Ok(())
+        }
+
+        async fn run_macro(&self, item: &ItemRef) -> Result<(), RpcError> {
+            Err(RpcError::not_found(item))
+        }
+
+        async fn set_enabled(&self, item: &ItemRef, _enabled: bool) -> Result<(), RpcError> {
+            Err(RpcError::not_found(item))
+        }
+
+        async fn list_plugins(&self) -> Result<Vec<PluginStatus>, RpcError> {
+            Ok(Vec::new())
+        }
+
+        async fn get_variable(&self, _name: &str) -> Result<Option<Value>, RpcError> {
+            Ok(None)
+        }
+
+        async fn set_variable(&self, _name: &str, _value: Value) -> Result<(), RpcError> {
+            Ok(())
+        }
+
+        async fn show_window(&self) -> Result<(), RpcError> {
+            self.received.lock().unwrap().push("show".into());
+            Ok(())
+        }
+
+        async fn shutdown(&self) -> Result<(), RpcError> {
+            self.received.lock().unwrap().push("shutdown".into());
+            self.stopped.notify_one();
+            Ok(())
+        }
+
+        fn log_store(&self) -> Option<Arc<LogStore>> {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_launch_forwards_to_primary() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path().to_string_lossy().into_owned();
+        let transport = default_transport();
+        let first = args(&["--multi", "--config-dir", &config_dir]).unwrap();
+
+        let listener = match acquire(transport.as_ref(), &first).await.unwrap() {
+            InstanceRole::Primary(listener) => listener,
+            InstanceRole::Forwarded => panic!("first launch must be primary"),
+        };
+        let recorder = Arc::new(Recorder::default());
+        let _server = IpcServer::serve(listener, recorder.clone());
+
+        let second = args(&["--multi", "--config-dir", &config_dir, "-event", "Hello"]).unwrap();
+        assert!(matches!(acquire(transport.as_ref(), &second).await.unwrap(), InstanceRole::Forwarded));
+        assert!(matches!(acquire(transport.as_ref(), &first).await.unwrap(), InstanceRole::Forwarded));
+        assert_eq!(*recorder.received.lock().unwrap(), ["event Hello", "show"]);
+
+        // The recorder cannot open files
+        let third = args(&["--multi", "--config-dir", &config_dir, "missing.egtree"]).unwrap();
+        let err = acquire(transport.as_ref(), &third).await.err().unwrap();
+        assert_eq!(err.exit_code(), EXIT_REJECTED);
+    }
+
+    #[tokio::test]
+    async fn test_restart_replaces_primary() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path().to_string_lossy().into_owned();
+        let transport = default_transport();
+        let first = args(&["--multi", "--config-dir", &config_dir]).unwrap();
+        let InstanceRole::Primary(listener) = acquire(transport.as_ref(), &first).await.unwrap() else {
+            panic!("first launch must be primary");
+        };
+        let recorder = Arc::new(Recorder::default());
+        let server = IpcServer::serve(listener, recorder.clone());
+        let stopping = recorder.clone();
+        tokio::spawn(async move {
+            stopping.stopped.notified().await;
+            drop(server);
+        });
+
+        let restart = args(&["--multi", "--config-dir", &config_dir, "-restart"]).unwrap();
+        assert!(restart.restart);
+        let role = acquire(transport.as_ref(), &restart).await.unwrap();
+        assert!(matches!(role, InstanceRole::Primary(_)));
+        assert_eq!(*recorder.received.lock().unwrap(), ["shutdown"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_settings_stop_startup() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join(DEFAULT_CONFIG_PATH), "version = 1\nplugins = \"none\"\n").unwrap();
+        let launch = LaunchArgs { config_dir: Some(dir.path().to_path_buf()), ..Default::default() };
+        let listener = default_transport().bind(&IpcEndpoint::at(dir.path().join("eg.sock"))).await.unwrap();
+        let started = PrimaryInstance::start(listener, &launch, None, Arc::new(LogStore::default()));
+        assert!(matches!(started, Err(Error::Config(_))));
+    }
+}
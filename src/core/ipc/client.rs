@@ -0,0 +1,152 @@
+//! Client for controlling a running instance
+
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use crate::core::logging::{LogQuery, StoredLogEntry};
+use super::frame::FramedStream;
+use super::protocol::{Command, ItemRef, Outcome, PluginStatus, Request, Response, TreeEntry};
+use super::{default_transport, IpcEndpoint, IpcError, IpcStream, Transport};
+
+/// Connection to a running instance
+pub struct IpcClient {
+    stream: FramedStream<Box<dyn IpcStream>>,
+    next_id: u64,
+}
+
+impl IpcClient {
+    /// Connect with the platform's default transport
+    pub async fn connect(endpoint: &IpcEndpoint) -> Result<Self, IpcError> {
+        Self::connect_with(default_transport().as_ref(), endpoint).await
+    }
+
+    /// Connect with a specific transport
+    pub async fn connect_with(transport: &dyn Transport, endpoint: &IpcEndpoint) -> Result<Self, IpcError> {
+        let stream = transport.connect(endpoint).await?;
+        Ok(Self { stream: FramedStream::new(stream), next_id: 1 })
+    }
+
+    /// Send a command and wait for its result
+    pub async fn call<T: DeserializeOwned>(&mut self, command: Command) -> Result<T, IpcError> {
+        let id = self.send(command).await?;
+        let result = receive(&mut self.stream, id).await?;
+        serde_json::from_value(result).map_err(|e| IpcError::Protocol(e.to_string()))
+    }
+
+    async fn send(&mut self, command: Command) -> Result<u64, IpcError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let frame = serde_json::to_vec(&Request::new(id, command)).map_err(|e| IpcError::Protocol(e.to_string()))?;
+        self.stream.send(&frame).await?;
+        Ok(id)
+    }
+
+    /// Raise an event, e.g. `Remote.Play`
+    pub async fn trigger_event(&mut self, name: &str, payload: Value) -> Result<(), IpcError> {
+        self.call::<Value>(Command::TriggerEvent { name: name.to_string(), payload }).await?;
+        Ok(())
+    }
+
+    /// Execute a macro
+    pub async fn run_macro(&mut self, item: ItemRef) -> Result<(), IpcError> {
+        self.call::<Value>(Command::RunMacro { item }).await?;
+        Ok(())
+    }
+
+    /// Enable or disable a tree item
+    pub async fn set_enabled(&mut self, item: ItemRef, enabled: bool) -> Result<(), IpcError> {
+        self.call::<Value>(Command::SetEnabled { item, enabled }).await?;
+        Ok(())
+    }
+
+    /// Loaded plugins and their states
+    pub async fn list_plugins(&mut self) -> Result<Vec<PluginStatus>, IpcError> {
+        self.call(Command::ListPlugins).await
+    }
+
+    /// Outline of the configuration tree
+    pub async fn list_tree(&mut self) -> Result<Vec<TreeEntry>, IpcError> {
+        self.call(Command::ListTree).await
+    }
+
+    /// All global variables
+    pub async fn list_variables(&mut self) -> Result<Map<String, Value>, IpcError> {
+        self.call(Command::ListVariables).await
+    }
+
+    /// Read a global variable
+    pub async fn get_variable(&mut self, name: &str) -> Result<Option<Value>, IpcError> {
+        let value: Value = self.call(Command::GetVariable { name: name.to_string() }).await?;
+        Ok(Some(value).filter(|value| !value.is_null()))
+    }
+
+    /// Set a global variable
+    pub async fn set_variable(&mut self, name: &str, value: Value) -> Result<(), IpcError> {
+        self.call::<Value>(Command::SetVariable { name: name.to_string(), value }).await?;
+        Ok(())
+    }
+
+    /// Open a configuration file in the running instance
+    pub async fn open_file(&mut self, path: &Path) -> Result<(), IpcError> {
+        self.call::<Value>(Command::OpenFile { path: path.to_path_buf() }).await?;
+        Ok(())
+    }
+
+    /// Bring the main window to the front
+    pub async fn show_window(&mut self) -> Result<(), IpcError> {
+        self.call::<Value>(Command::ShowWindow).await?;
+        Ok(())
+    }
+
+    /// Ask the running instance to exit
+    pub async fn shutdown(&mut self) -> Result<(), IpcError> {
+        self.call::<Value>(Command::Shutdown).await?;
+        Ok(())
+    }
+
+    /// Stored log entries matching `query`
+    pub async fn tail_log(&mut self, query: LogQuery) -> Result<Vec<StoredLogEntry>, IpcError> {
+        self.call(Command::TailLog { query, follow: false }).await
+    }
+
+    /// Stored entries matching `query`, then a follower yielding new ones;
+    /// the connection is dedicated to following from then on
+    pub async fn follow_log(mut self, query: LogQuery) -> Result<(Vec<StoredLogEntry>, LogFollower), IpcError> {
+        let id = self.send(Command::TailLog { query, follow: true }).await?;
+        let initial = receive(&mut self.stream, id).await?;
+        let initial = serde_json::from_value(initial).map_err(|e| IpcError::Protocol(e.to_string()))?;
+        Ok((initial, LogFollower { stream: self.stream, id }))
+    }
+}
+
+/// New log entries streamed by [`IpcClient::follow_log`]
+pub struct LogFollower {
+    stream: FramedStream<Box<dyn IpcStream>>,
+    id: u64,
+}
+
+impl LogFollower {
+    /// Wait for the next batch of entries; `None` once the server goes away
+    pub async fn next(&mut self) -> Result<Option<Vec<StoredLogEntry>>, IpcError> {
+        match receive(&mut self.stream, self.id).await {
+            Ok(entries) => serde_json::from_value(entries)
+                .map(Some)
+                .map_err(|e| IpcError::Protocol(e.to_string())),
+            Err(IpcError::Closed) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+async fn receive(stream: &mut FramedStream<Box<dyn IpcStream>>, id: u64) -> Result<Value, IpcError> {
+    let frame = stream.recv().await?.ok_or(IpcError::Closed)?;
+    let response: Response = serde_json::from_slice(&frame).map_err(|e| IpcError::Protocol(e.to_string()))?;
+    match response.outcome {
+        Outcome::Error(error) => Err(IpcError::Remote(error)),
+        Outcome::Result(_) if response.id != id => Err(IpcError::Protocol(format!(
+            "Expected a response to request {}, got {}",
+            id, response.id
+        ))),
+        Outcome::Result(value) => Ok(value),
+    }
+}
@@ -0,0 +1,105 @@
+//! Length-prefixed message framing
+//!
+//! Each frame is a 4-byte big-endian length followed by that many bytes of
+//! payload.
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use super::IpcError;
+
+/// Largest payload accepted in either direction
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Write one frame and flush it
+pub async fn write_frame<W: AsyncWrite + Unpin + ?Sized>(writer: &mut W, payload: &[u8]) -> Result<(), IpcError> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(IpcError::FrameTooLarge { len: payload.len(), max: MAX_FRAME_LEN });
+    }
+    writer.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Read one frame; `None` if the peer closed the stream between frames
+pub async fn read_frame<R: AsyncRead + Unpin + ?Sized>(reader: &mut R) -> Result<Option<Vec<u8>>, IpcError> {
+    let mut header = [0u8; 4];
+    let mut filled = 0;
+    while filled < header.len() {
+        match reader.read(&mut header[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(IpcError::Truncated),
+            n => filled += n,
+        }
+    }
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(IpcError::FrameTooLarge { len, max: MAX_FRAME_LEN });
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await.map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => IpcError::Truncated,
+        _ => IpcError::Io(e),
+    })?;
+    Ok(Some(payload))
+}
+
+/// A stream that sends and receives whole frames
+pub struct FramedStream<S> {
+    inner: S,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FramedStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Send one message
+    pub async fn send(&mut self, payload: &[u8]) -> Result<(), IpcError> {
+        write_frame(&mut self.inner, payload).await
+    }
+
+    /// Receive one message; `None` once the peer has disconnected
+    pub async fn recv(&mut self) -> Result<Option<Vec<u8>>, IpcError> {
+        read_frame(&mut self.inner).await
+    }
+
+    /// The underlying stream
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_partial_reads_keep_frames_whole() {
+        // A 1-byte pipe buffer forces every read to return a single byte
+        let (mut client, mut server) = tokio::io::duplex(1);
+        let writer = tokio::spawn(async move {
+            write_frame(&mut client, b"hello").await.unwrap();
+            write_frame(&mut client, b"world!").await.unwrap();
+        });
+
+        assert_eq!(read_frame(&mut server).await.unwrap().unwrap(), b"hello");
+        assert_eq!(read_frame(&mut server).await.unwrap().unwrap(), b"world!");
+        writer.await.unwrap();
+        assert!(read_frame(&mut server).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_and_truncated_frames() {
+        let mut header = ((MAX_FRAME_LEN + 1) as u32).to_be_bytes().to_vec();
+        assert!(matches!(
+            read_frame(&mut header.as_slice()).await,
+            Err(IpcError::FrameTooLarge { .. })
+        ));
+
+        header = 10u32.to_be_bytes().to_vec();
+        header.extend_from_slice(b"short");
+        assert!(matches!(read_frame(&mut header.as_slice()).await, Err(IpcError::Truncated)));
+        assert!(matches!(read_frame(&mut &[0u8, 0][..]).await, Err(IpcError::Truncated)));
+    }
+}
@@ -0,0 +1,219 @@
+//! [`CommandHandler`] backed by the document, plugin registry and globals
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use async_trait::async_trait;
+use serde_json::{Map, Value};
+use tokio::sync::{mpsc, Notify};
+use crate::core::event::{EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::dispatch;
+use crate::core::logging::LogStore;
+use crate::core::PluginRegistry;
+use crate::eg::globals::Globals;
+use crate::eg::tree::document::children_of;
+use crate::eg::tree::link::Link;
+use crate::eg::tree::{ActionCall, Document, Folder, Macro_, TreeItem};
+use super::protocol::{ItemKind, ItemRef, PluginStatus, RpcError, TreeEntry};
+use super::server::CommandHandler;
+
+/// Source reported for events raised over IPC
+pub const IPC_EVENT_SOURCE: &str = "ipc";
+
+/// Requests the GUI has to carry out on its own thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UiRequest {
+    ShowWindow,
+    OpenFile(PathBuf),
+    Quit,
+}
+
+/// Handles commands for the running instance
+pub struct InstanceHandler {
+    document: Arc<RwLock<Document>>,
+    registry: Arc<PluginRegistry>,
+    globals: Arc<tokio::sync::Mutex<Globals>>,
+    events: EventBus,
+    log_store: Option<Arc<LogStore>>,
+    ui: Option<mpsc::UnboundedSender<UiRequest>>,
+    shutdown: Option<Arc<Notify>>,
+}
+
+impl InstanceHandler {
+    pub fn new(
+        document: Arc<RwLock<Document>>,
+        registry: Arc<PluginRegistry>,
+        globals: Arc<tokio::sync::Mutex<Globals>>,
+        events: EventBus,
+    ) -> Self {
+        Self { document, registry, globals, events, log_store: None, ui: None, shutdown: None }
+    }
+
+    /// Answer `tail_log` from `store`
+    pub fn with_log_store(mut self, store: Arc<LogStore>) -> Self {
+        self.log_store = Some(store);
+        self
+    }
+
+    /// Pass window and file requests to the GUI; without one, files are
+    /// loaded directly and `show_window` is refused
+    pub fn with_ui(mut self, ui: mpsc::UnboundedSender<UiRequest>) -> Self {
+        self.ui = Some(ui);
+        self
+    }
+
+    /// Notify `shutdown` when a client asks a headless instance to exit;
+    /// with a GUI the window is asked to quit instead
+    pub fn with_shutdown(mut self, shutdown: Arc<Notify>) -> Self {
+        self.shutdown = Some(shutdown);
+        self
+    }
+
+    fn find(&self, item: &ItemRef) -> Result<Arc<RwLock<dyn TreeItem>>, RpcError> {
+        let document = self.document.read().map_err(RpcError::internal)?;
+        match item {
+            ItemRef::Id(id) => document.find_item(*id),
+            ItemRef::Path(path) => document.find_by_path(path),
+        }
+        .ok_or_else(|| RpcError::not_found(item))
+    }
+}
+
+/// Outline of `item` and everything below it
+fn tree_entry(item: &Arc<RwLock<dyn TreeItem>>) -> Option<TreeEntry> {
+    let children = children_of(item).iter().filter_map(tree_entry).collect();
+    let item = item.read().ok()?;
+    let any = item.as_any();
+    let kind = if any.is::<Folder>() {
+        ItemKind::Folder
+    } else if any.is::<Macro_>() {
+        ItemKind::Macro
+    } else if any.is::<ActionCall>() {
+        ItemKind::Action
+    } else if any.is::<Link>() {
+        ItemKind::Link
+    } else {
+        ItemKind::Other
+    };
+    Some(TreeEntry {
+        id: item.get_id(),
+        name: item.get_name().to_string(),
+        kind,
+        enabled: item.is_enabled(),
+        children,
+    })
+}
+
+#[async_trait]
+impl CommandHandler for InstanceHandler {
+    async fn trigger_event(&self, name: &str, payload: Value) -> Result<(), RpcError> {
+        if name.is_empty() {
+            return Err(RpcError::new(RpcError::INVALID_REQUEST, "Event name must not be empty"));
+        }
+        let event = NamedEvent::new(name, EventType::User, EventPayload::from_json(payload))
+            .with_source(IPC_EVENT_SOURCE);
+        self.events.publish(event);
+        Ok(())
+    }
+
+    async fn run_macro(&self, item: &ItemRef) -> Result<(), RpcError> {
+        let found = self.find(item)?;
+        if !found.read().map_err(RpcError::internal)?.as_any().is::<Macro_>() {
+            return Err(RpcError::new(RpcError::INVALID_REQUEST, format!("{} is not a macro", item)));
+        }
+        dispatch::bind_actions(&self.document, &self.registry).await;
+        tokio::task::spawn_blocking(move || found.write().map_err(RpcError::internal)?.execute(None).map_err(RpcError::internal))
+            .await
+            .map_err(RpcError::internal)?
+    }
+
+    async fn set_enabled(&self, item: &ItemRef, enabled: bool) -> Result<(), RpcError> {
+        let found = self.find(item)?;
+        found.write().map_err(RpcError::internal)?.set_enabled(enabled);
+        if let Ok(mut document) = self.document.write() {
+            document.set_modified(true);
+        }
+        Ok(())
+    }
+
+    async fn list_plugins(&self) -> Result<Vec<PluginStatus>, RpcError> {
+        Ok(self
+            .registry
+            .get_plugin_states()
+            .await
+            .into_iter()
+            .map(|(info, state)| PluginStatus {
+                id: info.id,
+                name: info.name,
+                version: info.version,
+                state,
+            })
+            .collect())
+    }
+
+    async fn list_tree(&self) -> Result<Vec<TreeEntry>, RpcError> {
+        let root = self.document.read().map_err(RpcError::internal)?.get_root();
+        let children = root.read().map_err(RpcError::internal)?.get_children().to_vec();
+        Ok(children.iter().filter_map(tree_entry).collect())
+    }
+
+    async fn list_variables(&self) -> Result<Map<String, Value>, RpcError> {
+        let globals = self.globals.lock().await;
+        Ok(globals
+            .var_names()
+            .into_iter()
+            .filter_map(|name| globals.get_var::<Value>(&name).map(|value| (name, value)))
+            .collect())
+    }
+
+    async fn get_variable(&self, name: &str) -> Result<Option<Value>, RpcError> {
+        Ok(self.globals.lock().await.get_var::<Value>(name))
+    }
+
+    async fn set_variable(&self, name: &str, value: Value) -> Result<(), RpcError> {
+        self.globals.lock().await.set_var(name, value);
+        Ok(())
+    }
+
+    async fn open_file(&self, path: &Path) -> Result<(), RpcError> {
+        if !path.is_file() {
+            return Err(RpcError::not_found(path.display()));
+        }
+        match &self.ui {
+            Some(ui) => ui
+                .send(UiRequest::OpenFile(path.to_path_buf()))
+                .map_err(|_| RpcError::internal("The main window has closed")),
+            None => self
+                .document
+                .write()
+                .map_err(RpcError::internal)?
+                .load(path.to_path_buf())
+                .map_err(RpcError::internal),
+        }
+    }
+
+    async fn show_window(&self) -> Result<(), RpcError> {
+        match &self.ui {
+            Some(ui) => ui
+                .send(UiRequest::ShowWindow)
+                .map_err(|_| RpcError::internal("The main window has closed")),
+            None => Err(RpcError::new(RpcError::UNSUPPORTED, "Running without a window")),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<(), RpcError> {
+        match (&self.ui, &self.shutdown) {
+            (Some(ui), _) => ui
+                .send(UiRequest::Quit)
+                .map_err(|_| RpcError::internal("The main window has closed")),
+            (None, Some(shutdown)) => {
+                shutdown.notify_one();
+                Ok(())
+            }
+            (None, None) => Err(RpcError::new(RpcError::UNSUPPORTED, "Cannot be stopped remotely")),
+        }
+    }
+
+    fn log_store(&self) -> Option<Arc<LogStore>> {
+        self.log_store.clone()
+    }
+}
@@ -0,0 +1,238 @@
+//! Local inter-process communication
+//!
+//! A [`Transport`] binds and connects to an [`IpcEndpoint`]: Unix domain
+//! sockets on Linux and macOS, named pipes on Windows. Streams carry
+//! length-prefixed frames (see [`frame`]) so a message is never split or
+//! merged by partial reads. On top of that, [`protocol`] defines the
+//! commands an [`IpcServer`] answers and an [`IpcClient`] sends.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+pub mod client;
+pub mod frame;
+pub mod handler;
+#[cfg(windows)]
+pub mod named_pipe;
+pub mod protocol;
+pub mod server;
+#[cfg(unix)]
+pub mod unix;
+
+pub use client::{IpcClient, LogFollower};
+pub use frame::{FramedStream, MAX_FRAME_LEN};
+pub use handler::{InstanceHandler, UiRequest};
+pub use protocol::{Command, ItemKind, ItemRef, PluginStatus, RpcError, TreeEntry, PROTOCOL_VERSION};
+pub use server::{CommandHandler, IpcServer};
+
+/// Error type for IPC operations
+#[derive(Debug, thiserror::Error)]
+pub enum IpcError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Frame of {len} bytes exceeds the {max} byte limit")]
+    FrameTooLarge { len: usize, max: usize },
+    #[error("Connection closed in the middle of a frame")]
+    Truncated,
+    #[error("Connection closed")]
+    Closed,
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Remote error: {0}")]
+    Remote(#[from] RpcError),
+}
+
+/// A connected, bidirectional byte stream
+pub trait IpcStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> IpcStream for T {}
+
+/// Where a server listens: a socket file or a pipe name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpcEndpoint {
+    address: PathBuf,
+}
+
+impl IpcEndpoint {
+    /// The platform's usual location for `name`: `$XDG_RUNTIME_DIR/<name>.sock`
+    /// (or the temp directory) on Unix, `\\.\pipe\<name>` on Windows
+    pub fn named(name: &str) -> Self {
+        let address = if cfg!(windows) {
+            PathBuf::from(format!(r"\\.\pipe\{}", name))
+        } else {
+            std::env::var_os("XDG_RUNTIME_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(format!("{}.sock", name))
+        };
+        Self { address }
+    }
+
+    /// An explicit socket path or pipe name
+    pub fn at(address: impl Into<PathBuf>) -> Self {
+        Self { address: address.into() }
+    }
+
+    /// Socket path or pipe name
+    pub fn address(&self) -> &Path {
+        &self.address
+    }
+}
+
+impl Default for IpcEndpoint {
+    /// Endpoint of the running EventGhost instance
+    fn default() -> Self {
+        Self::named(crate::core::constants::DEFAULT_IPC_NAME)
+    }
+}
+
+impl std::fmt::Display for IpcEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.address.display())
+    }
+}
+
+/// Server side of a transport
+#[async_trait]
+pub trait IpcListener: Send {
+    /// Wait for the next client
+    async fn accept(&mut self) -> io::Result<Box<dyn IpcStream>>;
+
+    /// Endpoint this listener is bound to
+    fn endpoint(&self) -> &IpcEndpoint;
+}
+
+/// A way of reaching another process on the same machine
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Start listening; fails with `AddrInUse` if another server owns the endpoint
+    async fn bind(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcListener>>;
+
+    /// Connect to a listening server
+    async fn connect(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcStream>>;
+}
+
+/// The transport for the current platform
+pub fn default_transport() -> Box<dyn Transport> {
+    #[cfg(unix)]
+    {
+        Box::new(unix::UnixTransport)
+    }
+    #[cfg(windows)]
+    {
+        Box::new(named_pipe::NamedPipeTransport)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, RwLock};
+    use serde_json::json;
+    use tempfile::tempdir;
+    use crate::core::event::EventBus;
+    use crate::core::logging::{LogEntry, LogQuery, LogStore};
+    use crate::core::PluginRegistry;
+    use crate::eg::globals::Globals;
+    use crate::eg::tree::{Document, Folder, Macro_};
+
+    #[tokio::test]
+    async fn test_framed_round_trip() {
+        let dir = tempdir().unwrap();
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+        let transport = default_transport();
+        let mut listener = transport.bind(&endpoint).await.unwrap();
+
+        let server = tokio::spawn(async move {
+            let mut stream = FramedStream::new(listener.accept().await.unwrap());
+            while let Some(frame) = stream.recv().await.unwrap() {
+                let mut reply = b"ack:".to_vec();
+                reply.extend_from_slice(&frame);
+                stream.send(&reply).await.unwrap();
+            }
+        });
+
+        let mut client = FramedStream::new(transport.connect(&endpoint).await.unwrap());
+        for message in [&b"first"[..], b"", b"third"] {
+            client.send(message).await.unwrap();
+        }
+        assert_eq!(client.recv().await.unwrap().unwrap(), b"ack:first");
+        assert_eq!(client.recv().await.unwrap().unwrap(), b"ack:");
+        assert_eq!(client.recv().await.unwrap().unwrap(), b"ack:third");
+
+        drop(client);
+        server.await.unwrap();
+    }
+
+    fn log(store: &LogStore, message: &str) {
+        store.push(LogEntry {
+            timestamp: chrono::Utc::now(),
+            level: log::Level::Info,
+            target: "core".into(),
+            message: message.into(),
+            metadata: serde_json::Value::Null,
+        });
+    }
+
+    #[tokio::test]
+    async fn test_client_commands() {
+        let dir = tempdir().unwrap();
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+
+        let document = Document::new();
+        let mut folder = Folder::new("Lights");
+        folder.add_child(Arc::new(RwLock::new(Macro_::new("Dim"))));
+        document.get_root().write().unwrap().add_child(Arc::new(RwLock::new(folder)));
+        let document = Arc::new(RwLock::new(document));
+
+        let events = EventBus::default();
+        let mut received = events.subscribe();
+        let store = Arc::new(LogStore::default());
+        log(&store, "started");
+        let handler = InstanceHandler::new(
+            document.clone(),
+            Arc::new(PluginRegistry::new(dir.path().to_path_buf()).unwrap()),
+            Arc::new(tokio::sync::Mutex::new(Globals::new())),
+            events,
+        )
+        .with_log_store(store.clone());
+        let server = IpcServer::start(default_transport().as_ref(), &endpoint, Arc::new(handler))
+            .await
+            .unwrap();
+
+        let mut client = IpcClient::connect(&endpoint).await.unwrap();
+        client.trigger_event("Remote.Play", json!({"volume": 3})).await.unwrap();
+        let event = received.recv().await.unwrap();
+        assert_eq!(event.get_id(), "Remote.Play");
+        assert_eq!(event.get_payload().to_json()["volume"], 3);
+
+        client.run_macro("Lights/Dim".parse().unwrap()).await.unwrap();
+        let err = client.run_macro("Lights".parse().unwrap()).await.unwrap_err();
+        assert!(matches!(err, IpcError::Remote(RpcError { code: RpcError::INVALID_REQUEST, .. })));
+        let err = client.run_macro("Nope".parse().unwrap()).await.unwrap_err();
+        assert!(matches!(err, IpcError::Remote(RpcError { code: RpcError::NOT_FOUND, .. })));
+
+        client.set_enabled("Lights/Dim".parse().unwrap(), false).await.unwrap();
+        let dim = document.read().unwrap().find_by_path("Lights/Dim").unwrap();
+        assert!(!dim.read().unwrap().is_enabled());
+
+        assert_eq!(client.get_variable("volume").await.unwrap(), None);
+        client.set_variable("volume", json!(11)).await.unwrap();
+        assert_eq!(client.get_variable("volume").await.unwrap(), Some(json!(11)));
+        assert!(client.list_plugins().await.unwrap().is_empty());
+
+        let tail = client.tail_log(LogQuery::default()).await.unwrap();
+        assert_eq!(tail.len(), 1);
+
+        let follower = IpcClient::connect(&endpoint).await.unwrap();
+        let (initial, mut follower) = follower.follow_log(LogQuery::default()).await.unwrap();
+        assert_eq!(initial[0].entry.message, "started");
+        log(&store, "later");
+        let next = follower.next().await.unwrap().unwrap();
+        assert_eq!(next[0].entry.message, "later");
+
+        drop(server);
+    }
+}
@@ -0,0 +1,105 @@
+//! Windows named pipe transport
+
+use std::io;
+use async_trait::async_trait;
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeServer, ServerOptions};
+use tokio::time::{sleep, Duration, Instant};
+use super::{IpcEndpoint, IpcListener, IpcStream, Transport};
+
+/// All pipe instances are busy
+const ERROR_PIPE_BUSY: i32 = 231;
+
+/// How long to wait for a busy pipe before giving up
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transport over named pipes
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NamedPipeTransport;
+
+/// Listening pipe
+///
+/// Windows serves each client on its own pipe instance, so a fresh instance
+/// is created before a connected one is handed out. Pipes vanish with their
+/// last handle; there is no file to clean up.
+pub struct NamedPipeListener {
+    endpoint: IpcEndpoint,
+    next: NamedPipeServer,
+}
+
+#[async_trait]
+impl Transport for NamedPipeTransport {
+    async fn bind(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcListener>> {
+        let next = ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .create(endpoint.address())
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::PermissionDenied => io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is already in use", endpoint),
+                ),
+                _ => e,
+            })?;
+        Ok(Box::new(NamedPipeListener { endpoint: endpoint.clone(), next }))
+    }
+
+    async fn connect(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcStream>> {
+        let deadline = Instant::now() + BUSY_TIMEOUT;
+        loop {
+            match ClientOptions::new().open(endpoint.address()) {
+                Ok(client) => return Ok(Box::new(client)),
+                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => {
+                    if Instant::now() >= deadline {
+                        return Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            format!("{} stayed busy for {:?}", endpoint, BUSY_TIMEOUT),
+                        ));
+                    }
+                    sleep(Duration::from_millis(50)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl IpcListener for NamedPipeListener {
+    async fn accept(&mut self) -> io::Result<Box<dyn IpcStream>> {
+        self.next.connect().await?;
+        let fresh = ServerOptions::new()
+            .reject_remote_clients(true)
+            .create(self.endpoint.address())?;
+        let connected = std::mem::replace(&mut self.next, fresh);
+        Ok(Box::new(connected))
+    }
+
+    fn endpoint(&self) -> &IpcEndpoint {
+        &self.endpoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ipc::FramedStream;
+
+    #[tokio::test]
+    async fn test_named_pipe() {
+        let endpoint = IpcEndpoint::named(&format!("eventghost-test-{}", std::process::id()));
+        let mut listener = NamedPipeTransport.bind(&endpoint).await.unwrap();
+        assert_eq!(
+            NamedPipeTransport.bind(&endpoint).await.err().unwrap().kind(),
+            io::ErrorKind::AddrInUse
+        );
+
+        let server = tokio::spawn(async move {
+            let mut stream = FramedStream::new(listener.accept().await.unwrap());
+            stream.recv().await.unwrap().unwrap()
+        });
+        let mut client = FramedStream::new(NamedPipeTransport.connect(&endpoint).await.unwrap());
+        client.send(b"Hello, world!").await.unwrap();
+
+        assert_eq!(server.await.unwrap(), b"Hello, world!");
+    }
+}
@@ -0,0 +1,238 @@
+//! Command protocol for controlling a running instance
+//!
+//! JSON-RPC style: every frame holds one JSON object. Requests carry a
+//! method name and parameters, responses echo the request `id` and carry
+//! either a `result` or an `error`. Both sides send [`PROTOCOL_VERSION`];
+//! the server refuses requests from a different major version.
+//!
+//! ```text
+//! -> {"version":1,"id":7,"method":"run_macro","params":{"item":{"path":"Lights/Dim"}}}
+//! <- {"version":1,"id":7,"result":null}
+//! ```
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use uuid::Uuid;
+use crate::core::logging::LogQuery;
+use crate::core::plugin::traits::PluginState;
+
+/// Protocol version spoken by this build
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A command sent to the running instance
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    pub version: u32,
+    /// Chosen by the client and echoed in the response
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+impl Request {
+    pub fn new(id: u64, command: Command) -> Self {
+        Self { version: PROTOCOL_VERSION, id, command }
+    }
+}
+
+/// Methods and their parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum Command {
+    /// Raise an event as if a plugin had produced it
+    TriggerEvent {
+        name: String,
+        #[serde(default)]
+        payload: Value,
+    },
+    /// Execute a macro
+    RunMacro { item: ItemRef },
+    /// Enable or disable a tree item
+    SetEnabled { item: ItemRef, enabled: bool },
+    /// Loaded plugins and their states
+    ListPlugins,
+    /// Outline of the configuration tree
+    ListTree,
+    /// All global variables and their values
+    ListVariables,
+    /// Read a global variable; `null` if unset
+    GetVariable { name: String },
+    /// Set a global variable
+    SetVariable { name: String, value: Value },
+    /// Open a configuration file, as if passed on the command line
+    OpenFile { path: PathBuf },
+    /// Bring the main window to the front
+    ShowWindow,
+    /// Exit, e.g. so a `-restart` launch can take over
+    Shutdown,
+    /// Stored log entries matching `query`; with `follow`, further
+    /// responses with the same `id` carry new entries as they arrive
+    TailLog {
+        #[serde(default)]
+        query: LogQuery,
+        #[serde(default)]
+        follow: bool,
+    },
+}
+
+/// A tree item addressed by ID or by path, e.g. `Lights/Evening/Dim`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemRef {
+    Id(Uuid),
+    Path(String),
+}
+
+impl FromStr for ItemRef {
+    type Err = std::convert::Infallible;
+
+    /// UUIDs (with or without braces) are IDs, anything else is a path
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim_start_matches('{').trim_end_matches('}');
+        Ok(match Uuid::parse_str(trimmed) {
+            Ok(id) => ItemRef::Id(id),
+            Err(_) => ItemRef::Path(s.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for ItemRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ItemRef::Id(id) => write!(f, "{}", id),
+            ItemRef::Path(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+/// Entry in the `list_plugins` result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginStatus {
+    pub id: Uuid,
+    pub name: String,
+    pub version: String,
+    pub state: PluginState,
+}
+
+/// What kind of tree item a [`TreeEntry`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Folder,
+    Macro,
+    Action,
+    Link,
+    Other,
+}
+
+/// Entry in the `list_tree` result
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeEntry {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: ItemKind,
+    pub enabled: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeEntry>,
+}
+
+/// Reply to a [`Request`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub version: u32,
+    /// `id` of the request, or 0 if the request could not be parsed
+    pub id: u64,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+impl Response {
+    pub fn result(id: u64, result: Value) -> Self {
+        Self { version: PROTOCOL_VERSION, id, outcome: Outcome::Result(result) }
+    }
+
+    pub fn error(id: u64, error: RpcError) -> Self {
+        Self { version: PROTOCOL_VERSION, id, outcome: Outcome::Error(error) }
+    }
+}
+
+/// Success or failure of a request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Result(Value),
+    Error(RpcError),
+}
+
+/// Error reported by the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (code {code})")]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    /// The frame is not valid JSON
+    pub const PARSE_ERROR: i32 = -32700;
+    /// Unknown method or malformed parameters
+    pub const INVALID_REQUEST: i32 = -32600;
+    /// The request failed inside the server
+    pub const INTERNAL_ERROR: i32 = -32603;
+    /// Client and server speak different protocol versions
+    pub const VERSION_MISMATCH: i32 = -32000;
+    /// The addressed item, plugin or variable does not exist
+    pub const NOT_FOUND: i32 = -32001;
+    /// The server does not support this command
+    pub const UNSUPPORTED: i32 = -32002;
+
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    pub fn not_found(what: impl std::fmt::Display) -> Self {
+        Self::new(Self::NOT_FOUND, format!("Not found: {}", what))
+    }
+
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        Self::new(Self::INTERNAL_ERROR, error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_wire_format() {
+        let request = Request::new(7, Command::RunMacro { item: "Lights/Dim".parse().unwrap() });
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({"version": 1, "id": 7, "method": "run_macro", "params": {"item": {"path": "Lights/Dim"}}})
+        );
+
+        let parsed: Request = serde_json::from_value(json!({"version": 1, "id": 2, "method": "list_plugins"})).unwrap();
+        assert_eq!(parsed.command, Command::ListPlugins);
+
+        let parsed: Request = serde_json::from_value(
+            json!({"version": 1, "id": 3, "method": "trigger_event", "params": {"name": "Remote.Play"}}),
+        )
+        .unwrap();
+        assert_eq!(parsed.command, Command::TriggerEvent { name: "Remote.Play".into(), payload: Value::Null });
+
+        let response = Response::error(7, RpcError::not_found("Lights/Dim"));
+        let value = serde_json::to_value(&response).unwrap();
+        assert_eq!(value["error"]["code"], RpcError::NOT_FOUND);
+        assert_eq!(serde_json::from_value::<Response>(value).unwrap(), response);
+    }
+
+    #[test]
+    fn test_item_ref_parsing() {
+        let id = Uuid::new_v4();
+        assert_eq!(format!("{{{}}}", id).parse::<ItemRef>().unwrap(), ItemRef::Id(id));
+        assert_eq!("Folder/Macro".parse::<ItemRef>().unwrap(), ItemRef::Path("Folder/Macro".into()));
+    }
+}
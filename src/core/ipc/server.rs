@@ -0,0 +1,232 @@
+//! Serves the command protocol to other processes
+
+use std::path::Path;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use crate::core::logging::{LogQuery, LogStore, StoredLogEntry};
+use super::frame::{read_frame, write_frame};
+use super::protocol::{Command, ItemRef, PluginStatus, Request, Response, RpcError, TreeEntry, PROTOCOL_VERSION};
+use super::{IpcEndpoint, IpcError, IpcListener, IpcStream, Transport};
+
+/// What the running instance does for each command
+#[async_trait]
+pub trait CommandHandler: Send + Sync {
+    async fn trigger_event(&self, name: &str, payload: Value) -> Result<(), RpcError>;
+
+    async fn run_macro(&self, item: &ItemRef) -> Result<(), RpcError>;
+
+    async fn set_enabled(&self, item: &ItemRef, enabled: bool) -> Result<(), RpcError>;
+
+    async fn list_plugins(&self) -> Result<Vec<PluginStatus>, RpcError>;
+
+    async fn list_tree(&self) -> Result<Vec<TreeEntry>, RpcError> {
+        Err(RpcError::new(RpcError::UNSUPPORTED, "No configuration tree"))
+    }
+
+    async fn list_variables(&self) -> Result<Map<String, Value>, RpcError> {
+        Err(RpcError::new(RpcError::UNSUPPORTED, "No global variables"))
+    }
+
+    async fn get_variable(&self, name: &str) -> Result<Option<Value>, RpcError>;
+
+    async fn set_variable(&self, name: &str, value: Value) -> Result<(), RpcError>;
+
+    async fn open_file(&self, path: &Path) -> Result<(), RpcError> {
+        Err(RpcError::new(RpcError::UNSUPPORTED, format!("Cannot open {}", path.display())))
+    }
+
+    async fn show_window(&self) -> Result<(), RpcError> {
+        Err(RpcError::new(RpcError::UNSUPPORTED, "No window to show"))
+    }
+
+    async fn shutdown(&self) -> Result<(), RpcError> {
+        Err(RpcError::new(RpcError::UNSUPPORTED, "Cannot be stopped remotely"))
+    }
+
+    /// Store answering `tail_log`; `None` if the log is not available
+    fn log_store(&self) -> Option<Arc<LogStore>>;
+}
+
+/// Accepts clients on an endpoint until dropped
+pub struct IpcServer {
+    endpoint: IpcEndpoint,
+    task: JoinHandle<()>,
+}
+
+impl IpcServer {
+    /// Bind `endpoint` and serve each client on its own task
+    pub async fn start(
+        transport: &dyn Transport,
+        endpoint: &IpcEndpoint,
+        handler: Arc<dyn CommandHandler>,
+    ) -> Result<Self, IpcError> {
+        let listener = transport.bind(endpoint).await?;
+        Ok(Self::serve(listener, handler))
+    }
+
+    /// Serve clients on an already bound listener
+    pub fn serve(listener: Box<dyn IpcListener>, handler: Arc<dyn CommandHandler>) -> Self {
+        let endpoint = listener.endpoint().clone();
+        let task = tokio::spawn(accept_loop(listener, handler));
+        Self { endpoint, task }
+    }
+
+    /// Endpoint clients connect to
+    pub fn endpoint(&self) -> &IpcEndpoint {
+        &self.endpoint
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn accept_loop(mut listener: Box<dyn IpcListener>, handler: Arc<dyn CommandHandler>) {
+    loop {
+        match listener.accept().await {
+            Ok(stream) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, handler).await {
+                        log::debug!("IPC client disconnected: {}", e);
+                    }
+                });
+            }
+            Err(e) => {
+                log::warn!("Failed to accept IPC client on {}: {}", listener.endpoint(), e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+        }
+    }
+}
+
+/// Answer requests from one client until it disconnects
+pub async fn serve_connection(stream: Box<dyn IpcStream>, handler: Arc<dyn CommandHandler>) -> Result<(), IpcError> {
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    while let Some(frame) = read_frame(&mut reader).await? {
+        let request = match parse_request(&frame) {
+            Ok(request) => request,
+            Err(response) => {
+                send(&mut writer, &response).await?;
+                continue;
+            }
+        };
+
+        if let Command::TailLog { query, follow: true } = &request.command {
+            return follow_log(reader, writer, handler.as_ref(), request.id, query).await;
+        }
+        let response = dispatch(handler.as_ref(), request).await;
+        send(&mut writer, &response).await?;
+    }
+    Ok(())
+}
+
+fn parse_request(frame: &[u8]) -> Result<Request, Response> {
+    let value: Value = serde_json::from_slice(frame)
+        .map_err(|e| Response::error(0, RpcError::new(RpcError::PARSE_ERROR, e.to_string())))?;
+    let id = value.get("id").and_then(Value::as_u64).unwrap_or(0);
+
+    let version = value.get("version").and_then(Value::as_u64);
+    if version != Some(PROTOCOL_VERSION as u64) {
+        return Err(Response::error(
+            id,
+            RpcError::new(
+                RpcError::VERSION_MISMATCH,
+                format!("Unsupported protocol version {:?}, expected {}", version, PROTOCOL_VERSION),
+            ),
+        ));
+    }
+    serde_json::from_value(value).map_err(|e| Response::error(id, RpcError::new(RpcError::INVALID_REQUEST, e.to_string())))
+}
+
+/// Run one command against `handler`
+pub async fn dispatch(handler: &dyn CommandHandler, request: Request) -> Response {
+    let result = match request.command {
+        Command::TriggerEvent { name, payload } => handler.trigger_event(&name, payload).await.map(|()| Value::Null),
+        Command::RunMacro { item } => handler.run_macro(&item).await.map(|()| Value::Null),
+        Command::SetEnabled { item, enabled } => handler.set_enabled(&item, enabled).await.map(|()| Value::Null),
+        Command::ListPlugins => handler.list_plugins().await.and_then(to_value),
+        Command::ListTree => handler.list_tree().await.and_then(to_value),
+        Command::ListVariables => handler.list_variables().await.map(Value::Object),
+        Command::GetVariable { name } => handler.get_variable(&name).await.map(Option::unwrap_or_default),
+        Command::SetVariable { name, value } => handler.set_variable(&name, value).await.map(|()| Value::Null),
+        Command::OpenFile { path } => handler.open_file(&path).await.map(|()| Value::Null),
+        Command::ShowWindow => handler.show_window().await.map(|()| Value::Null),
+        Command::Shutdown => handler.shutdown().await.map(|()| Value::Null),
+        Command::TailLog { query, .. } => log_store(handler).and_then(|store| to_value(store.query(&query))),
+    };
+    match result {
+        Ok(value) => Response::result(request.id, value),
+        Err(error) => Response::error(request.id, error),
+    }
+}
+
+fn log_store(handler: &dyn CommandHandler) -> Result<Arc<LogStore>, RpcError> {
+    handler
+        .log_store()
+        .ok_or_else(|| RpcError::new(RpcError::UNSUPPORTED, "The log is not available"))
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(RpcError::internal)
+}
+
+async fn send<W: AsyncWrite + Unpin>(writer: &mut W, response: &Response) -> Result<(), IpcError> {
+    let frame = serde_json::to_vec(response).map_err(|e| IpcError::Protocol(e.to_string()))?;
+    write_frame(writer, &frame).await
+}
+
+/// Send the stored entries, then every new matching entry until the client hangs up
+async fn follow_log<S: AsyncRead + AsyncWrite>(
+    mut reader: ReadHalf<S>,
+    mut writer: WriteHalf<S>,
+    handler: &dyn CommandHandler,
+    id: u64,
+    query: &LogQuery,
+) -> Result<(), IpcError> {
+    let store = match log_store(handler) {
+        Ok(store) => store,
+        Err(error) => return send(&mut writer, &Response::error(id, error)).await,
+    };
+
+    // Subscribe first so nothing logged in between is missed
+    let mut entries = store.subscribe();
+    let initial = store.query(query);
+    let mut last_seq = initial.last().map(|e| e.seq).or(query.after_seq).unwrap_or(0);
+    send(&mut writer, &Response::result(id, to_entries(&initial))).await?;
+
+    // Only the newest entries are limited; everything after that is streamed
+    let live = LogQuery { limit: None, ..query.clone() };
+    loop {
+        tokio::select! {
+            frame = read_frame(&mut reader) => {
+                // Nothing else is accepted on a following connection
+                if frame?.is_none() {
+                    return Ok(());
+                }
+            }
+            entry = entries.recv() => match entry {
+                Ok(entry) if entry.seq > last_seq && live.matches(&entry) => {
+                    last_seq = entry.seq;
+                    send(&mut writer, &Response::result(id, to_entries(&[entry]))).await?;
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    log::debug!("IPC log follower skipped {} entries", skipped);
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+fn to_entries(entries: &[StoredLogEntry]) -> Value {
+    serde_json::to_value(entries).unwrap_or_default()
+}
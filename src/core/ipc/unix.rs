@@ -0,0 +1,168 @@
+//! Unix domain socket transport
+
+use std::io;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use async_trait::async_trait;
+use tokio::net::{UnixListener, UnixStream};
+use super::{IpcEndpoint, IpcListener, IpcStream, Transport};
+
+/// Transport over Unix domain sockets
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UnixTransport;
+
+/// Listening socket; the socket file is removed on drop
+pub struct UnixSocketListener {
+    endpoint: IpcEndpoint,
+    listener: UnixListener,
+}
+
+#[async_trait]
+impl Transport for UnixTransport {
+    async fn bind(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcListener>> {
+        let path = endpoint.address();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(dir)?;
+
+        // Launches racing for the same path take turns, so one cannot remove
+        // a socket another has just bound as if it were stale
+        let _lock = lock(path).await?;
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) => {
+                // Never delete anything but a socket
+                if !meta.file_type().is_socket() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} exists and is not a socket", endpoint),
+                    ));
+                }
+                // A live server answers; a socket left behind by a crash does not
+                if UnixStream::connect(path).await.is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("{} is already in use", endpoint),
+                    ));
+                }
+                std::fs::remove_file(path)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        // Bind inside a private 0700 directory and link the socket into place
+        // once only the owning user may send commands, so nobody can connect
+        // in between even when `dir` is shared, e.g. the temp directory.
+        // Unlike a rename, the link fails if the path was claimed meanwhile.
+        let staging = tempfile::Builder::new().prefix(".eventghost-").tempdir_in(dir)?;
+        let staged = staging.path().join("socket");
+        let listener = UnixListener::bind(&staged)?;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::hard_link(&staged, path).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => {
+                io::Error::new(io::ErrorKind::AddrInUse, format!("{} is already in use", endpoint))
+            }
+            _ => e,
+        })?;
+        Ok(Box::new(UnixSocketListener { endpoint: endpoint.clone(), listener }))
+    }
+
+    async fn connect(&self, endpoint: &IpcEndpoint) -> io::Result<Box<dyn IpcStream>> {
+        Ok(Box::new(UnixStream::connect(endpoint.address()).await?))
+    }
+}
+
+#[async_trait]
+impl IpcListener for UnixSocketListener {
+    async fn accept(&mut self) -> io::Result<Box<dyn IpcStream>> {
+        let (stream, _) = self.listener.accept().await?;
+        Ok(Box::new(stream))
+    }
+
+    fn endpoint(&self) -> &IpcEndpoint {
+        &self.endpoint
+    }
+}
+
+/// Exclusive lock on `<path>.lock`, held until the file is dropped
+///
+/// The lock file stays behind; removing it would let a waiting launch lock
+/// a file nobody else can see anymore.
+async fn lock(path: &Path) -> io::Result<std::fs::File> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    let file = std::fs::OpenOptions::new().create(true).truncate(false).write(true).open(name)?;
+    tokio::task::spawn_blocking(move || file.lock().map(|()| file)).await?
+}
+
+impl Drop for UnixSocketListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.endpoint.address());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_bind_replaces_stale_socket_and_cleans_up() {
+        let dir = tempdir().unwrap();
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+
+        // Leave a socket file behind without a listener
+        drop(std::os::unix::net::UnixListener::bind(endpoint.address()).unwrap());
+        assert!(endpoint.address().exists());
+
+        let listener = UnixTransport.bind(&endpoint).await.unwrap();
+        let mode = std::fs::metadata(endpoint.address()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let err = UnixTransport.bind(&endpoint).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        drop(listener);
+        assert!(!endpoint.address().exists());
+        // The staging directory is gone too; only the lock file is left
+        let left: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(left, ["eg.sock.lock"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_simultaneous_binds_have_one_winner() {
+        let dir = tempdir().unwrap();
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+        // A stale socket, so every launch is tempted to replace it
+        drop(std::os::unix::net::UnixListener::bind(endpoint.address()).unwrap());
+
+        let binds: Vec<_> = (0..8)
+            .map(|_| {
+                let endpoint = endpoint.clone();
+                tokio::spawn(async move { UnixTransport.bind(&endpoint).await })
+            })
+            .collect();
+        let mut listeners = Vec::new();
+        for bind in binds {
+            match bind.await.unwrap() {
+                Ok(listener) => listeners.push(listener),
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::AddrInUse),
+            }
+        }
+        assert_eq!(listeners.len(), 1);
+        UnixTransport.connect(&endpoint).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_keeps_files_that_are_not_sockets() {
+        let dir = tempdir().unwrap();
+        let endpoint = IpcEndpoint::at(dir.path().join("eg.sock"));
+        std::fs::write(endpoint.address(), "not a socket").unwrap();
+
+        let err = UnixTransport.bind(&endpoint).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(endpoint.address()).unwrap(), "not a socket");
+    }
+}
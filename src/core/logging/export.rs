@@ -0,0 +1,285 @@
+//! Export log entries as HTML, CSV or plain text
+//!
+//! Replaces the legacy "Save log as…" command. Entries usually come from a
+//! [`LogStore`](super::LogStore) query, so the same filters work in the GUI
+//! and on the command line (`eventghost log export`).
+
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use log::Level;
+use super::{LogEntry, LoggingError, DEFAULT_TIME_FORMAT};
+
+/// Output format for [`export`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Standalone page with level colours
+    Html,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Same layout as the log file
+    Text,
+}
+
+impl ExportFormat {
+    /// File extension without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Html => "html",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Text => "txt",
+        }
+    }
+
+    /// Guess the format from a file name, e.g. `log.html`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = LoggingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "html" | "htm" => Ok(ExportFormat::Html),
+            "csv" => Ok(ExportFormat::Csv),
+            "txt" | "text" | "log" => Ok(ExportFormat::Text),
+            _ => Err(LoggingError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+/// Export settings
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Output format
+    pub format: ExportFormat,
+    /// `chrono` format string for timestamps; `None` leaves them out
+    pub time_format: Option<String>,
+    /// Show timestamps in UTC instead of local time
+    pub utc: bool,
+    /// Title of the HTML page
+    pub title: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Text,
+            time_format: Some(DEFAULT_TIME_FORMAT.to_string()),
+            utc: false,
+            title: "EventGhost Log".to_string(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Default options for `format`
+    pub fn new(format: ExportFormat) -> Self {
+        Self { format, ..Default::default() }
+    }
+
+    fn timestamp(&self, entry: &LogEntry) -> Option<String> {
+        let format = self.time_format.as_deref()?;
+        Some(if self.utc {
+            entry.timestamp.format(format).to_string()
+        } else {
+            entry.timestamp.with_timezone(&chrono::Local).format(format).to_string()
+        })
+    }
+}
+
+/// Write `entries` to `out` in the configured format
+pub fn export<W: Write>(entries: &[LogEntry], options: &ExportOptions, out: &mut W) -> Result<(), std::io::Error> {
+    match options.format {
+        ExportFormat::Html => write_html(entries, options, out),
+        ExportFormat::Csv => write_csv(entries, options, out),
+        ExportFormat::Text => write_text(entries, options, out),
+    }
+}
+
+/// Export into a string
+pub fn export_to_string(entries: &[LogEntry], options: &ExportOptions) -> String {
+    let mut out = Vec::new();
+    export(entries, options, &mut out).expect("writing to a Vec cannot fail");
+    String::from_utf8(out).expect("exporters only write UTF-8")
+}
+
+/// Export `entries`, usually the result of a [`LogQuery`](super::LogQuery), to a file;
+/// returns how many were written
+pub fn export_to_file(entries: &[LogEntry], options: &ExportOptions, path: &Path) -> Result<usize, LoggingError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    export(entries, options, &mut out)?;
+    out.flush()?;
+    Ok(entries.len())
+}
+
+fn write_text<W: Write>(entries: &[LogEntry], options: &ExportOptions, out: &mut W) -> Result<(), std::io::Error> {
+    for entry in entries {
+        if let Some(time) = options.timestamp(entry) {
+            write!(out, "{} ", time)?;
+        }
+        writeln!(out, "{:<5} [{}] {}", entry.level, entry.target, entry.message)?;
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(entries: &[LogEntry], options: &ExportOptions, out: &mut W) -> Result<(), std::io::Error> {
+    let timestamps = options.time_format.is_some();
+    if timestamps {
+        write!(out, "timestamp,")?;
+    }
+    write!(out, "level,target,message\r\n")?;
+
+    for entry in entries {
+        if let Some(time) = options.timestamp(entry) {
+            write!(out, "{},", csv_field(&time))?;
+        }
+        write!(
+            out,
+            "{},{},{}\r\n",
+            entry.level,
+            csv_field(&entry.target),
+            csv_field(&entry.message)
+        )?;
+    }
+    Ok(())
+}
+
+/// Quote a field if it contains a separator, quote or line break (RFC 4180)
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Same colours as the log pane in the main window
+fn level_colour(level: Level) -> Option<&'static str> {
+    match level {
+        Level::Error => Some("red"),
+        Level::Warn => Some("orange"),
+        Level::Info => Some("blue"),
+        Level::Debug | Level::Trace => None,
+    }
+}
+
+fn write_html<W: Write>(entries: &[LogEntry], options: &ExportOptions, out: &mut W) -> Result<(), std::io::Error> {
+    let mut style = String::from(
+        "body { font-family: monospace; }\n\
+         table { border-collapse: collapse; }\n\
+         td { padding: 0 0.5em; vertical-align: top; white-space: pre-wrap; }\n",
+    );
+    for level in [Level::Error, Level::Warn, Level::Info] {
+        if let Some(colour) = level_colour(level) {
+            let _ = writeln!(style, "tr.{} {{ color: {}; }}", level.as_str().to_lowercase(), colour);
+        }
+    }
+
+    let title = html_escape(&options.title);
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>", title)?;
+    writeln!(out, "<style>\n{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<table>", style, title)?;
+    for entry in entries {
+        write!(out, "<tr class=\"{}\">", entry.level.as_str().to_lowercase())?;
+        if let Some(time) = options.timestamp(entry) {
+            write!(out, "<td>{}</td>", html_escape(&time))?;
+        }
+        writeln!(
+            out,
+            "<td>{}</td><td>{}</td><td>{}</td></tr>",
+            entry.level,
+            html_escape(&entry.target),
+            html_escape(&entry.message)
+        )?;
+    }
+    writeln!(out, "</table>\n</body>\n</html>")
+}
+
+fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entries() -> Vec<LogEntry> {
+        let timestamp = chrono::Utc.with_ymd_and_hms(2024, 3, 1, 12, 30, 0).unwrap();
+        vec![
+            LogEntry {
+                timestamp,
+                level: Level::Info,
+                target: "core".into(),
+                message: "started".into(),
+                metadata: serde_json::Value::Null,
+            },
+            LogEntry {
+                timestamp,
+                level: Level::Error,
+                target: "plugin::Serial".into(),
+                message: "port \"COM1\", <closed>\nretrying".into(),
+                metadata: serde_json::Value::Null,
+            },
+        ]
+    }
+
+    fn options(format: ExportFormat) -> ExportOptions {
+        ExportOptions {
+            time_format: Some("%H:%M".into()),
+            utc: true,
+            ..ExportOptions::new(format)
+        }
+    }
+
+    #[test]
+    fn test_text_export() {
+        let text = export_to_string(&entries(), &options(ExportFormat::Text));
+        assert!(text.starts_with("12:30 INFO  [core] started\n"), "{}", text);
+
+        let untimed = ExportOptions { time_format: None, ..options(ExportFormat::Text) };
+        assert!(export_to_string(&entries(), &untimed).starts_with("INFO  [core] started\n"));
+    }
+
+    #[test]
+    fn test_csv_export_quotes_fields() {
+        let csv = export_to_string(&entries(), &options(ExportFormat::Csv));
+        let mut lines = csv.split("\r\n");
+        assert_eq!(lines.next(), Some("timestamp,level,target,message"));
+        assert_eq!(lines.next(), Some("12:30,INFO,core,started"));
+        assert_eq!(lines.next(), Some("12:30,ERROR,plugin::Serial,\"port \"\"COM1\"\", <closed>\nretrying\""));
+    }
+
+    #[test]
+    fn test_html_export_escapes_and_colours() {
+        let html = export_to_string(&entries(), &options(ExportFormat::Html));
+        assert!(html.contains("tr.error { color: red; }"));
+        assert!(html.contains("<tr class=\"error\"><td>12:30</td><td>ERROR</td>"));
+        assert!(html.contains("port &quot;COM1&quot;, &lt;closed&gt;"));
+        assert!(!html.contains("<closed>"));
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ExportFormat::from_path(Path::new("bug/log.HTML")), Some(ExportFormat::Html));
+        assert_eq!(ExportFormat::from_path(Path::new("log.csv")), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_path(Path::new("log")), None);
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+}
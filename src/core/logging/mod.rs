@@ -0,0 +1,603 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::{Serialize, Deserialize};
+use crate::core::constants::DEFAULT_LOG_PATH;
+
+pub mod export;
+pub mod store;
+
+pub use export::{ExportFormat, ExportOptions};
+pub use store::{LogQuery, LogStore, StoredLogEntry};
+
+/// Timestamp format used by the log file and exports
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// Error type for logging setup
+#[derive(Debug, thiserror::Error)]
+pub enum LoggingError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("A logger is already installed: {0}")]
+    AlreadyInstalled(#[from] log::SetLoggerError),
+    #[error("Unknown export format: {0}")]
+    UnknownFormat(String),
+}
+
+/// Logging configuration (`[global.log]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Log file path
+    pub file_path: PathBuf,
+    /// Console logging level
+    pub console_level: Level,
+    /// File logging level
+    pub file_level: Level,
+    /// Maximum log file size in bytes
+    pub max_file_size: u64,
+    /// Maximum number of backup files
+    pub max_backup_count: u32,
+    /// Optional JSON-lines file receiving every entry the log file receives
+    #[serde(default)]
+    pub json_path: Option<PathBuf>,
+    /// Level overrides by target, e.g. `plugin::MQTT = "debug"`; applies to
+    /// the target and everything below it, for all outputs
+    #[serde(default)]
+    pub target_levels: BTreeMap<String, LevelFilter>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            file_path: PathBuf::from(DEFAULT_LOG_PATH),
+            console_level: Level::Info,
+            file_level: Level::Info,
+            max_file_size: 10 * 1024 * 1024,
+            max_backup_count: 5,
+            json_path: None,
+            target_levels: BTreeMap::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// Most verbose level any output or override accepts
+    pub fn max_level(&self) -> LevelFilter {
+        self.target_levels
+            .values()
+            .copied()
+            .fold(self.console_level.max(self.file_level).to_level_filter(), Ord::max)
+    }
+
+    /// Console and file levels for `target`, after overrides
+    pub fn levels_for(&self, target: &str) -> (LevelFilter, LevelFilter) {
+        let matched = self
+            .target_levels
+            .iter()
+            .filter(|(prefix, _)| {
+                target == prefix.as_str()
+                    || target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len());
+        match matched {
+            Some((_, level)) => (*level, *level),
+            None => (self.console_level.to_level_filter(), self.file_level.to_level_filter()),
+        }
+    }
+}
+
+/// Custom log target for EventGhost
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogTarget {
+    /// Core system events
+    Core,
+    /// Plugin-related events
+    Plugin(String),
+    /// GUI-related events
+    Gui,
+    /// User actions
+    User,
+}
+
+impl LogTarget {
+    /// Target string to pass to the `log` macros, e.g. `plugin::MQTT`
+    pub fn as_target(&self) -> String {
+        match self {
+            LogTarget::Core => "core".to_string(),
+            LogTarget::Plugin(name) => format!("plugin::{}", name),
+            LogTarget::Gui => "gui".to_string(),
+            LogTarget::User => "user".to_string(),
+        }
+    }
+
+    /// Classify a `log` target; module paths count as core
+    pub fn parse(target: &str) -> Self {
+        if let Some(name) = target.strip_prefix("plugin::") {
+            return LogTarget::Plugin(name.to_string());
+        }
+        match target.split("::").next() {
+            Some("gui") => LogTarget::Gui,
+            Some("user") => LogTarget::User,
+            _ => LogTarget::Core,
+        }
+    }
+}
+
+/// Log entry with metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// Timestamp of the log entry
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Log level
+    pub level: Level,
+    /// Log target
+    pub target: String,
+    /// Log message
+    pub message: String,
+    /// Additional structured data
+    pub metadata: serde_json::Value,
+}
+
+impl LogEntry {
+    /// Capture a `log` record
+    pub fn from_record(record: &Record) -> Self {
+        let mut metadata = serde_json::Map::new();
+        if let LogTarget::Plugin(name) = LogTarget::parse(record.target()) {
+            metadata.insert("plugin".to_string(), name.into());
+        }
+        if let Some(module) = record.module_path() {
+            metadata.insert("module".to_string(), module.into());
+        }
+        if let Some(file) = record.file() {
+            metadata.insert("file".to_string(), file.into());
+        }
+        if let Some(line) = record.line() {
+            metadata.insert("line".to_string(), line.into());
+        }
+
+        Self {
+            timestamp: chrono::Utc::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            metadata: serde_json::Value::Object(metadata),
+        }
+    }
+}
+
+/// Size-rotated output file
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path) -> Result<Self, std::io::Error> {
+        if let Some(dir) = path.parent() {
+            if !dir.as_os_str().is_empty() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path: path.to_path_buf(), file, size })
+    }
+
+    fn rotate(&mut self, max_backup_count: u32) -> Result<(), std::io::Error> {
+        self.file.flush()?;
+
+        if max_backup_count == 0 {
+            self.file.set_len(0)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        let oldest = Logger::backup_path(&self.path, max_backup_count);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (1..max_backup_count).rev() {
+            let from = Logger::backup_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, Logger::backup_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, Logger::backup_path(&self.path, 1))?;
+
+        *self = Self::open(&self.path)?;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str, config: &LogConfig) -> Result<(), std::io::Error> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > config.max_file_size {
+            self.rotate(config.max_backup_count)?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// Logger implementation for EventGhost
+///
+/// Writes to the console and to `LogConfig::file_path` with independent
+/// levels, optionally mirrors file output as JSON lines, and keeps recent
+/// entries in a queryable [`LogStore`]. Once a file would grow past
+/// `max_file_size` it is renamed to `<file>.1`, older backups shift up by
+/// one and anything beyond `max_backup_count` is deleted. Clones share the
+/// same outputs.
+#[derive(Clone)]
+pub struct Logger {
+    config: Arc<RwLock<Arc<LogConfig>>>,
+    store: Arc<LogStore>,
+    file: Arc<Mutex<RotatingFile>>,
+    json: Arc<Mutex<Option<RotatingFile>>>,
+    formatter: Arc<LogFormatter>,
+}
+
+impl Logger {
+    /// Create a new logger instance
+    pub fn new(config: LogConfig) -> Result<Self, std::io::Error> {
+        let file = RotatingFile::open(&config.file_path)?;
+        let json = config.json_path.as_deref().map(RotatingFile::open).transpose()?;
+        Ok(Self {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            store: Arc::new(LogStore::default()),
+            file: Arc::new(Mutex::new(file)),
+            json: Arc::new(Mutex::new(json)),
+            formatter: Arc::new(LogFormatter::default()),
+        })
+    }
+
+    /// Update logger configuration
+    pub async fn update_config(&self, config: LogConfig) -> Result<(), std::io::Error> {
+        let mut current = self.config.write().unwrap();
+        if current.file_path != config.file_path {
+            *self.file.lock().unwrap() = RotatingFile::open(&config.file_path)?;
+        }
+        if current.json_path != config.json_path {
+            *self.json.lock().unwrap() = config.json_path.as_deref().map(RotatingFile::open).transpose()?;
+        }
+        log::set_max_level(config.max_level());
+        *current = Arc::new(config);
+        Ok(())
+    }
+
+    /// Current configuration
+    pub fn config(&self) -> Arc<LogConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Store of recent entries
+    pub fn store(&self) -> Arc<LogStore> {
+        self.store.clone()
+    }
+
+    /// Most recent entries, oldest first
+    pub fn recent_entries(&self) -> Vec<LogEntry> {
+        self.store
+            .query(&LogQuery::default())
+            .into_iter()
+            .map(|stored| stored.entry)
+            .collect()
+    }
+
+    /// Path of backup number `index`, e.g. `eventghost.log.2`
+    pub fn backup_path(path: &Path, index: u32) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    /// Rotate log files now, regardless of size
+    pub async fn rotate_logs(&self) -> Result<(), std::io::Error> {
+        let config = self.config();
+        self.file.lock().unwrap().rotate(config.max_backup_count)?;
+        if let Some(json) = self.json.lock().unwrap().as_mut() {
+            json.rotate(config.max_backup_count)?;
+        }
+        Ok(())
+    }
+
+    fn write_files(&self, config: &LogConfig, line: &str, entry: &LogEntry) -> Result<(), std::io::Error> {
+        self.file.lock().unwrap().write_line(line, config)?;
+        if let Some(json) = self.json.lock().unwrap().as_mut() {
+            let line = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+            json.write_line(&line, config)?;
+        }
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let (console, file) = self.config.read().unwrap().levels_for(metadata.target());
+        metadata.level() <= console.max(file)
+    }
+
+    fn log(&self, record: &Record) {
+        let config = self.config();
+        let (console, file) = config.levels_for(record.target());
+        if record.level() > console.max(file) {
+            return;
+        }
+
+        let line = self.formatter.format(record);
+        if record.level() <= console {
+            let _ = writeln!(std::io::stderr().lock(), "{}", line);
+        }
+
+        let entry = LogEntry::from_record(record);
+        if record.level() <= file {
+            if let Err(e) = self.write_files(&config, &line, &entry) {
+                let _ = writeln!(std::io::stderr().lock(), "Failed to write log file: {}", e);
+            }
+        }
+        self.store.push(entry);
+    }
+
+    fn flush(&self) {
+        let _ = self.file.lock().unwrap().file.flush();
+        if let Some(json) = self.json.lock().unwrap().as_mut() {
+            let _ = json.file.flush();
+        }
+    }
+}
+
+/// Log formatter for structured logging
+pub struct LogFormatter {
+    include_metadata: bool,
+    time_format: String,
+}
+
+impl Default for LogFormatter {
+    fn default() -> Self {
+        Self::new(false, DEFAULT_TIME_FORMAT)
+    }
+}
+
+impl LogFormatter {
+    /// Create a formatter with a `chrono` time format string
+    pub fn new(include_metadata: bool, time_format: &str) -> Self {
+        Self {
+            include_metadata,
+            time_format: time_format.to_string(),
+        }
+    }
+
+    /// Format a log record
+    pub fn format(&self, record: &Record) -> String {
+        let mut line = format!(
+            "{} {:<5} [{}] {}",
+            chrono::Local::now().format(&self.time_format),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if self.include_metadata {
+            if let (Some(file), Some(number)) = (record.file(), record.line()) {
+                line.push_str(&format!(" ({}:{})", file, number));
+            }
+        }
+        line
+    }
+}
+
+/// Console logger that also keeps what it prints in a [`LogStore`]
+///
+/// Lets remote clients follow the log when no log file is configured.
+struct ConsoleLogger {
+    inner: env_logger::Logger,
+    store: Arc<LogStore>,
+}
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.store.push(LogEntry::from_record(record));
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Log to stderr, filtered by `RUST_LOG`, and keep the entries in `store`
+pub fn init_console(store: Arc<LogStore>) -> Result<(), LoggingError> {
+    let inner = env_logger::Builder::from_default_env().build();
+    let level = inner.filter();
+    log::set_boxed_logger(Box::new(ConsoleLogger { inner, store }))?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// The logger [`init`] installed, if any
+static INSTALLED: OnceLock<Logger> = OnceLock::new();
+
+/// Initialize the logging system
+///
+/// Installs a [`Logger`] as the global `log` backend and returns a handle
+/// that can be used to change its configuration later.
+pub fn init(config: LogConfig) -> Result<Logger, LoggingError> {
+    let level = config.max_level();
+    let logger = Logger::new(config)?;
+    log::set_boxed_logger(Box::new(logger.clone()))?;
+    log::set_max_level(level);
+    let _ = INSTALLED.set(logger.clone());
+    Ok(logger)
+}
+
+/// Handle of the logger installed by [`init`]; `None` when logging to the
+/// console only
+pub fn installed() -> Option<Logger> {
+    INSTALLED.get().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn config(dir: &Path, max_file_size: u64, max_backup_count: u32) -> LogConfig {
+        LogConfig {
+            file_path: dir.join("eventghost.log"),
+            console_level: Level::Error,
+            file_level: Level::Debug,
+            max_file_size,
+            max_backup_count,
+            json_path: None,
+            target_levels: BTreeMap::new(),
+        }
+    }
+
+    fn log_line(logger: &Logger, level: Level, message: &str) {
+        logger.log(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(level)
+                .target("test")
+                .build(),
+        );
+    }
+
+    fn line_count(path: &Path) -> usize {
+        fs::read_to_string(path).map(|s| s.lines().count()).unwrap_or(0)
+    }
+
+    #[tokio::test]
+    async fn test_log_rotation() {
+        let dir = tempdir().unwrap();
+        let config = config(dir.path(), 200, 2);
+        let path = config.file_path.clone();
+        let logger = Logger::new(config).unwrap();
+
+        for i in 0..40 {
+            log_line(&logger, Level::Info, &format!("message number {}", i));
+        }
+
+        assert!(path.exists());
+        assert!(Logger::backup_path(&path, 1).exists());
+        assert!(Logger::backup_path(&path, 2).exists());
+        assert!(!Logger::backup_path(&path, 3).exists());
+        for file in [path.clone(), Logger::backup_path(&path, 1), Logger::backup_path(&path, 2)] {
+            assert!(fs::metadata(&file).unwrap().len() <= 200);
+        }
+
+        // The newest message is in the live file
+        assert!(fs::read_to_string(&path).unwrap().contains("message number 39"));
+
+        logger.rotate_logs().await.unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert!(fs::read_to_string(Logger::backup_path(&path, 1)).unwrap().contains("message number 39"));
+    }
+
+    #[test]
+    fn test_log_formatting() {
+        let formatter = LogFormatter::new(true, "%H:%M");
+        let line = formatter.format(
+            &Record::builder()
+                .args(format_args!("hello"))
+                .level(Level::Warn)
+                .target("core")
+                .file(Some("src/core/event.rs"))
+                .line(Some(12))
+                .build(),
+        );
+        assert!(line.ends_with("WARN  [core] hello (src/core/event.rs:12)"), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn test_levels_are_independent() {
+        let dir = tempdir().unwrap();
+        let mut config = config(dir.path(), 1 << 20, 1);
+        config.file_level = Level::Warn;
+        let path = config.file_path.clone();
+        let logger = Logger::new(config.clone()).unwrap();
+
+        log_line(&logger, Level::Info, "not in file");
+        log_line(&logger, Level::Warn, "in file");
+        assert_eq!(line_count(&path), 1);
+
+        config.file_level = Level::Trace;
+        logger.update_config(config).await.unwrap();
+        log_line(&logger, Level::Debug, "now in file");
+        assert_eq!(line_count(&path), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_logging() {
+        let dir = tempdir().unwrap();
+        let config = config(dir.path(), 4096, 50);
+        let path = config.file_path.clone();
+        let logger = Logger::new(config).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|thread| {
+                let logger = logger.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        log_line(&logger, Level::Info, &format!("thread {} line {}", thread, i));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        logger.flush();
+
+        let mut total = line_count(&path);
+        for index in 1..=50 {
+            total += line_count(&Logger::backup_path(&path, index));
+        }
+        assert_eq!(total, 800);
+        assert_eq!(logger.recent_entries().len(), 800);
+    }
+
+    #[tokio::test]
+    async fn test_plugin_level_override_and_json_sink() {
+        let dir = tempdir().unwrap();
+        let mut config = config(dir.path(), 1 << 20, 1);
+        config.file_level = Level::Info;
+        config.json_path = Some(dir.path().join("eventghost.jsonl"));
+        config
+            .target_levels
+            .insert(LogTarget::Plugin("MQTT".into()).as_target(), LevelFilter::Debug);
+        let logger = Logger::new(config.clone()).unwrap();
+
+        log_line(&logger, Level::Debug, "core debug is filtered");
+        logger.log(
+            &Record::builder()
+                .args(format_args!("mqtt debug is kept"))
+                .level(Level::Debug)
+                .target("plugin::MQTT")
+                .build(),
+        );
+        logger.flush();
+
+        assert_eq!(line_count(&config.file_path), 1);
+        let json = fs::read_to_string(config.json_path.as_ref().unwrap()).unwrap();
+        let entry: LogEntry = serde_json::from_str(json.lines().next().unwrap()).unwrap();
+        assert_eq!(entry.message, "mqtt debug is kept");
+        assert_eq!(entry.metadata["plugin"], "MQTT");
+
+        let stored = logger.store().query(&LogQuery {
+            targets: vec!["plugin::MQTT".into()],
+            ..Default::default()
+        });
+        assert_eq!(stored.len(), 1);
+        assert_eq!(config.max_level(), LevelFilter::Debug);
+    }
+}
@@ -0,0 +1,302 @@
+//! In-memory store of recent log entries
+//!
+//! Keeps the newest entries in arrival order with a per-target index, and
+//! answers [`LogQuery`] filters for the GUI log pane and IPC clients.
+//! Subscribers receive every new entry as it is stored.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use log::Level;
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use super::LogEntry;
+
+/// Default number of entries kept in memory
+pub const DEFAULT_STORE_CAPACITY: usize = 10_000;
+
+/// A stored entry with its position in the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredLogEntry {
+    /// Monotonic sequence number, usable as a cursor for tailing
+    pub seq: u64,
+    /// The entry itself
+    #[serde(flatten)]
+    pub entry: LogEntry,
+}
+
+/// Filter for [`LogStore::query`]; empty fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogQuery {
+    /// Least severe level to include, e.g. `Warn` returns warnings and errors
+    #[serde(default)]
+    pub level: Option<Level>,
+    /// Targets to include; `plugin::` matches every plugin target
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// Only entries at or after this time
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries before this time
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+    /// Only entries with a sequence number greater than this
+    #[serde(default)]
+    pub after_seq: Option<u64>,
+    /// Case-insensitive substring of the message
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Return at most this many of the newest matches
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// True if `entry` passes every filter except `limit`
+    pub fn matches(&self, stored: &StoredLogEntry) -> bool {
+        let entry = &stored.entry;
+        self.level.is_none_or(|level| entry.level <= level)
+            && (self.targets.is_empty() || self.targets.iter().any(|t| target_matches(&entry.target, t)))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp < until)
+            && self.after_seq.is_none_or(|seq| stored.seq > seq)
+            && self.text.as_ref().is_none_or(|text| {
+                entry.message.to_lowercase().contains(&text.to_lowercase())
+            })
+    }
+}
+
+/// `plugin::MQTT` matches the pattern `plugin::MQTT` and `plugin::`,
+/// `core::event` matches `core`.
+fn target_matches(target: &str, pattern: &str) -> bool {
+    target == pattern
+        || (pattern.ends_with("::") && target.starts_with(pattern))
+        || target
+            .strip_prefix(pattern)
+            .is_some_and(|rest| rest.starts_with("::"))
+}
+
+struct Inner {
+    entries: VecDeque<StoredLogEntry>,
+    by_target: HashMap<String, VecDeque<u64>>,
+    next_seq: u64,
+}
+
+/// Bounded, indexed store of recent log entries
+pub struct LogStore {
+    capacity: usize,
+    inner: Mutex<Inner>,
+    events: broadcast::Sender<StoredLogEntry>,
+}
+
+impl Default for LogStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_STORE_CAPACITY)
+    }
+}
+
+impl LogStore {
+    /// Create a store keeping at most `capacity` entries
+    pub fn new(capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(256);
+        Self {
+            capacity: capacity.max(1),
+            inner: Mutex::new(Inner {
+                entries: VecDeque::new(),
+                by_target: HashMap::new(),
+                next_seq: 1,
+            }),
+            events,
+        }
+    }
+
+    /// Add an entry, evicting the oldest one when full
+    pub fn push(&self, entry: LogEntry) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+
+        if inner.entries.len() >= self.capacity {
+            if let Some(evicted) = inner.entries.pop_front() {
+                let now_empty = match inner.by_target.get_mut(&evicted.entry.target) {
+                    Some(seqs) => {
+                        seqs.pop_front();
+                        seqs.is_empty()
+                    }
+                    None => false,
+                };
+                if now_empty {
+                    inner.by_target.remove(&evicted.entry.target);
+                }
+            }
+        }
+
+        inner.by_target.entry(entry.target.clone()).or_default().push_back(seq);
+        let stored = StoredLogEntry { seq, entry };
+        inner.entries.push_back(stored.clone());
+        drop(inner);
+
+        // No subscribers is fine
+        let _ = self.events.send(stored);
+        seq
+    }
+
+    /// Number of stored entries
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// True if nothing is stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Targets that currently have entries, sorted
+    pub fn targets(&self) -> Vec<String> {
+        let mut targets: Vec<String> = self.inner.lock().unwrap().by_target.keys().cloned().collect();
+        targets.sort();
+        targets
+    }
+
+    /// Matching entries, oldest first
+    pub fn query(&self, query: &LogQuery) -> Vec<StoredLogEntry> {
+        let inner = self.inner.lock().unwrap();
+        let first_seq = match inner.entries.front() {
+            Some(first) => first.seq,
+            None => return Vec::new(),
+        };
+
+        // Target filters are answered from the index, including sub-targets
+        // such as `core::event` for `core`
+        let indexed: Option<Vec<&VecDeque<u64>>> = if query.targets.is_empty() {
+            None
+        } else {
+            Some(
+                inner
+                    .by_target
+                    .iter()
+                    .filter(|(target, _)| query.targets.iter().any(|t| target_matches(target, t)))
+                    .map(|(_, seqs)| seqs)
+                    .collect(),
+            )
+        };
+
+        let mut matches: Vec<StoredLogEntry> = match indexed {
+            Some(targets) => {
+                let mut seqs: Vec<u64> = targets
+                    .into_iter()
+                    .flatten()
+                    .copied()
+                    .filter(|seq| query.after_seq.is_none_or(|after| *seq > after))
+                    .collect();
+                seqs.sort_unstable();
+                seqs.into_iter()
+                    .filter_map(|seq| inner.entries.get((seq - first_seq) as usize))
+                    .filter(|stored| query.matches(stored))
+                    .cloned()
+                    .collect()
+            }
+            None => {
+                let start = query
+                    .after_seq
+                    .map_or(0, |after| (after + 1).saturating_sub(first_seq) as usize);
+                inner
+                    .entries
+                    .iter()
+                    .skip(start)
+                    .filter(|stored| query.matches(stored))
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        if let Some(limit) = query.limit {
+            let excess = matches.len().saturating_sub(limit);
+            matches.drain(..excess);
+        }
+        matches
+    }
+
+    /// Receive every entry stored from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<StoredLogEntry> {
+        self.events.subscribe()
+    }
+
+    /// Remove all entries
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.by_target.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(level: Level, target: &str, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: Utc::now(),
+            level,
+            target: target.to_string(),
+            message: message.to_string(),
+            metadata: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let store = LogStore::new(100);
+        store.push(entry(Level::Info, "core", "started"));
+        store.push(entry(Level::Debug, "plugin::MQTT", "connected"));
+        let warn_seq = store.push(entry(Level::Warn, "plugin::MQTT", "reconnecting"));
+        store.push(entry(Level::Error, "plugin::Serial", "port closed"));
+
+        let warnings = store.query(&LogQuery { level: Some(Level::Warn), ..Default::default() });
+        assert_eq!(warnings.len(), 2);
+
+        let mqtt = store.query(&LogQuery { targets: vec!["plugin::MQTT".into()], ..Default::default() });
+        assert_eq!(mqtt.iter().map(|e| e.entry.message.as_str()).collect::<Vec<_>>(), ["connected", "reconnecting"]);
+
+        let plugins = store.query(&LogQuery { targets: vec!["plugin::".into()], ..Default::default() });
+        assert_eq!(plugins.len(), 3);
+
+        let tail = store.query(&LogQuery { after_seq: Some(warn_seq), ..Default::default() });
+        assert_eq!(tail.len(), 1);
+        assert_eq!(tail[0].entry.target, "plugin::Serial");
+
+        let last = store.query(&LogQuery { limit: Some(1), text: Some("CONNECT".into()), ..Default::default() });
+        assert_eq!(last[0].entry.message, "reconnecting");
+
+        let future = store.query(&LogQuery { since: Some(Utc::now() + chrono::Duration::hours(1)), ..Default::default() });
+        assert!(future.is_empty());
+    }
+
+    #[test]
+    fn test_capacity_evicts_oldest() {
+        let store = LogStore::new(3);
+        for i in 0..5 {
+            store.push(entry(Level::Info, if i % 2 == 0 { "core" } else { "gui" }, &i.to_string()));
+        }
+
+        let all = store.query(&LogQuery::default());
+        assert_eq!(all.iter().map(|e| e.seq).collect::<Vec<_>>(), [3, 4, 5]);
+        let core = store.query(&LogQuery { targets: vec!["core".into()], ..Default::default() });
+        assert_eq!(core.iter().map(|e| e.seq).collect::<Vec<_>>(), [3, 5]);
+        assert_eq!(store.targets(), ["core", "gui"]);
+    }
+
+    #[test]
+    fn test_parent_target_includes_children() {
+        let store = LogStore::new(10);
+        store.push(entry(Level::Info, "core", "starting"));
+        store.push(entry(Level::Info, "core::event", "dispatched"));
+        store.push(entry(Level::Info, "corelib", "unrelated"));
+        store.push(entry(Level::Info, "core::event::bus", "published"));
+
+        let core = store.query(&LogQuery { targets: vec!["core".into()], ..Default::default() });
+        assert_eq!(core.iter().map(|e| e.entry.message.as_str()).collect::<Vec<_>>(), ["starting", "dispatched", "published"]);
+        let event = store.query(&LogQuery { targets: vec!["core::event".into()], ..Default::default() });
+        assert_eq!(event.len(), 2);
+    }
+}
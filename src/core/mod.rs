@@ -1,20 +1,27 @@
 //! Core functionality for EventGhost
 //! 
 //! This module provides the core systems including:
-//! - Event system
+//! - Event system and dispatch to plugins and macros
 //! - Plugin system with hot-reloading
-//! - IPC via named pipes
+//! - IPC via Unix sockets or named pipes
+//! - HTTP REST and WebSocket control API
 //! - GUI abstractions
 //! - Configuration persistence
 //! - Logging system
 //! - Error handling
 
 pub mod config;
+pub mod constants;
 pub mod event;
+pub mod dispatch;
 pub mod plugin;
 pub mod error;
 pub mod logging;
-pub mod named_pipe;
+pub mod ipc;
+pub mod api;
+pub mod init;
+pub mod instance;
+pub mod utils;
 
 pub use error::Error;
 pub use config::Config;
@@ -29,10 +36,17 @@ pub use plugin::PluginInfo;
 pub use event::Event;
 pub use event::EventManager;
 pub use event::EventHandler;
+pub use event::{EventBus, NamedEvent};
 
 // Re-export config types
 pub use config::ConfigStore;
 pub use config::ConfigError;
+pub use config::ConfigManager;
+pub use config::FileConfigStore;
+pub use config::{ConfigLayer, ConfigLoader, LayeredConfig};
+
+// IPC types
+pub use ipc::{IpcClient, IpcEndpoint, IpcError, IpcServer, Transport};
 
 // TODO: Phase 2 - Advanced plugin functionality
 // pub use plugin::manager::PluginManager;
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::sync::RwLock;
 use std::sync::Arc;
 // use crate::core::Error;
@@ -31,6 +31,11 @@ impl PluginLoader {
         })
     }
 
+    /// Directory plugins are loaded from
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
     pub async fn load(&mut self) -> Result<(), LoaderError> {
         // Phase 1: Basic loading structure
         // TODO: Implement actual plugin loading
@@ -2,11 +2,13 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use serde_json::Value;
 use uuid::Uuid;
 // use crate::core::Error;
 use crate::core::config::Config;
-use super::traits::{Plugin, PluginInfo };
-// PluginState
+use crate::core::event::Event;
+use crate::eg::action::item::ActionItem;
+use super::traits::{Plugin, PluginInfo, PluginState};
 use super::loader::{PluginLoader, LoaderError};
 // use crate::core::error::{RegistryError};
 // use thiserror::Error;
@@ -43,16 +45,19 @@ impl From<LoaderError> for RegistryError {
     }
 }
 
+/// A registered plugin, shared between the registry and its callers
+pub type PluginHandle = Arc<RwLock<Box<dyn Plugin>>>;
+
 /// Registry for managing plugin instances
 pub struct PluginRegistry {
     /// Loaded plugins
-    plugins: Arc<RwLock<Vec<Arc<RwLock<Box<dyn Plugin>>>>>>,
+    plugins: Arc<RwLock<Vec<PluginHandle>>>,
     /// Plugin loader
-    loader: PluginLoader,
+    loader: RwLock<PluginLoader>,
     /// Plugin configurations
     configs: Arc<RwLock<HashMap<Uuid, Config>>>,
     /// Plugin directory
-    plugin_dir: PathBuf,
+    plugin_dir: RwLock<PathBuf>,
 }
 
 impl PluginRegistry {
@@ -60,12 +65,25 @@ impl PluginRegistry {
     pub fn new(plugin_dir: PathBuf) -> Result<Self, RegistryError> {
         Ok(Self {
             plugins: Arc::new(RwLock::new(Vec::new())),
-            loader: PluginLoader::new(plugin_dir.clone())?,
+            loader: RwLock::new(PluginLoader::new(plugin_dir.clone())?),
             configs: Arc::new(RwLock::new(HashMap::new())),
-            plugin_dir,
+            plugin_dir: RwLock::new(plugin_dir),
         })
     }
 
+    /// Register an already constructed plugin instance
+    pub async fn register(&self, plugin: Box<dyn Plugin>) -> Result<Uuid, RegistryError> {
+        let id = plugin.get_info().id;
+        let mut plugins = self.plugins.write().await;
+        for existing in plugins.iter() {
+            if existing.read().await.get_info().id == id {
+                return Err(RegistryError::AlreadyExists(id));
+            }
+        }
+        plugins.push(Arc::new(RwLock::new(plugin)));
+        Ok(id)
+    }
+
     /// Load a plugin from a file
     pub async fn load_plugin(&self, path: PathBuf) -> Result<Uuid, RegistryError> {
         // print the unused var path
@@ -83,46 +101,197 @@ impl PluginRegistry {
 
 
     /// Get a plugin by ID
-    pub async fn get_plugin(&self, id: Uuid) -> Result<Arc<RwLock<Box<dyn Plugin>>>, RegistryError> {
+    pub async fn get_plugin(&self, id: Uuid) -> Result<PluginHandle, RegistryError> {
+        for plugin in self.plugins.read().await.iter() {
+            if plugin.read().await.get_info().id == id {
+                return Ok(plugin.clone());
+            }
+        }
         Err(RegistryError::NotFound(id.to_string()))
     }
 
+    /// True if a plugin matching `id` by name or UUID is registered
+    pub async fn contains(&self, id: &str) -> bool {
+        for plugin in self.plugins.read().await.iter() {
+            if matches(&plugin.read().await.get_info(), id) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Stop and unregister every plugin matching `id` by name or UUID
+    ///
+    /// The plugins are unregistered even if they fail to stop; the first
+    /// failure is returned.
+    pub async fn remove(&self, id: &str) -> Result<(), RegistryError> {
+        let mut first_error = None;
+        let mut plugins = self.plugins.write().await;
+        let mut kept = Vec::with_capacity(plugins.len());
+        for handle in plugins.drain(..) {
+            let info = handle.read().await.get_info();
+            if !matches(&info, id) {
+                kept.push(handle);
+                continue;
+            }
+            if let Err(e) = stop(handle.write().await.as_mut()).await {
+                log::warn!("{}", e);
+                first_error.get_or_insert(e);
+            }
+            self.configs.write().await.remove(&info.id);
+        }
+        *plugins = kept;
+        first_error.map_or(Ok(()), Err)
+    }
+
     /// Get all loaded plugins
     pub async fn get_plugins(&self) -> Vec<PluginInfo> {
-        // TODO: Implement plugin listing
-        unimplemented!()
+        let mut infos = Vec::new();
+        for plugin in self.plugins.read().await.iter() {
+            infos.push(plugin.read().await.get_info());
+        }
+        infos
     }
 
-    /// Start a plugin
-    pub async fn start_plugin(&self, id: Uuid) -> Result<(), RegistryError> {
-        // print the unused var id
-        println!("Starting plugin: {:?}", id);
-        Ok(())
+    /// Info and current state of every loaded plugin
+    pub async fn get_plugin_states(&self) -> Vec<(PluginInfo, PluginState)> {
+        let mut states = Vec::new();
+        for plugin in self.plugins.read().await.iter() {
+            let plugin = plugin.read().await;
+            states.push((plugin.get_info(), plugin.get_state()));
+        }
+        states
     }
 
+    /// Start a plugin, initializing it first if needed
+    pub async fn start_plugin(&self, id: Uuid) -> Result<(), RegistryError> {
+        let plugin = self.get_plugin(id).await?;
+        let mut plugin = plugin.write().await;
+        start(plugin.as_mut()).await
+    }
 
     /// Stop a plugin
     pub async fn stop_plugin(&self, id: Uuid) -> Result<(), RegistryError> {
-        // print the unused var id
-        println!("Stopping plugin: {:?}", id);
-        Ok(())
+        let plugin = self.get_plugin(id).await?;
+        let mut plugin = plugin.write().await;
+        stop(plugin.as_mut()).await
+    }
+
+    /// Start every registered plugin.
+    ///
+    /// A plugin that fails to start does not keep the others from starting;
+    /// the first failure is returned.
+    pub async fn start_all(&self) -> Result<(), RegistryError> {
+        let mut first_error = None;
+        for plugin in self.plugins.read().await.iter() {
+            let mut plugin = plugin.write().await;
+            if let Err(e) = start(plugin.as_mut()).await {
+                log::warn!("{}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Stop every running plugin; the first failure is returned
+    pub async fn stop_all(&self) -> Result<(), RegistryError> {
+        let mut first_error = None;
+        for plugin in self.plugins.read().await.iter() {
+            let mut plugin = plugin.write().await;
+            if let Err(e) = stop(plugin.as_mut()).await {
+                log::warn!("{}", e);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
     }
 
+    /// Pass an event to every running plugin
+    pub async fn dispatch_event(&self, event: &dyn Event) {
+        for plugin in self.plugins.read().await.iter() {
+            let mut plugin = plugin.write().await;
+            if plugin.get_state() != PluginState::Running {
+                continue;
+            }
+            if let Err(e) = plugin.handle_event(event).await {
+                log::warn!("Plugin {} failed to handle {}: {}", plugin.get_name(), event.get_id(), e);
+            }
+        }
+    }
+
+    /// Create the action `name` of the plugin called `plugin`, e.g. for the
+    /// tree call `MQTT.Publish({...})`
+    pub async fn create_action(&self, plugin: &str, name: &str, args: &Value) -> Result<ActionItem, RegistryError> {
+        for handle in self.plugins.read().await.iter() {
+            let candidate = handle.read().await;
+            if candidate.get_name() != plugin {
+                continue;
+            }
+            return match candidate.create_action(name, args) {
+                Some(action) => action.map_err(|e| RegistryError::Plugin(e.to_string())),
+                None => Err(RegistryError::NotFound(format!("{}.{}", plugin, name))),
+            };
+        }
+        Err(RegistryError::NotFound(plugin.to_string()))
+    }
 
     /// Update plugin configuration
     pub async fn update_plugin_config(&self, id: Uuid, config: Config) -> Result<(), RegistryError> {
-        // print the unused var config
-        println!("Updating plugin configuration: {:?}", config);
+        log::debug!("Updating plugin configuration: {:?}", config.masked());
         // print the unused var id
         println!("ID: {:?}", id);
         Ok(())
     }
 
 
-    /// Get plugin configuration
+    /// Push a changed configuration to the plugins named in `plugin_ids`.
+    ///
+    /// Ids are matched against the plugin name or its UUID, the same way
+    /// `PluginConfig::id` refers to plugins. Every matching plugin is
+    /// updated even if an earlier one fails; the first failure is returned.
+    pub async fn apply_config(&self, config: &Config, plugin_ids: &[String]) -> Result<(), RegistryError> {
+        let mut first_error = None;
+        for plugin in self.plugins.read().await.iter() {
+            let mut plugin = plugin.write().await;
+            let info = plugin.get_info();
+            if !plugin_ids.iter().any(|id| matches(&info, id)) {
+                continue;
+            }
 
+            match plugin.update_config(config.clone()).await {
+                Ok(()) => {
+                    self.configs.write().await.insert(info.id, config.clone());
+                }
+                Err(e) => {
+                    log::warn!("Plugin {} rejected the new configuration: {}", info.name, e);
+                    first_error.get_or_insert(RegistryError::Plugin(format!("{}: {}", info.name, e)));
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Get plugin configuration
     pub async fn get_plugin_config(&self, id: Uuid) -> Result<Config, RegistryError> {
-        Err(RegistryError::NotFound(id.to_string()))
+        self.configs
+            .read()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| RegistryError::NotFound(id.to_string()))
+    }
+
+    /// Directory plugins are loaded from
+    pub async fn plugin_dir(&self) -> PathBuf {
+        self.plugin_dir.read().await.clone()
+    }
+
+    /// Switch to a different plugin directory; plugins already registered
+    /// stay loaded
+    pub async fn set_plugin_dir(&self, plugin_dir: PathBuf) -> Result<(), RegistryError> {
+        *self.loader.write().await = PluginLoader::new(plugin_dir.clone())?;
+        *self.plugin_dir.write().await = plugin_dir;
+        Ok(())
     }
 
     pub async fn load_all(&self) -> Result<(), RegistryError> {
@@ -134,6 +303,36 @@ impl PluginRegistry {
     }
 }
 
+/// True if `id` is the plugin's name or UUID, the two ways
+/// `PluginConfig::id` refers to plugins
+fn matches(info: &PluginInfo, id: &str) -> bool {
+    info.name == id || info.id.to_string() == id
+}
+
+async fn start(plugin: &mut dyn Plugin) -> Result<(), RegistryError> {
+    if plugin.get_state() == PluginState::Running {
+        return Ok(());
+    }
+    let mut result = Ok(());
+    if plugin.get_state() == PluginState::Created {
+        result = plugin.initialize().await;
+    }
+    if result.is_ok() {
+        result = plugin.start().await;
+    }
+    result.map_err(|e| RegistryError::Plugin(format!("{} failed to start: {}", plugin.get_name(), e)))
+}
+
+async fn stop(plugin: &mut dyn Plugin) -> Result<(), RegistryError> {
+    if plugin.get_state() != PluginState::Running {
+        return Ok(());
+    }
+    plugin
+        .stop()
+        .await
+        .map_err(|e| RegistryError::Plugin(format!("{} failed to stop: {}", plugin.get_name(), e)))
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
@@ -4,6 +4,7 @@ use uuid::Uuid;
 use serde::{Serialize, Deserialize};
 use crate::core::{Error, Event};
 use crate::core::config::Config;
+use crate::eg::action::item::ActionItem;
 
 /// Metadata about a plugin
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -91,6 +92,14 @@ pub trait Plugin: Send + Sync {
     
     /// Update plugin configuration
     async fn update_config(&mut self, config: Config) -> Result<(), Error>;
+
+    /// Create the action `name` for a tree call such as
+    /// `MQTT.Publish({"topic": "home/tv"})`, configured by the JSON `args`;
+    /// `None` if the plugin has no action of that name
+    fn create_action(&self, name: &str, args: &serde_json::Value) -> Option<Result<ActionItem, Error>> {
+        let _ = (name, args);
+        None
+    }
     
     /// Get plugin state as Any for downcasting
     fn as_any(&self) -> &dyn Any;
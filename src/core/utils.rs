@@ -1,4 +1,5 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use crate::core::constants::{DEFAULT_CONFIG_PATH, DEFAULT_KEYSTORE_PATH, DEFAULT_LOG_PATH, DEFAULT_MASTER_KEY_PATH};
 use crate::core::Error;
 
 pub fn ensure_directory(path: &Path) -> Result<(), Error> {
@@ -8,30 +9,67 @@ pub fn ensure_directory(path: &Path) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn get_app_data_dir() -> Result<std::path::PathBuf, Error> {
+pub fn get_app_data_dir() -> Result<PathBuf, Error> {
     let app_data = dirs::data_local_dir()
         .ok_or_else(|| Error::Config("Could not determine app data directory".into()))?;
     Ok(app_data.join("EventGhost"))
 }
 
-pub fn get_config_dir() -> Result<std::path::PathBuf, Error> {
-    let config_dir = get_app_data_dir()?.join("config");
-    ensure_directory(&config_dir)?;
-    Ok(config_dir)
+/// Per-user config directory; not created here but by whatever first
+/// writes a file into it, so reading settings leaves the disk alone
+pub fn get_config_dir() -> Result<PathBuf, Error> {
+    Ok(get_app_data_dir()?.join("config"))
 }
 
-pub fn get_plugin_dirs() -> Result<Vec<std::path::PathBuf>, Error> {
-    let mut dirs = vec![];
-    
-    // Add built-in plugins directory
+/// Settings file in the per-user config directory
+pub fn get_user_config_path() -> Result<PathBuf, Error> {
+    Ok(get_config_dir()?.join(DEFAULT_CONFIG_PATH))
+}
+
+/// Encrypted keystore for secret plugin settings
+pub fn get_keystore_path() -> Result<PathBuf, Error> {
+    Ok(get_config_dir()?.join(DEFAULT_KEYSTORE_PATH))
+}
+
+/// Master key file used when the keystore has no passphrase
+pub fn get_master_key_path() -> Result<PathBuf, Error> {
+    Ok(get_config_dir()?.join(DEFAULT_MASTER_KEY_PATH))
+}
+
+/// Log file in the per-user data directory; the directory is created when
+/// the file is opened
+pub fn get_log_path() -> Result<PathBuf, Error> {
+    Ok(get_app_data_dir()?.join("logs").join(DEFAULT_LOG_PATH))
+}
+
+/// Machine-wide settings file shared by all users
+pub fn get_system_config_path() -> PathBuf {
+    if cfg!(windows) {
+        let program_data = std::env::var_os("PROGRAMDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"));
+        program_data.join("EventGhost").join(DEFAULT_CONFIG_PATH)
+    } else {
+        PathBuf::from("/etc/eventghost").join(DEFAULT_CONFIG_PATH)
+    }
+}
+
+/// Plugins shipped next to the executable
+pub fn get_builtin_plugin_dir() -> Result<PathBuf, Error> {
     let app_dir = std::env::current_exe()?
         .parent()
         .ok_or_else(|| Error::Config("Could not determine application directory".into()))?
         .to_path_buf();
-    dirs.push(app_dir.join("plugins"));
+    Ok(app_dir.join("plugins"))
+}
 
-    // Add user plugins directory
-    dirs.push(get_app_data_dir()?.join("plugins"));
+/// Plugins installed by the user; the default for `GlobalConfig::plugin_dir`
+pub fn get_user_plugin_dir() -> Result<PathBuf, Error> {
+    Ok(get_app_data_dir()?.join("plugins"))
+}
+
+pub fn get_plugin_dirs() -> Result<Vec<PathBuf>, Error> {
+    let dirs = vec![get_builtin_plugin_dir()?, get_user_plugin_dir()?];
 
     // Ensure all directories exist
     for dir in &dirs {
@@ -43,4 +81,4 @@ pub fn get_plugin_dirs() -> Result<Vec<std::path::PathBuf>, Error> {
 
 pub fn format_error(error: &Error) -> String {
     format!("{:#}", error)
-} 
\ No newline at end of file
+}
@@ -4,7 +4,6 @@ use uuid::Uuid;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use super::base::ActionBase;
-use async_trait::async_trait;
 
 /// A group of actions that can be executed together
 pub struct ActionGroup {
@@ -2,16 +2,21 @@ use crate::core::Error;
 use crate::core::event::Event;
 use uuid::Uuid;
 use std::sync::Arc;
-use super::base::ActionBase;
+use super::base::{ActionBase, ActionError};
 // use async_trait::async_trait;
 
+/// Callback run when an [`ActionItem`] executes
+///
+/// Handlers run on the blocking thread pool, so they may wait on I/O.
+pub type ActionHandler = Arc<dyn Fn(&dyn Event) -> Result<(), Error> + Send + Sync>;
+
 /// A single action that can be executed
 pub struct ActionItem {
     id: Uuid,
     name: String,
     description: String,
     plugin_id: Uuid,
-    handler: Arc<dyn Fn(&dyn Event) -> Result<(), Error> + Send + Sync>,
+    handler: ActionHandler,
 }
 
 impl ActionItem {
@@ -30,6 +35,12 @@ impl ActionItem {
             handler: Arc::new(handler),
         }
     }
+
+    /// What the action does, e.g. to attach it to an
+    /// [`ActionCall`](crate::eg::tree::ActionCall) in the tree
+    pub fn handler(&self) -> ActionHandler {
+        self.handler.clone()
+    }
 }
 
 #[async_trait::async_trait]
@@ -51,7 +62,11 @@ impl ActionBase for ActionItem {
     }
     
     async fn execute(&mut self, event: &dyn Event) -> Result<(), Error> {
-        (self.handler)(event)
+        let handler = self.handler.clone();
+        let event = event.clone_event();
+        tokio::task::spawn_blocking(move || handler(event.as_ref()))
+            .await
+            .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?
     }
     
     fn can_execute(&self, event: Option<&dyn Event>) -> bool {
@@ -33,6 +33,11 @@ impl Bunch {
         self.values.remove(key)
     }
 
+    /// Names of all properties, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
     pub fn contains_key(&self, key: &str) -> bool {
         self.values.contains_key(key)
     }
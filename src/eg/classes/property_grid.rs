@@ -3,6 +3,7 @@ use gtk::{self, Box, TreeView, TreeStore, TreeViewColumn, CellRendererText};
 use gtk::glib;
 use super::UIComponent;
 use crate::core::Error;
+use crate::core::config::SecretString;
 use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -16,6 +17,7 @@ pub enum PropertyValueType {
     Bool,
     Color,
     Enum,
+    Secret,
     Custom
 }
 
@@ -27,6 +29,7 @@ pub enum PropertyValue {
     Bool(bool),
     Color(u32),
     Enum(String, Vec<String>),
+    Secret(SecretString),
     Custom(Arc<dyn Any + Send + Sync>),
 }
 
@@ -39,10 +42,25 @@ impl PropertyValue {
             PropertyValue::Bool(_) => PropertyValueType::Bool,
             PropertyValue::Color(_) => PropertyValueType::Color,
             PropertyValue::Enum(_, _) => PropertyValueType::Enum,
+            PropertyValue::Secret(_) => PropertyValueType::Secret,
             PropertyValue::Custom(_) => PropertyValueType::Custom,
         }
     }
 
+    /// Text shown in the grid; secret values are always masked
+    pub fn display_text(&self) -> String {
+        match self {
+            PropertyValue::String(s) => s.clone(),
+            PropertyValue::Int(i) => i.to_string(),
+            PropertyValue::Float(f) => f.to_string(),
+            PropertyValue::Bool(b) => b.to_string(),
+            PropertyValue::Color(c) => format!("#{:06X}", c),
+            PropertyValue::Enum(selected, _) => selected.clone(),
+            PropertyValue::Secret(secret) => secret.to_string(),
+            PropertyValue::Custom(_) => String::new(),
+        }
+    }
+
     pub fn as_string(&self) -> Option<&String> {
         if let PropertyValue::String(s) = self {
             Some(s)
@@ -210,6 +228,13 @@ impl PropertyGrid {
         ]);
     }
     
+    /// Add a row for a typed property, masking secret values
+    pub fn add_property(&self, property: &Property) {
+        let value = property.get_value();
+        let prop_type = format!("{:?}", value.get_type());
+        self.set_property(property.get_name(), &value.display_text(), &prop_type);
+    }
+
     pub fn clear(&self) {
         self.store.clear();
     }
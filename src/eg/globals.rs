@@ -68,6 +68,13 @@ impl Globals {
         })
     }
 
+    /// Names of all global variables, sorted
+    pub fn var_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.bunch.keys().map(str::to_string).collect();
+        names.sort();
+        names
+    }
+
     /// Remove a global variable
     pub fn remove_var(&mut self, name: &str) {
         self.bunch.remove(name);
@@ -1,6 +1,8 @@
 pub mod action;
 pub mod tree;
+#[cfg(feature = "gui")]
 pub mod classes;
+#[cfg(feature = "gui")]
 pub mod winapi;
 pub mod bunch;
 pub mod globals;
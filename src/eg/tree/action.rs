@@ -0,0 +1,177 @@
+use std::sync::{Arc, RwLock};
+use serde_json::Value;
+use crate::core::Error;
+use crate::core::event::Event;
+use crate::eg::action::item::ActionHandler;
+use super::item::{TreeItem, TreeItemInfo};
+
+/// An action inside a macro, e.g. `Winamp.PlayPause()` or
+/// `MQTT.Publish({"topic": "home/tv", "payload": "{payload}"})`
+///
+/// Loaded trees only know the call text; the handler that carries it out
+/// is attached once the plugin providing it is available. Arguments, if
+/// any, are JSON.
+#[derive(Clone)]
+pub struct ActionCall {
+    info: TreeItemInfo,
+    call: String,
+    handler: Option<ActionHandler>,
+}
+
+impl ActionCall {
+    pub fn new(call: &str) -> Self {
+        Self {
+            info: TreeItemInfo {
+                id: uuid::Uuid::new_v4(),
+                name: call.to_string(),
+                description: String::new(),
+                enabled: true,
+            },
+            call: call.to_string(),
+            handler: None,
+        }
+    }
+
+    /// Run `handler` when the action executes
+    pub fn with_handler(
+        mut self,
+        handler: impl Fn(&dyn Event) -> Result<(), Error> + Send + Sync + 'static,
+    ) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// Attach the handler of the plugin action this call names
+    pub fn set_handler(&mut self, handler: ActionHandler) {
+        self.handler = Some(handler);
+    }
+
+    pub fn has_handler(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    pub fn get_call(&self) -> &str {
+        &self.call
+    }
+
+    /// Plugin the call belongs to, the part before the first dot
+    pub fn get_plugin(&self) -> Option<&str> {
+        self.call.split_once('.').map(|(plugin, _)| plugin)
+    }
+
+    /// Action of the plugin, between the first dot and the arguments
+    pub fn get_action(&self) -> Option<&str> {
+        let (_, rest) = self.call.split_once('.')?;
+        Some(rest.split_once('(').map_or(rest, |(action, _)| action).trim())
+    }
+
+    /// Arguments between the parentheses as JSON; `null` if there are none
+    pub fn get_args(&self) -> Result<Value, Error> {
+        let Some((_, rest)) = self.call.split_once('(') else {
+            return Ok(Value::Null);
+        };
+        let args = rest
+            .trim_end()
+            .strip_suffix(')')
+            .ok_or_else(|| Error::Tree(format!("{}: missing ')'", self.call)))?
+            .trim();
+        if args.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(args).map_err(|e| Error::Tree(format!("{}: arguments are not JSON: {}", self.call, e)))
+    }
+}
+
+impl std::fmt::Debug for ActionCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ActionCall")
+            .field("info", &self.info)
+            .field("call", &self.call)
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+impl TreeItem for ActionCall {
+    fn get_id(&self) -> uuid::Uuid {
+        self.info.id
+    }
+
+    fn get_name(&self) -> &str {
+        &self.info.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.info.name = name.to_string();
+    }
+
+    fn get_description(&self) -> &str {
+        &self.info.description
+    }
+
+    fn set_description(&mut self, description: &str) {
+        self.info.description = description.to_string();
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.info.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.info.enabled = enabled;
+    }
+
+    fn execute(&mut self, event: Option<&dyn Event>) -> Result<(), Error> {
+        match (&self.handler, event) {
+            (Some(handler), Some(event)) => handler(event),
+            (Some(handler), None) => {
+                let event = crate::core::event::NamedEvent::new(
+                    &self.call,
+                    crate::core::event::EventType::User,
+                    crate::core::event::EventPayload::None,
+                );
+                handler(&event)
+            }
+            (None, _) => {
+                log::warn!("No handler for action {}", self.call);
+                Ok(())
+            }
+        }
+    }
+
+    fn can_execute(&self, _event: Option<&dyn Event>) -> bool {
+        self.is_enabled()
+    }
+
+    fn clone_item(&self) -> Arc<RwLock<dyn TreeItem>> {
+        Arc::new(RwLock::new(self.clone()))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_call_parts() {
+        let call = ActionCall::new(r#"MQTT.Publish({"topic": "home/tv", "payload": "(on)"})"#);
+        assert_eq!(call.get_plugin(), Some("MQTT"));
+        assert_eq!(call.get_action(), Some("Publish"));
+        assert_eq!(call.get_args().unwrap(), json!({"topic": "home/tv", "payload": "(on)"}));
+
+        assert_eq!(ActionCall::new("Winamp.Play()").get_args().unwrap(), Value::Null);
+        assert_eq!(ActionCall::new("Winamp.Play").get_action(), Some("Play"));
+        assert!(ActionCall::new("Winamp.Play(").get_args().is_err());
+        assert!(ActionCall::new("Winamp.Play(volume=3)").get_args().is_err());
+        assert_eq!(ActionCall::new("Play()").get_action(), None);
+    }
+}
@@ -1,8 +1,13 @@
 use std::sync::{Arc, RwLock};
 use std::path::PathBuf;
 use crate::core::Error;
+use crate::core::event::Event;
+use super::action::ActionCall;
+use super::file::{NodeKind, TreeFile, TreeNode};
 use super::root::Root;
 use super::item::TreeItem;
+use super::folder::Folder;
+use super::macro_::Macro_;
 
 #[derive(Debug)]
 pub struct Document {
@@ -57,19 +62,92 @@ impl Document {
         self.save()
     }
 
+    /// Replace the tree with the contents of a tree file or legacy `.egtree`
     pub fn load(&mut self, path: PathBuf) -> Result<(), Error> {
-        // TODO: Implement load logic
+        let file = TreeFile::load(&path)?;
+        let mut root = Root::new();
+        for item in build_items(&file.items) {
+            root.add_child(item);
+        }
+        *self.root.write().map_err(|_| Error::Tree("Document tree is poisoned".into()))? = root;
         self.file_path = Some(path);
         self.is_modified = false;
         Ok(())
     }
 
+    /// Run every enabled macro triggered by `event`
+    pub fn trigger(&self, event: &dyn Event) -> Result<(), Error> {
+        execute_all(&self.triggered_by(event)?, event)
+    }
+
+    /// Enabled items triggered by `event`, looking inside enabled folders
+    ///
+    /// Only the tree is locked while collecting, so the items can be run
+    /// after the document lock is released.
+    pub fn triggered_by(&self, event: &dyn Event) -> Result<Vec<Arc<RwLock<dyn TreeItem>>>, Error> {
+        let root = self.root.read().map_err(|_| Error::Tree("Document tree is poisoned".into()))?;
+        let mut pending: Vec<_> = root.get_children().iter().rev().cloned().collect();
+        let mut triggered = Vec::new();
+        while let Some(item) = pending.pop() {
+            let Ok(guard) = item.read() else { continue };
+            if !guard.is_enabled() {
+                continue;
+            }
+            if let Some(folder) = guard.as_any().downcast_ref::<Folder>() {
+                pending.extend(folder.get_children().iter().rev().cloned());
+            } else if guard.can_execute(Some(event)) {
+                drop(guard);
+                triggered.push(item);
+            }
+        }
+        Ok(triggered)
+    }
+
+    /// Action calls anywhere in the tree that have no handler yet
+    pub fn unbound_actions(&self) -> Vec<Arc<RwLock<dyn TreeItem>>> {
+        let Ok(root) = self.root.read() else {
+            return Vec::new();
+        };
+        let mut pending = root.get_children().to_vec();
+        drop(root);
+        let mut unbound = Vec::new();
+        while let Some(item) = pending.pop() {
+            let is_unbound = item
+                .read()
+                .is_ok_and(|item| item.as_any().downcast_ref::<ActionCall>().is_some_and(|call| !call.has_handler()));
+            if is_unbound {
+                unbound.push(item.clone());
+            }
+            pending.extend(children_of(&item));
+        }
+        unbound
+    }
+
+    /// Find an item anywhere in the tree by ID
     pub fn find_item(&self, id: uuid::Uuid) -> Option<Arc<RwLock<dyn TreeItem>>> {
-        if let Ok(root) = self.root.read() {
-            root.find_item(id)
-        } else {
-            None
+        let mut pending = self.root.read().ok()?.get_children().to_vec();
+        while let Some(item) = pending.pop() {
+            if item.read().ok()?.get_id() == id {
+                return Some(item);
+            }
+            pending.extend(children_of(&item));
         }
+        None
+    }
+
+    /// Find an item by the names leading to it, e.g. `Lights/Evening/Dim`
+    pub fn find_by_path(&self, path: &str) -> Option<Arc<RwLock<dyn TreeItem>>> {
+        let mut children = self.root.read().ok()?.get_children().to_vec();
+        let mut found = None;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let item = children
+                .iter()
+                .find(|child| child.read().is_ok_and(|child| child.get_name() == name))?
+                .clone();
+            children = children_of(&item);
+            found = Some(item);
+        }
+        found
     }
 
     pub fn clear(&mut self) {
@@ -85,4 +163,132 @@ impl Default for Document {
     fn default() -> Self {
         Self::new()
     }
-} 
\ No newline at end of file
+}
+
+/// Tree items for the nodes of a tree file
+///
+/// Plugins are configured in the settings file, so plugin nodes are skipped,
+/// as are events and actions that are not inside a macro.
+fn build_items(nodes: &[TreeNode]) -> Vec<Arc<RwLock<dyn TreeItem>>> {
+    let mut items: Vec<Arc<RwLock<dyn TreeItem>>> = Vec::new();
+    for node in nodes {
+        match node.kind {
+            NodeKind::Autostart | NodeKind::Folder => {
+                let name = if node.name.is_empty() { "Autostart" } else { &node.name };
+                let mut folder = Folder::new(name);
+                folder.set_enabled(node.enabled);
+                for child in build_items(&node.children) {
+                    folder.add_child(child);
+                }
+                items.push(Arc::new(RwLock::new(folder)));
+            }
+            NodeKind::Macro => {
+                let mut macro_ = Macro_::new(&node.name);
+                macro_.set_enabled(node.enabled);
+                for child in &node.children {
+                    match child.kind {
+                        NodeKind::Event if child.enabled => macro_.add_event(&child.name),
+                        NodeKind::Action => {
+                            let mut action = ActionCall::new(child.content.as_deref().unwrap_or(&child.name));
+                            action.set_enabled(child.enabled);
+                            macro_.add_action(Arc::new(RwLock::new(action)));
+                        }
+                        _ => log::debug!("Skipping {:?} {} in macro {}", child.kind, child.name, node.name),
+                    }
+                }
+                items.push(Arc::new(RwLock::new(macro_)));
+            }
+            NodeKind::Plugin | NodeKind::Event | NodeKind::Action => {
+                log::debug!("Skipping {:?} {} outside a macro", node.kind, node.name);
+            }
+        }
+    }
+    items
+}
+
+/// Run `items` in order, stopping at the first failure
+pub fn execute_all(items: &[Arc<RwLock<dyn TreeItem>>], event: &dyn Event) -> Result<(), Error> {
+    for item in items {
+        item.write()
+            .map_err(|_| Error::Tree("Tree item is poisoned".into()))?
+            .execute(Some(event))?;
+    }
+    Ok(())
+}
+
+/// Folders contain items and macros contain actions; everything else is a leaf
+pub(crate) fn children_of(item: &Arc<RwLock<dyn TreeItem>>) -> Vec<Arc<RwLock<dyn TreeItem>>> {
+    let Ok(item) = item.read() else {
+        return Vec::new();
+    };
+    if let Some(folder) = item.as_any().downcast_ref::<Folder>() {
+        folder.get_children().to_vec()
+    } else if let Some(macro_) = item.as_any().downcast_ref::<Macro_>() {
+        macro_.get_actions().to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_nested_items() {
+        let document = Document::new();
+        let macro_: Arc<RwLock<dyn TreeItem>> = Arc::new(RwLock::new(Macro_::new("Dim")));
+        let macro_id = macro_.read().unwrap().get_id();
+        let mut folder = Folder::new("Lights");
+        folder.add_child(macro_);
+        document.get_root().write().unwrap().add_child(Arc::new(RwLock::new(folder)));
+
+        assert_eq!(document.find_by_path("Lights/Dim").unwrap().read().unwrap().get_id(), macro_id);
+        assert!(document.find_by_path("/Lights/Dim/").is_some());
+        assert!(document.find_by_path("Lights/Bright").is_none());
+        assert!(document.find_by_path("").is_none());
+        assert_eq!(document.find_item(macro_id).unwrap().read().unwrap().get_name(), "Dim");
+    }
+
+    #[test]
+    fn test_load_and_trigger() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use crate::core::event::{EventPayload, EventType, NamedEvent};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree.json");
+        std::fs::write(&path, r#"{"version": 1, "items": [{"kind": "folder", "name": "Remote", "children": [
+            {"kind": "macro", "name": "Play", "children": [
+                {"kind": "event", "name": "Remote.Play*"},
+                {"kind": "action", "content": "Winamp.Play()"}
+            ]}
+        ]}]}"#).unwrap();
+
+        let mut document = Document::new();
+        document.load(path).unwrap();
+        let found = document.find_by_path("Remote/Play").unwrap();
+
+        // Loaded actions have no handler yet; swap in one that counts calls
+        let runs = Arc::new(AtomicUsize::new(0));
+        {
+            let mut found = found.write().unwrap();
+            let macro_ = found.as_any_mut().downcast_mut::<Macro_>().unwrap();
+            assert_eq!(macro_.get_events(), ["Remote.Play*"]);
+            let counter = runs.clone();
+            let call = ActionCall::new("Winamp.Play()").with_handler(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            });
+            *macro_.get_actions_mut() = vec![Arc::new(RwLock::new(call))];
+        }
+
+        let event = |name: &str| NamedEvent::new(name, EventType::User, EventPayload::None);
+        document.trigger(&event("Remote.PlayPause")).unwrap();
+        document.trigger(&event("Remote.Stop")).unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        found.write().unwrap().set_enabled(false);
+        document.trigger(&event("Remote.Play")).unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+}
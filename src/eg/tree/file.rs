@@ -0,0 +1,297 @@
+//! Tree file format and import of legacy `.egtree` files
+//!
+//! A tree file stores the configuration tree as nested [`TreeNode`]s in
+//! JSON or TOML, chosen by file extension. Legacy EventGhost saved the same
+//! tree as XML; [`TreeFile::from_legacy_xml`] reads that format, keeping any
+//! attribute it does not map to a field in [`TreeNode::attributes`].
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::core::Error;
+
+/// Current tree file format version
+pub const TREE_FILE_VERSION: u32 = 1;
+
+/// Kind of a tree node, named after the legacy XML elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Autostart,
+    Plugin,
+    Folder,
+    Macro,
+    Event,
+    Action,
+}
+
+impl NodeKind {
+    fn from_element(name: &str) -> Option<Self> {
+        match name {
+            "Autostart" => Some(NodeKind::Autostart),
+            "Plugin" => Some(NodeKind::Plugin),
+            "Folder" => Some(NodeKind::Folder),
+            "Macro" => Some(NodeKind::Macro),
+            "Event" => Some(NodeKind::Event),
+            "Action" => Some(NodeKind::Action),
+            _ => None,
+        }
+    }
+
+    /// Whether nodes of this kind may have children
+    pub fn is_container(&self) -> bool {
+        matches!(self, NodeKind::Autostart | NodeKind::Folder | NodeKind::Macro)
+    }
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// One item of the configuration tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub kind: NodeKind,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default = "enabled", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+    /// Action call such as `Winamp.Play()`, or a plugin's saved arguments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Legacy attributes without a field of their own, e.g. `Guid` or `Expanded`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    pub fn new(kind: NodeKind, name: &str) -> Self {
+        Self {
+            kind,
+            name: name.to_string(),
+            enabled: true,
+            content: None,
+            attributes: BTreeMap::new(),
+            children: Vec::new(),
+        }
+    }
+}
+
+/// A saved configuration tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreeFile {
+    pub version: u32,
+    /// Version of the legacy EventGhost that wrote the original file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legacy_version: Option<String>,
+    #[serde(default)]
+    pub items: Vec<TreeNode>,
+}
+
+impl Default for TreeFile {
+    fn default() -> Self {
+        Self {
+            version: TREE_FILE_VERSION,
+            legacy_version: None,
+            items: Vec::new(),
+        }
+    }
+}
+
+impl TreeFile {
+    /// Whether `path` looks like a legacy XML tree
+    pub fn is_legacy_path(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("egtree") || ext.eq_ignore_ascii_case("xml"))
+    }
+
+    /// Read a tree file, or a legacy `.egtree` file
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)?;
+        let file = if Self::is_legacy_path(path) {
+            Self::from_legacy_xml(&text)?
+        } else if is_toml(path) {
+            toml::from_str(&text).map_err(|e| Error::Tree(format!("{}: {}", path.display(), e.message())))?
+        } else {
+            serde_json::from_str(&text).map_err(|e| Error::Tree(format!("{}: {}", path.display(), e)))?
+        };
+        file.validate()?;
+        Ok(file)
+    }
+
+    /// Write as TOML if `path` ends in `.toml`, JSON otherwise
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let text = if is_toml(path) {
+            toml::to_string_pretty(self).map_err(|e| Error::Tree(e.to_string()))?
+        } else {
+            serde_json::to_string_pretty(self).map_err(|e| Error::Tree(e.to_string()))?
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Parse the XML written by legacy EventGhost
+    pub fn from_legacy_xml(text: &str) -> Result<Self, Error> {
+        let document = roxmltree::Document::parse(text).map_err(|e| Error::Tree(format!("Invalid XML: {}", e)))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "EventGhost" {
+            return Err(Error::Tree(format!(
+                "Expected an <EventGhost> document, found <{}>",
+                root.tag_name().name()
+            )));
+        }
+
+        Ok(Self {
+            version: TREE_FILE_VERSION,
+            legacy_version: root.attribute("Version").map(str::to_string),
+            items: legacy_children(root)?,
+        })
+    }
+
+    /// Check the structure, e.g. that only folders and macros have children
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.version > TREE_FILE_VERSION {
+            return Err(Error::Tree(format!(
+                "version: file format {} is newer than the supported {}",
+                self.version, TREE_FILE_VERSION
+            )));
+        }
+        fn check(nodes: &[TreeNode], path: &str) -> Result<(), Error> {
+            for (index, node) in nodes.iter().enumerate() {
+                let here = format!("{}[{}]", path, index);
+                if !node.children.is_empty() && !node.kind.is_container() {
+                    return Err(Error::Tree(format!("{}: {:?} nodes cannot have children", here, node.kind)));
+                }
+                if node.name.is_empty() && matches!(node.kind, NodeKind::Event) {
+                    return Err(Error::Tree(format!("{}: event name must not be empty", here)));
+                }
+                check(&node.children, &format!("{}.children", here))?;
+            }
+            Ok(())
+        }
+        check(&self.items, "items")
+    }
+
+    /// Number of nodes in the tree
+    pub fn node_count(&self) -> usize {
+        fn count(nodes: &[TreeNode]) -> usize {
+            nodes.iter().map(|node| 1 + count(&node.children)).sum()
+        }
+        count(&self.items)
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+fn legacy_children(element: roxmltree::Node) -> Result<Vec<TreeNode>, Error> {
+    let mut nodes = Vec::new();
+    for child in element.children().filter(|child| child.is_element()) {
+        let tag = child.tag_name().name();
+        let kind = NodeKind::from_element(tag).ok_or_else(|| {
+            let position = child.document().text_pos_at(child.range().start);
+            Error::Tree(format!("Unknown element <{}> at line {}", tag, position.row))
+        })?;
+
+        let mut node = TreeNode::new(kind, "");
+        for attribute in child.attributes() {
+            match attribute.name() {
+                "Name" => node.name = attribute.value().to_string(),
+                "Enabled" => node.enabled = !attribute.value().eq_ignore_ascii_case("false"),
+                name => {
+                    node.attributes.insert(name.to_string(), attribute.value().to_string());
+                }
+            }
+        }
+        // Plugins are named by their identifier
+        if node.name.is_empty() {
+            if let Some(identifier) = node.attributes.get("Identifier") {
+                node.name = identifier.clone();
+            }
+        }
+
+        if kind.is_container() {
+            node.children = legacy_children(child)?;
+        } else {
+            node.content = child
+                .text()
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string);
+        }
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const LEGACY: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<EventGhost Version="0.5.0.1388" Guid="{1A2B}" Time="1700000000.0">
+    <Autostart Name="Autostart" Expanded="True">
+        <Plugin Identifier="System" Guid="{A21F443B-221D-44E4-8596-E1ED7100E0A4}" File="System">
+            gAIpLg==
+        </Plugin>
+    </Autostart>
+    <Folder Name="Remote" Expanded="True">
+        <Macro Name="Play &amp; Pause" Enabled="False">
+            <Event Name="Remote.Play" />
+            <Action>Winamp.PlayPause()</Action>
+        </Macro>
+    </Folder>
+</EventGhost>"#;
+
+    #[test]
+    fn test_legacy_import() {
+        let tree = TreeFile::from_legacy_xml(LEGACY).unwrap();
+        assert_eq!(tree.legacy_version.as_deref(), Some("0.5.0.1388"));
+        assert_eq!(tree.node_count(), 6);
+
+        let plugin = &tree.items[0].children[0];
+        assert_eq!(plugin.name, "System");
+        assert_eq!(plugin.content.as_deref(), Some("gAIpLg=="));
+        assert_eq!(plugin.attributes["File"], "System");
+
+        let macro_ = &tree.items[1].children[0];
+        assert_eq!(macro_.name, "Play & Pause");
+        assert!(!macro_.enabled);
+        assert_eq!(macro_.children[0].kind, NodeKind::Event);
+        assert_eq!(macro_.children[1].content.as_deref(), Some("Winamp.PlayPause()"));
+
+        let err = TreeFile::from_legacy_xml("<EventGhost>\n<Bogus/></EventGhost>").unwrap_err();
+        assert!(err.to_string().contains("<Bogus> at line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_round_trip_json_and_toml() {
+        let dir = tempdir().unwrap();
+        let legacy = dir.path().join("tree.egtree");
+        std::fs::write(&legacy, LEGACY).unwrap();
+        let tree = TreeFile::load(&legacy).unwrap();
+
+        for name in ["tree.json", "tree.toml"] {
+            let path = dir.path().join(name);
+            tree.save(&path).unwrap();
+            assert_eq!(TreeFile::load(&path).unwrap(), tree);
+        }
+    }
+
+    #[test]
+    fn test_validate_rejects_children_of_leaves() {
+        let mut event = TreeNode::new(NodeKind::Event, "Remote.Play");
+        event.children.push(TreeNode::new(NodeKind::Action, ""));
+        let tree = TreeFile { items: vec![event], ..Default::default() };
+        assert!(tree.validate().unwrap_err().to_string().contains("items[0]"));
+    }
+}
@@ -29,4 +29,102 @@ impl Link {
             target_id: None,
         }
     }
-} 
\ No newline at end of file
+} 
+impl TreeLink for Link {
+    fn get_target(&self) -> Option<Arc<RwLock<dyn TreeItem>>> {
+        self.target.clone()
+    }
+
+    fn set_target(&mut self, target: Option<Arc<RwLock<dyn TreeItem>>>) -> Result<(), Error> {
+        self.target_id = match &target {
+            Some(item) => Some(
+                item.read()
+                    .map_err(|_| Error::Tree("Link target is poisoned".into()))?
+                    .get_id(),
+            ),
+            None => None,
+        };
+        self.target = target;
+        Ok(())
+    }
+
+    fn get_target_id(&self) -> Option<uuid::Uuid> {
+        self.target_id
+    }
+
+    fn resolve_target(&mut self, items: &[Arc<RwLock<dyn TreeItem>>]) -> Result<(), Error> {
+        let Some(id) = self.target_id else {
+            return Ok(());
+        };
+        let target = items
+            .iter()
+            .find(|item| item.read().is_ok_and(|item| item.get_id() == id))
+            .cloned()
+            .ok_or_else(|| Error::Tree(format!("Link target {} not found", id)))?;
+        self.target = Some(target);
+        Ok(())
+    }
+}
+
+impl TreeItem for Link {
+    fn get_id(&self) -> uuid::Uuid {
+        self.info.id
+    }
+
+    fn get_name(&self) -> &str {
+        &self.info.name
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.info.name = name.to_string();
+    }
+
+    fn get_description(&self) -> &str {
+        &self.info.description
+    }
+
+    fn set_description(&mut self, description: &str) {
+        self.info.description = description.to_string();
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.info.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.info.enabled = enabled;
+    }
+
+    /// Runs the target; a link must not point at the macro containing it
+    fn execute(&mut self, event: Option<&dyn crate::core::event::Event>) -> Result<(), Error> {
+        match &self.target {
+            Some(target) => target
+                .write()
+                .map_err(|_| Error::Tree("Link target is poisoned".into()))?
+                .execute(event),
+            None => Err(Error::Tree(format!("Link {} has no target", self.info.name))),
+        }
+    }
+
+    fn can_execute(&self, event: Option<&dyn crate::core::event::Event>) -> bool {
+        self.target
+            .as_ref()
+            .is_some_and(|target| target.read().is_ok_and(|target| target.is_enabled() && target.can_execute(event)))
+    }
+
+    fn clone_item(&self) -> Arc<RwLock<dyn TreeItem>> {
+        Arc::new(RwLock::new(Link {
+            info: self.info.clone(),
+            target: self.target.clone(),
+            target_id: self.target_id,
+        }))
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
@@ -8,6 +8,8 @@ pub struct Macro_ {
     info: TreeItemInfo,
     actions: Vec<Arc<RwLock<dyn TreeItem>>>,
     trigger_event: Option<Box<dyn Event + Send + Sync>>,
+    /// Names of the events that run this macro; `*` and `?` are wildcards
+    events: Vec<String>,
 }
 
 impl Macro_ {
@@ -21,6 +23,7 @@ impl Macro_ {
             },
             actions: Vec::new(),
             trigger_event: None,
+            events: Vec::new(),
         }
     }
 
@@ -58,6 +61,51 @@ impl Macro_ {
     pub fn get_trigger_event(&self) -> Option<&(dyn Event + Send + Sync)> {
         self.trigger_event.as_deref()
     }
+
+    /// Run this macro when an event called `name` occurs
+    pub fn add_event(&mut self, name: &str) {
+        self.events.push(name.to_string());
+    }
+
+    pub fn get_events(&self) -> &[String] {
+        &self.events
+    }
+
+    /// Whether `event` is one of the events that run this macro
+    pub fn is_triggered_by(&self, event: &dyn Event) -> bool {
+        let name = event.get_id();
+        self.trigger_event.as_ref().is_some_and(|trigger| trigger.get_id() == name)
+            || self.events.iter().any(|pattern| matches_pattern(pattern, name))
+    }
+}
+
+/// Shell-style match where `*` is any run of characters and `?` is one character
+pub(crate) fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    n = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 impl TreeItem for Macro_ {
@@ -109,20 +157,10 @@ impl TreeItem for Macro_ {
             return false;
         }
 
-        if let Some(trigger) = &self.trigger_event {
-            if let Some(event) = event {
-                // TODO: Implement event matching logic
-                // print the unused vars
-                println!("Trigger: {:?}", trigger);
-                println!("Event: {:?}", event);
-                true
-            } else {
-
-
-                false
-            }
-        } else {
-            true
+        // Run on request, or when one of the macro's events occurs
+        match event {
+            Some(event) => self.is_triggered_by(event),
+            None => true,
         }
     }
 
@@ -137,6 +175,7 @@ impl TreeItem for Macro_ {
                 }
             }).collect(),
             trigger_event: self.trigger_event.as_ref().map(|e| e.clone_event()),
+            events: self.events.clone(),
         }))
     }
 
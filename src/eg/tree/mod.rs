@@ -1,13 +1,17 @@
 pub mod item;
+pub mod action;
 pub mod link;
 pub mod folder;
 pub mod macro_;
 pub mod root;
 pub mod document;
+pub mod file;
 
 pub use item::TreeItem;
+pub use action::ActionCall;
 pub use link::TreeLink;
 pub use folder::Folder;
 pub use macro_::Macro_;
 pub use root::Root;
-pub use document::Document; 
\ No newline at end of file
+pub use document::Document;
+pub use file::{NodeKind, TreeFile, TreeNode}; 
\ No newline at end of file
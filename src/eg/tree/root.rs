@@ -60,6 +60,12 @@ impl Root {
     }
 }
 
+impl Default for Root {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TreeItem for Root {
     fn get_id(&self) -> uuid::Uuid {
         self.info.id
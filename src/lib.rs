@@ -1,5 +1,7 @@
+pub mod cli;
 pub mod core;
 pub mod eg;
+pub mod plugins;
 
 // Re-exports of common types
 pub use core::Error;
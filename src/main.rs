@@ -1,32 +1,133 @@
-mod core;
-mod eg;
-
-use gtk::prelude::*;
-use gtk::{self, Application};
-use gio::Resource;
-use crate::eg::classes::MainFrame;
+use std::sync::Arc;
+use eventghost::cli::{self, Invocation};
+use eventghost::core::logging::{self, LogStore};
 
 fn main() {
-    // Initialize GTK
-    gtk::init().expect("Failed to initialize GTK");
-
-    // Load and register resources
-    let resource_bytes = include_bytes!("resources.gresource");
-    let resource = Resource::from_data(&glib::Bytes::from_static(resource_bytes))
-        .expect("Failed to load resources");
-    gio::resources_register(&resource);
-
-    // Create application
-    let app = Application::builder()
-        .application_id("org.eventghost")
-        .build();
-
-    app.connect_activate(move |app| {
-        let mut main_frame = MainFrame::new(app).expect("Failed to create main window");
-        main_frame.update_button_tooltips();
-        main_frame.show();
-    });
-
-    // Run application
-    app.run();
-} 
\ No newline at end of file
+    let (args, headless) = match cli::parse(std::env::args()) {
+        Ok(Invocation::Run { launch, headless }) => (launch, headless),
+        Ok(Invocation::Command { command, launch }) => {
+            let _ = logging::init_console(Arc::new(LogStore::default()));
+            let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime");
+            std::process::exit(runtime.block_on(cli::execute(command, launch)));
+        }
+        Err(e) => e.exit(),
+    };
+    let log_store = match cli::start_logging(&args) {
+        Ok(log_store) => log_store,
+        Err(e) => {
+            eprintln!("Invalid settings: {}", e);
+            std::process::exit(cli::EXIT_FAILED);
+        }
+    };
+
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start async runtime");
+    if headless || !cfg!(feature = "gui") {
+        if !headless {
+            log::info!("Built without the gui feature; running headless");
+        }
+        std::process::exit(runtime.block_on(cli::run_headless(args, log_store)));
+    }
+
+    #[cfg(feature = "gui")]
+    gui::run(runtime, args, log_store);
+}
+
+#[cfg(feature = "gui")]
+mod gui {
+    use gtk::prelude::*;
+    use gtk::{self, Application};
+    use gio::Resource;
+    use eventghost::core::instance::{self, InstanceRole, LaunchArgs, PrimaryInstance};
+    use std::sync::Arc;
+    use eventghost::core::ipc::{default_transport, UiRequest};
+    use eventghost::core::logging::LogStore;
+    use eventghost::eg::classes::MainFrame;
+
+    pub fn run(runtime: tokio::runtime::Runtime, args: LaunchArgs, log_store: Arc<LogStore>) {
+        // Hand the command line to a running instance if there is one
+        let listener = match runtime.block_on(instance::acquire(default_transport().as_ref(), &args)) {
+            Ok(InstanceRole::Primary(listener)) => listener,
+            Ok(InstanceRole::Forwarded) => std::process::exit(instance::EXIT_FORWARDED),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(e.exit_code());
+            }
+        };
+
+        // Serve later launches and remote commands
+        let (ui_sender, mut ui_requests) = tokio::sync::mpsc::unbounded_channel();
+        let primary = {
+            let _guard = runtime.enter();
+            PrimaryInstance::start(listener, &args, Some(ui_sender), log_store).expect("Failed to initialize")
+        };
+        let document = primary.document.clone();
+        runtime.block_on(primary.start_plugins());
+
+        // Initialize GTK
+        gtk::init().expect("Failed to initialize GTK");
+
+        // Load and register resources
+        let resource_bytes = include_bytes!("resources.gresource");
+        let resource = Resource::from_data(&glib::Bytes::from_static(resource_bytes))
+            .expect("Failed to load resources");
+        gio::resources_register(&resource);
+
+        // Create application; GTK's own uniqueness check must not merge
+        // instances that were started with --multi
+        let flags = if args.multi {
+            gio::ApplicationFlags::NON_UNIQUE
+        } else {
+            gio::ApplicationFlags::empty()
+        };
+        let app = Application::builder()
+            .application_id("org.eventghost")
+            .flags(flags)
+            .build();
+
+        // Requests from the IPC server are carried out on the GTK main loop
+        let (gui_sender, gui_requests) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        runtime.spawn(async move {
+            while let Some(request) = ui_requests.recv().await {
+                if gui_sender.send(request).is_err() {
+                    break;
+                }
+            }
+        });
+        let app_handle = app.clone();
+        gui_requests.attach(None, move |request| {
+            match request {
+                UiRequest::ShowWindow => {}
+                UiRequest::Quit => {
+                    app_handle.quit();
+                    return glib::Continue(false);
+                }
+                UiRequest::OpenFile(path) => {
+                    if let Err(e) = document.write().unwrap().load(path.clone()) {
+                        eprintln!("Failed to open {}: {}", path.display(), e);
+                    }
+                }
+            }
+            if let Some(window) = app_handle.active_window() {
+                window.present();
+            }
+            glib::Continue(true)
+        });
+
+        // Our own command line is handled like a forwarded one
+        if let Err(e) = primary.handle(&args.requests) {
+            eprintln!("{}", e);
+        }
+
+        let hide = args.hide;
+        app.connect_activate(move |app| {
+            let mut main_frame = MainFrame::new(app).expect("Failed to create main window");
+            main_frame.update_button_tooltips();
+            if !hide {
+                main_frame.show();
+            }
+        });
+
+        // Run application; GTK must not see our own switches
+        app.run_with_args::<&str>(&[]);
+    }
+}
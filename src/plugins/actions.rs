@@ -0,0 +1,182 @@
+//! Built-in plugins that only provide actions
+//!
+//! The network sender, HTTP requests and e-mail keep no connection open;
+//! their plugins exist so tree calls such as `EMail.Send({...})` find a
+//! provider, and to carry the settings the actions are created with.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::core::config::{Config, ConfigManager};
+use crate::core::event::{Event, EventBus};
+use crate::core::plugin::{Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::eg::globals::Globals;
+use super::email::{self, EmailMessage, Mailer, SmtpSettings};
+use super::http::{self, HttpRequest, ResponseTargets};
+use super::network::{self, SenderSettings};
+use super::{action_args, plugin_accessors, plugin_info, reveal, settings_for};
+
+type Factory<S> = Box<dyn Fn(&S, Uuid, &str, &Value) -> Option<Result<ActionItem, Error>> + Send + Sync>;
+
+/// A plugin whose actions are created from its settings `S`
+pub struct ActionProvider<S> {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: S,
+    factory: Factory<S>,
+}
+
+impl<S> ActionProvider<S>
+where
+    S: DeserializeOwned + Default + PartialEq + Send + Sync + 'static,
+{
+    fn new(
+        name: &str,
+        description: &str,
+        settings: S,
+        factory: impl Fn(&S, Uuid, &str, &Value) -> Option<Result<ActionItem, Error>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            info: plugin_info(name, description, vec![PluginCapability::ActionProvider, PluginCapability::Configurable]),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            factory: Box::new(factory),
+        }
+    }
+
+    pub fn settings(&self) -> &S {
+        &self.settings
+    }
+}
+
+/// Arguments of the network sender's `Send` action
+#[derive(Deserialize)]
+struct SendArgs {
+    event: String,
+    #[serde(default)]
+    payload: Vec<String>,
+}
+
+pub const NETWORK_SENDER: &str = "NetworkSender";
+pub const HTTP: &str = "HTTP";
+pub const EMAIL: &str = "EMail";
+
+/// `NetworkSender.Send({"event": "Doorbell", "payload": ["front"]})`
+pub fn network_sender(settings: SenderSettings, secrets: Option<Arc<ConfigManager>>) -> ActionProvider<SenderSettings> {
+    ActionProvider::new(NETWORK_SENDER, "Sends events to a Network Event Receiver", settings, move |settings, id, name, args| {
+        if name != "Send" {
+            return None;
+        }
+        Some(action_args(name, args).and_then(|args: SendArgs| {
+            let password = reveal(secrets.as_deref(), settings.password.as_ref()).map_err(ActionError::ConfigurationFailed)?;
+            Ok(network::send_action(name, "Sends an event", id, settings.clone(), password, args.event, args.payload))
+        }))
+    })
+}
+
+/// `HTTP.Request({"method": "POST", "url": "...", "body": "{payload}"})`
+///
+/// Takes no settings; responses go to `globals` and `events`.
+pub fn http(globals: Arc<tokio::sync::Mutex<Globals>>, events: EventBus) -> ActionProvider<Value> {
+    let targets = ResponseTargets::default().with_globals(globals).with_event_bus(events);
+    ActionProvider::new(HTTP, "Sends HTTP requests", Value::Null, move |_, id, name, args| {
+        if name != "Request" {
+            return None;
+        }
+        Some(action_args(name, args).map(|request: HttpRequest| {
+            http::request_action(name, "Sends an HTTP request", id, request, targets.clone())
+        }))
+    })
+}
+
+/// `EMail.Send({"from": "...", "to": "...", "subject": "{suffix}"})`
+pub fn email(
+    settings: SmtpSettings,
+    secrets: Option<Arc<ConfigManager>>,
+    globals: Arc<tokio::sync::Mutex<Globals>>,
+) -> ActionProvider<SmtpSettings> {
+    ActionProvider::new(EMAIL, "Sends mail through an SMTP server", settings, move |settings, id, name, args| {
+        if name != "Send" {
+            return None;
+        }
+        Some(action_args(name, args).and_then(|message: EmailMessage| {
+            let password = reveal(secrets.as_deref(), settings.password.as_ref()).map_err(ActionError::ConfigurationFailed)?;
+            let mut mailer = Mailer::new(settings.clone()).with_globals(globals.clone());
+            if let Some(password) = password {
+                mailer = mailer.with_password(password);
+            }
+            Ok(email::send_action(name, "Sends a mail", id, mailer, message))
+        }))
+    })
+}
+
+#[async_trait]
+impl<S> Plugin for ActionProvider<S>
+where
+    S: DeserializeOwned + Default + PartialEq + Send + Sync + 'static,
+{
+    plugin_accessors!();
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// New settings apply to actions created afterwards
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        self.settings = settings_for(&config, &self.info.name).map_err(Error::Other)?;
+        self.config = Some(config);
+        Ok(())
+    }
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        (self.factory)(&self.settings, self.info.id, name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::eg::action::base::ActionBase;
+
+    #[test]
+    fn test_actions_are_created_from_settings() {
+        let globals = Arc::new(tokio::sync::Mutex::new(Globals::new()));
+        let http = http(globals.clone(), EventBus::default());
+        let request = http.create_action("Request", &json!({"url": "http://127.0.0.1:9/"})).unwrap().unwrap();
+        assert_eq!(request.get_plugin_id(), http.get_info().id);
+        assert!(http.create_action("Request", &json!({"method": "GET"})).unwrap().is_err());
+        assert!(http.create_action("Fetch", &Value::Null).is_none());
+
+        let mail = email(SmtpSettings::default(), None, globals);
+        assert_eq!(mail.settings().host, "localhost");
+        let message = json!({"from": "eg@localhost", "to": ["me@localhost"], "subject": "{suffix}", "body": ""});
+        assert!(mail.create_action("Send", &message).unwrap().is_ok());
+
+        let sender = network_sender(SenderSettings::default(), None);
+        assert!(sender.create_action("Send", &json!({"event": "Doorbell"})).unwrap().is_ok());
+    }
+}
@@ -0,0 +1,589 @@
+//! DBus plugin
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use zbus::message::Type;
+use zbus::zvariant::{self, OwnedValue};
+use zbus::{Connection, MatchRule, Message, MessageStream};
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, reconnect, EventQueue, EventSink};
+use super::mpris::{self, Players};
+use super::{notify_action, player_action, properties_json, DbusError, DbusSettings, Notification, PlayerCommand, Urgency};
+
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+enum Request {
+    Player { player: String, command: PlayerCommand },
+    Notify(Notification),
+}
+
+/// Queues requests for the plugin's bus connection while it is up; cheap
+/// to clone
+#[derive(Clone, Default)]
+pub struct DbusHandle(Arc<Mutex<Option<mpsc::UnboundedSender<Request>>>>);
+
+impl DbusHandle {
+    /// Send `command` to `player`, a full or short bus name, or to the
+    /// default player if empty
+    pub fn player(&self, player: &str, command: PlayerCommand) -> Result<(), DbusError> {
+        self.send(Request::Player { player: player.to_string(), command })
+    }
+
+    /// Show `notification`
+    pub fn notify(&self, notification: Notification) -> Result<(), DbusError> {
+        self.send(Request::Notify(notification))
+    }
+
+    fn send(&self, request: Request) -> Result<(), DbusError> {
+        let sender = self.0.lock().map_err(|_| DbusError::NotConnected)?;
+        sender.as_ref().ok_or(DbusError::NotConnected)?.send(request).map_err(|_| DbusError::NotConnected)
+    }
+
+    fn set(&self, sender: Option<mpsc::UnboundedSender<Request>>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = sender;
+        }
+    }
+}
+
+/// Raises MPRIS player changes as events and carries out player and
+/// notification requests
+///
+/// The bus is reconnected every `reconnect_ms` while it is unreachable.
+pub struct DbusClient {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: DbusSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    handle: DbusHandle,
+    task: Option<JoinHandle<()>>,
+}
+
+impl DbusClient {
+    pub const NAME: &'static str = "DBus";
+
+    pub fn new(settings: DbusSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Controls MPRIS media players and shows desktop notifications",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            handle: DbusHandle::default(),
+            task: None,
+        }
+    }
+
+    /// Publish player events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &DbusSettings {
+        &self.settings
+    }
+
+    /// Handle for [`player_action`](super::player_action)s and
+    /// [`notify_action`](super::notify_action)s
+    pub fn handle(&self) -> DbusHandle {
+        self.handle.clone()
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.handle.set(None);
+    }
+}
+
+impl Drop for DbusClient {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+async fn connect(settings: &DbusSettings) -> Result<Connection, DbusError> {
+    let connection = match settings.address.as_str() {
+        "" => Connection::session().await?,
+        address => zbus::connection::Builder::address(address)?.build().await?,
+    };
+    Ok(connection)
+}
+
+/// Stay connected until aborted
+async fn run(settings: DbusSettings, handle: DbusHandle, sink: EventSink) {
+    let (settings, handle, sink) = (&settings, &handle, &sink);
+    reconnect(
+        DbusClient::NAME,
+        "cannot connect to the bus",
+        Duration::from_millis(settings.reconnect_ms),
+        || connect(settings),
+        |connection| async move {
+            log::info!("{} connected to the bus", DbusClient::NAME);
+            if let Err(e) = serve(&connection, handle, settings, sink).await {
+                log::warn!("{}: lost the bus: {}", DbusClient::NAME, e);
+            }
+            handle.set(None);
+        },
+    )
+    .await
+}
+
+/// Raise player events and carry out requests until the connection fails
+async fn serve(
+    connection: &Connection,
+    handle: &DbusHandle,
+    settings: &DbusSettings,
+    sink: &EventSink,
+) -> Result<(), DbusError> {
+    // Subscribe before listing, so no player slips through in between
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path(mpris::PATH)?
+        .arg(0, mpris::PLAYER_INTERFACE)?
+        .build();
+    let mut changes = MessageStream::for_match_rule(rule, connection, None).await?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .arg0ns("org.mpris.MediaPlayer2")?
+        .build();
+    let mut owners = MessageStream::for_match_rule(rule, connection, None).await?;
+
+    let dbus = zbus::fdo::DBusProxy::new(connection).await?;
+    let mut players = Players::default();
+    for name in dbus.list_names().await? {
+        if mpris::short_name(&name).is_some() {
+            if let Ok(owner) = dbus.get_name_owner(name.as_ref()).await {
+                appeared(connection, &mut players, &name, &owner).await;
+            }
+        }
+    }
+    let (sender, mut requests) = mpsc::unbounded_channel();
+    handle.set(Some(sender));
+    // One worker makes the calls in the order they were asked for, so a
+    // slow player holds up later requests but not the signals meanwhile;
+    // it ends once the calls queued before this returns are done
+    let (calls, mut queue) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(call) = queue.recv().await {
+            if let Err(e) = call.await {
+                log::warn!("{}: {}", DbusClient::NAME, e);
+            }
+        }
+    });
+
+    loop {
+        tokio::select! {
+            message = changes.next() => {
+                let message = message.ok_or(DbusError::NotConnected)??;
+                changed(&message, &mut players, settings, sink);
+            }
+            message = owners.next() => {
+                let message = message.ok_or(DbusError::NotConnected)??;
+                let (name, _, owner): (String, String, String) = message.body().deserialize()?;
+                if owner.is_empty() {
+                    players.vanished(&name);
+                } else {
+                    appeared(connection, &mut players, &name, &owner).await;
+                }
+            }
+            Some(request) = requests.recv() => {
+                let _ = calls.send(execute(connection, &players, settings, request));
+            }
+        }
+    }
+}
+
+async fn appeared(connection: &Connection, players: &mut Players, name: &str, owner: &str) {
+    let properties = mpris::properties(connection, name).await.unwrap_or_else(|e| {
+        log::debug!("{}: no properties from {}: {}", DbusClient::NAME, name, e);
+        Default::default()
+    });
+    players.appeared(name, owner, &properties);
+}
+
+fn changed(message: &Message, players: &mut Players, settings: &DbusSettings, sink: &EventSink) {
+    let header = message.header();
+    let Some(owner) = header.sender() else { return };
+    let body = message.body();
+    let Ok((_, properties, _)) = body.deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>() else {
+        return;
+    };
+    let Some((name, events)) = players.changed(owner.as_str(), &properties_json(&properties)) else {
+        return;
+    };
+    let player = mpris::short_name(&name).unwrap_or(&name);
+    for (suffix, mut payload) in events {
+        let event_name = match settings.prefix.as_str() {
+            "" => format!("{}.{}", player, suffix),
+            prefix => format!("{}.{}.{}", prefix, player, suffix),
+        };
+        if let Value::Object(fields) = &mut payload {
+            fields.insert("player".into(), json!(player));
+            fields.insert("bus_name".into(), json!(name));
+        }
+        let event = NamedEvent::new(&event_name, EventType::Plugin, EventPayload::from_json(payload));
+        sink.emit(event.with_source(DbusClient::NAME));
+    }
+}
+
+/// Carry out `request`; the call owns what it needs, so it can be queued
+/// and answered while the connection goes on serving
+fn execute(
+    connection: &Connection,
+    players: &Players,
+    settings: &DbusSettings,
+    request: Request,
+) -> impl Future<Output = Result<(), DbusError>> + Send + 'static {
+    let connection = connection.clone();
+    let player = match &request {
+        Request::Player { player, .. } => {
+            let wanted = if player.is_empty() { &settings.player } else { player };
+            let names = players.names();
+            mpris::choose_player(&names, wanted).cloned().ok_or_else(|| DbusError::NoPlayer(wanted.clone()))
+        }
+        Request::Notify(_) => Ok(String::new()),
+    };
+    let app_name = settings.app_name.clone();
+    async move {
+        match request {
+            Request::Player { command, .. } => mpris::execute(&connection, &player?, &command).await,
+            Request::Notify(notification) => notify(&connection, &app_name, notification).await,
+        }
+    }
+}
+
+/// Show `notification` through the desktop's notification server
+async fn notify(connection: &Connection, app_name: &str, notification: Notification) -> Result<(), DbusError> {
+    let urgency: u8 = match notification.urgency {
+        Urgency::Low => 0,
+        Urgency::Normal => 1,
+        Urgency::Critical => 2,
+    };
+    let hints = HashMap::from([("urgency", zvariant::Value::from(urgency))]);
+    let body = (
+        app_name,
+        0u32,
+        notification.icon.as_str(),
+        notification.summary.as_str(),
+        notification.body.as_str(),
+        Vec::<&str>::new(),
+        hints,
+        notification.timeout_ms,
+    );
+    connection
+        .call_method(Some(NOTIFICATIONS), NOTIFICATIONS_PATH, Some(NOTIFICATIONS), "Notify", &body)
+        .await?;
+    Ok(())
+}
+
+/// Arguments of the `Player` action, e.g. `{"command": "volume", "level": 0.5}`
+#[derive(Deserialize)]
+struct PlayerArgs {
+    #[serde(default)]
+    player: String,
+    #[serde(flatten)]
+    command: PlayerCommand,
+}
+
+#[async_trait]
+impl Plugin for DbusClient {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        match name {
+            "Player" => Some(action_args(name, args).map(|args: PlayerArgs| {
+                player_action(name, "Controls a media player", self.info.id, self.handle(), &args.player, args.command)
+            })),
+            "Notify" => Some(action_args(name, args).map(|notification| {
+                notify_action(name, "Shows a desktop notification", self.info.id, self.handle(), notification)
+            })),
+            _ => None,
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.settings.validate() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.task = Some(tokio::spawn(run(self.settings.clone(), self.handle.clone(), self.sink.clone())));
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: DbusSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(DbusError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for DbusClient {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use uuid::Uuid;
+    use crate::eg::action::base::ActionBase;
+    use crate::plugins::dbus::{notify_action, player_action};
+
+    /// A `dbus-daemon` of the test's own
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+        _dir: tempfile::TempDir,
+    }
+
+    impl PrivateBus {
+        /// Fails the test where dbus-daemon is not installed rather than
+        /// letting it pass without checking anything; tests using it are
+        /// ignored unless asked for with `cargo test -- --ignored`
+        fn start() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let config = dir.path().join("bus.conf");
+            std::fs::write(
+                &config,
+                format!(
+                    r#"<busconfig>
+                        <type>session</type>
+                        <listen>unix:path={}/socket</listen>
+                        <auth>EXTERNAL</auth>
+                        <policy context="default"><allow send_destination="*"/><allow receive_sender="*"/><allow own="*"/></policy>
+                    </busconfig>"#,
+                    dir.path().display()
+                ),
+            )
+            .unwrap();
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .expect("dbus-daemon is needed to test the DBus plugin");
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address).unwrap();
+            Self { daemon, address: address.trim().to_string(), _dir: dir }
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    #[derive(Default)]
+    struct FakePlayer {
+        calls: Arc<Mutex<Vec<String>>>,
+        status: String,
+        volume: f64,
+        title: String,
+    }
+
+    #[zbus::interface(name = "org.mpris.MediaPlayer2.Player")]
+    impl FakePlayer {
+        fn play_pause(&self) {
+            self.calls.lock().unwrap().push("PlayPause".into());
+        }
+
+        fn next(&self) {
+            self.calls.lock().unwrap().push("Next".into());
+        }
+
+        fn seek(&self, offset: i64) {
+            self.calls.lock().unwrap().push(format!("Seek {}", offset));
+        }
+
+        #[zbus(property)]
+        fn playback_status(&self) -> String {
+            self.status.clone()
+        }
+
+        #[zbus(property)]
+        fn volume(&self) -> f64 {
+            self.volume
+        }
+
+        #[zbus(property)]
+        fn set_volume(&mut self, volume: f64) {
+            self.volume = volume;
+        }
+
+        #[zbus(property)]
+        fn metadata(&self) -> HashMap<String, OwnedValue> {
+            HashMap::from([("xesam:title".to_string(), OwnedValue::from(zvariant::Str::from(self.title.clone())))])
+        }
+    }
+
+    /// App name, summary, body and urgency
+    type Shown = (String, String, String, u8);
+
+    #[derive(Default)]
+    struct FakeNotifications {
+        shown: Arc<Mutex<Vec<Shown>>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotifications {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: String,
+            _replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let urgency = hints.get("urgency").and_then(|value| u8::try_from(value).ok()).unwrap_or_default();
+            self.shown.lock().unwrap().push((app_name, summary, body, urgency));
+            1
+        }
+    }
+
+    async fn wait_for<T>(mut check: impl FnMut() -> Option<T>) -> T {
+        for _ in 0..500 {
+            if let Some(value) = check() {
+                return value;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("timed out");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs dbus-daemon; run with `cargo test -- --ignored`"]
+    async fn test_private_bus() {
+        let bus = PrivateBus::start();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let player = FakePlayer { calls: calls.clone(), status: "Paused".into(), volume: 0.5, title: "First".into() };
+        let shown = Arc::new(Mutex::new(Vec::new()));
+        let notifications = FakeNotifications { shown: shown.clone() };
+        let service = zbus::connection::Builder::address(bus.address.as_str())
+            .unwrap()
+            .name("org.mpris.MediaPlayer2.fake.instance7")
+            .unwrap()
+            .serve_at(mpris::PATH, player)
+            .unwrap()
+            .name(NOTIFICATIONS)
+            .unwrap()
+            .serve_at(NOTIFICATIONS_PATH, notifications)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+
+        let mut client = DbusClient::new(DbusSettings { address: bus.address.clone(), player: "fake".into(), ..Default::default() });
+        client.initialize().await.unwrap();
+        client.start().await.unwrap();
+        let handle = client.handle();
+        // Requests are taken once the players on the bus are known
+        wait_for(|| handle.player("nobody", PlayerCommand::Stop).ok()).await;
+
+        // Known state raises nothing, changes do
+        let player = service.object_server().interface::<_, FakePlayer>(mpris::PATH).await.unwrap();
+        {
+            let mut fake = player.get_mut().await;
+            fake.status = "Playing".into();
+            fake.title = "Second".into();
+            fake.playback_status_changed(player.signal_emitter()).await.unwrap();
+            fake.metadata_changed(player.signal_emitter()).await.unwrap();
+        }
+        let (name, payload) = next_event(&mut client).await;
+        assert_eq!(name, "Mpris.fake.Playing");
+        assert_eq!(payload["bus_name"], "org.mpris.MediaPlayer2.fake.instance7");
+        let (name, payload) = next_event(&mut client).await;
+        assert_eq!(name, "Mpris.fake.TrackChanged");
+        assert_eq!(payload["title"], "Second");
+
+        let event = NamedEvent::new("Door.Open", EventType::User, EventPayload::from_json(json!({"door": "Front"})));
+        let actions = [
+            player_action("Toggle", "Play or pause", Uuid::new_v4(), handle.clone(), "", PlayerCommand::PlayPause),
+            player_action("Next", "Next track", Uuid::new_v4(), handle.clone(), "fake", PlayerCommand::Next),
+            player_action("Back", "Skip back", Uuid::new_v4(), handle.clone(), "", PlayerCommand::Seek { offset_ms: -5000 }),
+            player_action("Quiet", "Turn down", Uuid::new_v4(), handle.clone(), "", PlayerCommand::Volume { level: 0.25 }),
+        ];
+        for mut action in actions {
+            action.execute(&event).await.unwrap();
+        }
+        let (name, payload) = next_event(&mut client).await;
+        assert_eq!(name, "Mpris.fake.Volume");
+        assert_eq!(payload["volume"], 0.25);
+        assert_eq!(*calls.lock().unwrap(), ["PlayPause", "Next", "Seek -5000000"]);
+
+        let mut notification = crate::plugins::dbus::Notification::new("{payload.door} door", "Opened by {event}");
+        notification.urgency = Urgency::Critical;
+        let mut notify = notify_action("Popup", "Show the door", Uuid::new_v4(), handle, notification);
+        notify.execute(&event).await.unwrap();
+        let shown = wait_for(|| shown.lock().unwrap().first().cloned()).await;
+        assert_eq!(shown, ("EventGhost".into(), "Front door".into(), "Opened by Door.Open".into(), 2));
+
+        client.stop().await.unwrap();
+        drop(service);
+        drop(bus);
+    }
+}
@@ -0,0 +1,281 @@
+//! D-Bus on the Linux desktop: media players speaking MPRIS, such as VLC,
+//! mpv and Spotify, and `org.freedesktop.Notifications` popups
+//!
+//! The `DBus` plugin raises `Mpris.<player>.<change>` events when a player
+//! reports new properties:
+//!
+//! | Event                       | When                                     |
+//! |-----------------------------|------------------------------------------|
+//! | `Mpris.vlc.TrackChanged`    | another track started; title, artist, … |
+//! | `Mpris.vlc.Playing`         | playback started or resumed              |
+//! | `Mpris.vlc.Paused`          | playback paused                          |
+//! | `Mpris.vlc.Stopped`         | playback stopped                         |
+//! | `Mpris.vlc.Volume`          | the volume changed, from 0.0 to 1.0      |
+//!
+//! [`player_action`] controls a player and [`notify_action`] shows a popup.
+//! Both queue their request for the plugin's connection, so they fail only
+//! while the bus is unreachable; errors of the player or notification
+//! daemon are logged.
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "DBus"
+//! enabled = true
+//! settings = { player = "spotify" }
+//! ```
+
+pub mod client;
+pub mod mpris;
+
+pub use client::{DbusClient, DbusHandle};
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use zbus::zvariant;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::template;
+
+/// Error type for the D-Bus plugin
+#[derive(Debug, thiserror::Error)]
+pub enum DbusError {
+    #[error("D-Bus error: {0}")]
+    Bus(String),
+    #[error("Not connected to the bus")]
+    NotConnected,
+    #[error("No media player matches '{0}'")]
+    NoPlayer(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<zbus::Error> for DbusError {
+    fn from(e: zbus::Error) -> Self {
+        DbusError::Bus(e.to_string())
+    }
+}
+
+impl From<zbus::fdo::Error> for DbusError {
+    fn from(e: zbus::fdo::Error) -> Self {
+        DbusError::Bus(e.to_string())
+    }
+}
+
+impl From<DbusError> for Error {
+    fn from(e: DbusError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Settings of the `DBus` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DbusSettings {
+    /// Bus address such as `unix:path=/run/user/1000/bus`; empty for the
+    /// session bus
+    pub address: String,
+    /// Player controlled by actions that name none, e.g. `vlc`; empty for
+    /// the first one found
+    pub player: String,
+    /// Prefix of MPRIS event names; empty for none
+    pub prefix: String,
+    /// Application name shown on notifications
+    pub app_name: String,
+    /// Delay between attempts to connect to the bus
+    pub reconnect_ms: u64,
+}
+
+impl Default for DbusSettings {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            player: String::new(),
+            prefix: "Mpris".to_string(),
+            app_name: "EventGhost".to_string(),
+            reconnect_ms: 5000,
+        }
+    }
+}
+
+impl DbusSettings {
+    pub fn validate(&self) -> Result<(), DbusError> {
+        if !self.address.is_empty() {
+            zbus::Address::try_from(self.address.as_str()).map_err(|e| DbusError::Settings(e.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+/// Something a media player is told to do
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum PlayerCommand {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    /// Set the volume, from 0.0 to 1.0
+    Volume { level: f64 },
+    /// Move the position by `offset_ms`, backwards if negative
+    Seek { offset_ms: i64 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+/// A desktop notification; `summary` and `body` are
+/// [templates](crate::plugins::template) in actions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub summary: String,
+    #[serde(default)]
+    pub body: String,
+    /// Icon name or path; empty for the daemon's default
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub urgency: Urgency,
+    /// -1 leaves the duration to the daemon, 0 keeps it until closed
+    #[serde(default = "default_timeout")]
+    pub timeout_ms: i32,
+}
+
+fn default_timeout() -> i32 {
+    -1
+}
+
+impl Notification {
+    pub fn new(summary: &str, body: &str) -> Self {
+        Self {
+            summary: summary.to_string(),
+            body: body.to_string(),
+            icon: String::new(),
+            urgency: Urgency::Normal,
+            timeout_ms: default_timeout(),
+        }
+    }
+}
+
+/// Creates an action that sends `command` to `player`, or to the plugin's
+/// default player if `player` is empty
+pub fn player_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    handle: DbusHandle,
+    player: &str,
+    command: PlayerCommand,
+) -> ActionItem {
+    let player = player.to_string();
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |_event| {
+            handle
+                .player(&player, command.clone())
+                .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+/// Creates an action that shows `notification`, filled in from the event
+pub fn notify_action(name: &str, description: &str, plugin_id: Uuid, handle: DbusHandle, notification: Notification) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let rendered = Notification {
+                summary: template::render(&notification.summary, event),
+                body: template::render(&notification.body, event),
+                ..notification.clone()
+            };
+            handle.notify(rendered).map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+/// `value` as JSON; object paths and signatures become strings and file
+/// descriptors `null`
+pub fn to_json(value: &zvariant::Value) -> Value {
+    use zvariant::Value as V;
+    match value {
+        V::U8(n) => Value::from(*n),
+        V::Bool(b) => Value::from(*b),
+        V::I16(n) => Value::from(*n),
+        V::U16(n) => Value::from(*n),
+        V::I32(n) => Value::from(*n),
+        V::U32(n) => Value::from(*n),
+        V::I64(n) => Value::from(*n),
+        V::U64(n) => Value::from(*n),
+        V::F64(n) => Value::from(*n),
+        V::Str(s) => Value::from(s.as_str()),
+        V::Signature(s) => Value::from(s.to_string()),
+        V::ObjectPath(path) => Value::from(path.as_str()),
+        V::Value(inner) => to_json(inner),
+        V::Array(items) => Value::Array(items.iter().map(to_json).collect()),
+        V::Dict(dict) => Value::Object(
+            dict.iter()
+                .map(|(key, value)| {
+                    let key = match to_json(key) {
+                        Value::String(key) => key,
+                        other => other.to_string(),
+                    };
+                    (key, to_json(value))
+                })
+                .collect(),
+        ),
+        V::Structure(fields) => Value::Array(fields.fields().iter().map(to_json).collect()),
+        V::Fd(_) => Value::Null,
+    }
+}
+
+/// A property map as received from a player, as a JSON object
+pub fn properties_json<'a>(properties: impl IntoIterator<Item = (&'a String, &'a zvariant::OwnedValue)>) -> BTreeMap<String, Value> {
+    properties.into_iter().map(|(name, value)| (name.clone(), to_json(value))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_to_json() {
+        let mut metadata = HashMap::new();
+        metadata.insert("xesam:title", zvariant::Value::from("Song"));
+        metadata.insert("xesam:artist", zvariant::Value::from(vec!["A", "B"]));
+        metadata.insert("mpris:length", zvariant::Value::from(180_000_000i64));
+        metadata.insert("mpris:trackid", zvariant::Value::from(zvariant::ObjectPath::from_static_str_unchecked("/track/1")));
+        let value = zvariant::Value::from(metadata);
+        assert_eq!(
+            to_json(&zvariant::Value::Value(Box::new(value))),
+            json!({"xesam:title": "Song", "xesam:artist": ["A", "B"], "mpris:length": 180000000, "mpris:trackid": "/track/1"})
+        );
+    }
+
+    #[test]
+    fn test_settings() {
+        let command: PlayerCommand = serde_json::from_value(json!({"command": "seek", "offset_ms": -5000})).unwrap();
+        assert_eq!(command, PlayerCommand::Seek { offset_ms: -5000 });
+        let command: PlayerCommand = serde_json::from_value(json!({"command": "play_pause"})).unwrap();
+        assert_eq!(command, PlayerCommand::PlayPause);
+        assert!(DbusSettings::default().validate().is_ok());
+        assert!(DbusSettings { address: "nonsense".into(), ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! MPRIS media players: naming, property changes and commands
+
+use std::collections::{BTreeMap, HashMap};
+use serde_json::{json, Value};
+use zbus::zvariant::{self, OwnedValue};
+use zbus::Connection;
+use super::{properties_json, DbusError, PlayerCommand};
+
+/// Bus names of players start with this
+pub const BUS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+pub const PATH: &str = "/org/mpris/MediaPlayer2";
+pub const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// Track id players report while nothing is loaded
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// The player part of a bus name without an instance suffix, e.g. `mpv`
+/// for `org.mpris.MediaPlayer2.mpv.instance4242`
+pub fn short_name(bus_name: &str) -> Option<&str> {
+    let rest = bus_name.strip_prefix(BUS_PREFIX)?;
+    rest.split('.').next().filter(|name| !name.is_empty())
+}
+
+/// The first of `names` that is `wanted` as a full or short name; the
+/// first at all if `wanted` is empty
+pub fn choose_player<'a>(names: &'a [String], wanted: &str) -> Option<&'a String> {
+    names.iter().find(|name| {
+        wanted.is_empty() || name.as_str() == wanted || short_name(name) == Some(wanted) || name.strip_prefix(BUS_PREFIX) == Some(wanted)
+    })
+}
+
+/// The fields of a track worth an event, from MPRIS metadata
+pub fn track_payload(metadata: &Value) -> Value {
+    let text = |key: &str| metadata.get(key).and_then(Value::as_str).unwrap_or_default().to_string();
+    let list = |key: &str| match metadata.get(key) {
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "),
+        Some(Value::String(text)) => text.clone(),
+        _ => String::new(),
+    };
+    json!({
+        "track_id": text("mpris:trackid"),
+        "title": text("xesam:title"),
+        "artist": list("xesam:artist"),
+        "album": text("xesam:album"),
+        "url": text("xesam:url"),
+        "length_ms": metadata.get("mpris:length").and_then(Value::as_i64).map(|us| us / 1000),
+    })
+}
+
+/// What identifies a track; players without track ids are told apart by
+/// title and URL
+fn track_key(track: &Value) -> Option<String> {
+    let field = |key: &str| track[key].as_str().unwrap_or_default();
+    match field("track_id") {
+        "" | NO_TRACK if field("title").is_empty() && field("url").is_empty() => None,
+        "" | NO_TRACK => Some(format!("{}\n{}", field("title"), field("url"))),
+        id => Some(id.to_string()),
+    }
+}
+
+/// The last known properties of one player
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlayerState {
+    pub status: Option<String>,
+    pub track: Option<String>,
+    pub volume: Option<f64>,
+}
+
+impl PlayerState {
+    /// Take in changed properties; returns the event suffixes and payloads
+    /// for what actually changed
+    pub fn update(&mut self, changed: &BTreeMap<String, Value>) -> Vec<(String, Value)> {
+        let mut events = Vec::new();
+        if let Some(metadata) = changed.get("Metadata") {
+            let track = track_payload(metadata);
+            let key = track_key(&track);
+            if key.is_some() && key != self.track {
+                events.push(("TrackChanged".to_string(), track));
+            }
+            self.track = key;
+        }
+        if let Some(status) = changed.get("PlaybackStatus").and_then(Value::as_str) {
+            if self.status.as_deref() != Some(status) {
+                events.push((status.to_string(), json!({"status": status})));
+                self.status = Some(status.to_string());
+            }
+        }
+        if let Some(volume) = changed.get("Volume").and_then(Value::as_f64) {
+            if self.volume != Some(volume) {
+                events.push(("Volume".to_string(), json!({"volume": volume})));
+                self.volume = Some(volume);
+            }
+        }
+        events
+    }
+}
+
+/// The players on the bus, by well-known name, and who owns them
+#[derive(Debug, Default)]
+pub struct Players {
+    owners: HashMap<String, String>,
+    states: BTreeMap<String, PlayerState>,
+}
+
+impl Players {
+    /// `name` is now owned by the connection `owner`; `properties` are its
+    /// current ones and raise no events
+    pub fn appeared(&mut self, name: &str, owner: &str, properties: &BTreeMap<String, Value>) {
+        self.owners.retain(|_, known| known != name);
+        self.owners.insert(owner.to_string(), name.to_string());
+        let mut state = PlayerState::default();
+        state.update(properties);
+        self.states.insert(name.to_string(), state);
+    }
+
+    pub fn vanished(&mut self, name: &str) {
+        self.owners.retain(|_, known| known != name);
+        self.states.remove(name);
+    }
+
+    /// Properties changed on the connection `owner`; returns the player's
+    /// name and the events to raise
+    pub fn changed(&mut self, owner: &str, properties: &BTreeMap<String, Value>) -> Option<(String, Vec<(String, Value)>)> {
+        let name = self.owners.get(owner)?;
+        let events = self.states.get_mut(name)?.update(properties);
+        Some((name.clone(), events))
+    }
+
+    /// Well-known names, sorted
+    pub fn names(&self) -> Vec<String> {
+        self.states.keys().cloned().collect()
+    }
+}
+
+/// Current player properties of `bus_name`
+pub async fn properties(connection: &Connection, bus_name: &str) -> Result<BTreeMap<String, Value>, DbusError> {
+    let reply = connection
+        .call_method(Some(bus_name), PATH, Some(PROPERTIES_INTERFACE), "GetAll", &(PLAYER_INTERFACE,))
+        .await?;
+    let properties: HashMap<String, OwnedValue> = reply.body().deserialize()?;
+    Ok(properties_json(&properties))
+}
+
+/// Send `command` to the player at `bus_name`
+pub async fn execute(connection: &Connection, bus_name: &str, command: &PlayerCommand) -> Result<(), DbusError> {
+    let call = |method: &'static str| connection.call_method(Some(bus_name), PATH, Some(PLAYER_INTERFACE), method, &());
+    match command {
+        PlayerCommand::Play => call("Play").await?,
+        PlayerCommand::Pause => call("Pause").await?,
+        PlayerCommand::PlayPause => call("PlayPause").await?,
+        PlayerCommand::Stop => call("Stop").await?,
+        PlayerCommand::Next => call("Next").await?,
+        PlayerCommand::Previous => call("Previous").await?,
+        PlayerCommand::Seek { offset_ms } => {
+            connection
+                .call_method(Some(bus_name), PATH, Some(PLAYER_INTERFACE), "Seek", &(offset_ms * 1000,))
+                .await?
+        }
+        PlayerCommand::Volume { level } => {
+            let level = zvariant::Value::from(level.max(0.0));
+            connection
+                .call_method(Some(bus_name), PATH, Some(PROPERTIES_INTERFACE), "Set", &(PLAYER_INTERFACE, "Volume", level))
+                .await?
+        }
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(value: Value) -> BTreeMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(short_name("org.mpris.MediaPlayer2.mpv.instance4242"), Some("mpv"));
+        assert_eq!(short_name("org.mpris.MediaPlayer2.spotify"), Some("spotify"));
+        assert_eq!(short_name("org.freedesktop.Notifications"), None);
+
+        let names = vec!["org.mpris.MediaPlayer2.mpv.instance1".to_string(), "org.mpris.MediaPlayer2.vlc".to_string()];
+        assert_eq!(choose_player(&names, "vlc"), Some(&names[1]));
+        assert_eq!(choose_player(&names, "mpv.instance1"), Some(&names[0]));
+        assert_eq!(choose_player(&names, ""), Some(&names[0]));
+        assert_eq!(choose_player(&names, "spotify"), None);
+    }
+
+    #[test]
+    fn test_player_state() {
+        let mut players = Players::default();
+        players.appeared("org.mpris.MediaPlayer2.vlc", ":1.7", &changed(json!({"PlaybackStatus": "Paused", "Volume": 0.5})));
+
+        let (name, events) = players
+            .changed(":1.7", &changed(json!({"PlaybackStatus": "Playing", "Metadata": {
+                "mpris:trackid": "/track/1", "xesam:title": "Song", "xesam:artist": ["A", "B"], "mpris:length": 180_000_000i64,
+            }})))
+            .unwrap();
+        assert_eq!(name, "org.mpris.MediaPlayer2.vlc");
+        let names: Vec<&str> = events.iter().map(|(suffix, _)| suffix.as_str()).collect();
+        assert_eq!(names, ["TrackChanged", "Playing"]);
+        assert_eq!(events[0].1["artist"], "A, B");
+        assert_eq!(events[0].1["length_ms"], 180_000);
+
+        // Same track with new artwork, same volume
+        let events = players
+            .changed(":1.7", &changed(json!({"Metadata": {"mpris:trackid": "/track/1", "mpris:artUrl": "file:///a.png"}, "Volume": 0.5})))
+            .unwrap()
+            .1;
+        assert!(events.is_empty());
+
+        // Without track ids the title tells tracks apart
+        let untracked = |title: &str| changed(json!({"Metadata": {"mpris:trackid": NO_TRACK, "xesam:title": title}}));
+        assert_eq!(players.changed(":1.7", &untracked("Other")).unwrap().1.len(), 1);
+        assert!(players.changed(":1.7", &untracked("Other")).unwrap().1.is_empty());
+
+        players.vanished("org.mpris.MediaPlayer2.vlc");
+        assert!(players.changed(":1.7", &changed(json!({"Volume": 1.0}))).is_none());
+        assert!(players.names().is_empty());
+    }
+}
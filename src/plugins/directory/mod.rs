@@ -0,0 +1,261 @@
+//! File-system changes as events, the successor of the legacy
+//! `DirectoryWatcher` plugin
+//!
+//! Each configured directory is watched on its own or with everything
+//! below it. Changes raise `DirectoryWatcher.Created`, `.Modified`,
+//! `.Deleted` and `.Renamed` with the path as payload, once things have
+//! been quiet for `debounce_ms`, so saving a file gives one event rather
+//! than a burst. A file written to without pause is still reported every
+//! `max_delay_ms`:
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "DirectoryWatcher"
+//! enabled = true
+//!
+//! [[plugins.settings.directories]]
+//! path = "/home/me/Downloads"
+//! recursive = true
+//! include = ["*.torrent", "incoming/**"]
+//! exclude = ["*.part"]
+//! ```
+//!
+//! Patterns without a `/` match file names, others match the path below
+//! the watched directory; `*` and `?` stay within a path component, `**`
+//! spans any number of them, and `{a,b}` and `[ab]` offer alternatives.
+
+pub mod watcher;
+
+pub use watcher::DirectoryWatcher;
+
+use std::path::{Path, PathBuf};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use crate::core::Error;
+
+/// Error type for the directory watcher
+#[derive(Debug, thiserror::Error)]
+pub enum DirectoryError {
+    #[error("Cannot watch {path}: {reason}")]
+    Watch { path: PathBuf, reason: String },
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<DirectoryError> for Error {
+    fn from(e: DirectoryError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// What happened to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+impl Change {
+    pub fn name(self) -> &'static str {
+        match self {
+            Change::Created => "Created",
+            Change::Modified => "Modified",
+            Change::Deleted => "Deleted",
+            Change::Renamed => "Renamed",
+        }
+    }
+}
+
+/// One directory to watch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchedDirectory {
+    pub path: PathBuf,
+    /// Watch subdirectories too
+    #[serde(default)]
+    pub recursive: bool,
+    /// Patterns of paths to report; empty for all
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Patterns of paths never to report, even if included
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl WatchedDirectory {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), recursive: false, include: Vec::new(), exclude: Vec::new() }
+    }
+
+    pub fn recursive(mut self) -> Self {
+        self.recursive = true;
+        self
+    }
+
+    pub fn with_include(mut self, pattern: &str) -> Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    pub fn with_exclude(mut self, pattern: &str) -> Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    /// The compiled `include` and `exclude` patterns
+    pub fn filter(&self) -> Result<PathFilter, DirectoryError> {
+        Ok(PathFilter { include: Patterns::new(&self.include)?, exclude: Patterns::new(&self.exclude)? })
+    }
+}
+
+/// Decides which paths below a [`WatchedDirectory`] are reported
+#[derive(Debug, Clone)]
+pub struct PathFilter {
+    include: Patterns,
+    exclude: Patterns,
+}
+
+impl PathFilter {
+    /// Whether changes to `relative`, a path below the directory, are
+    /// reported
+    pub fn accepts(&self, relative: &Path) -> bool {
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        (self.include.is_empty() || self.include.matches(&relative)) && !self.exclude.matches(&relative)
+    }
+}
+
+/// Patterns with a `/`, matched against the whole path, and those without,
+/// matched against the last component
+#[derive(Debug, Clone)]
+struct Patterns {
+    paths: GlobSet,
+    names: GlobSet,
+}
+
+impl Patterns {
+    fn new(patterns: &[String]) -> Result<Self, DirectoryError> {
+        let (mut paths, mut names) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .map_err(|e| DirectoryError::Settings(e.to_string()))?;
+            match pattern.contains('/') {
+                true => paths.add(glob),
+                false => names.add(glob),
+            };
+        }
+        let build = |set: GlobSetBuilder| set.build().map_err(|e| DirectoryError::Settings(e.to_string()));
+        Ok(Self { paths: build(paths)?, names: build(names)? })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.names.is_empty()
+    }
+
+    /// `path` has `/` separators
+    fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self.paths.is_match(path) || self.names.is_match(name)
+    }
+}
+
+/// Settings of the `DirectoryWatcher` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirectorySettings {
+    pub directories: Vec<WatchedDirectory>,
+    /// How long a path must be left alone before its change is reported
+    pub debounce_ms: u64,
+    /// Longest wait from the first unreported change of a path
+    pub max_delay_ms: u64,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+}
+
+impl Default for DirectorySettings {
+    fn default() -> Self {
+        Self {
+            directories: Vec::new(),
+            debounce_ms: 250,
+            max_delay_ms: 5000,
+            prefix: DirectoryWatcher::NAME.to_string(),
+        }
+    }
+}
+
+impl DirectorySettings {
+    pub fn validate(&self) -> Result<(), DirectoryError> {
+        if self.max_delay_ms < self.debounce_ms {
+            return Err(DirectoryError::Settings("max_delay_ms is shorter than debounce_ms".into()));
+        }
+        for directory in &self.directories {
+            if directory.path.as_os_str().is_empty() {
+                return Err(DirectoryError::Settings("a directory has no path".into()));
+            }
+            if directory.include.iter().chain(&directory.exclude).any(String::is_empty) {
+                return Err(DirectoryError::Settings(format!("{} has an empty pattern", directory.path.display())));
+            }
+            directory.filter()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_path_filter() {
+        let accepts = |pattern: &str, path: &str| {
+            let filter = WatchedDirectory::new("/w").with_include(pattern).filter().unwrap();
+            filter.accepts(Path::new(path))
+        };
+        assert!(accepts("*.txt", "notes.txt"));
+        assert!(accepts("*.txt", "deep/down/notes.txt"));
+        assert!(!accepts("*.txt", "notes.txt.bak"));
+        assert!(accepts("report-??.pdf", "report-01.pdf"));
+        assert!(!accepts("report-??.pdf", "report-1.pdf"));
+        assert!(accepts("*.{jpg,png}", "photo.png"));
+
+        assert!(accepts("incoming/*", "incoming/a.zip"));
+        assert!(!accepts("incoming/*", "incoming/sub/a.zip"));
+        assert!(accepts("incoming/**", "incoming/sub/a.zip"));
+        assert!(accepts("**/build/*.o", "build/main.o"));
+        assert!(accepts("**/build/*.o", "src/net/build/main.o"));
+        assert!(!accepts("src/*.rs", "src/net/main.rs"));
+
+        // Used to backtrack once per way of splitting the path
+        let deep = format!("{}b", "a/".repeat(40));
+        assert!(!accepts(&"**/a".repeat(20), &deep));
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings: DirectorySettings = serde_json::from_value(json!({
+            "directories": [
+                {"path": "/tmp/in", "recursive": true, "include": ["*.csv"], "exclude": ["tmp/**"]},
+                {"path": "/tmp/out"},
+            ],
+            "debounce_ms": 100,
+        }))
+        .unwrap();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.directories[1], WatchedDirectory::new("/tmp/out"));
+        assert_eq!(settings.prefix, "DirectoryWatcher");
+
+        let filter = settings.directories[0].filter().unwrap();
+        assert!(filter.accepts(Path::new("2026/data.csv")));
+        assert!(!filter.accepts(Path::new("tmp/data.csv")));
+        assert!(!filter.accepts(Path::new("data.xlsx")));
+        assert!(settings.directories[1].filter().unwrap().accepts(Path::new("anything")));
+
+        let invalid = DirectorySettings { directories: vec![WatchedDirectory::new("/tmp").with_exclude("")], ..Default::default() };
+        assert!(invalid.validate().is_err());
+        let invalid = DirectorySettings { directories: vec![WatchedDirectory::new("/tmp").with_include("[a-")], ..Default::default() };
+        assert!(invalid.validate().is_err());
+    }
+}
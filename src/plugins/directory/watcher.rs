@@ -0,0 +1,452 @@
+//! DirectoryWatcher plugin
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use async_trait::async_trait;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::plugins::{plugin_accessors, plugin_info, sleep_until, EventQueue, EventSink};
+use super::{Change, DirectoryError, DirectorySettings, PathFilter};
+
+/// Raises changes below the configured directories as events
+pub struct DirectoryWatcher {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: DirectorySettings,
+    sink: EventSink,
+    queue: EventQueue,
+    watcher: Option<RecommendedWatcher>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl DirectoryWatcher {
+    pub const NAME: &'static str = "DirectoryWatcher";
+
+    pub fn new(settings: DirectorySettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Raises events when files are created, changed, renamed or deleted",
+                vec![PluginCapability::EventGenerator, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            watcher: None,
+            task: None,
+        }
+    }
+
+    /// Publish change events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &DirectorySettings {
+        &self.settings
+    }
+
+    /// Watch every configured directory and start reporting changes
+    fn watch(&mut self) -> Result<(), DirectoryError> {
+        self.settings.validate()?;
+        // Paths in notify's events start like the watched ones
+        let mut roots = Vec::with_capacity(self.settings.directories.len());
+        for directory in &self.settings.directories {
+            let path = directory.path.canonicalize().map_err(|e| DirectoryError::Watch {
+                path: directory.path.clone(),
+                reason: e.to_string(),
+            })?;
+            roots.push(Root { path, recursive: directory.recursive, filter: directory.filter()? });
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        })
+        .map_err(|e| DirectoryError::Watch { path: PathBuf::new(), reason: e.to_string() })?;
+        for root in &roots {
+            let mode = if root.recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            watcher
+                .watch(&root.path, mode)
+                .map_err(|e| DirectoryError::Watch { path: root.path.clone(), reason: e.to_string() })?;
+        }
+
+        let debouncer = Debouncer::new(Duration::from_millis(self.settings.debounce_ms), Duration::from_millis(self.settings.max_delay_ms));
+        self.watcher = Some(watcher);
+        self.task = Some(tokio::spawn(run(roots, self.settings.prefix.clone(), debouncer, rx, self.sink.clone())));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        self.watcher = None;
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A watched directory, with its path as notify reports it
+struct Root {
+    path: PathBuf,
+    recursive: bool,
+    filter: PathFilter,
+}
+
+/// A change waiting for its path to settle
+#[derive(Debug, Clone, PartialEq)]
+struct Pending {
+    change: Change,
+    root: usize,
+    /// The old path of a rename, or of a renamed path that was deleted
+    from: Option<PathBuf>,
+    /// When the first change folded into this one happened
+    since: Instant,
+    due: Instant,
+}
+
+/// Folds the bursts of raw notifications for a path into one change,
+/// reported once the path has been left alone for `delay`, or at the
+/// latest `max_delay` after its first change
+struct Debouncer {
+    delay: Duration,
+    max_delay: Duration,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+impl Debouncer {
+    fn new(delay: Duration, max_delay: Duration) -> Self {
+        Self { delay, max_delay, pending: HashMap::new() }
+    }
+
+    fn pending(&self, change: Change, root: usize, from: Option<PathBuf>, since: Instant, now: Instant) -> Pending {
+        let due = (now + self.delay).min(since + self.max_delay);
+        Pending { change, root, from, since, due }
+    }
+
+    fn push(&mut self, path: PathBuf, root: usize, change: Change, now: Instant) {
+        let previous = self.pending.remove(&path);
+        let since = previous.as_ref().map_or(now, |pending| pending.since);
+        let previous = previous.map(|pending| (pending.change, pending.from));
+        let (change, from) = match (previous, change) {
+            // Came and went before anyone noticed
+            (Some((Change::Created, _)), Change::Deleted) => return,
+            (Some((Change::Created, _)), _) => (Change::Created, None),
+            (Some((Change::Deleted, _)), Change::Created) => (Change::Modified, None),
+            (Some((Change::Renamed, from)), Change::Modified) => (Change::Renamed, from),
+            // Remember where it came from, in case this is the From of a move
+            (Some((Change::Renamed, from)), Change::Deleted) => (Change::Deleted, from),
+            (_, change) => (change, None),
+        };
+        let pending = self.pending(change, root, from, since, now);
+        self.pending.insert(path, pending);
+    }
+
+    /// `from` was renamed to `to`; replaces the deletion and creation
+    /// reported for either end of the move
+    fn rename(&mut self, from: PathBuf, to: PathBuf, root: usize, now: Instant) {
+        let replaced = self.pending.remove(&to).map(|pending| pending.since);
+        let moved = self.pending.remove(&from);
+        let since = moved.iter().map(|pending| pending.since).chain(replaced).min().unwrap_or(now);
+        let (change, from) = match moved {
+            Some(Pending { change: Change::Created, .. }) => (Change::Created, None),
+            Some(Pending { from: Some(original), .. }) if original == to => (Change::Modified, None),
+            Some(Pending { from: Some(original), .. }) => (Change::Renamed, Some(original)),
+            _ => (Change::Renamed, Some(from)),
+        };
+        let pending = self.pending(change, root, from, since, now);
+        self.pending.insert(to, pending);
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        self.pending.values().map(|pending| pending.due).min()
+    }
+
+    /// Remove the changes that have settled by `now`, oldest first
+    fn take_due(&mut self, now: Instant) -> Vec<(PathBuf, Pending)> {
+        let paths: Vec<PathBuf> = self.pending.iter().filter(|(_, pending)| pending.due <= now).map(|(path, _)| path.clone()).collect();
+        let mut due: Vec<(PathBuf, Pending)> = paths
+            .into_iter()
+            .filter_map(|path| self.pending.remove(&path).map(|pending| (path, pending)))
+            .collect();
+        due.sort_by_key(|(_, pending)| pending.due);
+        due
+    }
+}
+
+/// The directory `path` belongs to, if changes to it are reported
+fn root_of(roots: &[Root], path: &Path) -> Option<usize> {
+    let (index, root) = roots
+        .iter()
+        .enumerate()
+        .filter(|(_, root)| path.starts_with(&root.path))
+        .max_by_key(|(_, root)| root.path.as_os_str().len())?;
+    root.filter.accepts(path.strip_prefix(&root.path).ok()?).then_some(index)
+}
+
+/// Feed a notification from notify into `debouncer`
+fn record(debouncer: &mut Debouncer, roots: &[Root], event: notify::Event, now: Instant) {
+    let change = match event.kind {
+        EventKind::Create(_) => Change::Created,
+        EventKind::Modify(ModifyKind::Name(RenameMode::From)) => Change::Deleted,
+        EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Change::Created,
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            // Both ends were already seen as From and To; if only one of
+            // them is reported that stays a deletion or creation
+            if let [from, to] = event.paths.as_slice() {
+                if let (Some(_), Some(root)) = (root_of(roots, from), root_of(roots, to)) {
+                    debouncer.rename(from.clone(), to.clone(), root, now);
+                }
+            }
+            return;
+        }
+        // Backends that cannot tell which end of a move they saw
+        EventKind::Modify(ModifyKind::Name(_)) => {
+            for path in event.paths {
+                let change = if path.exists() { Change::Created } else { Change::Deleted };
+                if let Some(root) = root_of(roots, &path) {
+                    debouncer.push(path, root, change, now);
+                }
+            }
+            return;
+        }
+        EventKind::Modify(_) => Change::Modified,
+        EventKind::Remove(_) => Change::Deleted,
+        _ => return,
+    };
+    for path in event.paths {
+        if let Some(root) = root_of(roots, &path) {
+            debouncer.push(path, root, change, now);
+        }
+    }
+}
+
+/// Turn notifications into events until the watcher is dropped
+async fn run(
+    roots: Vec<Root>,
+    prefix: String,
+    mut debouncer: Debouncer,
+    mut notifications: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    sink: EventSink,
+) {
+    loop {
+        let due = debouncer.next_due();
+        tokio::select! {
+            notification = notifications.recv() => match notification {
+                Some(Ok(event)) => record(&mut debouncer, &roots, event, Instant::now()),
+                Some(Err(e)) => log::warn!("{}: {}", DirectoryWatcher::NAME, e),
+                None => return,
+            },
+            _ = sleep_until(due) => {}
+        }
+        for (path, pending) in debouncer.take_due(Instant::now()) {
+            sink.emit(change_event(&prefix, &roots[pending.root], &path, &pending));
+        }
+    }
+}
+
+fn change_event(prefix: &str, root: &Root, path: &Path, pending: &Pending) -> NamedEvent {
+    let name = match prefix {
+        "" => pending.change.name().to_string(),
+        prefix => format!("{}.{}", prefix, pending.change.name()),
+    };
+    let path = match (pending.change, &pending.from) {
+        (Change::Deleted, Some(original)) => original.as_path(),
+        _ => path,
+    };
+    let mut payload = json!({
+        "path": path.to_string_lossy(),
+        "name": path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default(),
+        "directory": root.path.to_string_lossy(),
+    });
+    if let (Change::Renamed, Some(from)) = (pending.change, &pending.from) {
+        payload["from"] = json!(from.to_string_lossy());
+    }
+    NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(payload)).with_source(DirectoryWatcher::NAME)
+}
+
+#[async_trait]
+impl Plugin for DirectoryWatcher {
+    plugin_accessors!();
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.watch() {
+            self.shutdown();
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: DirectorySettings =
+            crate::plugins::settings_for(&config, Self::NAME).map_err(DirectoryError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for DirectoryWatcher {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use crate::plugins::directory::WatchedDirectory;
+    use std::fs;
+
+    #[test]
+    fn test_debouncer() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(100), Duration::from_millis(1000));
+        debouncer.push("/w/a".into(), 0, Change::Created, start);
+        debouncer.push("/w/a".into(), 0, Change::Modified, start + Duration::from_millis(50));
+        debouncer.push("/w/b".into(), 0, Change::Modified, start + Duration::from_millis(55));
+        debouncer.push("/w/c".into(), 0, Change::Created, start);
+        debouncer.push("/w/c".into(), 0, Change::Deleted, start);
+        assert!(debouncer.take_due(start + Duration::from_millis(100)).is_empty());
+
+        // A save by replacing the file is a modification
+        debouncer.push("/w/d".into(), 0, Change::Deleted, start + Duration::from_millis(60));
+        debouncer.push("/w/d".into(), 0, Change::Created, start + Duration::from_millis(60));
+        let due = debouncer.take_due(start + Duration::from_millis(160));
+        let changes: Vec<(&str, Change)> = due.iter().map(|(path, pending)| (path.to_str().unwrap(), pending.change)).collect();
+        assert_eq!(changes, [("/w/a", Change::Created), ("/w/b", Change::Modified), ("/w/d", Change::Modified)]);
+        assert_eq!(debouncer.next_due(), None);
+
+        // Moves arrive as From, To and Both
+        debouncer.push("/w/e".into(), 0, Change::Deleted, start);
+        debouncer.push("/w/f".into(), 0, Change::Created, start);
+        debouncer.rename("/w/e".into(), "/w/f".into(), 0, start);
+        debouncer.push("/w/f".into(), 0, Change::Deleted, start);
+        debouncer.push("/w/g".into(), 0, Change::Created, start);
+        debouncer.rename("/w/f".into(), "/w/g".into(), 0, start);
+        let due = debouncer.take_due(start + Duration::from_millis(100));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].0, PathBuf::from("/w/g"));
+        assert_eq!(due[0].1.change, Change::Renamed);
+        assert_eq!(due[0].1.from, Some(PathBuf::from("/w/e")));
+
+        // Deleting a renamed path deletes what was there before
+        debouncer.rename("/w/g".into(), "/w/h".into(), 0, start);
+        debouncer.push("/w/h".into(), 0, Change::Deleted, start);
+        let (path, pending) = debouncer.take_due(start + Duration::from_millis(100)).remove(0);
+        let root = Root { path: "/w".into(), recursive: false, filter: WatchedDirectory::new("/w").filter().unwrap() };
+        let event = change_event("", &root, &path, &pending);
+        assert_eq!(event.get_id(), "Deleted");
+        assert_eq!(event.get_payload().to_json()["path"], "/w/g");
+    }
+
+    #[test]
+    fn test_continuous_writes_are_reported() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(100), Duration::from_millis(1000));
+        let mut reported = Vec::new();
+        for step in 0..46 {
+            let now = start + Duration::from_millis(step * 50);
+            reported.extend(debouncer.take_due(now).into_iter().map(|(_, pending)| pending.since));
+            debouncer.push("/w/log".into(), 0, Change::Modified, now);
+        }
+        assert_eq!(reported, [start, start + Duration::from_millis(1000)]);
+        assert_eq!(debouncer.next_due(), Some(start + Duration::from_millis(2250 + 100)));
+
+        let settings = DirectorySettings { max_delay_ms: 100, debounce_ms: 250, ..Default::default() };
+        assert!(settings.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_watch_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let mut watcher = DirectoryWatcher::new(DirectorySettings {
+            directories: vec![WatchedDirectory::new(&root).recursive().with_exclude("*.tmp")],
+            debounce_ms: 50,
+            ..Default::default()
+        });
+        watcher.initialize().await.unwrap();
+        watcher.start().await.unwrap();
+
+        fs::write(root.join("ignored.tmp"), "x").unwrap();
+        fs::write(root.join("a.txt"), "one").unwrap();
+        let (name, payload) = next_event(&mut watcher).await;
+        assert_eq!(name, "DirectoryWatcher.Created");
+        assert_eq!(payload["name"], "a.txt");
+        assert_eq!(payload["directory"], root.to_str().unwrap());
+
+        fs::write(root.join("a.txt"), "two").unwrap();
+        assert_eq!(next_event(&mut watcher).await.0, "DirectoryWatcher.Modified");
+
+        fs::rename(root.join("a.txt"), root.join("b.txt")).unwrap();
+        let (name, payload) = next_event(&mut watcher).await;
+        assert_eq!(name, "DirectoryWatcher.Renamed");
+        assert_eq!(payload["path"], root.join("b.txt").to_str().unwrap());
+        assert_eq!(payload["from"], root.join("a.txt").to_str().unwrap());
+
+        fs::remove_file(root.join("b.txt")).unwrap();
+        assert_eq!(next_event(&mut watcher).await.0, "DirectoryWatcher.Deleted");
+
+        fs::create_dir(root.join("sub")).unwrap();
+        assert_eq!(next_event(&mut watcher).await.1["name"], "sub");
+        fs::write(root.join("sub").join("c.txt"), "deep").unwrap();
+        let (name, payload) = next_event(&mut watcher).await;
+        assert_eq!(name, "DirectoryWatcher.Created");
+        assert_eq!(payload["path"], root.join("sub").join("c.txt").to_str().unwrap());
+
+        watcher.stop().await.unwrap();
+
+        let mut missing = DirectoryWatcher::new(DirectorySettings {
+            directories: vec![WatchedDirectory::new(root.join("missing"))],
+            ..Default::default()
+        });
+        assert!(missing.start().await.is_err());
+        assert_eq!(missing.get_state(), PluginState::Failed);
+    }
+}
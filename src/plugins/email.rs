@@ -0,0 +1,371 @@
+//! Sending e-mail over SMTP, replacing the legacy `E-mail` plugin's send
+//! action
+//!
+//! The server is described by [`SmtpSettings`], with the password kept in
+//! the keystore like other secret settings:
+//!
+//! ```toml
+//! host = "smtp.example.com"
+//! security = "starttls"
+//! username = "eventghost@example.com"
+//! password = { "$secret" = "smtp" }
+//! ```
+//!
+//! Addresses, subject and body of an [`EmailMessage`] are
+//! [templates](crate::plugins::template) that may use the triggering event
+//! and global variables, e.g. `Door opened at {var.time}: {payload.name}`.
+
+use std::sync::Arc;
+use std::time::Duration;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::config::{ConfigManager, SecretRef, SecretString};
+use crate::core::event::Event;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::eg::globals::Globals;
+use crate::plugins::{block_on, template};
+
+/// Error type for sending e-mail
+#[derive(Debug, thiserror::Error)]
+pub enum EmailError {
+    #[error("Invalid address '{address}': {reason}")]
+    Address { address: String, reason: String },
+    #[error("Invalid message: {0}")]
+    Message(String),
+    #[error("SMTP error: {0}")]
+    Smtp(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<lettre::transport::smtp::Error> for EmailError {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        EmailError::Smtp(e.to_string())
+    }
+}
+
+impl From<EmailError> for Error {
+    fn from(e: EmailError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// How the connection to the server is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Security {
+    /// Plain text, for servers on the local machine or network only
+    None,
+    /// Upgrade with `STARTTLS`, failing if the server does not offer it
+    #[default]
+    StartTls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+impl Security {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Security::None => 25,
+            Security::StartTls => 587,
+            Security::Tls => 465,
+        }
+    }
+}
+
+/// The SMTP server mails are sent through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SmtpSettings {
+    pub host: String,
+    /// Defaults to the usual port of `security`
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: Security,
+    /// Log in if set
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<SecretRef>,
+    #[serde(default = "timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn timeout_ms() -> u64 {
+    30_000
+}
+
+/// A server on the local machine
+impl Default for SmtpSettings {
+    fn default() -> Self {
+        Self::new("localhost", Security::default())
+    }
+}
+
+impl SmtpSettings {
+    pub fn new(host: &str, security: Security) -> Self {
+        Self { host: host.to_string(), port: None, security, username: None, password: None, timeout_ms: timeout_ms() }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or_else(|| self.security.default_port())
+    }
+
+    /// Decrypt the password from the keystore of `manager`
+    pub fn reveal_password(&self, manager: &ConfigManager) -> Result<Option<SecretString>, EmailError> {
+        self.password
+            .as_ref()
+            .map(|secret| manager.reveal_secret(secret).map_err(|e| EmailError::Settings(e.to_string())))
+            .transpose()
+    }
+}
+
+/// A mail to send; every field but `html` is a
+/// [template](crate::plugins::template)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailMessage {
+    /// e.g. `EventGhost <eventghost@example.com>`
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    /// Send the body as HTML instead of plain text
+    #[serde(default)]
+    pub html: bool,
+}
+
+impl EmailMessage {
+    pub fn new(from: &str, to: &str, subject: &str, body: &str) -> Self {
+        Self {
+            from: from.to_string(),
+            to: vec![to.to_string()],
+            cc: Vec::new(),
+            subject: subject.to_string(),
+            body: body.to_string(),
+            html: false,
+        }
+    }
+
+    /// Fill in the templates from `event` and `globals`
+    pub fn render(&self, event: &dyn Event, globals: Option<&Globals>) -> Result<Message, EmailError> {
+        let render = |text: &str| template::render_with(text, event, globals);
+        let mailbox = |address: &str| {
+            let address = render(address);
+            address.parse::<Mailbox>().map_err(|e| EmailError::Address { address, reason: e.to_string() })
+        };
+        if self.to.is_empty() {
+            return Err(EmailError::Message("no recipients".into()));
+        }
+        let mut builder = Message::builder().from(mailbox(&self.from)?).subject(render(&self.subject));
+        for address in &self.to {
+            builder = builder.to(mailbox(address)?);
+        }
+        for address in &self.cc {
+            builder = builder.cc(mailbox(address)?);
+        }
+        let content_type = if self.html { ContentType::TEXT_HTML } else { ContentType::TEXT_PLAIN };
+        builder
+            .header(content_type)
+            .body(render(&self.body))
+            .map_err(|e| EmailError::Message(e.to_string()))
+    }
+}
+
+/// Sends mails through one server
+#[derive(Clone)]
+pub struct Mailer {
+    settings: SmtpSettings,
+    password: Option<SecretString>,
+    globals: Option<Arc<tokio::sync::Mutex<Globals>>>,
+}
+
+impl Mailer {
+    pub fn new(settings: SmtpSettings) -> Self {
+        Self { settings, password: None, globals: None }
+    }
+
+    /// Log in with `username` from the settings and `password`
+    pub fn with_password(mut self, password: SecretString) -> Self {
+        self.password = Some(password);
+        self
+    }
+
+    /// Fill `{var.name}` placeholders from `globals`
+    pub fn with_globals(mut self, globals: Arc<tokio::sync::Mutex<Globals>>) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    pub fn settings(&self) -> &SmtpSettings {
+        &self.settings
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, EmailError> {
+        let settings = &self.settings;
+        let tls = || TlsParameters::new(settings.host.clone());
+        let tls = match settings.security {
+            Security::None => Tls::None,
+            Security::StartTls => Tls::Required(tls()?),
+            Security::Tls => Tls::Wrapper(tls()?),
+        };
+        let mut builder = SmtpTransport::builder_dangerous(&settings.host)
+            .port(settings.port())
+            .tls(tls)
+            .timeout(Some(Duration::from_millis(settings.timeout_ms)));
+        match (&settings.username, &self.password) {
+            (Some(username), Some(password)) => {
+                builder = builder.credentials(Credentials::new(username.clone(), password.expose().to_string()));
+            }
+            (Some(username), None) => {
+                return Err(EmailError::Settings(format!("no password for '{}'", username)));
+            }
+            (None, _) => {}
+        }
+        Ok(builder.build())
+    }
+
+    /// Render `message` for `event` and send it, resolving once the server
+    /// accepted or refused it
+    ///
+    /// The SMTP exchange blocks, so it runs on the blocking pool.
+    pub async fn send(&self, message: &EmailMessage, event: &dyn Event) -> Result<(), EmailError> {
+        let mail = match &self.globals {
+            Some(globals) => message.render(event, Some(&*globals.lock().await))?,
+            None => message.render(event, None)?,
+        };
+        let transport = self.transport()?;
+        tokio::task::spawn_blocking(move || transport.send(&mail))
+            .await
+            .map_err(|e| EmailError::Smtp(e.to_string()))??;
+        Ok(())
+    }
+}
+
+/// Creates an action that sends `message` through `mailer`
+///
+/// The action fails if the server does not accept the mail.
+pub fn send_action(name: &str, description: &str, plugin_id: Uuid, mailer: Mailer, message: EmailMessage) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            block_on(mailer.send(&message, event)).map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            log::debug!("Mail '{}' sent through {}", message.subject, mailer.settings.host);
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use serde_json::json;
+    use crate::core::event::{EventPayload, EventType, NamedEvent};
+    use crate::eg::action::base::ActionBase;
+
+    /// Accepts one mail on a local port; returns the port and a handle
+    /// giving the commands and the message received
+    fn smtp_server() -> (u16, std::thread::JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut reply = |text: &str| writer.write_all(format!("{}\r\n", text).as_bytes()).unwrap();
+            reply("220 localhost ESMTP stand-in");
+            let (mut commands, mut data) = (Vec::new(), String::new());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let verb = line.split(' ').next().unwrap_or_default().to_ascii_uppercase();
+                commands.push(line);
+                match verb.as_str() {
+                    "EHLO" => reply("250-localhost\r\n250 AUTH PLAIN LOGIN"),
+                    "AUTH" => reply("235 2.7.0 Accepted"),
+                    "DATA" => {
+                        reply("354 End data with <CR><LF>.<CR><LF>");
+                        loop {
+                            let mut line = String::new();
+                            reader.read_line(&mut line).unwrap();
+                            if line == ".\r\n" {
+                                break;
+                            }
+                            data.push_str(&line);
+                        }
+                        reply("250 2.0.0 Queued");
+                    }
+                    "QUIT" => {
+                        reply("221 Bye");
+                        break;
+                    }
+                    _ => reply("250 OK"),
+                }
+            }
+            (commands, data)
+        });
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn test_send_action() {
+        let (port, server) = smtp_server();
+        let mut settings = SmtpSettings::new("127.0.0.1", Security::None);
+        settings.port = Some(port);
+        settings.username = Some("eg".into());
+        let globals = Arc::new(tokio::sync::Mutex::new(Globals::new()));
+        globals.lock().await.set_var("house", "Cottage".to_string());
+        let mailer = Mailer::new(settings).with_password("hunter2".into()).with_globals(globals);
+        let mut message = EmailMessage::new(
+            "EventGhost <eg@example.com>",
+            "{payload.who}@example.com",
+            "{var.house}: {payload.door} opened",
+            "Event {event}",
+        );
+        message.cc.push("log@example.com".into());
+        let mut action = send_action("Mail", "Mail the door state", Uuid::new_v4(), mailer.clone(), message.clone());
+
+        let event = NamedEvent::new("Door.Open", EventType::User, EventPayload::from_json(json!({"who": "ann", "door": "Back door"})));
+        action.execute(&event).await.unwrap();
+        let (commands, data) = server.join().unwrap();
+
+        let plain = BASE64.encode("\0eg\0hunter2");
+        assert!(commands.iter().any(|command| command.ends_with(&plain)), "{:?}", commands);
+        assert!(commands.contains(&"MAIL FROM:<eg@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<ann@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<log@example.com>".to_string()));
+        assert!(data.contains("Subject: Cottage: Back door opened\r\n"), "{}", data);
+        assert!(data.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(data.ends_with("\r\nEvent Door.Open\r\n"), "{}", data);
+
+        message.to = vec!["{payload.missing}".into()];
+        let mut settings = mailer.settings().clone();
+        let sent = mailer.send(&message, &event).await;
+        assert!(matches!(sent, Err(EmailError::Address { .. })));
+
+        settings.port = Some(1);
+        let unreachable = Mailer::new(settings).with_password("hunter2".into());
+        let mut action = send_action("Mail", "Nobody listens", Uuid::new_v4(), unreachable, EmailMessage::new("a@b.c", "d@e.f", "", ""));
+        let event = NamedEvent::new("Door.Open", EventType::User, EventPayload::Text(String::new()));
+        assert!(action.execute(&event).await.is_err());
+    }
+}
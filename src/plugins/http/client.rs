@@ -0,0 +1,103 @@
+//! A small HTTP/1.1 client over hyper, with TLS through rustls
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue, HOST, USER_AGENT};
+use hyper::{Method, Request, Uri};
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
+use super::{HttpError, HttpResponse};
+
+/// Largest response body that is read
+pub const MAX_BODY: usize = 8 * 1024 * 1024;
+
+/// A request with its templates filled in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outgoing {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+    pub timeout: Duration,
+}
+
+/// Send `request` on a fresh connection; redirects are not followed
+pub async fn send(request: &Outgoing) -> Result<HttpResponse, HttpError> {
+    tokio::time::timeout(request.timeout, exchange(request))
+        .await
+        .map_err(|_| HttpError::Timeout(request.timeout.as_millis() as u64))?
+}
+
+async fn exchange(request: &Outgoing) -> Result<HttpResponse, HttpError> {
+    let invalid = |reason: &str| HttpError::InvalidUrl { url: request.uri.to_string(), reason: reason.to_string() };
+    let tls = match request.uri.scheme_str() {
+        Some("http") => false,
+        Some("https") => true,
+        _ => return Err(invalid("only http and https are supported")),
+    };
+    let host = request.uri.host().ok_or_else(|| invalid("no host"))?;
+    let port = request.uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let stream = TcpStream::connect((host.trim_start_matches('[').trim_end_matches(']'), port)).await?;
+
+    if tls {
+        let name = ServerName::try_from(host.to_string()).map_err(|_| invalid("bad host name"))?;
+        let stream = tls_connector()?.connect(name, stream).await?;
+        over(stream, request).await
+    } else {
+        over(stream, request).await
+    }
+}
+
+/// Run one request over `stream`
+async fn over<S>(stream: S, outgoing: &Outgoing) -> Result<HttpResponse, HttpError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    let driver = tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::debug!("HTTP connection ended: {}", e);
+        }
+    });
+
+    let target = outgoing.uri.path_and_query().map_or("/", |target| target.as_str());
+    let mut request = Request::builder().method(outgoing.method.clone()).uri(target);
+    let headers = request.headers_mut().expect("the builder has no errors yet");
+    if let Some(authority) = outgoing.uri.authority() {
+        headers.insert(HOST, HeaderValue::from_str(authority.as_str()).map_err(|e| HttpError::Http(e.to_string()))?);
+    }
+    headers.insert(USER_AGENT, HeaderValue::from_static(concat!("EventGhost/", env!("CARGO_PKG_VERSION"))));
+    for (name, value) in &outgoing.headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| HttpError::Http(e.to_string()))?;
+        headers.insert(name, HeaderValue::from_str(value).map_err(|e| HttpError::Http(e.to_string()))?);
+    }
+    let request = request
+        .body(Full::new(Bytes::from(outgoing.body.clone())))
+        .map_err(|e| HttpError::Http(e.to_string()))?;
+
+    let response = sender.send_request(request).await?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = Limited::new(response.into_body(), MAX_BODY)
+        .collect()
+        .await
+        .map_err(|e| HttpError::Http(e.to_string()))?
+        .to_bytes()
+        .to_vec();
+    driver.abort();
+    Ok(HttpResponse { status, headers, body })
+}
+
+/// Trusts the system's certificate authorities; built on first use
+fn tls_connector() -> Result<TlsConnector, HttpError> {
+    crate::plugins::tls_client_config().map(TlsConnector::from).map_err(HttpError::Tls)
+}
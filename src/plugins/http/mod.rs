@@ -0,0 +1,364 @@
+//! HTTP in both directions: an action calling REST APIs and the `Webhook`
+//! plugin receiving calls from other tools
+//!
+//! [`request_action`] sends a request whose URL, headers and body are
+//! [templates](crate::plugins::template), values in the URL
+//! percent-encoded, waits for the answer and can keep its status and body
+//! in global variables or raise them as an event, so later actions of the
+//! macro can use them:
+//!
+//! ```toml
+//! method = "POST"
+//! url = "http://hass.local:8123/api/services/light/turn_on"
+//! headers = { Authorization = "Bearer abc", Content-Type = "application/json" }
+//! body = '{{"entity_id": "light.{payload.room}"}}'
+//! body_escape = "json"
+//! status_variable = "hass_status"
+//! response_event = "HASS.Response"
+//! ```
+//!
+//! [`WebhookListener`] turns `POST`s on configured paths into events.
+
+pub mod client;
+pub mod webhook;
+
+pub use webhook::{Webhook, WebhookListener, WebhookSettings};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use hyper::{Method, Uri};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::eg::globals::Globals;
+use crate::plugins::block_on;
+use crate::plugins::template::{self, Escape};
+use client::Outgoing;
+
+/// Error type for HTTP requests and the webhook listener
+#[derive(Debug, thiserror::Error)]
+pub enum HttpError {
+    #[error("Invalid URL '{url}': {reason}")]
+    InvalidUrl { url: String, reason: String },
+    #[error("No response within {0} ms")]
+    Timeout(u64),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("HTTP error: {0}")]
+    Http(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<hyper::Error> for HttpError {
+    fn from(e: hyper::Error) -> Self {
+        HttpError::Http(e.to_string())
+    }
+}
+
+impl From<HttpError> for Error {
+    fn from(e: HttpError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// What a request action sends; strings are
+/// [templates](crate::plugins::template)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HttpRequest {
+    #[serde(default = "get")]
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// How values are written into `body`; `json` escapes them for JSON
+    /// strings
+    #[serde(default)]
+    pub body_escape: Escape,
+    #[serde(default = "timeout_ms")]
+    pub timeout_ms: u64,
+    /// Global variable that receives the response status
+    #[serde(default)]
+    pub status_variable: Option<String>,
+    /// Global variable that receives the response body, parsed if it is
+    /// JSON
+    #[serde(default)]
+    pub body_variable: Option<String>,
+    /// Event raised with the response as payload
+    #[serde(default)]
+    pub response_event: Option<String>,
+}
+
+fn get() -> String {
+    "GET".to_string()
+}
+
+fn timeout_ms() -> u64 {
+    10_000
+}
+
+impl HttpRequest {
+    pub fn new(method: &str, url: &str) -> Self {
+        Self {
+            method: method.to_string(),
+            url: url.to_string(),
+            headers: BTreeMap::new(),
+            body: None,
+            body_escape: Escape::Plain,
+            timeout_ms: timeout_ms(),
+            status_variable: None,
+            body_variable: None,
+            response_event: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn with_body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
+    /// A body that is JSON, with values escaped to keep it so
+    pub fn with_json_body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self.body_escape = Escape::Json;
+        self
+    }
+
+    /// Fill in the templates from `event` and `globals`; values in the URL
+    /// are percent-encoded
+    pub fn render(&self, event: &dyn Event, globals: Option<&Globals>) -> Result<Outgoing, HttpError> {
+        let url = template::render_escaped(&self.url, event, globals, Escape::Url);
+        let uri: Uri = url.parse().map_err(|e: hyper::http::uri::InvalidUri| HttpError::InvalidUrl {
+            url: url.clone(),
+            reason: e.to_string(),
+        })?;
+        let method = Method::from_bytes(self.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| HttpError::Settings(format!("unknown method '{}'", self.method)))?;
+        Ok(Outgoing {
+            method,
+            uri,
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), template::render_with(value, event, globals)))
+                .collect(),
+            body: self
+                .body
+                .as_ref()
+                .map(|body| template::render_escaped(body, event, globals, self.body_escape).into_bytes())
+                .unwrap_or_default(),
+            timeout: Duration::from_millis(self.timeout_ms),
+        })
+    }
+}
+
+/// A received response
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    /// Header names in lower case
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// The body as JSON if it parses, otherwise as text
+    pub fn body_value(&self) -> Value {
+        body_value(&self.body)
+    }
+}
+
+/// `body` as JSON if it parses, otherwise as text
+pub(crate) fn body_value(body: &[u8]) -> Value {
+    serde_json::from_slice(body).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// Where request actions put responses
+#[derive(Clone, Default)]
+pub struct ResponseTargets {
+    globals: Option<Arc<tokio::sync::Mutex<Globals>>>,
+    events: Option<EventBus>,
+}
+
+impl ResponseTargets {
+    pub fn with_globals(mut self, globals: Arc<tokio::sync::Mutex<Globals>>) -> Self {
+        self.globals = Some(globals);
+        self
+    }
+
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    async fn deliver(&self, request: &HttpRequest, event_name: Option<String>, outgoing: &Outgoing, response: &HttpResponse) {
+        if let Some(globals) = &self.globals {
+            let mut globals = globals.lock().await;
+            if let Some(name) = &request.status_variable {
+                globals.set_var(name, Value::from(response.status));
+            }
+            if let Some(name) = &request.body_variable {
+                globals.set_var(name, response.body_value());
+            }
+        }
+        if let (Some(events), Some(name)) = (&self.events, event_name) {
+            let payload = json!({
+                "method": outgoing.method.as_str(),
+                "url": outgoing.uri.to_string(),
+                "status": response.status,
+                "headers": response.headers,
+                "body": response.body_value(),
+            });
+            events.publish(NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(payload)));
+        }
+    }
+}
+
+/// Creates an action that sends `request` and waits for the response,
+/// handing it to `targets`
+///
+/// The action fails if no response arrives, not on error statuses.
+pub fn request_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    request: HttpRequest,
+    targets: ResponseTargets,
+) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            block_on(async {
+                let (outgoing, event_name) = {
+                    let globals = match &targets.globals {
+                        Some(globals) => Some(globals.lock().await),
+                        None => None,
+                    };
+                    let event_name = request.response_event.as_ref().map(|name| template::render_with(name, event, globals.as_deref()));
+                    (request.render(event, globals.as_deref())?, event_name)
+                };
+                let response = client::send(&outgoing).await?;
+                targets.deliver(&request, event_name, &outgoing, &response).await;
+                log::debug!("{} {} answered {}", outgoing.method, outgoing.uri, response.status);
+                Ok::<_, HttpError>(())
+            })
+            .map_err(|e| ActionError::ExecutionFailed(e.to_string()).into())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use crate::eg::action::base::ActionBase;
+
+    #[test]
+    fn test_render() {
+        let request: HttpRequest = serde_json::from_value(json!({
+            "method": "put",
+            "url": "http://hass.local:8123/api/{payload.room}",
+            "headers": {"X-Room": "{payload.room}"},
+            "body": "{{\"on\": {payload.on}}}",
+        }))
+        .unwrap();
+        let event = NamedEvent::new("Lights", EventType::User, EventPayload::from_json(json!({"room": "hall", "on": true})));
+        let outgoing = request.render(&event, None).unwrap();
+        assert_eq!(outgoing.method, Method::PUT);
+        assert_eq!(outgoing.uri.path(), "/api/hall");
+        assert_eq!(outgoing.headers["X-Room"], "hall");
+        assert_eq!(outgoing.body, b"{\"on\": true}");
+        assert_eq!(outgoing.timeout, Duration::from_secs(10));
+
+        assert!(HttpRequest::new("GET", "not a url").render(&event, None).is_err());
+        assert!(HttpRequest::new("G ET", "http://x/").render(&event, None).is_err());
+    }
+
+    #[test]
+    fn test_render_escapes_url_and_reads_globals() {
+        let mut globals = Globals::new();
+        globals.set_var("token", "a&b".to_string());
+        let request = HttpRequest::new("POST", "http://x/say/{payload}?key={var.token}")
+            .with_header("X-Token", "{var.token}")
+            .with_json_body(r#"{{"token": "{var.token}"}}"#);
+        let event = NamedEvent::new("Say", EventType::User, EventPayload::Text("hi there/you".into()));
+        let outgoing = request.render(&event, Some(&globals)).unwrap();
+        assert_eq!(outgoing.uri.path(), "/say/hi%20there%2Fyou");
+        assert_eq!(outgoing.uri.query(), Some("key=a%26b"));
+        assert_eq!(outgoing.headers["X-Token"], "a&b");
+        assert_eq!(serde_json::from_slice::<Value>(&outgoing.body).unwrap(), json!({"token": "a&b"}));
+    }
+
+    #[test]
+    fn test_json_body() {
+        let request = HttpRequest::new("POST", "http://hass.local/api").with_json_body(r#"{{"say": "{payload}"}}"#);
+        let event = NamedEvent::new("Say", EventType::User, EventPayload::Text(r#"the "hall""#.into()));
+        let outgoing = request.render(&event, None).unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&outgoing.body).unwrap(), json!({"say": r#"the "hall""#}));
+        let settings: HttpRequest = serde_json::from_value(json!({"url": "http://x/", "body_escape": "json"})).unwrap();
+        assert_eq!(settings.body_escape, Escape::Json);
+    }
+
+    #[tokio::test]
+    async fn test_request_action() {
+        let app = Router::new().route(
+            "/echo",
+            post(|headers: axum::http::HeaderMap, body: String| async move {
+                let room = headers.get("x-room").and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+                (axum::http::StatusCode::CREATED, Json(json!({"room": room, "got": body})))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let globals = Arc::new(tokio::sync::Mutex::new(Globals::new()));
+        let events = EventBus::default();
+        let mut received = events.subscribe();
+        let mut request = HttpRequest::new("POST", &format!("http://{}/echo", address))
+            .with_header("X-Room", "{payload.room}")
+            .with_body("dim to {payload.level}");
+        request.status_variable = Some("status".into());
+        request.body_variable = Some("answer".into());
+        request.response_event = Some("Echo.{payload.room}".into());
+        let targets = ResponseTargets::default().with_globals(globals.clone()).with_event_bus(events);
+        let mut action = request_action("Echo", "Call the echo service", Uuid::new_v4(), request, targets);
+
+        let event = NamedEvent::new("Dim", EventType::User, EventPayload::from_json(json!({"room": "hall", "level": 30})));
+        action.execute(&event).await.unwrap();
+        {
+            let globals = globals.lock().await;
+            assert_eq!(globals.get_var::<Value>("status"), Some(json!(201)));
+            assert_eq!(globals.get_var::<Value>("answer"), Some(json!({"room": "hall", "got": "dim to 30"})));
+        }
+        let response = received.recv().await.unwrap();
+        assert_eq!(response.get_id(), "Echo.hall");
+        assert_eq!(response.get_payload().to_json()["headers"]["content-type"], "application/json");
+
+        let mut missing = request_action(
+            "Missing",
+            "Nobody listens",
+            Uuid::new_v4(),
+            HttpRequest::new("GET", "http://127.0.0.1:1/"),
+            ResponseTargets::default(),
+        );
+        assert!(missing.execute(&event).await.is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Webhook plugin
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Query};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::JoinHandle;
+use crate::core::api::same_token;
+use crate::core::config::{Config, ConfigManager, SecretRef, SecretString};
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::plugins::{plugin_accessors, plugin_info, reveal, EventQueue, EventSink};
+use super::{body_value, HttpError};
+
+/// Header for tools that cannot send `Authorization`
+const TOKEN_HEADER: &str = "x-webhook-token";
+
+/// One path that accepts `POST`s
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    /// e.g. `/github`
+    pub path: String,
+    /// Event name after the prefix; empty to derive it from the path,
+    /// `/ci/done` giving `ci.done`
+    #[serde(default)]
+    pub event: String,
+}
+
+impl Webhook {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), event: String::new() }
+    }
+
+    fn event_name(&self, prefix: &str) -> String {
+        let suffix = match self.event.as_str() {
+            "" => self.path.trim_matches('/').replace('/', "."),
+            event => event.to_string(),
+        };
+        match prefix {
+            "" => suffix,
+            prefix => format!("{}.{}", prefix, suffix),
+        }
+    }
+}
+
+/// Settings of the `Webhook` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookSettings {
+    pub address: IpAddr,
+    pub port: u16,
+    /// Keystore reference to the token required as `Authorization: Bearer`,
+    /// `X-Webhook-Token` or a `token` query parameter; none to accept anyone
+    pub token: Option<SecretRef>,
+    pub hooks: Vec<Webhook>,
+    /// Largest accepted body in bytes
+    pub max_body: usize,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 8090,
+            token: None,
+            hooks: Vec::new(),
+            max_body: 1024 * 1024,
+            prefix: WebhookListener::NAME.to_string(),
+        }
+    }
+}
+
+impl WebhookSettings {
+    pub fn validate(&self) -> Result<(), HttpError> {
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if !hook.path.starts_with('/') || hook.path.contains(['{', '}', '*']) {
+                return Err(HttpError::Settings(format!("'{}' is not a plain absolute path", hook.path)));
+            }
+            if self.hooks[..index].iter().any(|other| other.path == hook.path) {
+                return Err(HttpError::Settings(format!("'{}' is configured twice", hook.path)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Raises an event for every `POST` on a configured path
+pub struct WebhookListener {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: WebhookSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    secrets: Option<Arc<ConfigManager>>,
+    task: Option<JoinHandle<()>>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl WebhookListener {
+    pub const NAME: &'static str = "Webhook";
+
+    pub fn new(settings: WebhookSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Raises events for webhooks posted by other tools",
+                vec![PluginCapability::EventGenerator, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            secrets: None,
+            task: None,
+            local_addr: None,
+        }
+    }
+
+    /// Publish webhook events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    /// Reveal the token from the keystore of `secrets`
+    pub fn with_secrets(mut self, secrets: Arc<ConfigManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    pub fn settings(&self) -> &WebhookSettings {
+        &self.settings
+    }
+
+    /// Address the listener is bound to while running
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    fn listen(&mut self) -> Result<(), HttpError> {
+        self.settings.validate()?;
+        let token = reveal(self.secrets.as_deref(), self.settings.token.as_ref()).map_err(HttpError::Settings)?;
+        let listener = std::net::TcpListener::bind((self.settings.address, self.settings.port))?;
+        listener.set_nonblocking(true)?;
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let local_addr = listener.local_addr()?;
+        log::info!("{} listening on {}", Self::NAME, local_addr);
+
+        let app = router(&self.settings, token, self.sink.clone());
+        self.local_addr = Some(local_addr);
+        self.task = Some(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                log::error!("{} stopped: {}", WebhookListener::NAME, e);
+            }
+        }));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.local_addr = None;
+    }
+}
+
+impl Drop for WebhookListener {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// A route per hook; other paths answer 404 and other methods 405
+fn router(settings: &WebhookSettings, token: Option<SecretString>, sink: EventSink) -> Router {
+    let token = Arc::new(token);
+    let mut router = Router::new();
+    for hook in &settings.hooks {
+        let name: Arc<str> = hook.event_name(&settings.prefix).into();
+        let path: Arc<str> = hook.path.as_str().into();
+        let (token, sink) = (token.clone(), sink.clone());
+        router = router.route(
+            &hook.path,
+            post(move |headers: HeaderMap, Query(query): Query<BTreeMap<String, String>>, body: Bytes| async move {
+                receive(&name, &path, &token, &sink, headers, query, body)
+            }),
+        );
+    }
+    router.layer(DefaultBodyLimit::max(settings.max_body))
+}
+
+fn receive(
+    name: &str,
+    path: &str,
+    token: &Option<SecretString>,
+    sink: &EventSink,
+    headers: HeaderMap,
+    mut query: BTreeMap<String, String>,
+    body: Bytes,
+) -> StatusCode {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+    let presented = header(AUTHORIZATION.as_str())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| header(TOKEN_HEADER))
+        .map(str::to_string)
+        .or_else(|| query.get("token").cloned());
+    query.remove("token");
+    if let Some(token) = token {
+        if !presented.is_some_and(|presented| same_token(presented.trim(), token.expose())) {
+            return StatusCode::UNAUTHORIZED;
+        }
+    }
+
+    let payload = json!({
+        "path": path,
+        "query": query,
+        "content_type": header(CONTENT_TYPE.as_str()).unwrap_or_default(),
+        "body": body_value(&body),
+    });
+    sink.emit(NamedEvent::new(name, EventType::Plugin, EventPayload::from_json(payload)).with_source(WebhookListener::NAME));
+    StatusCode::NO_CONTENT
+}
+
+#[async_trait]
+impl Plugin for WebhookListener {
+    plugin_accessors!();
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.listen() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: WebhookSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(HttpError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for WebhookListener {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use hyper::{Method, Uri};
+    use crate::plugins::http::client::{send, Outgoing};
+    use crate::testing::mock_secrets;
+
+    fn post(address: SocketAddr, target: &str, headers: &[(&str, &str)], body: &str) -> Outgoing {
+        Outgoing {
+            method: Method::POST,
+            uri: format!("http://{}{}", address, target).parse::<Uri>().unwrap(),
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+            body: body.as_bytes().to_vec(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_webhooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut listener = WebhookListener::new(WebhookSettings {
+            port: 0,
+            token: Some(SecretRef::new("webhook")),
+            hooks: vec![Webhook::new("/ci/done"), Webhook { path: "/github".into(), event: "Push".into() }],
+            ..Default::default()
+        })
+        .with_secrets(mock_secrets(dir.path(), &[("webhook", "secret")]));
+        listener.initialize().await.unwrap();
+        listener.start().await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let json = [("Authorization", "Bearer secret"), ("Content-Type", "application/json")];
+        let response = send(&post(address, "/github?branch=main", &json, r#"{"ref": "main"}"#)).await.unwrap();
+        assert_eq!(response.status, 204);
+        let event = listener.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "Webhook.Push");
+        let payload = event.get_payload().to_json();
+        assert_eq!(payload["body"]["ref"], "main");
+        assert_eq!(payload["query"], json!({"branch": "main"}));
+        assert_eq!(payload["content_type"], "application/json");
+
+        let response = send(&post(address, "/ci/done?token=secret", &[], "build 42 passed")).await.unwrap();
+        assert_eq!(response.status, 204);
+        let event = listener.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "Webhook.ci.done");
+        assert_eq!(event.get_payload().to_json()["body"], "build 42 passed");
+        assert_eq!(event.get_payload().to_json()["query"], json!({}));
+
+        assert_eq!(send(&post(address, "/github", &[("X-Webhook-Token", "guess")], "")).await.unwrap().status, 401);
+        assert_eq!(send(&post(address, "/other", &[("X-Webhook-Token", "secret")], "")).await.unwrap().status, 404);
+        let mut get = post(address, "/github", &[("X-Webhook-Token", "secret")], "");
+        get.method = Method::GET;
+        assert_eq!(send(&get).await.unwrap().status, 405);
+
+        listener.stop().await.unwrap();
+        let invalid = WebhookSettings { hooks: vec![Webhook::new("relative")], ..Default::default() };
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! LIRC client plugin
+
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, reconnect, sleep_until, EventQueue, EventSink};
+use super::{send_action, ButtonLine, LircError, LircSettings};
+
+/// Raises button presses decoded by lircd as events
+///
+/// The connection is re-established every `reconnect_ms` while lircd is
+/// away.
+pub struct LircClient {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: LircSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    task: Option<JoinHandle<()>>,
+}
+
+impl LircClient {
+    pub const NAME: &'static str = "LIRC";
+
+    pub fn new(settings: LircSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Receives and sends infrared codes through lircd",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            task: None,
+        }
+    }
+
+    /// Publish button events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &LircSettings {
+        &self.settings
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for LircClient {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Stay connected to lircd until aborted
+async fn run(settings: LircSettings, sink: EventSink) {
+    let (settings, sink) = (&settings, &sink);
+    let what = format!("cannot reach lircd at {}", settings.socket.display());
+    reconnect(
+        LircClient::NAME,
+        &what,
+        Duration::from_millis(settings.reconnect_ms),
+        || UnixStream::connect(&settings.socket),
+        |stream| async move {
+            log::info!("{} connected to {}", LircClient::NAME, settings.socket.display());
+            match read_buttons(stream, settings, sink).await {
+                Ok(()) => log::warn!("{}: lircd closed the connection", LircClient::NAME),
+                Err(e) => log::warn!("{}: connection lost: {}", LircClient::NAME, e),
+            }
+        },
+    )
+    .await
+}
+
+/// The button being held
+struct Held {
+    name: String,
+    line: ButtonLine,
+    deadline: Instant,
+}
+
+/// Turn button lines into press and release events until the connection ends
+async fn read_buttons(stream: UnixStream, settings: &LircSettings, sink: &EventSink) -> Result<(), LircError> {
+    let release_timeout = Duration::from_millis(settings.release_timeout_ms);
+    let mut lines = BufReader::new(stream).lines();
+    let mut held: Option<Held> = None;
+
+    let release = |held: Option<Held>| {
+        if let Some(held) = held {
+            sink.emit(button_event(&format!("{}.Released", held.name), &held.line));
+        }
+    };
+
+    loop {
+        let deadline = held.as_ref().map(|held| held.deadline);
+        let line = tokio::select! {
+            line = lines.next_line() => line,
+            _ = sleep_until(deadline) => {
+                release(held.take());
+                continue;
+            }
+        };
+        let line = match line {
+            Ok(Some(line)) => line,
+            other => {
+                release(held.take());
+                return other.map(|_| ()).map_err(LircError::from);
+            }
+        };
+        let Some(button) = ButtonLine::parse(&line) else {
+            continue;
+        };
+
+        let name = event_name(settings, &button);
+        match &mut held {
+            Some(current) if current.name == name && button.repeat > 0 => {
+                current.deadline = Instant::now() + release_timeout;
+                current.line = button;
+            }
+            _ => {
+                release(held.take());
+                sink.emit(button_event(&name, &button));
+                held = Some(Held { name, line: button, deadline: Instant::now() + release_timeout });
+            }
+        }
+    }
+}
+
+fn event_name(settings: &LircSettings, line: &ButtonLine) -> String {
+    let mut parts = Vec::with_capacity(3);
+    if !settings.prefix.is_empty() {
+        parts.push(settings.prefix.as_str());
+    }
+    if settings.include_remote {
+        parts.push(&line.remote);
+    }
+    parts.push(&line.button);
+    parts.join(".")
+}
+
+fn button_event(name: &str, line: &ButtonLine) -> NamedEvent {
+    let payload = json!({
+        "remote": line.remote,
+        "button": line.button,
+        "code": line.code,
+        "repeat": line.repeat,
+    });
+    NamedEvent::new(name, EventType::KeyPress, EventPayload::from_json(payload)).with_source(LircClient::NAME)
+}
+
+#[async_trait]
+impl Plugin for LircClient {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        match name {
+            "Send" => Some(action_args(name, args).map(|command| {
+                send_action(name, "Sends an infrared code", self.info.id, &self.settings, command)
+            })),
+            _ => None,
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        self.task = Some(tokio::spawn(run(self.settings.clone(), self.sink.clone())));
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: LircSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(LircError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for LircClient {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_with_fake_lircd() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("lircd");
+        let lircd = UnixListener::bind(&socket).unwrap();
+        let mut client = LircClient::new(LircSettings {
+            socket,
+            release_timeout_ms: 100,
+            reconnect_ms: 10,
+            ..Default::default()
+        });
+        client.initialize().await.unwrap();
+        client.start().await.unwrap();
+
+        let (mut stream, _) = lircd.accept().await.unwrap();
+        stream.write_all(b"0000000000000001 00 KEY_UP tv\n").await.unwrap();
+        let event = client.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "LIRC.KEY_UP");
+        assert_eq!(event.get_type(), EventType::KeyPress);
+        assert_eq!(event.get_payload().to_json()["remote"], "tv");
+
+        // Repeats extend the press instead of raising it again
+        stream.write_all(b"0000000000000001 01 KEY_UP tv\nBEGIN\nSIGHUP\nEND\n").await.unwrap();
+        stream.write_all(b"0000000000000001 02 KEY_UP tv\n").await.unwrap();
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_UP.Released");
+
+        // Another button ends the held one at once
+        stream.write_all(b"0000000000000002 00 KEY_DOWN tv\n0000000000000003 00 KEY_OK tv\n").await.unwrap();
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_DOWN");
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_DOWN.Released");
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_OK");
+
+        // Losing lircd releases the button, and the client comes back
+        drop(stream);
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_OK.Released");
+        let (mut stream, _) = lircd.accept().await.unwrap();
+        stream.write_all(b"0000000000000004 00 KEY_MUTE tv\n").await.unwrap();
+        assert_eq!(next_event(&mut client).await.0, "LIRC.KEY_MUTE");
+
+        client.stop().await.unwrap();
+    }
+
+    #[test]
+    fn test_event_name() {
+        let line = ButtonLine::parse("0000000000000001 00 KEY_UP tv").unwrap();
+        let settings = LircSettings { include_remote: true, ..Default::default() };
+        assert_eq!(event_name(&settings, &line), "LIRC.tv.KEY_UP");
+        let settings = LircSettings { prefix: String::new(), ..Default::default() };
+        assert_eq!(event_name(&settings, &line), "KEY_UP");
+    }
+}
@@ -0,0 +1,270 @@
+//! LIRC infrared remotes through lircd's Unix socket
+//!
+//! lircd writes one line per decoded IR frame:
+//!
+//! ```text
+//! 000000037ff07bee 00 KEY_VOLUMEUP philips
+//! 000000037ff07bee 01 KEY_VOLUMEUP philips
+//! ```
+//!
+//! that is code, repeat count in hex, button and remote. A button press
+//! raises `LIRC.<button>` (or `LIRC.<remote>.<button>` with
+//! `include_remote`); repeats while the button is held extend that event,
+//! and once they stop for `release_timeout_ms` the press ends with
+//! `LIRC.<button>.Released`.
+//!
+//! Commands are written as a line, e.g. `SEND_ONCE philips KEY_POWER`, and
+//! answered with `BEGIN`, the command, `SUCCESS` or `ERROR`, optional
+//! `DATA` lines and `END`; button lines may arrive in between.
+
+pub mod client;
+
+pub use client::LircClient;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use uuid::Uuid;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{block_on, template};
+
+/// Where lircd puts its socket on most distributions
+pub const DEFAULT_SOCKET: &str = "/var/run/lirc/lircd";
+
+/// Error type for the LIRC plugin
+#[derive(Debug, thiserror::Error)]
+pub enum LircError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("lircd refused '{command}': {message}")]
+    Refused { command: String, message: String },
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<LircError> for Error {
+    fn from(e: LircError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Settings of the `LIRC` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LircSettings {
+    /// lircd's socket
+    pub socket: PathBuf,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    /// Put the remote's name before the button's in event names
+    pub include_remote: bool,
+    /// A held button counts as released after this long without repeats
+    pub release_timeout_ms: u64,
+    /// Delay between attempts to reach lircd
+    pub reconnect_ms: u64,
+    /// How long to wait for lircd to answer a command
+    pub command_timeout_ms: u64,
+}
+
+impl Default for LircSettings {
+    fn default() -> Self {
+        Self {
+            socket: PathBuf::from(DEFAULT_SOCKET),
+            prefix: LircClient::NAME.to_string(),
+            include_remote: false,
+            release_timeout_ms: 200,
+            reconnect_ms: 5_000,
+            command_timeout_ms: 2_000,
+        }
+    }
+}
+
+/// One decoded IR frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ButtonLine {
+    pub code: String,
+    pub repeat: u32,
+    pub button: String,
+    pub remote: String,
+}
+
+impl ButtonLine {
+    /// Parse a button line; `None` for anything else lircd writes
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let code = fields.next()?;
+        let repeat = u32::from_str_radix(fields.next()?, 16).ok()?;
+        let button = fields.next()?;
+        let remote = fields.next()?;
+        if fields.next().is_some() || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(Self {
+            code: code.to_string(),
+            repeat,
+            button: button.to_string(),
+            remote: remote.to_string(),
+        })
+    }
+}
+
+/// Send `button` of `remote` once, or `repeats` more times, through the
+/// lircd at `socket`
+pub async fn send_once(socket: &Path, remote: &str, button: &str, repeats: u32, timeout: Duration) -> Result<(), LircError> {
+    let command = match repeats {
+        0 => format!("SEND_ONCE {} {}", remote, button),
+        n => format!("SEND_ONCE {} {} {}", remote, button, n),
+    };
+    let exchange = async {
+        let mut stream = UnixStream::connect(socket).await?;
+        stream.write_all(format!("{}\n", command).as_bytes()).await?;
+        read_reply(BufReader::new(stream), &command).await
+    };
+    tokio::time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| LircError::Protocol(format!("no reply to '{}'", command)))?
+}
+
+/// Next line from lircd, without its line break
+async fn next_line(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<String, LircError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(LircError::Protocol("lircd closed the connection".into()));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+/// Wait for the reply to `command`, skipping button lines
+async fn read_reply(mut reader: impl AsyncBufRead + Unpin, command: &str) -> Result<(), LircError> {
+    let reader = &mut reader;
+    loop {
+        if next_line(reader).await? != "BEGIN" {
+            continue;
+        }
+        if next_line(reader).await? != command {
+            // A reply to someone else, e.g. the SIGHUP broadcast
+            while next_line(reader).await? != "END" {}
+            continue;
+        }
+        let success = match next_line(reader).await?.as_str() {
+            "SUCCESS" => true,
+            "ERROR" => false,
+            other => return Err(LircError::Protocol(format!("unexpected reply status '{}'", other))),
+        };
+        let mut data = Vec::new();
+        loop {
+            match next_line(reader).await?.as_str() {
+                "END" => break,
+                "DATA" => {
+                    let count: usize =
+                        next_line(reader).await?.parse().map_err(|_| LircError::Protocol("bad DATA count".into()))?;
+                    for _ in 0..count {
+                        data.push(next_line(reader).await?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        return match success {
+            true => Ok(()),
+            false => Err(LircError::Refused { command: command.to_string(), message: data.join(" ") }),
+        };
+    }
+}
+
+/// What a send action sends; remote and button are
+/// [templates](crate::plugins::template)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendCommand {
+    pub remote: String,
+    pub button: String,
+    /// Extra repetitions, for buttons the device wants held
+    #[serde(default)]
+    pub repeats: u32,
+}
+
+/// Creates an action that sends `command` through the lircd of `settings`,
+/// waiting for lircd's answer
+pub fn send_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    settings: &LircSettings,
+    command: SendCommand,
+) -> ActionItem {
+    let socket = settings.socket.clone();
+    let timeout = Duration::from_millis(settings.command_timeout_ms);
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let remote = template::render(&command.remote, event);
+            let button = template::render(&command.button, event);
+            block_on(send_once(&socket, &remote, &button, command.repeats, timeout))
+                .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader as StdBufReader, Cursor, Write};
+    use std::os::unix::net::UnixListener;
+    use crate::core::event::{EventPayload, EventType, NamedEvent};
+    use crate::eg::action::base::ActionBase;
+
+    #[test]
+    fn test_parse_button_line() {
+        let line = ButtonLine::parse("000000037ff07bee 0a KEY_UP philips\n").unwrap();
+        assert_eq!(line.repeat, 10);
+        assert_eq!(line.button, "KEY_UP");
+        assert_eq!(line.remote, "philips");
+        assert!(ButtonLine::parse("BEGIN").is_none());
+        assert!(ButtonLine::parse("SEND_ONCE philips KEY_UP").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_reply() {
+        let reply = "0000 00 KEY_UP philips\nBEGIN\nSIGHUP\nEND\nBEGIN\nSEND_ONCE tv KEY_POWER\nSUCCESS\nEND\n";
+        assert!(read_reply(Cursor::new(reply), "SEND_ONCE tv KEY_POWER").await.is_ok());
+
+        let reply = "BEGIN\nSEND_ONCE tv KEY_NOPE\nERROR\nDATA\n1\nunknown command: \"KEY_NOPE\"\nEND\n";
+        let error = read_reply(Cursor::new(reply), "SEND_ONCE tv KEY_NOPE").await.unwrap_err();
+        assert!(matches!(error, LircError::Refused { message, .. } if message.contains("KEY_NOPE")));
+    }
+
+    #[tokio::test]
+    async fn test_send_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lircd");
+        let listener = UnixListener::bind(&path).unwrap();
+        let lircd = std::thread::spawn(move || {
+            let mut commands = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut command = String::new();
+                StdBufReader::new(&stream).read_line(&mut command).unwrap();
+                let reply = format!("BEGIN\n{}SUCCESS\nEND\n", command);
+                stream.write_all(reply.as_bytes()).unwrap();
+                commands.push(command);
+            }
+            commands
+        });
+
+        send_once(&path, "tv", "KEY_POWER", 2, Duration::from_secs(5)).await.unwrap();
+        let settings = LircSettings { socket: path.clone(), ..Default::default() };
+        let command = SendCommand { remote: "tv".into(), button: "{suffix}".into(), repeats: 0 };
+        let mut action = send_action("Send", "Send a button", Uuid::new_v4(), &settings, command);
+        action.execute(&NamedEvent::new("Remote.KEY_MUTE", EventType::User, EventPayload::None)).await.unwrap();
+        assert_eq!(lircd.join().unwrap(), ["SEND_ONCE tv KEY_POWER 2\n", "SEND_ONCE tv KEY_MUTE\n"]);
+    }
+}
@@ -0,0 +1,328 @@
+//! Built-in plugins
+//!
+//! Built-in plugins are enabled by adding an entry to the settings file
+//! whose `id` is the plugin name, e.g.
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "NetworkReceiver"
+//! enabled = true
+//! settings = { port = 1024, password = { "$secret" = "network" } }
+//! ```
+//!
+//! Passwords and tokens are [secret references](crate::core::config::secrets)
+//! into the keystore, revealed through [`ConfigManager::reveal_secret`] when
+//! the plugin connects.
+
+pub mod actions;
+#[cfg(unix)]
+pub mod dbus;
+pub mod directory;
+pub mod email;
+pub mod http;
+#[cfg(unix)]
+pub mod lirc;
+pub mod mqtt;
+pub mod network;
+#[cfg(target_os = "linux")]
+pub mod process;
+pub mod scheduler;
+#[cfg(unix)]
+pub mod serial;
+pub mod sun;
+pub mod template;
+pub mod xpl;
+
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::{mpsc, Mutex};
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use uuid::Uuid;
+use crate::core::config::{Config, ConfigManager, SecretRef, SecretString};
+use crate::core::event::{Event, EventBus, NamedEvent};
+use crate::core::plugin::{PluginCapability, PluginInfo};
+use crate::core::{Error, Plugin};
+use crate::eg::globals::Globals;
+use crate::eg::action::base::ActionError;
+
+/// Create the built-in plugins enabled in `config`; their events go to `events`,
+/// their secret settings are revealed through `secrets` and their actions
+/// see the variables in `globals`
+///
+/// Entries for plugins that are not built in are left to the plugin loader.
+pub fn builtin(
+    config: &Config,
+    events: &EventBus,
+    secrets: Option<&Arc<ConfigManager>>,
+    globals: &Arc<Mutex<Globals>>,
+) -> Vec<Box<dyn Plugin>> {
+    let mut plugins: Vec<Box<dyn Plugin>> = Vec::new();
+    for entry in config.plugins.iter().filter(|entry| entry.enabled) {
+        let plugin: Result<Box<dyn Plugin>, String> = match entry.id.as_str() {
+            network::NetworkReceiver::NAME => settings_for(config, &entry.id).map(|settings| {
+                let receiver = network::NetworkReceiver::new(settings).with_event_bus(events.clone());
+                Box::new(with_secrets(receiver, secrets, network::NetworkReceiver::with_secrets)) as _
+            }),
+            actions::NETWORK_SENDER => settings_for(config, &entry.id)
+                .map(|settings| Box::new(actions::network_sender(settings, secrets.cloned())) as _),
+            actions::HTTP => Ok(Box::new(actions::http(globals.clone(), events.clone())) as _),
+            actions::EMAIL => settings_for(config, &entry.id)
+                .map(|settings| Box::new(actions::email(settings, secrets.cloned(), globals.clone())) as _),
+            directory::DirectoryWatcher::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(directory::DirectoryWatcher::new(settings).with_event_bus(events.clone())) as _),
+            #[cfg(unix)]
+            dbus::DbusClient::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(dbus::DbusClient::new(settings).with_event_bus(events.clone())) as _),
+            http::WebhookListener::NAME => settings_for(config, &entry.id).map(|settings| {
+                let listener = http::WebhookListener::new(settings).with_event_bus(events.clone());
+                Box::new(with_secrets(listener, secrets, http::WebhookListener::with_secrets)) as _
+            }),
+            #[cfg(unix)]
+            lirc::LircClient::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(lirc::LircClient::new(settings).with_event_bus(events.clone())) as _),
+            mqtt::MqttClient::NAME => settings_for(config, &entry.id).map(|settings| {
+                let client = mqtt::MqttClient::new(settings).with_event_bus(events.clone());
+                Box::new(with_secrets(client, secrets, mqtt::MqttClient::with_secrets)) as _
+            }),
+            #[cfg(target_os = "linux")]
+            process::ProcessWatcher::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(process::ProcessWatcher::new(settings).with_event_bus(events.clone())) as _),
+            scheduler::Scheduler::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(scheduler::Scheduler::new(settings).with_event_bus(events.clone())) as _),
+            #[cfg(unix)]
+            serial::SerialClient::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(serial::SerialClient::new(settings).with_event_bus(events.clone())) as _),
+            sun::SunTracker::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(sun::SunTracker::new(settings).with_event_bus(events.clone())) as _),
+            xpl::XplGateway::NAME => settings_for(config, &entry.id)
+                .map(|settings| Box::new(xpl::XplGateway::new(settings).with_event_bus(events.clone())) as _),
+            _ => continue,
+        };
+        match plugin {
+            Ok(plugin) => plugins.push(plugin),
+            Err(e) => log::warn!("Not starting {}: {}", entry.id, e),
+        }
+    }
+    plugins
+}
+
+/// Typed settings of the plugin entry `id`; defaults if there is no entry
+/// or it has no settings
+pub fn settings_for<T: DeserializeOwned + Default>(config: &Config, id: &str) -> Result<T, String> {
+    match config.plugins.iter().find(|entry| entry.id == id) {
+        Some(entry) if !entry.settings.is_null() => serde_path_to_error::deserialize(&entry.settings)
+            .map_err(|e| format!("plugins.{}.settings.{}: {}", id, e.path(), e.inner())),
+        _ => Ok(T::default()),
+    }
+}
+
+fn with_secrets<P>(plugin: P, secrets: Option<&Arc<ConfigManager>>, attach: fn(P, Arc<ConfigManager>) -> P) -> P {
+    match secrets {
+        Some(secrets) => attach(plugin, secrets.clone()),
+        None => plugin,
+    }
+}
+
+/// Clear text of the optional `secret` from the keystore of `secrets`
+pub(crate) fn reveal(secrets: Option<&ConfigManager>, secret: Option<&SecretRef>) -> Result<Option<SecretString>, String> {
+    let Some(secret) = secret else {
+        return Ok(None);
+    };
+    let secrets = secrets.ok_or_else(|| format!("no keystore to reveal secret '{}' from", secret.name))?;
+    secrets.reveal_secret(secret).map(Some).map_err(|e| e.to_string())
+}
+
+/// Arguments of the plugin action `action`, as given in a tree call
+pub(crate) fn action_args<T: DeserializeOwned>(action: &str, args: &Value) -> Result<T, Error> {
+    serde_path_to_error::deserialize(args)
+        .map_err(|e| ActionError::ConfigurationFailed(format!("{}: {}: {}", action, e.path(), e.inner())).into())
+}
+
+/// Wait until `deadline`, or forever without one
+pub(crate) async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Drive `future` to completion from a synchronous action handler
+///
+/// Handlers run on the runtime's blocking pool, whose handle drives the
+/// future; called outside a runtime, e.g. from a test, a private one does.
+pub(crate) fn block_on<T, E: From<std::io::Error>>(future: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => tokio::runtime::Builder::new_current_thread().enable_all().build()?.block_on(future),
+    }
+}
+
+/// TLS client settings trusting the system's certificate authorities,
+/// built once and shared by the HTTP and MQTT clients
+pub(crate) fn tls_client_config() -> Result<Arc<ClientConfig>, String> {
+    static CONFIG: OnceLock<Result<Arc<ClientConfig>, String>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let mut roots = RootCertStore::empty();
+            let native = rustls_native_certs::load_native_certs();
+            for e in &native.errors {
+                log::warn!("Skipping system certificates: {}", e);
+            }
+            roots.add_parsable_certificates(native.certs);
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map(|builder| Arc::new(builder.with_root_certificates(roots).with_no_client_auth()))
+                .map_err(|e| e.to_string())
+        })
+        .clone()
+}
+
+/// The [`Plugin`] methods every built-in plugin answers from its `info`,
+/// `state` and `config` fields; expand inside `impl Plugin`
+///
+/// `initialize` stays with each plugin, `#[async_trait]` cannot rewrite
+/// async methods a macro produces.
+macro_rules! plugin_accessors {
+    () => {
+        fn get_info(&self) -> $crate::core::plugin::PluginInfo {
+            self.info.clone()
+        }
+
+        fn get_capabilities(&self) -> Vec<$crate::core::plugin::PluginCapability> {
+            self.info.capabilities.clone()
+        }
+
+        fn get_state(&self) -> $crate::core::plugin::PluginState {
+            self.state
+        }
+
+        fn get_config(&self) -> Option<&$crate::core::config::Config> {
+            self.config.as_ref()
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            self
+        }
+
+        fn get_name(&self) -> &str {
+            &self.info.name
+        }
+
+        fn get_description(&self) -> &str {
+            &self.info.description
+        }
+
+        fn get_author(&self) -> &str {
+            &self.info.author
+        }
+
+        fn get_version(&self) -> &str {
+            &self.info.version
+        }
+    };
+}
+pub(crate) use plugin_accessors;
+
+/// Keep a connection up until aborted: `connect`, hand the connection to
+/// `serve` until it ends, and try again `retry` later
+///
+/// Only the first failure in a row is logged, as `<name>: <what>: <error>`;
+/// the other end may be away for long.
+#[cfg(unix)]
+pub(crate) async fn reconnect<C, E: std::fmt::Display, F: Future<Output = Result<C, E>>, S: Future<Output = ()>>(
+    name: &str,
+    what: &str,
+    retry: Duration,
+    mut connect: impl FnMut() -> F,
+    mut serve: impl FnMut(C) -> S,
+) {
+    let mut reported = false;
+    loop {
+        match connect().await {
+            Ok(connection) => {
+                reported = false;
+                serve(connection).await;
+            }
+            Err(e) if !reported => {
+                log::warn!("{}: {}: {}", name, what, e);
+                reported = true;
+            }
+            Err(_) => {}
+        }
+        tokio::time::sleep(retry).await;
+    }
+}
+
+/// Description of a built-in plugin
+pub(crate) fn plugin_info(name: &str, description: &str, capabilities: Vec<PluginCapability>) -> PluginInfo {
+    PluginInfo {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        description: description.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        author: "EventGhost".to_string(),
+        homepage: None,
+        platforms: vec!["all".to_string()],
+        capabilities,
+    }
+}
+
+/// Where a built-in plugin's events go
+///
+/// Every event plugin here takes a `with_event_bus` builder: events go to
+/// the bus given to it, otherwise they queue up in an [`EventQueue`] for
+/// [`EventGenerator::generate_event`](crate::core::plugin::EventGenerator::generate_event).
+#[derive(Clone)]
+pub(crate) enum EventSink {
+    Bus(EventBus),
+    Queue(mpsc::UnboundedSender<NamedEvent>),
+}
+
+impl EventSink {
+    /// A sink feeding a new queue
+    pub(crate) fn queue() -> (Self, EventQueue) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (EventSink::Queue(sender), EventQueue(Mutex::new(receiver)))
+    }
+
+    pub(crate) fn emit(&self, event: NamedEvent) {
+        match self {
+            EventSink::Bus(bus) => {
+                bus.publish(event);
+            }
+            EventSink::Queue(queue) => {
+                let _ = queue.send(event);
+            }
+        }
+    }
+}
+
+/// Events waiting for `EventGenerator::generate_event`
+pub(crate) struct EventQueue(Mutex<mpsc::UnboundedReceiver<NamedEvent>>);
+
+impl EventQueue {
+    /// Wait for the next event of plugin `name`
+    pub(crate) async fn next(&self, name: &str) -> Result<Box<dyn Event>, Error> {
+        match self.0.lock().await.recv().await {
+            Some(event) => Ok(Box::new(event)),
+            None => Err(Error::Other(format!("{} has stopped", name))),
+        }
+    }
+
+    /// An event if one is waiting
+    #[cfg(test)]
+    pub(crate) async fn try_next(&self) -> Option<NamedEvent> {
+        self.0.lock().await.try_recv().ok()
+    }
+}
+
+/// Name and JSON payload of the next event `plugin` generates, failing the
+/// test after five seconds
+#[cfg(test)]
+pub(crate) async fn next_event(plugin: &mut impl crate::core::plugin::EventGenerator) -> (String, Value) {
+    let event = tokio::time::timeout(Duration::from_secs(5), plugin.generate_event()).await.unwrap().unwrap();
+    (event.get_id().to_string(), event.get_payload().to_json())
+}
@@ -0,0 +1,609 @@
+//! MQTT client plugin
+
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use rumqttc::v5::mqttbytes::v5::LastWill as V5LastWill;
+use rumqttc::v5::mqttbytes::QoS as V5QoS;
+use rumqttc::Outgoing;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::core::config::{Config, ConfigManager};
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, reveal, tls_client_config, EventQueue, EventSink};
+use super::{publish_action, MqttError, MqttPublisher, MqttSettings, MqttVersion, Qos};
+
+/// Outstanding requests before publishing reports the client as busy
+const REQUEST_CAPACITY: usize = 64;
+
+/// How long stopping waits for the disconnect to go out
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A client of either protocol version
+#[derive(Clone)]
+pub(crate) enum Connection {
+    V4(rumqttc::AsyncClient),
+    V5(rumqttc::v5::AsyncClient),
+}
+
+impl Connection {
+    pub(crate) fn publish(&self, topic: &str, payload: &[u8], qos: Qos, retain: bool) -> Result<(), MqttError> {
+        match self {
+            Connection::V4(client) => client.try_publish(topic, qos.into(), retain, payload.to_vec()).map_err(client_error),
+            Connection::V5(client) => client.try_publish(topic, qos.into(), retain, payload.to_vec()).map_err(client_error),
+        }
+    }
+
+    fn subscribe(&self, filter: &str, qos: Qos) -> Result<(), MqttError> {
+        match self {
+            Connection::V4(client) => client.try_subscribe(filter, qos.into()).map_err(client_error),
+            Connection::V5(client) => client.try_subscribe(filter, qos.into()).map_err(client_error),
+        }
+    }
+
+    fn disconnect(&self) {
+        match self {
+            Connection::V4(client) => {
+                let _ = client.try_disconnect();
+            }
+            Connection::V5(client) => {
+                let _ = client.try_disconnect();
+            }
+        }
+    }
+}
+
+fn client_error(e: impl std::fmt::Display) -> MqttError {
+    MqttError::Client(e.to_string())
+}
+
+impl From<Qos> for rumqttc::QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => rumqttc::QoS::AtMostOnce,
+            Qos::AtLeastOnce => rumqttc::QoS::AtLeastOnce,
+            Qos::ExactlyOnce => rumqttc::QoS::ExactlyOnce,
+        }
+    }
+}
+
+impl From<Qos> for V5QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => V5QoS::AtMostOnce,
+            Qos::AtLeastOnce => V5QoS::AtLeastOnce,
+            Qos::ExactlyOnce => V5QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// What the event loop of either version reported
+enum Step {
+    Connected,
+    Message { topic: String, payload: Vec<u8> },
+    Disconnected,
+    Failed(String),
+    Other,
+}
+
+enum EventLoop {
+    V4(Box<rumqttc::EventLoop>),
+    V5(Box<rumqttc::v5::EventLoop>),
+}
+
+impl EventLoop {
+    async fn next(&mut self) -> Step {
+        use rumqttc::v5::{Event as V5Event, Incoming as V5Incoming};
+        use rumqttc::{Event as V4Event, Incoming as V4Incoming};
+
+        match self {
+            EventLoop::V4(eventloop) => match eventloop.poll().await {
+                Ok(V4Event::Incoming(V4Incoming::ConnAck(_))) => Step::Connected,
+                Ok(V4Event::Incoming(V4Incoming::Publish(publish))) => Step::Message {
+                    topic: publish.topic,
+                    payload: publish.payload.to_vec(),
+                },
+                Ok(V4Event::Outgoing(Outgoing::Disconnect)) => Step::Disconnected,
+                Ok(_) => Step::Other,
+                Err(e) => Step::Failed(e.to_string()),
+            },
+            EventLoop::V5(eventloop) => match eventloop.poll().await {
+                Ok(V5Event::Incoming(V5Incoming::ConnAck(_))) => Step::Connected,
+                Ok(V5Event::Incoming(V5Incoming::Publish(publish))) => Step::Message {
+                    topic: String::from_utf8_lossy(&publish.topic).into_owned(),
+                    payload: publish.payload.to_vec(),
+                },
+                Ok(V5Event::Outgoing(Outgoing::Disconnect)) => Step::Disconnected,
+                Ok(_) => Step::Other,
+                Err(e) => Step::Failed(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Subscribes to topics on an MQTT broker and raises their messages as
+/// events
+///
+/// The connection is kept up while the plugin runs: after a failure the
+/// client reconnects, waiting `reconnect_min_ms` and doubling the wait up
+/// to `reconnect_max_ms`, and subscribes again once the broker accepts it.
+pub struct MqttClient {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: MqttSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    publisher: MqttPublisher,
+    secrets: Option<Arc<ConfigManager>>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl MqttClient {
+    pub const NAME: &'static str = "MQTT";
+
+    pub fn new(settings: MqttSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Subscribes to and publishes on an MQTT broker",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            publisher: MqttPublisher::default(),
+            secrets: None,
+            task: None,
+        }
+    }
+
+    /// Publish received messages on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    /// Reveal the broker password from the keystore of `secrets`
+    pub fn with_secrets(mut self, secrets: Arc<ConfigManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    pub fn settings(&self) -> &MqttSettings {
+        &self.settings
+    }
+
+    /// Handle for publish actions; usable before the plugin starts
+    pub fn publisher(&self) -> MqttPublisher {
+        self.publisher.clone()
+    }
+
+    fn connect(&mut self) -> Result<(), MqttError> {
+        self.settings.validate()?;
+        let settings = self.settings.clone();
+        let password = reveal(self.secrets.as_deref(), settings.password.as_ref()).map_err(MqttError::Settings)?;
+        let password = password.as_ref().map(|password| password.expose()).unwrap_or_default();
+        let client_id = match settings.client_id.as_str() {
+            "" => format!("eventghost-{}", Uuid::new_v4().simple()),
+            id => id.to_string(),
+        };
+        // Version 5 refuses keep alives under five seconds
+        let keep_alive = Duration::from_secs(settings.keep_alive_secs.max(5));
+        let transport = if settings.tls {
+            let config = tls_client_config().map_err(MqttError::Settings)?;
+            rumqttc::Transport::tls_with_config(rumqttc::TlsConfiguration::Rustls(config))
+        } else {
+            rumqttc::Transport::tcp()
+        };
+
+        let (connection, eventloop) = match settings.protocol {
+            MqttVersion::V311 => {
+                let mut options = rumqttc::MqttOptions::new(client_id, &settings.host, settings.port);
+                options.set_keep_alive(keep_alive);
+                options.set_transport(transport);
+                if !settings.username.is_empty() {
+                    options.set_credentials(&settings.username, password);
+                }
+                if let Some(will) = &settings.last_will {
+                    options.set_last_will(rumqttc::LastWill::new(
+                        &will.topic,
+                        will.payload.as_bytes(),
+                        will.qos.into(),
+                        will.retain,
+                    ));
+                }
+                let (client, eventloop) = rumqttc::AsyncClient::new(options, REQUEST_CAPACITY);
+                (Connection::V4(client), EventLoop::V4(Box::new(eventloop)))
+            }
+            MqttVersion::V5 => {
+                let mut options = rumqttc::v5::MqttOptions::new(client_id, &settings.host, settings.port);
+                options.set_keep_alive(keep_alive);
+                options.set_transport(transport);
+                if !settings.username.is_empty() {
+                    options.set_credentials(&settings.username, password);
+                }
+                if let Some(will) = &settings.last_will {
+                    options.set_last_will(V5LastWill::new(
+                        &will.topic,
+                        will.payload.as_bytes(),
+                        will.qos.into(),
+                        will.retain,
+                        None,
+                    ));
+                }
+                let (client, eventloop) = rumqttc::v5::AsyncClient::new(options, REQUEST_CAPACITY);
+                (Connection::V5(client), EventLoop::V5(Box::new(eventloop)))
+            }
+        };
+
+        log::info!("{} connecting to {}:{}", Self::NAME, settings.host, settings.port);
+        self.publisher.set(Some(connection.clone()));
+        self.task = Some(tokio::spawn(run(eventloop, connection, settings, self.sink.clone())));
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) {
+        let Some(mut task) = self.task.take() else {
+            return;
+        };
+        if let Some(connection) = self.publisher.take() {
+            connection.disconnect();
+        }
+        if tokio::time::timeout(DISCONNECT_TIMEOUT, &mut task).await.is_err() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for MqttClient {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Drive the connection until the client disconnects
+async fn run(mut eventloop: EventLoop, connection: Connection, settings: MqttSettings, sink: EventSink) {
+    let min_delay = Duration::from_millis(settings.reconnect_min_ms);
+    let max_delay = Duration::from_millis(settings.reconnect_max_ms).max(min_delay);
+    let mut delay = min_delay;
+
+    loop {
+        match eventloop.next().await {
+            Step::Connected => {
+                log::info!("{} connected to {}:{}", MqttClient::NAME, settings.host, settings.port);
+                delay = min_delay;
+                for subscription in &settings.subscriptions {
+                    if let Err(e) = connection.subscribe(&subscription.topic, subscription.qos) {
+                        log::warn!("{}: cannot subscribe to {}: {}", MqttClient::NAME, subscription.topic, e);
+                    }
+                }
+            }
+            Step::Message { topic, payload } => {
+                let event = NamedEvent::new(&settings.event_name(&topic), EventType::Plugin, decode_payload(&payload))
+                    .with_source(MqttClient::NAME);
+                sink.emit(event);
+            }
+            Step::Disconnected => break,
+            Step::Failed(e) => {
+                log::warn!("{}: connection lost: {}; retrying in {:?}", MqttClient::NAME, e, delay);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(max_delay);
+            }
+            Step::Other => {}
+        }
+    }
+}
+
+/// JSON objects and arrays as JSON, anything else as text
+fn decode_payload(payload: &[u8]) -> EventPayload {
+    if payload.is_empty() {
+        return EventPayload::None;
+    }
+    match serde_json::from_slice::<serde_json::Value>(payload) {
+        Ok(value) if value.is_object() || value.is_array() => EventPayload::from_json(value),
+        _ => EventPayload::Text(String::from_utf8_lossy(payload).into_owned()),
+    }
+}
+
+#[async_trait]
+impl Plugin for MqttClient {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        match name {
+            "Publish" => Some(action_args(name, args).map(|message| {
+                publish_action(name, "Publishes a message on the broker", self.info.id, self.publisher(), message)
+            })),
+            _ => None,
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.connect() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown().await;
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: MqttSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(MqttError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown().await;
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for MqttClient {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use rumqttc::mqttbytes::Error as PacketError;
+    use rumqttc::{ConnAck, ConnectReturnCode, Packet, PubAck, Publish, SubAck, SubscribeReasonCode};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use crate::eg::action::base::ActionBase;
+    use crate::plugins::mqtt::{publish_action, LastWill, PublishMessage, Subscription};
+
+    const MAX_PACKET: usize = 64 * 1024;
+
+    /// Just enough of a broker to talk to one client at a time
+    struct StandIn {
+        stream: TcpStream,
+        buffer: BytesMut,
+    }
+
+    impl StandIn {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.unwrap();
+            Self { stream, buffer: BytesMut::new() }
+        }
+
+        async fn read(&mut self) -> Packet {
+            loop {
+                match Packet::read(&mut self.buffer, MAX_PACKET) {
+                    Ok(Packet::PingReq) => self.write(Packet::PingResp).await,
+                    Ok(packet) => return packet,
+                    Err(PacketError::InsufficientBytes(_)) => {
+                        let read = self.stream.read_buf(&mut self.buffer).await.unwrap();
+                        assert!(read > 0, "client closed the connection");
+                    }
+                    Err(e) => panic!("malformed packet: {:?}", e),
+                }
+            }
+        }
+
+        async fn write(&mut self, packet: Packet) {
+            let mut buffer = BytesMut::new();
+            packet.write(&mut buffer, MAX_PACKET).unwrap();
+            self.stream.write_all(&buffer).await.unwrap();
+        }
+
+        /// Accept the connection and the subscription that follows it
+        async fn handshake(&mut self) -> (rumqttc::Connect, Vec<String>) {
+            let Packet::Connect(connect) = self.read().await else { panic!("expected CONNECT") };
+            assert_eq!(connect.last_will.as_ref().unwrap().topic, "eventghost/status");
+            self.write(Packet::ConnAck(ConnAck::new(ConnectReturnCode::Success, false))).await;
+
+            let Packet::Subscribe(subscribe) = self.read().await else { panic!("expected SUBSCRIBE") };
+            let codes = subscribe.filters.iter().map(|f| SubscribeReasonCode::Success(f.qos)).collect();
+            self.write(Packet::SubAck(SubAck::new(subscribe.pkid, codes))).await;
+            (connect, subscribe.filters.into_iter().map(|f| f.path).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_against_stand_in_broker() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = MqttClient::new(MqttSettings {
+            host: "127.0.0.1".into(),
+            port: listener.local_addr().unwrap().port(),
+            client_id: "eg-test".into(),
+            subscriptions: vec![Subscription { topic: "home/+/state".into(), qos: Qos::AtLeastOnce, event: None }],
+            last_will: Some(LastWill {
+                topic: "eventghost/status".into(),
+                payload: "offline".into(),
+                qos: Qos::AtMostOnce,
+                retain: true,
+            }),
+            reconnect_min_ms: 10,
+            ..Default::default()
+        });
+        client.initialize().await.unwrap();
+        client.start().await.unwrap();
+
+        let mut broker = StandIn::accept(&listener).await;
+        let (connect, filters) = broker.handshake().await;
+        assert_eq!(connect.client_id, "eg-test");
+        assert_eq!(filters, ["home/+/state"]);
+
+        broker
+            .write(Packet::Publish(Publish::new("home/door/state", rumqttc::QoS::AtMostOnce, r#"{"open":true}"#)))
+            .await;
+        let event = client.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "MQTT.home/door/state");
+        assert_eq!(event.get_source(), Some(MqttClient::NAME));
+        assert_eq!(event.get_payload().to_json(), json!({"open": true}));
+
+        let mut action = publish_action(
+            "Echo",
+            "Publishes the event back",
+            Uuid::new_v4(),
+            client.publisher(),
+            PublishMessage {
+                topic: "eventghost/{suffix}".into(),
+                payload: "{payload.open}".into(),
+                qos: Qos::AtLeastOnce,
+                retain: true,
+            },
+        );
+        action.execute(event.as_ref()).await.unwrap();
+        let Packet::Publish(publish) = broker.read().await else { panic!("expected PUBLISH") };
+        assert_eq!(publish.topic, "eventghost/home/door/state");
+        assert_eq!(&publish.payload[..], b"true");
+        assert!(publish.retain);
+        assert_eq!(publish.qos, rumqttc::QoS::AtLeastOnce);
+        broker.write(Packet::PubAck(PubAck::new(publish.pkid))).await;
+
+        // The client comes back and subscribes again
+        drop(broker);
+        let mut broker = StandIn::accept(&listener).await;
+        let (_, filters) = broker.handshake().await;
+        assert_eq!(filters, ["home/+/state"]);
+
+        client.stop().await.unwrap();
+        assert!(matches!(broker.read().await, Packet::Disconnect));
+        assert!(matches!(
+            client.publisher().publish("a", b"", Qos::AtMostOnce, false),
+            Err(MqttError::NotConnected)
+        ));
+    }
+
+    /// The same conversation over MQTT v5
+    mod v5 {
+        use super::*;
+        use rumqttc::v5::mqttbytes::v5::{ConnAck, ConnectReturnCode, Packet, PubAck, Publish, SubAck, SubscribeReasonCode};
+        use rumqttc::v5::mqttbytes::{Error as PacketError, QoS};
+        use crate::plugins::mqtt::MqttVersion;
+
+        struct StandIn {
+            stream: TcpStream,
+            buffer: BytesMut,
+        }
+
+        impl StandIn {
+            async fn accept(listener: &TcpListener) -> Self {
+                let (stream, _) = listener.accept().await.unwrap();
+                Self { stream, buffer: BytesMut::new() }
+            }
+
+            async fn read(&mut self) -> Packet {
+                loop {
+                    match Packet::read(&mut self.buffer, Some(MAX_PACKET as u32)) {
+                        Ok(Packet::PingReq(_)) => self.write(Packet::PingResp(rumqttc::v5::mqttbytes::v5::PingResp)).await,
+                        Ok(packet) => return packet,
+                        Err(PacketError::InsufficientBytes(_)) => {
+                            let read = self.stream.read_buf(&mut self.buffer).await.unwrap();
+                            assert!(read > 0, "client closed the connection");
+                        }
+                        Err(e) => panic!("malformed packet: {:?}", e),
+                    }
+                }
+            }
+
+            async fn write(&mut self, packet: Packet) {
+                let mut buffer = BytesMut::new();
+                packet.write(&mut buffer, None).unwrap();
+                self.stream.write_all(&buffer).await.unwrap();
+            }
+
+            /// rumqttc cannot parse the short DISCONNECT it sends itself, so
+            /// only the packet type is checked
+            async fn read_disconnect(&mut self) {
+                while self.buffer.is_empty() {
+                    let read = self.stream.read_buf(&mut self.buffer).await.unwrap();
+                    assert!(read > 0, "client closed the connection");
+                }
+                assert_eq!(self.buffer[0] >> 4, 14, "expected DISCONNECT");
+            }
+        }
+
+        #[tokio::test]
+        async fn test_against_stand_in_broker() {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut client = MqttClient::new(MqttSettings {
+                host: "127.0.0.1".into(),
+                port: listener.local_addr().unwrap().port(),
+                client_id: "eg-test-v5".into(),
+                protocol: MqttVersion::V5,
+                subscriptions: vec![Subscription { topic: "home/#".into(), qos: Qos::AtLeastOnce, event: None }],
+                last_will: Some(LastWill {
+                    topic: "eventghost/status".into(),
+                    payload: "offline".into(),
+                    qos: Qos::AtMostOnce,
+                    retain: true,
+                }),
+                reconnect_min_ms: 10,
+                ..Default::default()
+            });
+            client.initialize().await.unwrap();
+            client.start().await.unwrap();
+
+            let mut broker = StandIn::accept(&listener).await;
+            let Packet::Connect(connect, will, _) = broker.read().await else { panic!("expected CONNECT") };
+            assert_eq!(connect.client_id, "eg-test-v5");
+            assert_eq!(&will.unwrap().topic[..], b"eventghost/status");
+            let connack = ConnAck { session_present: false, code: ConnectReturnCode::Success, properties: None };
+            broker.write(Packet::ConnAck(connack)).await;
+
+            let Packet::Subscribe(subscribe) = broker.read().await else { panic!("expected SUBSCRIBE") };
+            assert_eq!(subscribe.filters.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), ["home/#"]);
+            let return_codes = subscribe.filters.iter().map(|f| SubscribeReasonCode::Success(f.qos)).collect();
+            broker.write(Packet::SubAck(SubAck { pkid: subscribe.pkid, return_codes, properties: None })).await;
+
+            broker.write(Packet::Publish(Publish::new("home/light", QoS::AtMostOnce, "on", None))).await;
+            let event = client.generate_event().await.unwrap();
+            assert_eq!(event.get_id(), "MQTT.home/light");
+            assert!(matches!(event.get_payload(), EventPayload::Text(text) if text == "on"));
+
+            client.publisher().publish("eventghost/ack", b"done", Qos::AtLeastOnce, false).unwrap();
+            let Packet::Publish(publish) = broker.read().await else { panic!("expected PUBLISH") };
+            assert_eq!(&publish.topic[..], b"eventghost/ack");
+            assert_eq!(&publish.payload[..], b"done");
+            assert_eq!(publish.qos, QoS::AtLeastOnce);
+            broker.write(Packet::PubAck(PubAck::new(publish.pkid, None))).await;
+
+            client.stop().await.unwrap();
+            broker.read_disconnect().await;
+        }
+    }
+
+    #[test]
+    fn test_decode_payload() {
+        assert!(matches!(decode_payload(b""), EventPayload::None));
+        assert!(matches!(decode_payload(b"42"), EventPayload::Text(text) if text == "42"));
+        assert!(matches!(decode_payload(b"on"), EventPayload::Text(text) if text == "on"));
+        assert_eq!(decode_payload(b"[1,2]").to_json(), json!([1, 2]));
+    }
+}
@@ -0,0 +1,282 @@
+//! MQTT client, the successor of the legacy `MQTT Client` plugin
+//!
+//! Messages on subscribed topics become events named `<prefix>.<topic>`,
+//! e.g. `MQTT.home/door/state`, or `<prefix>.<event>` for subscriptions
+//! that name their event. JSON objects and arrays arrive as JSON payloads,
+//! anything else as text. [`publish_action`] sends messages whose topic and
+//! payload are [templates](crate::plugins::template) filled from the
+//! triggering event.
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "MQTT"
+//! enabled = true
+//!
+//! [plugins.settings]
+//! host = "broker.local"
+//! port = 8883
+//! tls = true
+//! subscriptions = [{ topic = "home/+/state" }, { topic = "alarm", qos = 1, event = "Alarm" }]
+//! last_will = { topic = "eventghost/status", payload = "offline", retain = true }
+//! ```
+
+pub mod client;
+
+pub use client::MqttClient;
+
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::config::SecretRef;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::template;
+
+/// Error type for the MQTT plugin
+#[derive(Debug, thiserror::Error)]
+pub enum MqttError {
+    #[error("Not connected to a broker")]
+    NotConnected,
+    #[error("Invalid QoS level {0}, expected 0, 1 or 2")]
+    InvalidQos(u8),
+    #[error("Invalid topic filter '{0}'")]
+    InvalidFilter(String),
+    #[error("Client error: {0}")]
+    Client(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<MqttError> for Error {
+    fn from(e: MqttError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Protocol version spoken to the broker
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MqttVersion {
+    #[default]
+    #[serde(rename = "3.1.1")]
+    V311,
+    #[serde(rename = "5")]
+    V5,
+}
+
+/// Delivery guarantee, written as 0, 1 or 2 in the settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum Qos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl TryFrom<u8> for Qos {
+    type Error = MqttError;
+
+    fn try_from(level: u8) -> Result<Self, Self::Error> {
+        match level {
+            0 => Ok(Qos::AtMostOnce),
+            1 => Ok(Qos::AtLeastOnce),
+            2 => Ok(Qos::ExactlyOnce),
+            other => Err(MqttError::InvalidQos(other)),
+        }
+    }
+}
+
+impl From<Qos> for u8 {
+    fn from(qos: Qos) -> Self {
+        qos as u8
+    }
+}
+
+/// A topic filter to subscribe to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Topic filter, may contain `+` and `#`
+    pub topic: String,
+    #[serde(default)]
+    pub qos: Qos,
+    /// Event name suffix for matching messages instead of the topic
+    #[serde(default)]
+    pub event: Option<String>,
+}
+
+/// Message the broker publishes if the connection is lost
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LastWill {
+    pub topic: String,
+    #[serde(default)]
+    pub payload: String,
+    #[serde(default)]
+    pub qos: Qos,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+/// Settings of the `MQTT` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    /// Connect over TLS, trusting the system's certificate authorities;
+    /// brokers usually listen for it on port 8883
+    pub tls: bool,
+    /// Client identifier; empty picks a random one
+    pub client_id: String,
+    /// Empty for brokers without authentication
+    pub username: String,
+    /// Keystore reference to the password of `username`
+    pub password: Option<SecretRef>,
+    pub protocol: MqttVersion,
+    pub keep_alive_secs: u64,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    pub subscriptions: Vec<Subscription>,
+    pub last_will: Option<LastWill>,
+    /// First delay before reconnecting; doubles per failed attempt
+    pub reconnect_min_ms: u64,
+    /// Longest delay between reconnect attempts
+    pub reconnect_max_ms: u64,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            tls: false,
+            client_id: String::new(),
+            username: String::new(),
+            password: None,
+            protocol: MqttVersion::default(),
+            keep_alive_secs: 30,
+            prefix: MqttClient::NAME.to_string(),
+            subscriptions: Vec::new(),
+            last_will: None,
+            reconnect_min_ms: 1_000,
+            reconnect_max_ms: 60_000,
+        }
+    }
+}
+
+impl MqttSettings {
+    /// Check what serde cannot
+    pub fn validate(&self) -> Result<(), MqttError> {
+        match self.subscriptions.iter().find(|s| !rumqttc::valid_filter(&s.topic)) {
+            Some(invalid) => Err(MqttError::InvalidFilter(invalid.topic.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Event name for a message on `topic`
+    pub fn event_name(&self, topic: &str) -> String {
+        let suffix = self
+            .subscriptions
+            .iter()
+            .find(|s| rumqttc::matches(topic, &s.topic))
+            .and_then(|s| s.event.as_deref())
+            .unwrap_or(topic);
+        if self.prefix.is_empty() {
+            suffix.to_string()
+        } else {
+            format!("{}.{}", self.prefix, suffix)
+        }
+    }
+}
+
+/// Sends messages over the plugin's connection; cheap to clone
+///
+/// Publishing fails while the plugin is stopped; messages accepted while
+/// the connection is being re-established are sent once it is back.
+#[derive(Clone, Default)]
+pub struct MqttPublisher(Arc<Mutex<Option<client::Connection>>>);
+
+impl MqttPublisher {
+    pub fn publish(&self, topic: &str, payload: &[u8], qos: Qos, retain: bool) -> Result<(), MqttError> {
+        match &*self.0.lock().map_err(|e| MqttError::Client(e.to_string()))? {
+            Some(connection) => connection.publish(topic, payload, qos, retain),
+            None => Err(MqttError::NotConnected),
+        }
+    }
+
+    fn set(&self, connection: Option<client::Connection>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = connection;
+        }
+    }
+
+    fn take(&self) -> Option<client::Connection> {
+        self.0.lock().ok().and_then(|mut current| current.take())
+    }
+}
+
+/// What a publish action sends
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PublishMessage {
+    /// Topic template, e.g. `eventghost/{suffix}`
+    pub topic: String,
+    /// Payload template, e.g. `{payload}`
+    #[serde(default)]
+    pub payload: String,
+    #[serde(default)]
+    pub qos: Qos,
+    #[serde(default)]
+    pub retain: bool,
+}
+
+/// Creates an action that publishes `message` through `publisher`
+pub fn publish_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    publisher: MqttPublisher,
+    message: PublishMessage,
+) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let topic = template::render(&message.topic, event);
+            let payload = template::render(&message.payload, event);
+            publisher
+                .publish(&topic, payload.as_bytes(), message.qos, message.retain)
+                .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings() {
+        let settings: MqttSettings = serde_json::from_value(json!({
+            "protocol": "5",
+            "tls": true,
+            "subscriptions": [{"topic": "home/+/state"}, {"topic": "alarm/#", "qos": 2, "event": "Alarm"}],
+        }))
+        .unwrap();
+        assert_eq!(settings.protocol, MqttVersion::V5);
+        assert!(settings.tls && !MqttSettings::default().tls);
+        assert_eq!(settings.subscriptions[1].qos, Qos::ExactlyOnce);
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.event_name("home/door/state"), "MQTT.home/door/state");
+        assert_eq!(settings.event_name("alarm/zone/1"), "MQTT.Alarm");
+
+        assert!(serde_json::from_value::<MqttSettings>(json!({"subscriptions": [{"topic": "a", "qos": 3}]})).is_err());
+        let invalid = MqttSettings {
+            subscriptions: vec![Subscription { topic: "a/#/b".into(), qos: Qos::AtMostOnce, event: None }],
+            ..Default::default()
+        };
+        assert!(matches!(invalid.validate(), Err(MqttError::InvalidFilter(_))));
+    }
+}
@@ -0,0 +1,66 @@
+//! Network Event Receiver and Sender, wire compatible with legacy EventGhost
+//!
+//! Each connection carries events as `\n` terminated lines:
+//!
+//! 1. sender: `quintessence`
+//! 2. receiver: a random cookie
+//! 3. sender: uppercase hex MD5 of `<cookie>:<password>`
+//! 4. receiver: `accept`, or it closes the connection
+//! 5. sender: any number of `payload <text>` lines followed by the event
+//!    name, repeated as needed, then `close`
+
+pub mod receiver;
+pub mod sender;
+
+pub use receiver::{NetworkReceiver, ReceiverSettings};
+pub use sender::{send_action, send_event, SenderSettings};
+
+use md5::{Digest, Md5};
+use crate::core::Error;
+
+/// Port the legacy plugins default to
+pub const DEFAULT_PORT: u16 = 1024;
+/// Prefix of received event names, as in `TCP.Play`
+pub const DEFAULT_PREFIX: &str = "TCP";
+
+const HELLO: &str = "quintessence";
+const ACCEPT: &str = "accept";
+const CLOSE: &str = "close";
+const PAYLOAD: &str = "payload ";
+/// Payload marker legacy senders add to events without a release
+const WITHOUT_RELEASE: &str = "withoutRelease";
+/// Ends the last event started `withoutRelease`
+const BUTTON_RELEASED: &str = "ButtonReleased";
+
+/// Error type for the network plugins
+#[derive(Debug, thiserror::Error)]
+pub enum NetworkError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The receiver rejected the password")]
+    Rejected,
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<NetworkError> for Error {
+    fn from(e: NetworkError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Answer to the receiver's cookie
+///
+/// MD5 is broken as a hash; the protocol uses it as a challenge-response so
+/// the password never crosses the wire in clear. Nothing else should use it.
+pub fn challenge_response(cookie: &str, password: &str) -> String {
+    Md5::digest(format!("{}:{}", cookie, password)).iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// A protocol line without its terminator; legacy senders end the hello
+/// with `\n\r`, leaving a stray `\r` at the start of the next line
+fn trim_line(line: &str) -> &str {
+    line.trim_matches(|c| c == '\r' || c == '\n')
+}
@@ -0,0 +1,433 @@
+//! Network Event Receiver plugin
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::core::config::{Config, ConfigManager, SecretRef, SecretString};
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::plugins::{plugin_accessors, plugin_info, reveal, EventQueue, EventSink};
+use super::{
+    challenge_response, trim_line, NetworkError, ACCEPT, BUTTON_RELEASED, CLOSE, DEFAULT_PORT, DEFAULT_PREFIX,
+    HELLO, PAYLOAD, WITHOUT_RELEASE,
+};
+
+/// A connection that sends nothing for this long is dropped
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest line accepted, in bytes; longer ones drop the connection
+const MAX_LINE: u64 = 4096;
+
+/// Most payload lines one event may carry
+const MAX_PAYLOAD_LINES: usize = 64;
+
+/// Connections served at once; further senders wait to be accepted
+const MAX_CONNECTIONS: usize = 16;
+
+/// Settings of the `NetworkReceiver` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiverSettings {
+    /// Address to listen on; anything but loopback accepts senders from the
+    /// network
+    pub address: IpAddr,
+    /// TCP port; 0 picks a free one
+    pub port: u16,
+    /// Keystore reference to the password senders must know; required
+    pub password: Option<SecretRef>,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+}
+
+impl Default for ReceiverSettings {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            password: None,
+            prefix: DEFAULT_PREFIX.to_string(),
+        }
+    }
+}
+
+/// Accepts events from legacy Network Event Senders
+pub struct NetworkReceiver {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: ReceiverSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    secrets: Option<Arc<ConfigManager>>,
+    server: Option<JoinHandle<()>>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl NetworkReceiver {
+    pub const NAME: &'static str = "NetworkReceiver";
+
+    pub fn new(settings: ReceiverSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Receives events from Network Event Senders",
+                vec![PluginCapability::EventGenerator, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            secrets: None,
+            server: None,
+            local_addr: None,
+        }
+    }
+
+    /// Publish received events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    /// Reveal the password from the keystore of `secrets`
+    pub fn with_secrets(mut self, secrets: Arc<ConfigManager>) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    pub fn settings(&self) -> &ReceiverSettings {
+        &self.settings
+    }
+
+    /// Address the receiver listens on while running
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    async fn listen(&mut self) -> Result<(), NetworkError> {
+        let password = reveal(self.secrets.as_deref(), self.settings.password.as_ref())
+            .map_err(NetworkError::Settings)?
+            .ok_or_else(|| NetworkError::Settings("a password is required".into()))?;
+        let password = Arc::new(password);
+        let listener = TcpListener::bind((self.settings.address, self.settings.port)).await?;
+        self.local_addr = Some(listener.local_addr()?);
+        log::info!("{} listening on {}", Self::NAME, listener.local_addr()?);
+        if !self.settings.address.is_loopback() {
+            log::warn!("{} accepts events from anyone who can reach {}", Self::NAME, listener.local_addr()?);
+        }
+
+        let settings = self.settings.clone();
+        let sink = self.sink.clone();
+        let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+        self.server = Some(tokio::spawn(async move {
+            loop {
+                let Ok(permit) = connections.clone().acquire_owned().await else {
+                    break;
+                };
+                let (stream, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        log::warn!("{}: accept failed: {}", NetworkReceiver::NAME, e);
+                        continue;
+                    }
+                };
+                let (settings, password, sink) = (settings.clone(), password.clone(), sink.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &settings, &password, &sink).await {
+                        log::debug!("{}: connection from {} ended: {}", NetworkReceiver::NAME, peer, e);
+                    }
+                    drop(permit);
+                });
+            }
+        }));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(server) = self.server.take() {
+            server.abort();
+        }
+        self.local_addr = None;
+    }
+}
+
+impl Drop for NetworkReceiver {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Run the protocol on one connection
+async fn serve(
+    stream: TcpStream,
+    settings: &ReceiverSettings,
+    password: &SecretString,
+    sink: &EventSink,
+) -> Result<(), NetworkError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+
+    let mut next_line = async |line: &mut String| -> Result<bool, NetworkError> {
+        line.clear();
+        let read = match tokio::time::timeout(IDLE_TIMEOUT, (&mut reader).take(MAX_LINE).read_line(line)).await {
+            Ok(read) => read?,
+            Err(_) => return Err(NetworkError::Protocol("Timed out waiting for the sender".into())),
+        };
+        if read as u64 == MAX_LINE && !line.ends_with('\n') {
+            return Err(NetworkError::Protocol(format!("Line longer than {} bytes", MAX_LINE)));
+        }
+        Ok(read > 0)
+    };
+
+    if !next_line(&mut line).await? || trim_line(&line) != HELLO {
+        return Err(NetworkError::Protocol("Expected the hello line".into()));
+    }
+    let cookie = Uuid::new_v4().simple().to_string();
+    writer.write_all(format!("{}\n", cookie).as_bytes()).await?;
+
+    // Only the last 32 characters count, as in the legacy receiver
+    let expected = challenge_response(&cookie, password.expose());
+    loop {
+        if !next_line(&mut line).await? {
+            return Ok(());
+        }
+        let answer = trim_line(&line).trim();
+        if answer.is_empty() {
+            continue;
+        }
+        let answer = answer.as_bytes();
+        let answer = &answer[answer.len().saturating_sub(32)..];
+        if !answer.eq_ignore_ascii_case(expected.as_bytes()) {
+            return Err(NetworkError::Rejected);
+        }
+        writer.write_all(format!("{}\n", ACCEPT).as_bytes()).await?;
+        break;
+    }
+
+    let mut payload = Vec::new();
+    while next_line(&mut line).await? {
+        let text = trim_line(&line);
+        if text == CLOSE {
+            break;
+        } else if let Some(value) = text.strip_prefix(PAYLOAD) {
+            if payload.len() == MAX_PAYLOAD_LINES {
+                return Err(NetworkError::Protocol(format!("More than {} payload lines", MAX_PAYLOAD_LINES)));
+            }
+            payload.push(value.to_string());
+        } else if text == BUTTON_RELEASED || text.is_empty() {
+            payload.clear();
+        } else {
+            payload.retain(|value| value != WITHOUT_RELEASE);
+            sink.emit(received_event(&settings.prefix, text, std::mem::take(&mut payload)));
+        }
+    }
+    Ok(())
+}
+
+fn received_event(prefix: &str, name: &str, mut payload: Vec<String>) -> NamedEvent {
+    let name = if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+    let payload = match payload.len() {
+        0 => EventPayload::None,
+        1 => EventPayload::Text(payload.remove(0)),
+        _ => EventPayload::from_json(serde_json::Value::from(payload)),
+    };
+    NamedEvent::new(&name, EventType::Plugin, payload).with_source(NetworkReceiver::NAME)
+}
+
+#[async_trait]
+impl Plugin for NetworkReceiver {
+    plugin_accessors!();
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.listen().await {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: ReceiverSettings =
+            crate::plugins::settings_for(&config, Self::NAME).map_err(NetworkError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for NetworkReceiver {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::network::{send_event, SenderSettings};
+    use crate::testing::mock_secrets;
+    use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+    async fn running_receiver(password: &str) -> (NetworkReceiver, SenderSettings) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut receiver = NetworkReceiver::new(ReceiverSettings {
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            password: Some(SecretRef::new("network")),
+            ..Default::default()
+        })
+        .with_secrets(mock_secrets(dir.path(), &[("network", password)]));
+        receiver.initialize().await.unwrap();
+        receiver.start().await.unwrap();
+        let sender = SenderSettings {
+            host: "127.0.0.1".to_string(),
+            port: receiver.local_addr().unwrap().port(),
+            password: Some(SecretRef::new("network")),
+            ..Default::default()
+        };
+        (receiver, sender)
+    }
+
+    #[tokio::test]
+    async fn test_sender_and_receiver() {
+        let (mut receiver, sender) = running_receiver("secret").await;
+
+        let settings = sender.clone();
+        let password = Some(SecretString::new("secret"));
+        tokio::task::spawn_blocking(move || {
+            send_event(&settings, password.as_ref(), "Play", &[]).unwrap();
+            send_event(&settings, password.as_ref(), "Volume", &["42".to_string()]).unwrap();
+            send_event(&settings, password.as_ref(), "Move", &["1".to_string(), "2".to_string()]).unwrap();
+        })
+        .await
+        .unwrap();
+
+        let event = receiver.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "TCP.Play");
+        assert_eq!(event.get_source(), Some(NetworkReceiver::NAME));
+        assert!(matches!(event.get_payload(), EventPayload::None));
+
+        let event = receiver.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "TCP.Volume");
+        assert!(matches!(event.get_payload(), EventPayload::Text(text) if text == "42"));
+
+        let event = receiver.generate_event().await.unwrap();
+        assert_eq!(event.get_payload().to_json(), serde_json::json!(["1", "2"]));
+
+        receiver.stop().await.unwrap();
+        assert!(receiver.local_addr().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_rejected() {
+        let (mut receiver, sender) = running_receiver("secret").await;
+
+        let guess = SecretString::new("guess");
+        let result = tokio::task::spawn_blocking(move || send_event(&sender, Some(&guess), "Play", &[])).await.unwrap();
+        assert!(matches!(result, Err(NetworkError::Rejected)), "{:?}", result);
+
+        receiver.stop().await.unwrap();
+        assert!(receiver.queue.try_next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_non_ascii_answer_is_rejected() {
+        let (mut receiver, _) = running_receiver("secret").await;
+
+        let stream = TcpStream::connect(receiver.local_addr().unwrap()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        writer.write_all(format!("{}\n", HELLO).as_bytes()).await.unwrap();
+        let mut cookie = String::new();
+        reader.read_line(&mut cookie).await.unwrap();
+        writer.write_all(format!("{}\n", "é".repeat(20)).as_bytes()).await.unwrap();
+        let mut answer = String::new();
+        assert_eq!(reader.read_line(&mut answer).await.unwrap(), 0);
+
+        receiver.stop().await.unwrap();
+    }
+
+    /// Connect and pass the challenge, as a sender would
+    async fn authenticated(receiver: &NetworkReceiver, password: &str) -> (BufReader<OwnedReadHalf>, OwnedWriteHalf) {
+        let stream = TcpStream::connect(receiver.local_addr().unwrap()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        writer.write_all(format!("{}\n", HELLO).as_bytes()).await.unwrap();
+        let mut cookie = String::new();
+        reader.read_line(&mut cookie).await.unwrap();
+        let answer = challenge_response(trim_line(&cookie), password);
+        writer.write_all(format!("{}\n", answer).as_bytes()).await.unwrap();
+        let mut accepted = String::new();
+        reader.read_line(&mut accepted).await.unwrap();
+        assert_eq!(trim_line(&accepted), ACCEPT);
+        (reader, writer)
+    }
+
+    #[tokio::test]
+    async fn test_oversized_input_drops_the_connection() {
+        let (mut receiver, _) = running_receiver("secret").await;
+
+        let (mut reader, mut writer) = authenticated(&receiver, "secret").await;
+        let _ = writer.write_all(&vec![b'x'; MAX_LINE as usize + 1]).await;
+        let mut rest = String::new();
+        assert!(!matches!(reader.read_line(&mut rest).await, Ok(read) if read > 0));
+
+        let (mut reader, mut writer) = authenticated(&receiver, "secret").await;
+        let lines = format!("{}1\n", PAYLOAD).repeat(MAX_PAYLOAD_LINES + 1);
+        let _ = writer.write_all(format!("{}Play\n", lines).as_bytes()).await;
+        assert!(!matches!(reader.read_line(&mut rest).await, Ok(read) if read > 0));
+
+        receiver.stop().await.unwrap();
+        assert!(receiver.queue.try_next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_password_is_required() {
+        let mut receiver = NetworkReceiver::new(ReceiverSettings { port: 0, ..Default::default() });
+        assert_eq!(receiver.settings().address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        receiver.initialize().await.unwrap();
+        assert!(receiver.start().await.is_err());
+        assert_eq!(receiver.get_state(), PluginState::Failed);
+    }
+
+    #[test]
+    fn test_challenge_response() {
+        // MD5 of "cookie:secret"
+        assert_eq!(challenge_response("cookie", "secret"), "251C2683D2F64104E4D49533965C5430");
+    }
+}
@@ -0,0 +1,114 @@
+//! Network Event Sender
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::config::{ConfigManager, SecretRef, SecretString};
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use super::{challenge_response, trim_line, NetworkError, ACCEPT, CLOSE, DEFAULT_PORT, HELLO, PAYLOAD};
+
+/// Settings of a Network Event Sender
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SenderSettings {
+    /// Host running the receiver
+    pub host: String,
+    /// Port of the receiver
+    pub port: u16,
+    /// Keystore reference to the password the receiver expects; none for
+    /// an empty password
+    pub password: Option<SecretRef>,
+    /// Limit for connecting and for each read or write
+    pub timeout_ms: u64,
+}
+
+impl Default for SenderSettings {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: DEFAULT_PORT,
+            password: None,
+            timeout_ms: 5000,
+        }
+    }
+}
+
+impl SenderSettings {
+    /// Decrypt the password from the keystore of `manager`
+    pub fn reveal_password(&self, manager: &ConfigManager) -> Result<Option<SecretString>, NetworkError> {
+        crate::plugins::reveal(Some(manager), self.password.as_ref()).map_err(NetworkError::Settings)
+    }
+}
+
+/// Send one event to a receiver expecting `password`, blocking until it is
+/// delivered
+pub fn send_event(
+    settings: &SenderSettings,
+    password: Option<&SecretString>,
+    event: &str,
+    payload: &[String],
+) -> Result<(), NetworkError> {
+    let timeout = Duration::from_millis(settings.timeout_ms);
+    let address = (settings.host.as_str(), settings.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| NetworkError::Protocol(format!("Cannot resolve {}", settings.host)))?;
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+
+    // The legacy sender ends the hello with "\n\r"; keep that for old receivers
+    writer.write_all(format!("{}\n\r", HELLO).as_bytes())?;
+    if reader.read_line(&mut line)? == 0 {
+        return Err(NetworkError::Protocol("Receiver closed the connection".into()));
+    }
+    let cookie = trim_line(&line).to_string();
+    writer.write_all(format!("{}\n", challenge_response(&cookie, password.map(SecretString::expose).unwrap_or_default())).as_bytes())?;
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    if trim_line(&line) != ACCEPT {
+        return Err(NetworkError::Rejected);
+    }
+
+    let mut message = String::new();
+    for value in payload {
+        message.push_str(&format!("{}{}\n", PAYLOAD, value));
+    }
+    message.push_str(&format!("{}\n{}\n", event, CLOSE));
+    writer.write_all(message.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Creates an action that sends `event` to a Network Event Receiver
+/// expecting `password`, as revealed by [`SenderSettings::reveal_password`]
+///
+/// The event is sent with [`send_event`]; like every action handler it runs
+/// on the blocking thread pool, not on a runtime worker.
+pub fn send_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    settings: SenderSettings,
+    password: Option<SecretString>,
+    event: String,
+    payload: Vec<String>,
+) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |_| {
+            send_event(&settings, password.as_ref(), &event, &payload)
+                .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
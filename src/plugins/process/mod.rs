@@ -0,0 +1,259 @@
+//! Programs starting and stopping as events, the successor of the legacy
+//! `ProcessWatcher` plugin
+//!
+//! `/proc` is polled every `poll_ms`; the netlink process connector would
+//! be quicker but needs `CAP_NET_ADMIN`. A new process raises
+//! `Process.Created.<name>` and one that is gone `Process.Destroyed.<name>`,
+//! with its PID, parent and command line as payload. Names are those of the
+//! executable, e.g. `firefox`, and filters are patterns on them:
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "ProcessWatcher"
+//! enabled = true
+//! settings = { include = ["vlc", "steam*"], poll_ms = 2000 }
+//! ```
+//!
+//! Processes running when the plugin starts raise nothing, and neither do
+//! kernel threads unless `kernel_threads` is set.
+
+pub mod watcher;
+
+pub use watcher::ProcessWatcher;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::core::Error;
+use crate::eg::tree::macro_::matches_pattern;
+
+/// Error type for the process watcher
+#[derive(Debug, thiserror::Error)]
+pub enum ProcessError {
+    #[error("Cannot read {path}: {source}")]
+    Proc { path: PathBuf, source: io::Error },
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<ProcessError> for Error {
+    fn from(e: ProcessError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Settings of the `ProcessWatcher` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessSettings {
+    /// How often to look for new and vanished processes
+    pub poll_ms: u64,
+    /// Patterns of process names to report, `*` and `?` as wildcards;
+    /// empty for all
+    pub include: Vec<String>,
+    /// Patterns of process names never to report
+    pub exclude: Vec<String>,
+    /// Report kernel threads such as `kworker` as well
+    pub kernel_threads: bool,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    /// Where procfs is mounted
+    pub proc_root: PathBuf,
+}
+
+impl Default for ProcessSettings {
+    fn default() -> Self {
+        Self {
+            poll_ms: 1000,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            kernel_threads: false,
+            prefix: "Process".to_string(),
+            proc_root: PathBuf::from("/proc"),
+        }
+    }
+}
+
+impl ProcessSettings {
+    pub fn validate(&self) -> Result<(), ProcessError> {
+        if self.poll_ms == 0 {
+            return Err(ProcessError::Settings("poll_ms must be above 0".into()));
+        }
+        if self.include.iter().chain(&self.exclude).any(String::is_empty) {
+            return Err(ProcessError::Settings("empty name pattern".into()));
+        }
+        Ok(())
+    }
+
+    /// Whether starts and stops of `process` are reported
+    pub fn accepts(&self, process: &ProcessInfo) -> bool {
+        let matches = |pattern: &String| matches_pattern(pattern, &process.name);
+        (self.kernel_threads || !process.is_kernel_thread())
+            && (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// A running process as seen in `/proc`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    pub name: String,
+    /// Arguments, the program first; empty for kernel threads
+    pub cmdline: Vec<String>,
+    /// Clock ticks after boot when the process started; tells a new
+    /// process apart from an old one with the same PID
+    pub started: u64,
+}
+
+impl ProcessInfo {
+    /// Read `<proc_root>/<pid>`; `None` if the process is gone
+    pub fn read(proc_root: &Path, pid: u32) -> Option<Self> {
+        let dir = proc_root.join(pid.to_string());
+        let stat = fs::read_to_string(dir.join("stat")).ok()?;
+        let cmdline = fs::read(dir.join("cmdline")).ok()?;
+        let cmdline: Vec<String> = cmdline
+            .split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+
+        // `pid (comm) state ppid ...`, where comm may hold spaces and
+        // parentheses of its own
+        let open = stat.find('(')?;
+        let close = stat.rfind(')')?;
+        let comm = stat.get(open + 1..close)?;
+        let fields: Vec<&str> = stat.get(close + 1..)?.split_whitespace().collect();
+        let ppid = fields.get(1)?.parse().ok()?;
+        let started = fields.get(19)?.parse().ok()?;
+
+        Some(Self { pid, ppid, name: process_name(comm, &cmdline), cmdline, started })
+    }
+
+    pub fn is_kernel_thread(&self) -> bool {
+        self.cmdline.is_empty()
+    }
+
+    pub fn payload(&self) -> serde_json::Value {
+        json!({
+            "pid": self.pid,
+            "ppid": self.ppid,
+            "name": self.name,
+            "cmdline": self.cmdline.join(" "),
+            "args": self.cmdline,
+        })
+    }
+}
+
+/// Longest `comm` the kernel keeps
+const COMM_LEN: usize = 15;
+
+/// `comm`, or the program's file name where the kernel cut `comm` short;
+/// programs that rewrite their arguments, e.g. `sshd: user@pts/0`, keep
+/// `comm`
+fn process_name(comm: &str, cmdline: &[String]) -> String {
+    let program = cmdline
+        .first()
+        .and_then(|arg| arg.split(' ').next())
+        .and_then(|arg| arg.rsplit('/').next())
+        .unwrap_or_default();
+    if comm.len() >= COMM_LEN && program.starts_with(comm) {
+        program.to_string()
+    } else {
+        comm.to_string()
+    }
+}
+
+/// Every process in `proc_root`, by PID
+pub fn snapshot(proc_root: &Path) -> Result<HashMap<u32, ProcessInfo>, ProcessError> {
+    let entries = fs::read_dir(proc_root).map_err(|source| ProcessError::Proc { path: proc_root.to_path_buf(), source })?;
+    Ok(entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| ProcessInfo::read(proc_root, pid).map(|process| (pid, process)))
+        .collect())
+}
+
+/// Processes in `after` but not in `before`, and the other way round,
+/// each sorted by PID
+pub fn diff<'a>(
+    before: &'a HashMap<u32, ProcessInfo>,
+    after: &'a HashMap<u32, ProcessInfo>,
+) -> (Vec<&'a ProcessInfo>, Vec<&'a ProcessInfo>) {
+    let missing = |from: &'a HashMap<u32, ProcessInfo>, to: &HashMap<u32, ProcessInfo>| {
+        let mut missing: Vec<&ProcessInfo> = from
+            .values()
+            .filter(|process| to.get(&process.pid).is_none_or(|other| other.started != process.started))
+            .collect();
+        missing.sort_by_key(|process| process.pid);
+        missing
+    };
+    (missing(after, before), missing(before, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, name: &str, started: u64) -> ProcessInfo {
+        ProcessInfo { pid, ppid: 1, name: name.into(), cmdline: vec![name.into()], started }
+    }
+
+    #[test]
+    fn test_diff() {
+        let before: HashMap<u32, ProcessInfo> =
+            [process(1, "init", 1), process(20, "vlc", 50), process(30, "bash", 60)].into_iter().map(|p| (p.pid, p)).collect();
+        // 30 was reused by another process in between
+        let after: HashMap<u32, ProcessInfo> =
+            [process(1, "init", 1), process(30, "steam", 90), process(40, "vim", 95)].into_iter().map(|p| (p.pid, p)).collect();
+        let (created, destroyed) = diff(&before, &after);
+        let names = |processes: Vec<&ProcessInfo>| processes.iter().map(|p| p.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(created), ["steam", "vim"]);
+        assert_eq!(names(destroyed), ["vlc", "bash"]);
+    }
+
+    #[test]
+    fn test_read_proc() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid = dir.path().join("4242");
+        fs::create_dir(&pid).unwrap();
+        let stat = "4242 (Web Content (x)) S 4200 4242 4200 0 -1 4194560 2000 0 0 0 10 5 0 0 20 0 30 0 123456 1000 100";
+        fs::write(pid.join("stat"), stat).unwrap();
+        fs::write(pid.join("cmdline"), b"/usr/lib/firefox/firefox\0-contentproc\0").unwrap();
+        fs::create_dir(dir.path().join("self")).unwrap();
+        // A truncated or garbled stat is skipped rather than a panic
+        for (garbled, stat) in [("4243", "4243 ) S ("), ("4244", "4244 (sh")] {
+            fs::create_dir(dir.path().join(garbled)).unwrap();
+            fs::write(dir.path().join(garbled).join("stat"), stat).unwrap();
+            fs::write(dir.path().join(garbled).join("cmdline"), b"sh\0").unwrap();
+        }
+
+        let processes = snapshot(dir.path()).unwrap();
+        assert_eq!(processes.len(), 1);
+        let process = &processes[&4242];
+        assert_eq!(process.ppid, 4200);
+        assert_eq!(process.started, 123456);
+        assert_eq!(process.name, "Web Content (x)");
+        assert_eq!(process.payload()["cmdline"], "/usr/lib/firefox/firefox -contentproc");
+
+        assert_eq!(process_name("gnome-terminal-", &["/usr/bin/gnome-terminal-server".into()]), "gnome-terminal-server");
+        assert_eq!(process_name("sshd", &["sshd: me@pts/0".into()]), "sshd");
+        assert_eq!(process_name("kworker/0:1", &[]), "kworker/0:1");
+    }
+
+    #[test]
+    fn test_filters() {
+        let settings = ProcessSettings { include: vec!["steam*".into(), "vlc".into()], exclude: vec!["steamwebhelper".into()], ..Default::default() };
+        assert!(settings.accepts(&process(1, "vlc", 0)));
+        assert!(settings.accepts(&process(2, "steam", 0)));
+        assert!(!settings.accepts(&process(3, "steamwebhelper", 0)));
+        assert!(!settings.accepts(&process(4, "bash", 0)));
+        let kernel = ProcessInfo { cmdline: Vec::new(), ..process(5, "kworker/0:1", 0) };
+        assert!(!ProcessSettings::default().accepts(&kernel));
+        let settings = ProcessSettings { kernel_threads: true, include: vec!["kworker*".into()], ..Default::default() };
+        assert!(settings.accepts(&kernel));
+    }
+}
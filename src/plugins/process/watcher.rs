@@ -0,0 +1,208 @@
+//! ProcessWatcher plugin
+
+use std::collections::HashMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::plugins::{plugin_accessors, plugin_info, EventQueue, EventSink};
+use super::{diff, snapshot, ProcessError, ProcessInfo, ProcessSettings};
+
+/// Raises events when processes start and stop
+pub struct ProcessWatcher {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: ProcessSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    task: Option<JoinHandle<()>>,
+}
+
+impl ProcessWatcher {
+    pub const NAME: &'static str = "ProcessWatcher";
+
+    pub fn new(settings: ProcessSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Raises events when programs start and stop",
+                vec![PluginCapability::EventGenerator, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            task: None,
+        }
+    }
+
+    /// Publish process events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &ProcessSettings {
+        &self.settings
+    }
+
+    /// Take the first snapshot, so that running processes raise nothing,
+    /// and poll from there
+    fn watch(&mut self) -> Result<(), ProcessError> {
+        self.settings.validate()?;
+        let processes = snapshot(&self.settings.proc_root)?;
+        self.task = Some(tokio::spawn(run(self.settings.clone(), processes, self.sink.clone())));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for ProcessWatcher {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Poll `/proc` until aborted
+async fn run(settings: ProcessSettings, mut processes: HashMap<u32, ProcessInfo>, sink: EventSink) {
+    let mut interval = tokio::time::interval(Duration::from_millis(settings.poll_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+    let mut reported = false;
+    loop {
+        interval.tick().await;
+        let root = settings.proc_root.clone();
+        let current = match tokio::task::spawn_blocking(move || snapshot(&root)).await {
+            Ok(Ok(current)) => current,
+            Ok(Err(e)) if !reported => {
+                log::warn!("{}: {}", ProcessWatcher::NAME, e);
+                reported = true;
+                continue;
+            }
+            _ => continue,
+        };
+        reported = false;
+
+        let (created, destroyed) = diff(&processes, &current);
+        for process in destroyed.into_iter().filter(|process| settings.accepts(process)) {
+            sink.emit(process_event(&settings, "Destroyed", process));
+        }
+        for process in created.into_iter().filter(|process| settings.accepts(process)) {
+            sink.emit(process_event(&settings, "Created", process));
+        }
+        processes = current;
+    }
+}
+
+fn process_event(settings: &ProcessSettings, change: &str, process: &ProcessInfo) -> NamedEvent {
+    let name = match settings.prefix.as_str() {
+        "" => format!("{}.{}", change, process.name),
+        prefix => format!("{}.{}.{}", prefix, change, process.name),
+    };
+    NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(process.payload())).with_source(ProcessWatcher::NAME)
+}
+
+#[async_trait]
+impl Plugin for ProcessWatcher {
+    plugin_accessors!();
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.watch() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: ProcessSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(ProcessError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for ProcessWatcher {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use std::process::Command;
+
+    #[tokio::test]
+    async fn test_watch_sleep() {
+        let mut watcher = ProcessWatcher::new(ProcessSettings {
+            poll_ms: 20,
+            include: vec!["sleep".into()],
+            ..Default::default()
+        });
+        watcher.initialize().await.unwrap();
+        watcher.start().await.unwrap();
+
+        let mut child = Command::new("sleep").arg("37").spawn().unwrap();
+        let pid = child.id();
+        // Other tests may start processes too, wait for ours
+        let (name, payload) = loop {
+            let (name, payload) = next_event(&mut watcher).await;
+            if payload["pid"] == pid {
+                break (name, payload);
+            }
+        };
+        assert_eq!(name, "Process.Created.sleep");
+        assert_eq!(payload["cmdline"], "sleep 37");
+        assert_eq!(payload["ppid"], std::process::id());
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        let (name, payload) = loop {
+            let (name, payload) = next_event(&mut watcher).await;
+            if payload["pid"] == pid {
+                break (name, payload);
+            }
+        };
+        assert_eq!(name, "Process.Destroyed.sleep");
+        assert_eq!(payload["args"][1], "37");
+
+        watcher.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+//! Cron expressions
+//!
+//! Five fields, minute hour day-of-month month day-of-week, each `*`, a
+//! value, a range `a-b`, a step `*/n` or `a-b/n`, or a comma separated list
+//! of those. Months and weekdays may be named (`jan`, `mon`), Sunday is 0
+//! or 7, and as in Vixie cron a job whose day-of-month and day-of-week are
+//! both restricted runs when either matches. `@yearly`, `@monthly`,
+//! `@weekly`, `@daily` and `@hourly` are shorthands.
+//!
+//! Times are wall-clock times in the given zone. When clocks go forward,
+//! a time in the skipped hour runs at the end of the gap; when they go
+//! back, a time in the repeated hour runs only the first time round,
+//! except that jobs running hourly or more often run through it as well.
+
+use std::fmt;
+use std::str::FromStr;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use chrono::offset::LocalResult;
+use super::SchedulerError;
+
+/// Longest search for the next match, to give up on `0 0 30 2 *`; counted
+/// in days, as 29 February has no date five years on
+const SEARCH_DAYS: i64 = 5 * 366;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// The allowed values of one field as a bit set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u64,
+    /// Written as `*` or `*/n`, which matters for the day fields
+    any: bool,
+}
+
+impl Field {
+    fn parse(text: &str, min: u32, max: u32, names: &[&str], name_base: u32) -> Result<Self, String> {
+        let value = |text: &str| -> Result<u32, String> {
+            let lower = text.to_ascii_lowercase();
+            let value = match names.iter().position(|name| *name == lower) {
+                Some(index) => index as u32 + name_base,
+                None => text.parse().map_err(|_| format!("'{}' is not a number", text))?,
+            };
+            match (min..=max).contains(&value) {
+                true => Ok(value),
+                false => Err(format!("{} is outside {}-{}", value, min, max)),
+            }
+        };
+
+        let mut bits = 0u64;
+        for item in text.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => {
+                    let step: u32 = step.parse().map_err(|_| format!("'{}' is not a step", step))?;
+                    if step == 0 {
+                        return Err("a step of 0".into());
+                    }
+                    (range, step)
+                }
+                None => (item, 1),
+            };
+            let (first, last) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((first, last)) => (value(first)?, value(last)?),
+                    None if step > 1 => (value(range)?, max),
+                    None => {
+                        let single = value(range)?;
+                        (single, single)
+                    }
+                },
+            };
+            if first > last {
+                return Err(format!("the range {}-{} is backwards", first, last));
+            }
+            for value in (first..=last).step_by(step as usize) {
+                bits |= 1 << value;
+            }
+        }
+        Ok(Self { bits, any: text.starts_with('*') })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        self.bits & (1 << value) != 0
+    }
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    text: String,
+    minute: Field,
+    hour: Field,
+    day: Field,
+    month: Field,
+    weekday: Field,
+}
+
+impl FromStr for Cron {
+    type Err = SchedulerError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| SchedulerError::InvalidCron { expression: text.to_string(), reason };
+        let expanded = match text.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(format!("expected 5 fields, found {}", fields.len())));
+        };
+
+        let mut weekday = Field::parse(weekday, 0, 7, &WEEKDAYS, 0).map_err(invalid)?;
+        if weekday.matches(7) {
+            weekday.bits |= 1;
+        }
+        Ok(Self {
+            text: text.trim().to_string(),
+            minute: Field::parse(minute, 0, 59, &[], 0).map_err(invalid)?,
+            hour: Field::parse(hour, 0, 23, &[], 0).map_err(invalid)?,
+            day: Field::parse(day, 1, 31, &[], 0).map_err(invalid)?,
+            month: Field::parse(month, 1, 12, &MONTHS, 1).map_err(invalid)?,
+            weekday,
+        })
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+impl Cron {
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let day = self.day.matches(date.day());
+        let weekday = self.weekday.matches(date.weekday().num_days_from_sunday());
+        match (self.day.any, self.weekday.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The first time strictly after `after` the expression matches
+    ///
+    /// When clocks go back and an hour repeats, jobs that run hourly or more
+    /// often keep running through the repeat, as they would in real time;
+    /// others run only the first time.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let start = after.naive_local();
+        let next = self.search(after, start);
+        if !self.hour.any {
+            return next;
+        }
+        // Still in the first pass through a repeated hour: its second pass
+        // is behind `start` on the wall clock but ahead in real time
+        let LocalResult::Ambiguous(_, repeated) = after.timezone().from_local_datetime(&start) else {
+            return next;
+        };
+        if repeated <= *after {
+            return next;
+        }
+        let shift = repeated.naive_utc() - after.naive_utc();
+        let again = self.search(after, start - shift);
+        next.into_iter().chain(again).min()
+    }
+
+    /// First match after `after`, scanning wall-clock times from `start`
+    fn search<Tz: TimeZone>(&self, after: &DateTime<Tz>, start: NaiveDateTime) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let mut time = start.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = start + Duration::days(SEARCH_DAYS);
+
+        while time < limit {
+            let date = time.date();
+            if !self.month.matches(date.month()) {
+                let (year, month) = match date.month() {
+                    12 => (date.year() + 1, 1),
+                    month => (date.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(date) {
+                time = date.succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hour.matches(time.hour()) {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if !self.minute.matches(time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                // Of the two times when clocks go back, the first still ahead
+                let found = match zone.from_local_datetime(&time) {
+                    LocalResult::Ambiguous(earlier, later) => Some(if earlier > *after { earlier } else { later }),
+                    _ => resolve_local(&zone, time),
+                };
+                match found {
+                    Some(found) if found > *after => return Some(found),
+                    _ => time += Duration::minutes(1),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// `local` in `zone`: the first of two times when clocks go back, the end
+/// of the gap when `local` was skipped
+pub fn resolve_local<Tz: TimeZone>(zone: &Tz, local: NaiveDateTime) -> Option<DateTime<Tz>> {
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Some(time),
+        LocalResult::None => {
+            let minute = local.with_second(0)?.with_nanosecond(0)?;
+            (1..=24 * 60).find_map(|later| zone.from_local_datetime(&(minute + Duration::minutes(later))).earliest())
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Local, MappedLocalTime, NaiveTime};
+
+    /// Central European time in 2026: summer time from 29 March to 25 October
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) struct Cet;
+
+    impl Cet {
+        fn winter() -> FixedOffset {
+            FixedOffset::east_opt(3600).unwrap()
+        }
+
+        fn summer() -> FixedOffset {
+            FixedOffset::east_opt(7200).unwrap()
+        }
+    }
+
+    impl TimeZone for Cet {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<FixedOffset> {
+            let fits = |offset: FixedOffset| self.offset_from_utc_datetime(&(*local - offset)) == offset;
+            match (fits(Cet::summer()), fits(Cet::winter())) {
+                (true, true) => MappedLocalTime::Ambiguous(Cet::summer(), Cet::winter()),
+                (true, false) => MappedLocalTime::Single(Cet::summer()),
+                (false, true) => MappedLocalTime::Single(Cet::winter()),
+                (false, false) => MappedLocalTime::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_time(NaiveTime::MIN))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at_one = |month, day| NaiveDate::from_ymd_opt(2026, month, day).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            match (at_one(3, 29)..at_one(10, 25)).contains(utc) {
+                true => Cet::summer(),
+                false => Cet::winter(),
+            }
+        }
+    }
+
+    fn cet(text: &str) -> DateTime<Cet> {
+        resolve_local(&Cet, NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    fn next(cron: &str, after: &str) -> String {
+        let next = cron.parse::<Cron>().unwrap().next_after(&cet(after)).unwrap();
+        next.format("%Y-%m-%d %H:%M %:z").to_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        for invalid in ["* * * *", "60 * * * *", "* * 0 * *", "5-1 * * * *", "*/0 * * * *", "* * * foo *"] {
+            assert!(invalid.parse::<Cron>().is_err(), "{}", invalid);
+        }
+        let cron: Cron = "0,30 8-18/2 * JAN-mar mon-fri".parse().unwrap();
+        assert!(cron.minute.matches(30) && !cron.minute.matches(15));
+        assert!(cron.hour.matches(10) && !cron.hour.matches(9));
+        assert!(cron.month.matches(3) && !cron.month.matches(4));
+        assert!(cron.weekday.matches(5) && !cron.weekday.matches(0));
+        assert!("0 0 * * 7".parse::<Cron>().unwrap().weekday.matches(0));
+        assert_eq!(cron.to_string(), "0,30 8-18/2 * JAN-mar mon-fri");
+    }
+
+    #[test]
+    fn test_next_after() {
+        assert_eq!(next("*/15 * * * *", "2026-06-01 10:07"), "2026-06-01 10:15 +02:00");
+        assert_eq!(next("0 7 * * mon-fri", "2026-06-05 07:00"), "2026-06-08 07:00 +02:00");
+        assert_eq!(next("@monthly", "2026-12-15 12:00"), "2027-01-01 00:00 +01:00");
+        // Either day field matches when both are restricted
+        assert_eq!(next("0 0 13 * fri", "2026-06-01 00:00"), "2026-06-05 00:00 +02:00");
+        assert_eq!(next("0 0 29 2 *", "2026-03-01 00:00"), "2028-02-29 00:00 +01:00");
+        assert_eq!(next("0 0 29 2 *", "2028-02-29 00:00"), "2032-02-29 00:00 +01:00");
+        assert_eq!(next("@hourly", "2028-02-29 12:30"), "2028-02-29 13:00 +01:00");
+        assert!("0 0 30 2 *".parse::<Cron>().unwrap().next_after(&cet("2026-01-01 00:00")).is_none());
+        assert!("* * * * *".parse::<Cron>().unwrap().next_after(&Local::now()).is_some());
+    }
+
+    #[test]
+    fn test_daylight_saving() {
+        // 02:30 does not exist on 29 March; it runs when the gap ends
+        assert_eq!(next("30 2 * * *", "2026-03-29 00:00"), "2026-03-29 03:00 +02:00");
+        assert_eq!(next("30 2 * * *", "2026-03-29 03:00"), "2026-03-30 02:30 +02:00");
+        // 02:30 happens twice on 25 October; it runs the first time only
+        assert_eq!(next("30 2 * * *", "2026-10-25 00:00"), "2026-10-25 02:30 +02:00");
+        let second_round = cet("2026-10-25 02:45").with_timezone(&Cet) + Duration::hours(1);
+        assert_eq!(second_round.offset(), &Cet::winter());
+        let after = "30 2 * * *".parse::<Cron>().unwrap().next_after(&second_round).unwrap();
+        assert_eq!(after.format("%Y-%m-%d %H:%M %:z").to_string(), "2026-10-26 02:30 +01:00");
+        // Hourly and more frequent jobs run through the repeated hour too
+        assert_eq!(next("0 * * * *", "2026-10-25 02:10"), "2026-10-25 02:00 +01:00");
+        assert_eq!(next("*/15 * * * *", "2026-10-25 02:50"), "2026-10-25 02:00 +01:00");
+        let first_round_end = cet("2026-10-25 02:59");
+        assert_eq!(first_round_end.offset(), &Cet::summer());
+        let cron: Cron = "* * * * *".parse().unwrap();
+        let mut time = first_round_end;
+        for _ in 0..60 {
+            let next = cron.next_after(&time).unwrap();
+            assert_eq!(next - time, Duration::minutes(1));
+            time = next;
+        }
+        assert_eq!(time.format("%H:%M %:z").to_string(), "02:59 +01:00");
+        assert_eq!(next("0 * * * *", "2026-10-25 02:59"), "2026-10-25 02:00 +01:00");
+    }
+}
@@ -0,0 +1,202 @@
+//! Named timers, the successor of the legacy `Timer` and `Scheduler`
+//! plugins
+//!
+//! Each timer fires `<prefix>.<name>`, e.g. `Scheduler.Morning`, with the
+//! timer's name and how often it has fired as payload. Timers run on
+//! intervals, once at a local date and time, or on a [cron](cron)
+//! schedule:
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "Scheduler"
+//! enabled = true
+//!
+//! [[plugins.settings.timers]]
+//! name = "Morning"
+//! type = "cron"
+//! cron = "0 7 * * mon-fri"
+//!
+//! [[plugins.settings.timers]]
+//! name = "Poll"
+//! type = "interval"
+//! every_ms = 30000
+//! repeat = 10
+//!
+//! [[plugins.settings.timers]]
+//! name = "Party"
+//! type = "at"
+//! at = "2026-12-31T23:59:00"
+//! ```
+//!
+//! [`control_action`] starts, stops and resets timers at runtime.
+
+pub mod cron;
+pub mod timers;
+
+pub use cron::Cron;
+pub use timers::{Scheduler, SchedulerHandle, TimerStatus};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::template;
+
+/// Error type for the scheduler plugin
+#[derive(Debug, thiserror::Error)]
+pub enum SchedulerError {
+    #[error("Invalid cron expression '{expression}': {reason}")]
+    InvalidCron { expression: String, reason: String },
+    #[error("No timer named '{0}'")]
+    UnknownTimer(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<SchedulerError> for Error {
+    fn from(e: SchedulerError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// When a timer fires
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Schedule {
+    /// Every `every_ms`, `repeat` times or forever
+    Interval {
+        every_ms: u64,
+        #[serde(default)]
+        repeat: Option<u32>,
+    },
+    /// Once, at a local date and time
+    At { at: NaiveDateTime },
+    /// On a cron schedule in local time
+    Cron { cron: String },
+}
+
+/// One named timer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimerSettings {
+    pub name: String,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    /// Run from the start; otherwise it waits for a start action
+    #[serde(default = "autostart")]
+    pub autostart: bool,
+}
+
+fn autostart() -> bool {
+    true
+}
+
+/// Settings of the `Scheduler` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerSettings {
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    pub timers: Vec<TimerSettings>,
+}
+
+impl Default for SchedulerSettings {
+    fn default() -> Self {
+        Self {
+            prefix: Scheduler::NAME.to_string(),
+            timers: Vec::new(),
+        }
+    }
+}
+
+impl SchedulerSettings {
+    pub fn validate(&self) -> Result<(), SchedulerError> {
+        for (index, timer) in self.timers.iter().enumerate() {
+            if timer.name.is_empty() {
+                return Err(SchedulerError::Settings(format!("timer {} has no name", index + 1)));
+            }
+            if self.timers[..index].iter().any(|other| other.name == timer.name) {
+                return Err(SchedulerError::Settings(format!("there are two timers named '{}'", timer.name)));
+            }
+            match &timer.schedule {
+                Schedule::Interval { every_ms: 0, .. } => {
+                    return Err(SchedulerError::Settings(format!("timer '{}' has an interval of 0", timer.name)));
+                }
+                Schedule::Cron { cron } => {
+                    cron.parse::<Cron>()?;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a control action does to its timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimerCommand {
+    /// Run a stopped timer; intervals count from now
+    Start,
+    /// Stop a timer until it is started again
+    Stop,
+    /// Zero the count and run the schedule from now
+    Reset,
+}
+
+/// Creates an action that applies `command` to the timer named by the
+/// [template](crate::plugins::template) `timer`
+pub fn control_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    handle: SchedulerHandle,
+    command: TimerCommand,
+    timer: &str,
+) -> ActionItem {
+    let timer = timer.to_string();
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let timer = template::render(&timer, event);
+            let result = match command {
+                TimerCommand::Start => handle.start(&timer),
+                TimerCommand::Stop => handle.stop(&timer),
+                TimerCommand::Reset => handle.reset(&timer),
+            };
+            result.map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_settings() {
+        let settings: SchedulerSettings = serde_json::from_value(json!({
+            "timers": [
+                {"name": "Poll", "type": "interval", "every_ms": 500, "repeat": 3},
+                {"name": "Party", "type": "at", "at": "2026-12-31T23:59:00", "autostart": false},
+                {"name": "Morning", "type": "cron", "cron": "0 7 * * mon-fri"},
+            ]
+        }))
+        .unwrap();
+        assert_eq!(settings.timers[0].schedule, Schedule::Interval { every_ms: 500, repeat: Some(3) });
+        assert!(!settings.timers[1].autostart && settings.timers[2].autostart);
+        assert!(settings.validate().is_ok());
+
+        let mut invalid = settings.clone();
+        invalid.timers[2].schedule = Schedule::Cron { cron: "0 7 * *".into() };
+        assert!(matches!(invalid.validate(), Err(SchedulerError::InvalidCron { .. })));
+        let mut invalid = settings;
+        invalid.timers[1].name = "Poll".into();
+        assert!(matches!(invalid.validate(), Err(SchedulerError::Settings(_))));
+    }
+}
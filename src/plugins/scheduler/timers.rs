@@ -0,0 +1,382 @@
+//! Scheduler plugin
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, EventQueue, EventSink};
+use super::cron::{resolve_local, Cron};
+use super::{control_action, Schedule, SchedulerError, SchedulerSettings, TimerCommand, TimerSettings};
+
+/// Longest sleep between looks at the clock, so timers stay on time when
+/// the system clock is changed or the machine wakes from sleep
+const MAX_SLEEP: Duration = Duration::from_secs(30);
+
+struct Timer {
+    settings: TimerSettings,
+    cron: Option<Cron>,
+    running: bool,
+    count: u32,
+    next: Option<DateTime<Local>>,
+}
+
+impl Timer {
+    fn new(settings: &TimerSettings) -> Result<Self, SchedulerError> {
+        let cron = match &settings.schedule {
+            Schedule::Cron { cron } => Some(cron.parse()?),
+            _ => None,
+        };
+        Ok(Self { settings: settings.clone(), cron, running: false, count: 0, next: None })
+    }
+
+    /// Run the schedule from `now`
+    fn arm(&mut self, now: DateTime<Local>) {
+        self.next = match &self.settings.schedule {
+            Schedule::Interval { every_ms, repeat } => match repeat {
+                Some(repeat) if self.count >= *repeat => None,
+                _ => Some(now + Duration::from_millis(*every_ms)),
+            },
+            Schedule::At { at } => resolve_local(&Local, *at).filter(|at| *at > now),
+            Schedule::Cron { .. } => self.cron.as_ref().and_then(|cron| cron.next_after(&now)),
+        };
+        self.running = self.next.is_some();
+    }
+
+    fn stop(&mut self) {
+        self.running = false;
+        self.next = None;
+    }
+
+    /// Count a firing that was due at `due` and schedule the next one
+    fn fired(&mut self, due: DateTime<Local>, now: DateTime<Local>) {
+        self.count += 1;
+        self.next = match &self.settings.schedule {
+            Schedule::Interval { repeat: Some(repeat), .. } if self.count >= *repeat => None,
+            Schedule::Interval { every_ms, .. } => {
+                // Keep the rhythm, but do not catch up on missed beats
+                let every = Duration::from_millis(*every_ms);
+                Some(due + every).filter(|next| *next > now).or(Some(now + every))
+            }
+            Schedule::At { .. } => None,
+            Schedule::Cron { .. } => self.cron.as_ref().and_then(|cron| cron.next_after(&now)),
+        };
+        self.running = self.next.is_some();
+    }
+}
+
+/// A timer's state as the handle reports it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimerStatus {
+    pub name: String,
+    pub running: bool,
+    /// Firings since the start or last reset
+    pub count: u32,
+    pub next: Option<DateTime<Local>>,
+}
+
+/// Controls the timers of a running scheduler; cheap to clone
+#[derive(Clone, Default)]
+pub struct SchedulerHandle {
+    timers: Arc<Mutex<Vec<Timer>>>,
+    wake: Arc<Notify>,
+}
+
+impl SchedulerHandle {
+    pub fn start(&self, name: &str) -> Result<(), SchedulerError> {
+        self.update(name, |timer, now| {
+            if !timer.running {
+                timer.arm(now);
+            }
+        })
+    }
+
+    pub fn stop(&self, name: &str) -> Result<(), SchedulerError> {
+        self.update(name, |timer, _| timer.stop())
+    }
+
+    pub fn reset(&self, name: &str) -> Result<(), SchedulerError> {
+        self.update(name, |timer, now| {
+            timer.count = 0;
+            timer.arm(now);
+        })
+    }
+
+    pub fn status(&self) -> Vec<TimerStatus> {
+        self.lock()
+            .iter()
+            .map(|timer| TimerStatus {
+                name: timer.settings.name.clone(),
+                running: timer.running,
+                count: timer.count,
+                next: timer.next,
+            })
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Timer>> {
+        self.timers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn update(&self, name: &str, change: impl FnOnce(&mut Timer, DateTime<Local>)) -> Result<(), SchedulerError> {
+        let mut timers = self.lock();
+        let timer = timers
+            .iter_mut()
+            .find(|timer| timer.settings.name == name)
+            .ok_or_else(|| SchedulerError::UnknownTimer(name.to_string()))?;
+        change(timer, Local::now());
+        drop(timers);
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    /// Events of the timers due at `now`, and when the next one is due
+    fn fire_due(&self, now: DateTime<Local>, prefix: &str) -> (Vec<NamedEvent>, Option<DateTime<Local>>) {
+        let mut timers = self.lock();
+        let mut events = Vec::new();
+        for timer in timers.iter_mut().filter(|timer| timer.running) {
+            let Some(due) = timer.next.filter(|due| *due <= now) else {
+                continue;
+            };
+            timer.fired(due, now);
+            events.push(timer_event(prefix, &timer.settings.name, timer.count));
+        }
+        let next = timers.iter().filter(|timer| timer.running).filter_map(|timer| timer.next).min();
+        (events, next)
+    }
+}
+
+fn timer_event(prefix: &str, name: &str, count: u32) -> NamedEvent {
+    let event = if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+    let payload = json!({"timer": name, "count": count});
+    NamedEvent::new(&event, EventType::Plugin, EventPayload::from_json(payload)).with_source(Scheduler::NAME)
+}
+
+/// Fire timers until aborted
+async fn run(handle: SchedulerHandle, prefix: String, sink: EventSink) {
+    loop {
+        let (events, next) = handle.fire_due(Local::now(), &prefix);
+        for event in events {
+            sink.emit(event);
+        }
+        let wait = next
+            .map(|next| (next - Local::now()).to_std().unwrap_or_default())
+            .map_or(MAX_SLEEP, |wait| wait.min(MAX_SLEEP));
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = handle.wake.notified() => {}
+        }
+    }
+}
+
+/// Fires named events on intervals, at set times and on cron schedules
+pub struct Scheduler {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: SchedulerSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    handle: SchedulerHandle,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub const NAME: &'static str = "Scheduler";
+
+    pub fn new(settings: SchedulerSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Fires events on intervals, at set times and on cron schedules",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            handle: SchedulerHandle::default(),
+            task: None,
+        }
+    }
+
+    /// Publish timer events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &SchedulerSettings {
+        &self.settings
+    }
+
+    /// Handle for control actions; its timers exist while the plugin runs
+    pub fn handle(&self) -> SchedulerHandle {
+        self.handle.clone()
+    }
+
+    fn schedule(&mut self) -> Result<(), SchedulerError> {
+        self.settings.validate()?;
+        let now = Local::now();
+        let mut timers = self.settings.timers.iter().map(Timer::new).collect::<Result<Vec<_>, _>>()?;
+        for timer in timers.iter_mut().filter(|timer| timer.settings.autostart) {
+            timer.arm(now);
+        }
+        *self.handle.lock() = timers;
+        self.task = Some(tokio::spawn(run(self.handle.clone(), self.settings.prefix.clone(), self.sink.clone())));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.handle.lock().clear();
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Arguments of the `Start`, `Stop` and `Reset` actions
+#[derive(Deserialize)]
+struct TimerArgs {
+    timer: String,
+}
+
+#[async_trait]
+impl Plugin for Scheduler {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        let command = match name {
+            "Start" => TimerCommand::Start,
+            "Stop" => TimerCommand::Stop,
+            "Reset" => TimerCommand::Reset,
+            _ => return None,
+        };
+        Some(action_args(name, args).map(|args: TimerArgs| {
+            control_action(name, "Controls a timer", self.info.id, self.handle(), command, &args.timer)
+        }))
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.schedule() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: SchedulerSettings =
+            crate::plugins::settings_for(&config, Self::NAME).map_err(SchedulerError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for Scheduler {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use uuid::Uuid;
+    use crate::eg::action::base::ActionBase;
+    use crate::plugins::scheduler::{control_action, TimerCommand};
+
+    fn interval(name: &str, every_ms: u64, repeat: Option<u32>, autostart: bool) -> TimerSettings {
+        TimerSettings { name: name.into(), schedule: Schedule::Interval { every_ms, repeat }, autostart }
+    }
+
+    #[tokio::test]
+    async fn test_timers() {
+        let at = (Local::now() + Duration::from_millis(250)).naive_local();
+        let mut scheduler = Scheduler::new(SchedulerSettings {
+            timers: vec![
+                interval("Poll", 40, Some(2), true),
+                TimerSettings { name: "Once".into(), schedule: Schedule::At { at }, autostart: true },
+                interval("Manual", 200, None, false),
+            ],
+            ..Default::default()
+        });
+        scheduler.initialize().await.unwrap();
+        scheduler.start().await.unwrap();
+
+        assert_eq!(next_event(&mut scheduler).await, ("Scheduler.Poll".into(), json!({"timer": "Poll", "count": 1})));
+        assert_eq!(next_event(&mut scheduler).await, ("Scheduler.Poll".into(), json!({"timer": "Poll", "count": 2})));
+        assert_eq!(next_event(&mut scheduler).await.0, "Scheduler.Once");
+        let status = scheduler.handle().status();
+        assert!(status.iter().all(|timer| !timer.running && timer.next.is_none()));
+        assert_eq!(status.iter().map(|timer| timer.count).collect::<Vec<_>>(), [2, 1, 0]);
+
+        let handle = scheduler.handle();
+        let control = |command, timer| control_action("Control", "Controls a timer", Uuid::new_v4(), handle.clone(), command, timer);
+        let trigger = NamedEvent::new("Remote.Play", EventType::Plugin, EventPayload::None);
+        control(TimerCommand::Reset, "Manual").execute(&trigger).await.unwrap();
+        assert_eq!(next_event(&mut scheduler).await, ("Scheduler.Manual".into(), json!({"timer": "Manual", "count": 1})));
+        control(TimerCommand::Stop, "Manual").execute(&trigger).await.unwrap();
+        assert!(!scheduler.handle().status()[2].running);
+        assert!(control(TimerCommand::Start, "Nope").execute(&trigger).await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(scheduler.queue.try_next().await.is_none());
+        scheduler.stop().await.unwrap();
+        assert!(scheduler.handle().status().is_empty());
+    }
+
+    #[test]
+    fn test_interval_does_not_catch_up() {
+        let mut timer = Timer::new(&interval("Poll", 1_000, None, true)).unwrap();
+        let now = Local::now();
+        timer.arm(now);
+        let due = timer.next.unwrap();
+        timer.fired(due, due + Duration::from_secs(10));
+        assert_eq!(timer.next, Some(due + Duration::from_secs(11)));
+        timer.fired(due + Duration::from_secs(11), due + Duration::from_millis(11_100));
+        assert_eq!(timer.next, Some(due + Duration::from_secs(12)));
+    }
+}
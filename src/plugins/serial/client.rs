@@ -0,0 +1,293 @@
+//! Serial plugin
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, reconnect, EventQueue, EventSink};
+use super::{send_action, to_hex, unescape, DataFormat, Framer, SerialError, SerialPort, SerialSettings};
+
+/// Writes to the plugin's port while it is open; cheap to clone
+#[derive(Clone, Default)]
+pub struct SerialWriter(Arc<Mutex<Option<mpsc::UnboundedSender<Vec<u8>>>>>);
+
+impl SerialWriter {
+    /// Queue `data` for the port
+    pub fn write(&self, data: Vec<u8>) -> Result<(), SerialError> {
+        let sender = self.0.lock().map_err(|_| SerialError::NotOpen)?;
+        sender.as_ref().ok_or(SerialError::NotOpen)?.send(data).map_err(|_| SerialError::NotOpen)
+    }
+
+    fn set(&self, sender: Option<mpsc::UnboundedSender<Vec<u8>>>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = sender;
+        }
+    }
+}
+
+/// Raises the frames read from a serial port as events
+///
+/// The port is reopened every `reconnect_ms` while it is missing.
+pub struct SerialClient {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: SerialSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    writer: SerialWriter,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SerialClient {
+    pub const NAME: &'static str = "Serial";
+
+    pub fn new(settings: SerialSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Reads and writes line protocols on a serial port",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            writer: SerialWriter::default(),
+            task: None,
+        }
+    }
+
+    /// Publish received frames on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &SerialSettings {
+        &self.settings
+    }
+
+    /// Handle for [`send_action`](super::send_action)s
+    pub fn writer(&self) -> SerialWriter {
+        self.writer.clone()
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.writer.set(None);
+    }
+}
+
+impl Drop for SerialClient {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Keep the port open until aborted
+async fn run(settings: SerialSettings, writer: SerialWriter, sink: EventSink) {
+    let (settings, writer, sink) = (&settings, &writer, &sink);
+    let what = format!("cannot open {}", settings.port.display());
+    reconnect(
+        SerialClient::NAME,
+        &what,
+        Duration::from_millis(settings.reconnect_ms),
+        || std::future::ready(SerialPort::open(settings)),
+        |port| async move {
+            log::info!("{} opened {}", SerialClient::NAME, settings.port.display());
+            let (sender, receiver) = mpsc::unbounded_channel();
+            writer.set(Some(sender));
+            // A new connection starts with no partial frame
+            let mut framer = Framer::new(unescape(&settings.delimiter).unwrap_or_default(), settings.max_frame);
+            if let Err(e) = serve(&port, &mut framer, receiver, settings, sink).await {
+                log::warn!("{}: lost {}: {}", SerialClient::NAME, settings.port.display(), e);
+            }
+            writer.set(None);
+        },
+    )
+    .await
+}
+
+/// Raise frames and write queued data until the port fails
+async fn serve(
+    port: &SerialPort,
+    framer: &mut Framer,
+    mut outgoing: mpsc::UnboundedReceiver<Vec<u8>>,
+    settings: &SerialSettings,
+    sink: &EventSink,
+) -> Result<(), SerialError> {
+    let mut buffer = [0; 1024];
+    loop {
+        tokio::select! {
+            read = port.read(&mut buffer) => {
+                let count = read?;
+                if count == 0 {
+                    return Err(SerialError::Io(std::io::ErrorKind::UnexpectedEof.into()));
+                }
+                for frame in framer.push(&buffer[..count]) {
+                    sink.emit(frame_event(settings, &frame));
+                }
+            }
+            Some(data) = outgoing.recv() => port.write_all(&data).await?,
+        }
+    }
+}
+
+fn frame_event(settings: &SerialSettings, frame: &[u8]) -> NamedEvent {
+    let text = String::from_utf8_lossy(frame);
+    let printable = !text.trim().is_empty() && !text.chars().any(char::is_control);
+    let suffix = if settings.data_events && printable { text.trim() } else { "Received" };
+    let name = match settings.prefix.as_str() {
+        "" => suffix.to_string(),
+        prefix => format!("{}.{}", prefix, suffix),
+    };
+    let payload = json!({
+        "data": text,
+        "hex": to_hex(frame),
+        "port": settings.port.to_string_lossy(),
+    });
+    NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(payload)).with_source(SerialClient::NAME)
+}
+
+/// Arguments of the `Send` action
+#[derive(Deserialize)]
+struct SendArgs {
+    data: String,
+    #[serde(default)]
+    format: DataFormat,
+}
+
+#[async_trait]
+impl Plugin for SerialClient {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        match name {
+            "Send" => Some(action_args(name, args).map(|args: SendArgs| {
+                send_action(name, "Writes to the serial port", self.info.id, self.writer(), &args.data, args.format)
+            })),
+            _ => None,
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.settings.validate() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.task = Some(tokio::spawn(run(self.settings.clone(), self.writer.clone(), self.sink.clone())));
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: SerialSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(SerialError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for SerialClient {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::next_event;
+    use crate::eg::action::base::ActionBase;
+    use crate::plugins::serial::{port::open_pty, send_action, DataFormat};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_with_pty() {
+        let (controller, terminal) = open_pty().unwrap();
+        let device = SerialPort::from_file(controller).unwrap();
+
+        // The port shows up after the plugin started, like a USB adapter
+        let dir = tempfile::tempdir().unwrap();
+        let port = dir.path().join("ttyTEST");
+        let mut client = SerialClient::new(SerialSettings {
+            port: port.clone(),
+            baud: 115200,
+            delimiter: "\\r".into(),
+            reconnect_ms: 10,
+            ..Default::default()
+        });
+        client.initialize().await.unwrap();
+        client.start().await.unwrap();
+        let writer = client.writer();
+        assert!(matches!(writer.write(b"early".to_vec()), Err(SerialError::NotOpen)));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        std::os::unix::fs::symlink(&terminal, &port).unwrap();
+        while writer.write(Vec::new()).is_err() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        device.write_all(b"PWON\rMV5").await.unwrap();
+        let (name, payload) = next_event(&mut client).await;
+        assert_eq!(name, "Serial.PWON");
+        assert_eq!(payload["hex"], "50 57 4F 4E");
+        device.write_all(b"5\r\x02\x03\r").await.unwrap();
+        assert_eq!(next_event(&mut client).await.0, "Serial.MV55");
+        let (name, payload) = next_event(&mut client).await;
+        assert_eq!(name, "Serial.Received");
+        assert_eq!(payload["hex"], "02 03");
+
+        let mut action = send_action("Volume", "Set the volume", Uuid::new_v4(), writer.clone(), "MV{payload.level}\\r", DataFormat::Text);
+        let event = NamedEvent::new("Volume", EventType::User, EventPayload::from_json(json!({"level": 40})));
+        action.execute(&event).await.unwrap();
+        let mut hex = send_action("Power", "Power off", Uuid::new_v4(), writer, "50 57 53 54 41 4E 44 42 59 0D", DataFormat::Hex);
+        hex.execute(&event).await.unwrap();
+
+        let expected = b"MV40\rPWSTANDBY\r";
+        let mut received = Vec::new();
+        let mut buffer = [0; 64];
+        while received.len() < expected.len() {
+            let count = tokio::time::timeout(Duration::from_secs(5), device.read(&mut buffer)).await.unwrap().unwrap();
+            received.extend_from_slice(&buffer[..count]);
+        }
+        assert_eq!(received, expected);
+
+        client.stop().await.unwrap();
+    }
+}
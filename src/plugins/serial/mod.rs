@@ -0,0 +1,315 @@
+//! Serial ports speaking simple line protocols, the common ground of the
+//! legacy `Serial`, `DenonSerial`, `MarantzSerial` and similar plugins
+//!
+//! Bytes read from the port are cut into frames at `delimiter` and each
+//! frame raises `Serial.<frame>`, e.g. `Serial.PWON` for a Denon receiver
+//! answering `PWON\r`, with the text and its hex dump as payload. Settings
+//! follow the usual `9600 8N1` notation:
+//!
+//! ```toml
+//! [[plugins]]
+//! id = "Serial"
+//! enabled = true
+//! settings = { port = "/dev/ttyUSB0", baud = 9600, parity = "none", delimiter = "\\r" }
+//! ```
+//!
+//! The port is reopened every `reconnect_ms` while it is missing, e.g. an
+//! unplugged USB adapter. [`send_action`] writes text with escapes such as
+//! `\r` and `\x02`, or hex such as `02 50 57 03`. Device plugins can build
+//! on [`SerialPort`] and [`Framer`] directly.
+
+pub mod client;
+pub mod port;
+
+pub use client::{SerialClient, SerialWriter};
+pub use port::SerialPort;
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::template;
+
+/// Error type for the serial plugin
+#[derive(Debug, thiserror::Error)]
+pub enum SerialError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The port is not open")]
+    NotOpen,
+    #[error("Invalid data '{data}': {reason}")]
+    InvalidData { data: String, reason: String },
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<SerialError> for Error {
+    fn from(e: SerialError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    #[default]
+    None,
+    /// RTS/CTS
+    Hardware,
+    /// XON/XOFF
+    Software,
+}
+
+/// Settings of the `Serial` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialSettings {
+    pub port: PathBuf,
+    pub baud: u32,
+    /// 5 to 8
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    /// End of a received frame, with escapes; empty to take whatever
+    /// arrives at once as a frame
+    pub delimiter: String,
+    /// Frames longer than this are cut without a delimiter
+    pub max_frame: usize,
+    /// Name events after the received text; otherwise all are
+    /// `<prefix>.Received`
+    pub data_events: bool,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    /// Delay between attempts to open the port
+    pub reconnect_ms: u64,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            port: PathBuf::from("/dev/ttyS0"),
+            baud: 9600,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            delimiter: "\\n".to_string(),
+            max_frame: 4096,
+            data_events: true,
+            prefix: SerialClient::NAME.to_string(),
+            reconnect_ms: 2000,
+        }
+    }
+}
+
+impl SerialSettings {
+    pub fn validate(&self) -> Result<(), SerialError> {
+        if !(5..=8).contains(&self.data_bits) {
+            return Err(SerialError::Settings(format!("{} data bits", self.data_bits)));
+        }
+        if !(1..=2).contains(&self.stop_bits) {
+            return Err(SerialError::Settings(format!("{} stop bits", self.stop_bits)));
+        }
+        if port::baud_rate(self.baud).is_none() {
+            return Err(SerialError::Settings(format!("unsupported baud rate {}", self.baud)));
+        }
+        if self.max_frame == 0 {
+            return Err(SerialError::Settings("max_frame must be above 0".into()));
+        }
+        unescape(&self.delimiter)?;
+        Ok(())
+    }
+}
+
+/// Cuts a byte stream into frames ending in a delimiter
+#[derive(Debug, Clone)]
+pub struct Framer {
+    delimiter: Vec<u8>,
+    max_frame: usize,
+    buffer: Vec<u8>,
+}
+
+impl Framer {
+    pub fn new(delimiter: Vec<u8>, max_frame: usize) -> Self {
+        Self { delimiter, max_frame, buffer: Vec::new() }
+    }
+
+    /// Add received bytes; returns the frames they complete, without
+    /// delimiters
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        if self.delimiter.is_empty() {
+            return data.chunks(self.max_frame).map(<[u8]>::to_vec).collect();
+        }
+        self.buffer.extend_from_slice(data);
+        let mut frames = Vec::new();
+        while let Some(end) = self.buffer.windows(self.delimiter.len()).position(|window| window == self.delimiter) {
+            frames.push(self.buffer[..end].to_vec());
+            self.buffer.drain(..end + self.delimiter.len());
+        }
+        while self.buffer.len() >= self.max_frame {
+            frames.push(self.buffer.drain(..self.max_frame).collect());
+        }
+        frames
+    }
+
+    /// Forget a partial frame, e.g. after reconnecting
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Bytes of `text` with `\r`, `\n`, `\t`, `\0`, `\\` and `\xNN` escapes
+pub fn unescape(text: &str) -> Result<Vec<u8>, SerialError> {
+    let invalid = |reason: &str| SerialError::InvalidData { data: text.to_string(), reason: reason.to_string() };
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&digits, 16).ok().filter(|_| digits.len() == 2);
+                bytes.push(byte.ok_or_else(|| invalid("\\x needs two hex digits"))?);
+            }
+            Some(other) => return Err(invalid(&format!("unknown escape \\{}", other))),
+            None => return Err(invalid("ends in a backslash")),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Bytes of hex digits such as `02 50 57 03` or `0250`
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, SerialError> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(SerialError::InvalidData { data: text.to_string(), reason: "odd number of hex digits".into() });
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16)
+                .map_err(|_| SerialError::InvalidData { data: text.to_string(), reason: format!("'{}' is not hex", pair) })
+        })
+        .collect()
+}
+
+/// `bytes` as upper-case hex pairs separated by spaces
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+/// How the data of a send action is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    /// Text with escapes, see [`unescape`]
+    #[default]
+    Text,
+    /// Hex digits, see [`parse_hex`]
+    Hex,
+}
+
+impl DataFormat {
+    pub fn encode(self, data: &str) -> Result<Vec<u8>, SerialError> {
+        match self {
+            DataFormat::Text => unescape(data),
+            DataFormat::Hex => parse_hex(data),
+        }
+    }
+}
+
+/// Creates an action that writes the [template](crate::plugins::template)
+/// `data` to the port behind `writer`
+pub fn send_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    writer: SerialWriter,
+    data: &str,
+    format: DataFormat,
+) -> ActionItem {
+    let data = data.to_string();
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let bytes = format
+                .encode(&template::render(&data, event))
+                .map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            writer.write(bytes).map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framer() {
+        let mut framer = Framer::new(b"\r".to_vec(), 8);
+        assert!(framer.push(b"PW").is_empty());
+        assert_eq!(framer.push(b"ON\rMVUP\rSI"), [b"PWON".to_vec(), b"MVUP".to_vec()]);
+        assert_eq!(framer.push(b"CD\r\r"), [b"SICD".to_vec(), Vec::new()]);
+        assert_eq!(framer.push(b"0123456789"), [b"01234567".to_vec()]);
+        framer.clear();
+        assert_eq!(framer.push(b"X\r"), [b"X".to_vec()]);
+
+        let mut framer = Framer::new(b"\r\n".to_vec(), 64);
+        assert_eq!(framer.push(b"OK\r"), Vec::<Vec<u8>>::new());
+        assert_eq!(framer.push(b"\nERR\r\n"), [b"OK".to_vec(), b"ERR".to_vec()]);
+
+        let mut framer = Framer::new(Vec::new(), 2);
+        assert_eq!(framer.push(b"abc"), [b"ab".to_vec(), b"c".to_vec()]);
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(unescape("PWON\\r").unwrap(), b"PWON\r");
+        assert_eq!(unescape("\\x02MV50\\x03\\\\").unwrap(), b"\x02MV50\x03\\");
+        assert!(unescape("\\x2").is_err());
+        assert!(unescape("\\q").is_err());
+        assert_eq!(parse_hex("02 50 57 03").unwrap(), b"\x02PW\x03");
+        assert_eq!(parse_hex("0aFF").unwrap(), [0x0a, 0xff]);
+        assert!(parse_hex("123").is_err());
+        assert!(parse_hex("zz").is_err());
+        assert_eq!(to_hex(b"\x02PW"), "02 50 57");
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings: SerialSettings =
+            serde_json::from_value(serde_json::json!({"port": "/dev/ttyUSB0", "baud": 115200, "parity": "even", "delimiter": "\\r"}))
+                .unwrap();
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.parity, Parity::Even);
+        assert!(SerialSettings { baud: 12345, ..Default::default() }.validate().is_err());
+        assert!(SerialSettings { data_bits: 9, ..Default::default() }.validate().is_err());
+        assert!(SerialSettings { delimiter: "\\".into(), ..Default::default() }.validate().is_err());
+    }
+}
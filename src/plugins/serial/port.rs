@@ -0,0 +1,167 @@
+//! Raw serial ports through termios
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use tokio::io::unix::AsyncFd;
+use super::{FlowControl, Parity, SerialError, SerialSettings};
+
+/// The termios speed for `baud`, if the platform has one
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn baud_rate(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        300 => libc::B300,
+        600 => libc::B600,
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        1500000 => libc::B1500000,
+        2000000 => libc::B2000000,
+        3000000 => libc::B3000000,
+        4000000 => libc::B4000000,
+        _ => return None,
+    })
+}
+
+/// The termios speed for `baud`; BSD speeds are the rates themselves
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub fn baud_rate(baud: u32) -> Option<libc::speed_t> {
+    matches!(baud, 300 | 600 | 1200 | 2400 | 4800 | 9600 | 19200 | 38400 | 57600 | 115200 | 230400)
+        .then_some(baud as libc::speed_t)
+}
+
+/// An open serial port in raw mode
+pub struct SerialPort {
+    fd: AsyncFd<File>,
+}
+
+impl SerialPort {
+    /// Open `settings.port` and apply its speed and framing
+    pub fn open(settings: &SerialSettings) -> Result<Self, SerialError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(&settings.port)?;
+        configure(&file, settings)?;
+        Ok(Self { fd: AsyncFd::new(file)? })
+    }
+
+    /// Wrap a device that is open already, e.g. the other end of a pty
+    pub fn from_file(file: File) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        // Plain fcntl calls on a descriptor we own
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd: AsyncFd::new(file)? })
+    }
+
+    /// Read what has arrived, waiting for at least one byte
+    pub async fn read(&self, buffer: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            if let Ok(result) = guard.try_io(|file| file.get_ref().read(buffer)) {
+                return result;
+            }
+        }
+    }
+
+    pub async fn write_all(&self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let mut guard = self.fd.writable().await?;
+            if let Ok(written) = guard.try_io(|file| file.get_ref().write(data)) {
+                data = &data[written?..];
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Put `file` in raw mode with the speed and framing of `settings`
+fn configure(file: &File, settings: &SerialSettings) -> Result<(), SerialError> {
+    let speed = baud_rate(settings.baud)
+        .ok_or_else(|| SerialError::Settings(format!("unsupported baud rate {}", settings.baud)))?;
+    let fd = file.as_raw_fd();
+    // termios is plain data, filled in by tcgetattr before use
+    let mut tio: libc::termios = unsafe { std::mem::zeroed() };
+    check(unsafe { libc::tcgetattr(fd, &mut tio) })?;
+    unsafe { libc::cfmakeraw(&mut tio) };
+
+    tio.c_cflag |= libc::CREAD | libc::CLOCAL;
+    tio.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB | libc::CRTSCTS);
+    tio.c_cflag |= match settings.data_bits {
+        5 => libc::CS5,
+        6 => libc::CS6,
+        7 => libc::CS7,
+        _ => libc::CS8,
+    };
+    match settings.parity {
+        Parity::None => tio.c_iflag &= !libc::INPCK,
+        Parity::Even => {
+            tio.c_cflag |= libc::PARENB;
+            tio.c_iflag |= libc::INPCK;
+        }
+        Parity::Odd => {
+            tio.c_cflag |= libc::PARENB | libc::PARODD;
+            tio.c_iflag |= libc::INPCK;
+        }
+    }
+    if settings.stop_bits == 2 {
+        tio.c_cflag |= libc::CSTOPB;
+    }
+    tio.c_iflag &= !(libc::IXON | libc::IXOFF | libc::IXANY);
+    match settings.flow_control {
+        FlowControl::None => {}
+        FlowControl::Hardware => tio.c_cflag |= libc::CRTSCTS,
+        FlowControl::Software => tio.c_iflag |= libc::IXON | libc::IXOFF,
+    }
+    tio.c_cc[libc::VMIN] = 1;
+    tio.c_cc[libc::VTIME] = 0;
+
+    check(unsafe { libc::cfsetispeed(&mut tio, speed) })?;
+    check(unsafe { libc::cfsetospeed(&mut tio, speed) })?;
+    check(unsafe { libc::tcsetattr(fd, libc::TCSANOW, &tio) })?;
+    // Drop whatever piled up while nobody was listening
+    check(unsafe { libc::tcflush(fd, libc::TCIOFLUSH) })?;
+    Ok(())
+}
+
+/// A pseudo-terminal pair: the controlling end and the path of the
+/// terminal, which behaves like a serial port
+#[cfg(test)]
+pub(crate) fn open_pty() -> io::Result<(File, std::path::PathBuf)> {
+    use std::os::unix::io::FromRawFd;
+
+    let fd = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+    check(fd)?;
+    // Owned from here on, closed on error
+    let controller = unsafe { File::from_raw_fd(fd) };
+    check(unsafe { libc::grantpt(fd) })?;
+    check(unsafe { libc::unlockpt(fd) })?;
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned();
+    Ok((controller, path.into()))
+}
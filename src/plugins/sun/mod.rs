@@ -0,0 +1,253 @@
+//! Sunrise, sunset and twilight, computed locally
+//!
+//! Times follow the sunrise equation as used by NOAA's calculator, good to
+//! a minute or two away from the poles. Where the sun does not reach an
+//! altitude on a day, e.g. no sunset in polar summer, that event is
+//! skipped.
+//!
+//! [`is_dark`] makes a condition for
+//! [`conditional_action`](crate::eg::action::common::conditional_action):
+//!
+//! ```ignore
+//! let dark = sun::is_dark(tracker.settings().location()?, Darkness::Civil);
+//! conditional_action("Lights", "Only after dusk", plugin_id, dark, lights_on);
+//! ```
+//!
+//! In a tree, `SunTracker.IfDark({"event": "Lights.On"})` and `IfLight`
+//! fire an event only while it is dark or light.
+
+pub mod tracker;
+
+pub use tracker::SunTracker;
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use crate::core::event::Event;
+use crate::core::Error;
+
+/// Julian date of 2000-01-01 12:00 UTC
+const J2000: f64 = 2_451_545.0;
+/// Julian date of the Unix epoch
+const UNIX_EPOCH_JD: f64 = 2_440_587.5;
+/// Tilt of the earth's axis in degrees
+const OBLIQUITY: f64 = 23.4397;
+
+/// Error type for the sun tracker
+#[derive(Debug, thiserror::Error)]
+pub enum SunError {
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+}
+
+impl From<SunError> for Error {
+    fn from(e: SunError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// A place on earth, in degrees; north and east are positive
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn validate(&self) -> Result<(), SunError> {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(SunError::Settings(format!("{}, {} is not on earth", self.latitude, self.longitude)));
+        }
+        Ok(())
+    }
+}
+
+/// The events the tracker fires, in the order of a day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SolarEvent {
+    NauticalDawn,
+    CivilDawn,
+    Sunrise,
+    SolarNoon,
+    Sunset,
+    CivilDusk,
+    NauticalDusk,
+}
+
+impl SolarEvent {
+    pub const ALL: [SolarEvent; 7] = [
+        SolarEvent::NauticalDawn,
+        SolarEvent::CivilDawn,
+        SolarEvent::Sunrise,
+        SolarEvent::SolarNoon,
+        SolarEvent::Sunset,
+        SolarEvent::CivilDusk,
+        SolarEvent::NauticalDusk,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SolarEvent::NauticalDawn => "NauticalDawn",
+            SolarEvent::CivilDawn => "CivilDawn",
+            SolarEvent::Sunrise => "Sunrise",
+            SolarEvent::SolarNoon => "SolarNoon",
+            SolarEvent::Sunset => "Sunset",
+            SolarEvent::CivilDusk => "CivilDusk",
+            SolarEvent::NauticalDusk => "NauticalDusk",
+        }
+    }
+
+    /// Altitude of the sun's centre at the event and whether it is rising
+    fn altitude(self) -> Option<(f64, bool)> {
+        match self {
+            SolarEvent::NauticalDawn => Some((Darkness::Nautical.altitude(), true)),
+            SolarEvent::CivilDawn => Some((Darkness::Civil.altitude(), true)),
+            SolarEvent::Sunrise => Some((Darkness::Sunset.altitude(), true)),
+            SolarEvent::SolarNoon => None,
+            SolarEvent::Sunset => Some((Darkness::Sunset.altitude(), false)),
+            SolarEvent::CivilDusk => Some((Darkness::Civil.altitude(), false)),
+            SolarEvent::NauticalDusk => Some((Darkness::Nautical.altitude(), false)),
+        }
+    }
+}
+
+/// How far below the horizon the sun must be to count as dark
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Darkness {
+    /// Between sunset and sunrise
+    Sunset,
+    /// After civil dusk: street lights on
+    #[default]
+    Civil,
+    /// After nautical dusk: the horizon is gone
+    Nautical,
+    /// After astronomical dusk: full night
+    Astronomical,
+}
+
+impl Darkness {
+    /// Altitude of the sun's centre in degrees; sunset allows for
+    /// refraction and the sun's radius
+    pub fn altitude(self) -> f64 {
+        match self {
+            Darkness::Sunset => -0.833,
+            Darkness::Civil => -6.0,
+            Darkness::Nautical => -12.0,
+            Darkness::Astronomical => -18.0,
+        }
+    }
+}
+
+fn sin(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+fn julian_date(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 86_400_000.0 + UNIX_EPOCH_JD
+}
+
+fn from_julian_date(jd: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(((jd - UNIX_EPOCH_JD) * 86_400_000.0).round() as i64)
+}
+
+/// Mean anomaly and ecliptic longitude of the sun, `days` after J2000
+fn ecliptic(days: f64) -> (f64, f64) {
+    let anomaly = (357.5291 + 0.985_600_28 * days).rem_euclid(360.0);
+    let centre = 1.9148 * sin(anomaly) + 0.0200 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+    (anomaly, (anomaly + centre + 180.0 + 102.9372).rem_euclid(360.0))
+}
+
+/// When `event` happens in the solar day around noon UTC of `date`
+pub fn event_time(location: Location, date: NaiveDate, event: SolarEvent) -> Option<DateTime<Utc>> {
+    let noon = Utc.with_ymd_and_hms(date.year(), date.month(), date.day(), 12, 0, 0).single()?;
+    let day = (julian_date(noon) - J2000 + 0.0008).round();
+    let mean_noon = day - location.longitude / 360.0;
+    let (anomaly, longitude) = ecliptic(mean_noon);
+    let transit = J2000 + mean_noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * longitude);
+
+    let Some((altitude, rising)) = event.altitude() else {
+        return from_julian_date(transit);
+    };
+    let declination = (sin(longitude) * sin(OBLIQUITY)).asin().to_degrees();
+    let cos_hour_angle = (sin(altitude) - sin(location.latitude) * sin(declination))
+        / (cos(location.latitude) * cos(declination));
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    from_julian_date(if rising { transit - hour_angle / 360.0 } else { transit + hour_angle / 360.0 })
+}
+
+/// Altitude of the sun's centre above the horizon at `time`, in degrees
+pub fn altitude(location: Location, time: DateTime<Utc>) -> f64 {
+    let days = julian_date(time) - J2000;
+    let (_, longitude) = ecliptic(days);
+    let declination = (sin(longitude) * sin(OBLIQUITY)).asin().to_degrees();
+    let right_ascension = (sin(longitude) * cos(OBLIQUITY)).atan2(cos(longitude)).to_degrees();
+    let sidereal = 280.460_618_37 + 360.985_647_366_29 * days;
+    let hour_angle = sidereal + location.longitude - right_ascension;
+    (sin(location.latitude) * sin(declination) + cos(location.latitude) * cos(declination) * cos(hour_angle))
+        .asin()
+        .to_degrees()
+}
+
+/// A condition that holds while the sun is further below the horizon at
+/// `location` than `darkness` asks
+pub fn is_dark(location: Location, darkness: Darkness) -> impl Fn(Option<&dyn Event>) -> bool + Send + Sync + Clone + 'static {
+    move |_| altitude(location, Utc::now()) < darkness.altitude()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: Location = Location { latitude: 51.5074, longitude: -0.1278 };
+    const TROMSO: Location = Location { latitude: 69.6492, longitude: 18.9553 };
+
+    fn at(location: Location, date: (i32, u32, u32), event: SolarEvent) -> Option<String> {
+        let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+        event_time(location, date, event).map(|time| time.format("%H:%M").to_string())
+    }
+
+    fn near(actual: Option<String>, expected: &str) {
+        let minutes = |text: &str| {
+            let (hours, minutes) = text.split_once(':').unwrap();
+            hours.parse::<i32>().unwrap() * 60 + minutes.parse::<i32>().unwrap()
+        };
+        let actual = actual.expect("the event should happen");
+        assert!((minutes(&actual) - minutes(expected)).abs() <= 2, "{} is not near {}", actual, expected);
+    }
+
+    #[test]
+    fn test_event_times() {
+        // Published UTC times for London
+        near(at(LONDON, (2026, 6, 21), SolarEvent::Sunrise), "03:43");
+        near(at(LONDON, (2026, 6, 21), SolarEvent::Sunset), "20:21");
+        near(at(LONDON, (2026, 6, 21), SolarEvent::SolarNoon), "12:02");
+        near(at(LONDON, (2026, 12, 21), SolarEvent::Sunrise), "08:04");
+        near(at(LONDON, (2026, 12, 21), SolarEvent::CivilDusk), "16:33");
+        near(at(LONDON, (2026, 12, 21), SolarEvent::NauticalDawn), "06:41");
+
+        // Midnight sun and polar night
+        assert!(at(TROMSO, (2026, 6, 21), SolarEvent::Sunset).is_none());
+        assert!(at(TROMSO, (2026, 12, 21), SolarEvent::Sunrise).is_none());
+        assert!(at(TROMSO, (2026, 12, 21), SolarEvent::NauticalDawn).is_some());
+    }
+
+    #[test]
+    fn test_altitude() {
+        let date = NaiveDate::from_ymd_opt(2026, 6, 21).unwrap();
+        for event in SolarEvent::ALL {
+            let time = event_time(LONDON, date, event).unwrap();
+            let expected = event.altitude().map_or(61.9, |(altitude, _)| altitude);
+            let actual = altitude(LONDON, time);
+            assert!((actual - expected).abs() < 0.5, "{:?}: {} instead of {}", event, actual, expected);
+        }
+        let midnight = Utc.with_ymd_and_hms(2026, 12, 21, 0, 0, 0).unwrap();
+        assert!(altitude(LONDON, midnight) < Darkness::Astronomical.altitude());
+    }
+}
@@ -0,0 +1,343 @@
+//! SunTracker plugin
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Days, Local, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::common::conditional_action;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, EventQueue, EventSink};
+use super::{event_time, is_dark, Darkness, Location, SolarEvent, SunError};
+
+/// Longest sleep between looks at the clock, as in the scheduler
+const MAX_SLEEP: Duration = Duration::from_secs(30);
+/// Events missed by more than this, e.g. while suspended, are dropped
+const MISSED_GRACE: chrono::Duration = chrono::Duration::minutes(5);
+
+/// Settings of the `SunTracker` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SunSettings {
+    /// Required; there is no sensible place to assume
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Events to fire
+    pub events: Vec<SolarEvent>,
+    /// Minutes to fire an event early (negative) or late, e.g.
+    /// `{ Sunset = -30 }`
+    pub offsets_minutes: BTreeMap<SolarEvent, i64>,
+    /// What [`SunTracker::is_dark`] counts as dark
+    pub dark_below: Darkness,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+}
+
+impl Default for SunSettings {
+    fn default() -> Self {
+        Self {
+            latitude: None,
+            longitude: None,
+            events: SolarEvent::ALL.to_vec(),
+            offsets_minutes: BTreeMap::new(),
+            dark_below: Darkness::default(),
+            prefix: SunTracker::NAME.to_string(),
+        }
+    }
+}
+
+impl SunSettings {
+    pub fn location(&self) -> Result<Location, SunError> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => Ok(Location { latitude, longitude }),
+            _ => Err(SunError::Settings("latitude and longitude are required".into())),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SunError> {
+        self.location()?.validate()?;
+        match self.offsets_minutes.iter().find(|(_, minutes)| minutes.abs() > 12 * 60) {
+            Some((event, _)) => Err(SunError::Settings(format!("the offset of {} is over 12 hours", event.name()))),
+            None => Ok(()),
+        }
+    }
+
+    /// The first configured event, offset included, strictly after `after`
+    pub fn next_event(&self, after: DateTime<Utc>) -> Option<(SolarEvent, DateTime<Utc>)> {
+        let location = self.location().ok()?;
+        let today = after.date_naive();
+        let days = [today.checked_sub_days(Days::new(1)), Some(today), today.checked_add_days(Days::new(1)), today.checked_add_days(Days::new(2))];
+        days.into_iter()
+            .flatten()
+            .flat_map(|date| self.events.iter().map(move |event| (date, *event)))
+            .filter_map(|(date, event)| {
+                let offset = chrono::Duration::minutes(self.offsets_minutes.get(&event).copied().unwrap_or(0));
+                Some((event, event_time(location, date, event)? + offset))
+            })
+            .filter(|(_, time)| *time > after)
+            .min_by_key(|(_, time)| *time)
+    }
+}
+
+/// Fires events at dawn, sunrise, solar noon, sunset and dusk
+pub struct SunTracker {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: SunSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SunTracker {
+    pub const NAME: &'static str = "SunTracker";
+
+    pub fn new(settings: SunSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Fires events at dawn, sunrise, solar noon, sunset and dusk",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            task: None,
+        }
+    }
+
+    /// Publish solar events on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &SunSettings {
+        &self.settings
+    }
+
+    /// Condition for conditional actions: is it darker than `dark_below`?
+    pub fn is_dark(&self) -> Result<impl Fn(Option<&dyn Event>) -> bool + Send + Sync + Clone + 'static, SunError> {
+        Ok(is_dark(self.settings.location()?, self.settings.dark_below))
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for SunTracker {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Fire events until aborted
+async fn run(settings: SunSettings, sink: EventSink) {
+    let mut after = Utc::now();
+    loop {
+        let Some((event, time)) = settings.next_event(after) else {
+            tokio::time::sleep(MAX_SLEEP).await;
+            after = Utc::now();
+            continue;
+        };
+        let wait = (time - Utc::now()).to_std().unwrap_or_default();
+        tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+        let now = Utc::now();
+        if now < time {
+            continue;
+        }
+        if now - time <= MISSED_GRACE {
+            sink.emit(solar_event(&settings, event, time));
+        } else {
+            log::info!("{}: skipping {} missed at {}", SunTracker::NAME, event.name(), time.with_timezone(&Local));
+        }
+        after = time;
+    }
+}
+
+fn solar_event(settings: &SunSettings, event: SolarEvent, time: DateTime<Utc>) -> NamedEvent {
+    let name = match settings.prefix.as_str() {
+        "" => event.name().to_string(),
+        prefix => format!("{}.{}", prefix, event.name()),
+    };
+    let payload = json!({
+        "event": event.name(),
+        "time": time.with_timezone(&Local).to_rfc3339(),
+        "offset_minutes": settings.offsets_minutes.get(&event).copied().unwrap_or(0),
+    });
+    NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(payload)).with_source(SunTracker::NAME)
+}
+
+/// Arguments of the `IfDark` and `IfLight` actions
+#[derive(Deserialize)]
+struct IfArgs {
+    event: String,
+    #[serde(default)]
+    payload: Value,
+}
+
+/// Fire `args.event` through `sink` when run
+fn fire_action(name: &str, plugin_id: Uuid, sink: EventSink, args: IfArgs) -> ActionItem {
+    ActionItem::new(name, "Fires an event", plugin_id, move |_| {
+        let payload = EventPayload::from_json(args.payload.clone());
+        sink.emit(NamedEvent::new(&args.event, EventType::Plugin, payload).with_source(SunTracker::NAME));
+        Ok(())
+    })
+}
+
+#[async_trait]
+impl Plugin for SunTracker {
+    plugin_accessors!();
+
+    /// `SunTracker.IfDark({"event": "Lights.On"})` fires the event only while
+    /// it is darker than `dark_below`; `IfLight` only while it is not
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        let wanted = match name {
+            "IfDark" => true,
+            "IfLight" => false,
+            _ => return None,
+        };
+        Some(action_args(name, args).and_then(|args: IfArgs| {
+            let dark = self.is_dark()?;
+            let fire = fire_action(name, self.info.id, self.sink.clone(), args);
+            let description = if wanted { "Fires an event while it is dark" } else { "Fires an event while it is light" };
+            Ok(conditional_action(name, description, self.info.id, move |event| dark(event) == wanted, Box::new(fire)))
+        }))
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.settings.validate() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.task = Some(tokio::spawn(run(self.settings.clone(), self.sink.clone())));
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: SunSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(SunError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for SunTracker {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_next_event() {
+        let settings: SunSettings = serde_json::from_value(json!({
+            "latitude": 51.5074,
+            "longitude": -0.1278,
+            "events": ["Sunrise", "Sunset"],
+            "offsets_minutes": {"Sunset": -30},
+        }))
+        .unwrap();
+        assert!(settings.validate().is_ok());
+
+        let morning = Utc.with_ymd_and_hms(2026, 6, 21, 3, 0, 0).unwrap();
+        let (event, sunrise) = settings.next_event(morning).unwrap();
+        assert_eq!(event, SolarEvent::Sunrise);
+        let (event, sunset) = settings.next_event(sunrise).unwrap();
+        assert_eq!(event, SolarEvent::Sunset);
+        assert_eq!(sunset, event_time(settings.location().unwrap(), morning.date_naive(), SolarEvent::Sunset).unwrap() - chrono::Duration::minutes(30));
+        let (event, tomorrow) = settings.next_event(sunset).unwrap();
+        assert_eq!(event, SolarEvent::Sunrise);
+        assert_eq!(tomorrow.date_naive(), morning.date_naive().succ_opt().unwrap());
+
+        let payload = solar_event(&settings, SolarEvent::Sunset, sunset);
+        assert_eq!(payload.get_id(), "SunTracker.Sunset");
+        assert_eq!(payload.get_payload().to_json()["offset_minutes"], -30);
+
+        let invalid = SunSettings { latitude: Some(91.0), ..settings.clone() };
+        assert!(invalid.validate().is_err());
+        let missing = SunSettings { longitude: None, ..settings };
+        assert!(missing.validate().is_err());
+        assert!(missing.next_event(morning).is_none());
+        assert!(SunSettings::default().validate().is_err());
+    }
+
+    fn london() -> SunSettings {
+        SunSettings { latitude: Some(51.5074), longitude: Some(-0.1278), dark_below: Darkness::Sunset, ..Default::default() }
+    }
+
+    #[test]
+    fn test_is_dark_condition() {
+        let tracker = SunTracker::new(london());
+        let dark = tracker.is_dark().unwrap();
+        let altitude = super::super::altitude(tracker.settings().location().unwrap(), Utc::now());
+        assert_eq!(dark(None), altitude < Darkness::Sunset.altitude());
+        assert!(SunTracker::new(SunSettings::default()).is_dark().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conditional_actions() {
+        use crate::eg::action::base::ActionBase;
+
+        let tracker = SunTracker::new(london());
+        let dark = tracker.is_dark().unwrap()(None);
+        let args = json!({"event": "Lights.On", "payload": {"room": "hall"}});
+        for name in ["IfDark", "IfLight"] {
+            let mut action = tracker.create_action(name, &args).unwrap().unwrap();
+            action.execute(&NamedEvent::new("Evening", EventType::User, EventPayload::None)).await.unwrap();
+        }
+        let fired = tracker.queue.try_next().await.unwrap();
+        assert_eq!(fired.get_id(), "Lights.On");
+        assert_eq!(fired.get_payload().to_json()["room"], "hall");
+        assert!(tracker.queue.try_next().await.is_none());
+        let expected = if dark { "IfDark" } else { "IfLight" };
+        assert_eq!(tracker.create_action(expected, &args).unwrap().unwrap().get_name(), expected);
+
+        assert!(tracker.create_action("IfDark", &json!({})).unwrap().is_err());
+        assert!(tracker.create_action("Sunrise", &args).is_none());
+        let nowhere = SunTracker::new(SunSettings::default());
+        assert!(nowhere.create_action("IfDark", &args).unwrap().is_err());
+    }
+}
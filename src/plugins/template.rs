@@ -0,0 +1,204 @@
+//! Placeholders in action settings, filled from the triggering event
+//!
+//! | Placeholder        | Value for `MQTT.home/door` with payload `{"open": true}` |
+//! |--------------------|-----------------------------------------------------------|
+//! | `{event}`          | `MQTT.home/door`                                          |
+//! | `{prefix}`         | `MQTT`                                                    |
+//! | `{suffix}`         | `home/door`                                               |
+//! | `{source}`         | the plugin or client that raised the event, or empty      |
+//! | `{payload}`        | `{"open":true}`; text payloads without quotes             |
+//! | `{payload.open}`   | `true`; a dotted path into a JSON payload                 |
+//! | `{var.name}`       | global variable `name`, with [`render_with`] only         |
+//!
+//! `{{` and `}}` stand for literal braces. Unknown placeholders are kept
+//! as written, so a typo shows up in the output instead of vanishing.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::core::event::Event;
+use crate::eg::globals::Globals;
+
+/// How values are written in place of their placeholders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Escape {
+    /// As they are
+    #[default]
+    Plain,
+    /// Escaped for a JSON string, so `{"say": "{payload}"}` stays valid
+    /// whatever the payload holds
+    Json,
+    /// Percent-encoded but for unreserved characters, so a value stays one
+    /// path segment or query value, `/`, `&` and `?` included
+    Url,
+}
+
+impl Escape {
+    fn apply(self, value: String) -> String {
+        match self {
+            Escape::Plain => value,
+            Escape::Json => {
+                let quoted = Value::String(value).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            }
+            Escape::Url => {
+                let mut encoded = String::with_capacity(value.len());
+                for byte in value.bytes() {
+                    match byte {
+                        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+                        _ => encoded.push_str(&format!("%{:02X}", byte)),
+                    }
+                }
+                encoded
+            }
+        }
+    }
+}
+
+/// `template` with every placeholder replaced
+pub fn render(template: &str, event: &dyn Event) -> String {
+    render_with(template, event, None)
+}
+
+/// Like [`render`], also filling `{var.name}` from `globals`
+///
+/// Variables are found if they hold JSON, text, a bool, an integer or a
+/// float; a dotted path after the name reaches into JSON values.
+pub fn render_with(template: &str, event: &dyn Event, globals: Option<&Globals>) -> String {
+    render_escaped(template, event, globals, Escape::Plain)
+}
+
+/// Like [`render_with`], writing values as `escape` says
+pub fn render_escaped(template: &str, event: &dyn Event, globals: Option<&Globals>, escape: Escape) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            out.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+        let close = match tail.strip_prefix('{').and_then(|inner| inner.find('}')) {
+            Some(close) => close + 1,
+            None => {
+                out.push_str(&tail[..1]);
+                rest = &tail[1..];
+                continue;
+            }
+        };
+        match placeholder(&tail[1..close], event, globals) {
+            Some(value) => out.push_str(&escape.apply(value)),
+            None => out.push_str(&tail[..=close]),
+        }
+        rest = &tail[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn placeholder(name: &str, event: &dyn Event, globals: Option<&Globals>) -> Option<String> {
+    let id = event.get_id();
+    match name {
+        "event" => Some(id.to_string()),
+        "prefix" => Some(id.split_once('.').map_or(id, |(prefix, _)| prefix).to_string()),
+        "suffix" => Some(id.split_once('.').map_or("", |(_, suffix)| suffix).to_string()),
+        "source" => Some(event.get_source().unwrap_or_default().to_string()),
+        "payload" => Some(text(&event.get_payload().to_json())),
+        _ => {
+            if let Some(path) = name.strip_prefix("var.") {
+                let (name, path) = path.split_once('.').map_or((path, None), |(name, path)| (name, Some(path)));
+                let value = variable(globals?, name)?;
+                return lookup(&value, path.unwrap_or_default());
+            }
+            let path = name.strip_prefix("payload.")?;
+            lookup(&event.get_payload().to_json(), path)
+        }
+    }
+}
+
+/// The text at dotted `path` in `value`; an empty path is `value` itself
+fn lookup(value: &Value, path: &str) -> Option<String> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+        .map(text)
+}
+
+fn variable(globals: &Globals, name: &str) -> Option<Value> {
+    globals
+        .get_var::<Value>(name)
+        .or_else(|| globals.get_var::<String>(name).map(Value::from))
+        .or_else(|| globals.get_var::<bool>(name).map(Value::from))
+        .or_else(|| globals.get_var::<i64>(name).map(Value::from))
+        .or_else(|| globals.get_var::<f64>(name).map(Value::from))
+}
+
+/// Strings as they are, `null` as nothing, everything else as JSON
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::core::event::{EventPayload, EventType, NamedEvent};
+
+    #[test]
+    fn test_render() {
+        let event = NamedEvent::new(
+            "MQTT.home/door",
+            EventType::Plugin,
+            EventPayload::from_json(json!({"open": true, "sensors": [{"id": 7}]})),
+        )
+        .with_source("MQTT");
+
+        assert_eq!(render("eg/{suffix}/state", &event), "eg/home/door/state");
+        assert_eq!(render("{prefix}:{source}:{payload.open}", &event), "MQTT:MQTT:true");
+        assert_eq!(render("{payload.sensors.0.id}", &event), "7");
+        assert_eq!(render("{payload}", &event), r#"{"open":true,"sensors":[{"id":7}]}"#);
+        assert_eq!(render("{{event}} {missing} {payload.nope} {", &event), "{event} {missing} {payload.nope} {");
+
+        let text = NamedEvent::new("Timer", EventType::Plugin, EventPayload::Text("on".into()));
+        assert_eq!(render("{event}.{suffix}={payload}", &text), "Timer.=on");
+    }
+
+    #[test]
+    fn test_render_with() {
+        let mut globals = Globals::new();
+        globals.set_var("room", "hall".to_string());
+        globals.set_var("level", 30i64);
+        globals.set_var("weather", json!({"outside": {"temp": 4.5}}));
+        let event = NamedEvent::new("Dim", EventType::User, EventPayload::from_json(json!({"to": 10})));
+
+        assert_eq!(
+            render_with("{var.room}: {var.level} -> {payload.to}, {var.weather.outside.temp}", &event, Some(&globals)),
+            "hall: 30 -> 10, 4.5"
+        );
+        assert_eq!(render_with("{var.missing} {var.room.x}", &event, Some(&globals)), "{var.missing} {var.room.x}");
+        assert_eq!(render("{var.room}", &event), "{var.room}");
+    }
+
+    #[test]
+    fn test_render_escaped() {
+        let event = NamedEvent::new("Say", EventType::User, EventPayload::Text("a \"quote\"\n\\".into()));
+        let body = render_escaped(r#"{{"say": "{payload}", "event": "{event}"}}"#, &event, None, Escape::Json);
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap(), json!({"say": "a \"quote\"\n\\", "event": "Say"}));
+        assert_eq!(render_escaped("{payload}", &event, None, Escape::Plain), "a \"quote\"\n\\");
+
+        let query = NamedEvent::new("Search", EventType::User, EventPayload::Text("rock & roll/ü?".into()));
+        assert_eq!(
+            render_escaped("http://x/find?q={payload}&from={event}", &query, None, Escape::Url),
+            "http://x/find?q=rock%20%26%20roll%2F%C3%BC%3F&from=Search"
+        );
+    }
+}
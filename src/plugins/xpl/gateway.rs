@@ -0,0 +1,384 @@
+//! xPL gateway plugin
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use uuid::Uuid;
+use crate::core::config::Config;
+use crate::core::event::{Event, EventBus, EventPayload, EventType, NamedEvent};
+use crate::core::plugin::{EventGenerator, Plugin, PluginCapability, PluginInfo, PluginState};
+use crate::core::Error;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::{action_args, plugin_accessors, plugin_info, EventQueue, EventSink};
+use super::{send_action, Link, MessageType, XplError, XplMessage, XplSender, XplSettings, MAX_MESSAGE_SIZE};
+
+/// Heartbeat interval while looking for the hub
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(3);
+/// How long to look for the hub at [`DISCOVERY_INTERVAL`]
+const DISCOVERY_PERIOD: Duration = Duration::from_secs(120);
+/// Heartbeat interval after that until the hub answers
+const SLOW_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Bridges the xPL network and the event system
+pub struct XplGateway {
+    info: PluginInfo,
+    state: PluginState,
+    config: Option<Config>,
+    settings: XplSettings,
+    sink: EventSink,
+    queue: EventQueue,
+    sender: XplSender,
+    task: Option<JoinHandle<()>>,
+    local_addr: Option<SocketAddr>,
+}
+
+impl XplGateway {
+    pub const NAME: &'static str = "xPL";
+
+    pub fn new(settings: XplSettings) -> Self {
+        let (sink, queue) = EventSink::queue();
+        Self {
+            info: plugin_info(
+                Self::NAME,
+                "Sends and receives xPL messages",
+                vec![PluginCapability::EventGenerator, PluginCapability::ActionProvider, PluginCapability::Configurable],
+            ),
+            state: PluginState::Created,
+            config: None,
+            settings,
+            sink,
+            queue,
+            sender: XplSender::default(),
+            task: None,
+            local_addr: None,
+        }
+    }
+
+    /// Publish received messages on `bus`
+    pub fn with_event_bus(mut self, bus: EventBus) -> Self {
+        self.sink = EventSink::Bus(bus);
+        self
+    }
+
+    pub fn settings(&self) -> &XplSettings {
+        &self.settings
+    }
+
+    /// Handle for send actions; usable before the plugin starts
+    pub fn sender(&self) -> XplSender {
+        self.sender.clone()
+    }
+
+    /// Address the gateway listens on while running
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    fn listen(&mut self) -> Result<(), XplError> {
+        self.settings.validate()?;
+        let socket = UdpSocket::bind((self.settings.address, self.settings.port))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        let local_addr = socket.local_addr()?;
+        let destination = SocketAddr::new(self.settings.broadcast, self.settings.hub_port);
+
+        self.sender.set(Some(Link {
+            socket: socket.try_clone()?,
+            destination,
+            source: self.settings.source(),
+        }));
+        let heartbeat = Heartbeat {
+            interval_minutes: self.settings.interval_minutes,
+            port: local_addr.port(),
+            remote_ip: remote_ip(self.settings.address, destination),
+        };
+        let socket = tokio::net::UdpSocket::from_std(socket)?;
+        log::info!("{} listening on {} as {}", Self::NAME, local_addr, self.settings.source());
+
+        self.local_addr = Some(local_addr);
+        self.task = Some(tokio::spawn(run(
+            socket,
+            self.sender.clone(),
+            heartbeat,
+            self.settings.clone(),
+            self.sink.clone(),
+        )));
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        let Some(task) = self.task.take() else {
+            return;
+        };
+        task.abort();
+        if let Some(port) = self.local_addr.take().map(|addr| addr.port()) {
+            let goodbye = Heartbeat {
+                interval_minutes: self.settings.interval_minutes,
+                port,
+                remote_ip: remote_ip(self.settings.address, SocketAddr::new(self.settings.broadcast, self.settings.hub_port)),
+            };
+            if let Err(e) = self.sender.send(goodbye.message("hbeat.end")) {
+                log::debug!("{}: cannot send hbeat.end: {}", Self::NAME, e);
+            }
+        }
+        self.sender.set(None);
+    }
+}
+
+impl Drop for XplGateway {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// What our heartbeats say
+struct Heartbeat {
+    interval_minutes: u8,
+    port: u16,
+    remote_ip: IpAddr,
+}
+
+impl Heartbeat {
+    fn message(&self, schema: &str) -> XplMessage {
+        XplMessage::new(MessageType::Status, schema)
+            .with_value("interval", self.interval_minutes)
+            .with_value("port", self.port)
+            .with_value("remote-ip", self.remote_ip)
+    }
+
+    /// Whether `message` is this heartbeat coming back from the hub
+    fn is_echo(&self, message: &XplMessage) -> bool {
+        message.schema == "hbeat.app" && message.get("port") == Some(self.port.to_string().as_str())
+    }
+}
+
+/// Address other devices reach us at: the one we listen on, or else the
+/// one the system would send to `destination` from
+fn remote_ip(address: IpAddr, destination: SocketAddr) -> IpAddr {
+    if !address.is_unspecified() {
+        return address;
+    }
+    UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+        .and_then(|probe| {
+            probe.set_broadcast(true)?;
+            probe.connect(destination)?;
+            probe.local_addr()
+        })
+        .map(|local| local.ip())
+        .ok()
+        .filter(|ip| !ip.is_unspecified())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// 2 to 6 seconds, as the spec asks of answers to `hbeat.request`
+fn request_delay() -> Duration {
+    Duration::from_millis(2_000 + (Uuid::new_v4().as_u128() % 4_000) as u64)
+}
+
+/// Receive messages and keep the heartbeat going
+async fn run(socket: tokio::net::UdpSocket, sender: XplSender, heartbeat: Heartbeat, settings: XplSettings, sink: EventSink) {
+    let source = settings.source();
+    let interval = Duration::from_secs(u64::from(settings.interval_minutes) * 60);
+    let started = Instant::now();
+    let mut confirmed = false;
+    let mut next_beat = started;
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE * 2];
+
+    loop {
+        let received = tokio::select! {
+            _ = tokio::time::sleep_until(next_beat) => {
+                if let Err(e) = sender.send(heartbeat.message("hbeat.app")) {
+                    log::warn!("{}: cannot send heartbeat: {}", XplGateway::NAME, e);
+                }
+                next_beat = Instant::now() + match (confirmed, started.elapsed() < DISCOVERY_PERIOD) {
+                    (true, _) => interval,
+                    (false, true) => DISCOVERY_INTERVAL,
+                    (false, false) => SLOW_DISCOVERY_INTERVAL,
+                };
+                continue;
+            }
+            received = socket.recv_from(&mut buffer) => received,
+        };
+        let length = match received {
+            Ok((length, _)) => length,
+            Err(e) => {
+                log::debug!("{}: receive failed: {}", XplGateway::NAME, e);
+                continue;
+            }
+        };
+        let text = std::str::from_utf8(&buffer[..length]).map_err(|e| XplError::Malformed(e.to_string()));
+        let message = match text.and_then(XplMessage::parse) {
+            Ok(message) => message,
+            Err(e) => {
+                log::debug!("{}: ignoring message: {}", XplGateway::NAME, e);
+                continue;
+            }
+        };
+
+        if message.source.eq_ignore_ascii_case(&source) {
+            if !confirmed && heartbeat.is_echo(&message) {
+                log::info!("{}: hub found", XplGateway::NAME);
+                confirmed = true;
+                next_beat = Instant::now() + interval;
+            }
+            continue;
+        }
+        if message.kind == MessageType::Command && message.schema == "hbeat.request" && message.is_for(&source) {
+            next_beat = next_beat.min(Instant::now() + request_delay());
+        }
+        if settings.only_targeted && !message.is_for(&source) {
+            continue;
+        }
+        if message.schema_class() == "hbeat" && !settings.heartbeat_events {
+            continue;
+        }
+        sink.emit(received_event(&settings.prefix, &message));
+    }
+}
+
+fn received_event(prefix: &str, message: &XplMessage) -> NamedEvent {
+    let suffix = format!("{}.{}", message.kind.short_name(), message.schema);
+    let name = if prefix.is_empty() { suffix } else { format!("{}.{}", prefix, suffix) };
+    NamedEvent::new(&name, EventType::Plugin, EventPayload::from_json(message.to_json())).with_source(XplGateway::NAME)
+}
+
+#[async_trait]
+impl Plugin for XplGateway {
+    plugin_accessors!();
+
+    fn create_action(&self, name: &str, args: &Value) -> Option<Result<ActionItem, Error>> {
+        match name {
+            "Send" => Some(action_args(name, args).map(|message| {
+                send_action(name, "Sends an xPL message", self.info.id, self.sender(), message)
+            })),
+            _ => None,
+        }
+    }
+
+    async fn initialize(&mut self) -> Result<(), Error> {
+        self.state = PluginState::Initialized;
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.listen() {
+            self.state = PluginState::Failed;
+            return Err(e.into());
+        }
+        self.state = PluginState::Running;
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), Error> {
+        self.shutdown();
+        self.state = PluginState::Stopped;
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, _event: &dyn Event) -> Result<(), Error> {
+        Ok(())
+    }
+
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        let settings: XplSettings = crate::plugins::settings_for(&config, Self::NAME).map_err(XplError::Settings)?;
+        self.config = Some(config);
+        if settings == self.settings {
+            return Ok(());
+        }
+        self.settings = settings;
+        if self.state == PluginState::Running {
+            self.shutdown();
+            self.start().await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventGenerator for XplGateway {
+    async fn generate_event(&mut self) -> Result<Box<dyn Event>, Error> {
+        self.queue.next(Self::NAME).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::eg::action::base::ActionBase;
+    use crate::plugins::xpl::{send_action, SendMessage};
+
+    /// Next message with `schema` the hub receives
+    async fn hub_receives(hub: &tokio::net::UdpSocket, schema: &str) -> XplMessage {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        loop {
+            let (length, _) = tokio::time::timeout(Duration::from_secs(5), hub.recv_from(&mut buffer)).await.unwrap().unwrap();
+            let message = XplMessage::parse(std::str::from_utf8(&buffer[..length]).unwrap()).unwrap();
+            if message.schema == schema {
+                return message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gateway() {
+        let hub = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut gateway = XplGateway::new(XplSettings {
+            instance: "test".into(),
+            address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            broadcast: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            hub_port: hub.local_addr().unwrap().port(),
+            ..Default::default()
+        });
+        gateway.initialize().await.unwrap();
+        gateway.start().await.unwrap();
+        let gateway_addr = gateway.local_addr().unwrap();
+
+        let beat = hub_receives(&hub, "hbeat.app").await;
+        assert_eq!(beat.kind, MessageType::Status);
+        assert_eq!(beat.source, "eventgh-ghost.test");
+        assert_eq!(beat.get("port"), Some(gateway_addr.port().to_string().as_str()));
+        assert_eq!(beat.get("remote-ip"), Some("127.0.0.1"));
+        assert_eq!(beat.get("interval"), Some("5"));
+
+        // The hub echoes the heartbeat and forwards other devices' messages
+        hub.send_to(beat.to_string().as_bytes(), gateway_addr).await.unwrap();
+        let mut sensor = XplMessage::new(MessageType::Trigger, "sensor.basic").with_value("device", "door");
+        sensor.source = "acme-sensor.hall".into();
+        let mut other_beat = XplMessage::new(MessageType::Status, "hbeat.app").with_value("interval", 5);
+        other_beat.source = "acme-sensor.hall".into();
+        for message in [&other_beat, &sensor] {
+            hub.send_to(message.to_string().as_bytes(), gateway_addr).await.unwrap();
+        }
+
+        let event = gateway.generate_event().await.unwrap();
+        assert_eq!(event.get_id(), "xPL.trig.sensor.basic");
+        assert_eq!(event.get_source(), Some(XplGateway::NAME));
+        assert_eq!(event.get_payload().to_json()["body"], json!({"device": "door"}));
+
+        let mut action = send_action(
+            "Light on",
+            "Switches the light for the sensor",
+            Uuid::new_v4(),
+            gateway.sender(),
+            SendMessage {
+                kind: MessageType::Command,
+                target: "acme-lamp.{payload.body.device}".into(),
+                schema: "x10.basic".into(),
+                body: vec!["command=on".into(), "device=a1".into()],
+            },
+        );
+        action.execute(event.as_ref()).await.unwrap();
+        let command = hub_receives(&hub, "x10.basic").await;
+        assert_eq!(command.kind, MessageType::Command);
+        assert_eq!(command.target, "acme-lamp.door");
+        assert_eq!(command.body, [("command".into(), "on".into()), ("device".into(), "a1".into())]);
+
+        gateway.stop().await.unwrap();
+        assert_eq!(hub_receives(&hub, "hbeat.end").await.source, "eventgh-ghost.test");
+        assert!(gateway.queue.try_next().await.is_none());
+    }
+}
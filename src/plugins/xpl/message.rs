@@ -0,0 +1,234 @@
+//! The xPL message format
+//!
+//! ```text
+//! xpl-trig
+//! {
+//! hop=1
+//! source=acme-sensor.hall
+//! target=*
+//! }
+//! sensor.basic
+//! {
+//! device=door
+//! current=open
+//! }
+//! ```
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use super::XplError;
+
+/// Message types; heartbeats are status messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageType {
+    #[serde(rename = "xpl-cmnd")]
+    Command,
+    #[serde(rename = "xpl-stat")]
+    Status,
+    #[serde(rename = "xpl-trig")]
+    Trigger,
+}
+
+impl MessageType {
+    /// The header line, e.g. `xpl-cmnd`
+    pub fn header(self) -> &'static str {
+        match self {
+            MessageType::Command => "xpl-cmnd",
+            MessageType::Status => "xpl-stat",
+            MessageType::Trigger => "xpl-trig",
+        }
+    }
+
+    /// The header without `xpl-`, as used in event names
+    pub fn short_name(self) -> &'static str {
+        &self.header()[4..]
+    }
+
+    fn from_header(header: &str) -> Option<Self> {
+        match header {
+            "xpl-cmnd" => Some(MessageType::Command),
+            "xpl-stat" => Some(MessageType::Status),
+            "xpl-trig" => Some(MessageType::Trigger),
+            _ => None,
+        }
+    }
+}
+
+/// One xPL message
+#[derive(Debug, Clone, PartialEq)]
+pub struct XplMessage {
+    pub kind: MessageType,
+    pub hop: u8,
+    /// `vendor-device.instance` of the sender
+    pub source: String,
+    /// `vendor-device.instance` of the receiver, or `*` for everyone
+    pub target: String,
+    /// `class.type`, e.g. `hbeat.app`
+    pub schema: String,
+    /// Body lines in order; names may repeat
+    pub body: Vec<(String, String)>,
+}
+
+impl XplMessage {
+    /// A message to everyone from nobody yet; the sender fills in the source
+    pub fn new(kind: MessageType, schema: &str) -> Self {
+        Self {
+            kind,
+            hop: 1,
+            source: String::new(),
+            target: "*".to_string(),
+            schema: schema.to_string(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_target(mut self, target: &str) -> Self {
+        self.target = target.to_string();
+        self
+    }
+
+    pub fn with_value(mut self, name: &str, value: impl ToString) -> Self {
+        self.body.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// First value of body entry `name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.body.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Class part of the schema, e.g. `hbeat`
+    pub fn schema_class(&self) -> &str {
+        self.schema.split_once('.').map_or(self.schema.as_str(), |(class, _)| class)
+    }
+
+    /// Whether `source` is meant to act on this message
+    pub fn is_for(&self, source: &str) -> bool {
+        self.target == "*" || self.target.eq_ignore_ascii_case(source)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, XplError> {
+        let mut lines = text.lines().map(|line| line.trim_end_matches('\r'));
+        let malformed = |what: &str| XplError::Malformed(what.to_string());
+
+        let kind = lines
+            .next()
+            .and_then(|line| MessageType::from_header(line.trim()))
+            .ok_or_else(|| malformed("unknown message type"))?;
+        let header = block(&mut lines).ok_or_else(|| malformed("unterminated header"))?;
+        let schema = lines.next().map(str::trim).filter(|s| s.contains('.')).ok_or_else(|| malformed("missing schema"))?;
+        let body = block(&mut lines).ok_or_else(|| malformed("unterminated body"))?;
+
+        let field = |name: &str| header.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+        Ok(Self {
+            kind,
+            hop: field("hop").and_then(|hop| hop.parse().ok()).ok_or_else(|| malformed("bad hop count"))?,
+            source: field("source").ok_or_else(|| malformed("missing source"))?,
+            target: field("target").ok_or_else(|| malformed("missing target"))?,
+            schema: schema.to_string(),
+            body,
+        })
+    }
+
+    /// Fail if a field would break out of its line, e.g. a rendered value
+    /// carrying extra `name=value` lines or a `}` that closes the body early
+    pub fn check_lines(&self) -> Result<(), XplError> {
+        let fields = [("source", &self.source), ("target", &self.target), ("schema", &self.schema)];
+        let body = self.body.iter().flat_map(|(name, value)| [(name.as_str(), name), (name.as_str(), value)]);
+        match fields.into_iter().chain(body).find(|(_, text)| text.contains(['\n', '\r'])) {
+            Some((name, _)) => Err(XplError::Malformed(format!("{} contains a line break", name))),
+            None => Ok(()),
+        }
+    }
+
+    /// The message as an event payload; repeated body names become arrays
+    pub fn to_json(&self) -> Value {
+        let mut body = Map::new();
+        for (name, value) in &self.body {
+            match body.get_mut(name) {
+                None => {
+                    body.insert(name.clone(), Value::from(value.as_str()));
+                }
+                Some(Value::Array(values)) => values.push(Value::from(value.as_str())),
+                Some(first) => *first = Value::from(vec![first.take(), Value::from(value.as_str())]),
+            }
+        }
+        serde_json::json!({
+            "type": self.kind,
+            "hop": self.hop,
+            "source": self.source,
+            "target": self.target,
+            "schema": self.schema,
+            "body": body,
+        })
+    }
+}
+
+/// `{`, `name=value` lines, `}`
+fn block<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Option<Vec<(String, String)>> {
+    if lines.next()?.trim() != "{" {
+        return None;
+    }
+    let mut entries = Vec::new();
+    loop {
+        let line = lines.next()?;
+        if line.trim() == "}" {
+            return Some(entries);
+        }
+        if let Some((name, value)) = line.split_once('=') {
+            entries.push((name.trim().to_ascii_lowercase(), value.to_string()));
+        }
+    }
+}
+
+impl fmt::Display for XplMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{{\nhop={}\nsource={}\ntarget={}\n}}\n{}\n{{\n", self.kind.header(), self.hop, self.source, self.target, self.schema)?;
+        for (name, value) in &self.body {
+            writeln!(f, "{}={}", name, value)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_format() {
+        let text = "xpl-trig\n{\nhop=1\nsource=acme-sensor.hall\ntarget=*\n}\nsensor.basic\n{\ndevice=door\ncurrent=open\ndevice=window\n}\n";
+        let message = XplMessage::parse(text).unwrap();
+        assert_eq!(message.kind, MessageType::Trigger);
+        assert_eq!(message.source, "acme-sensor.hall");
+        assert_eq!(message.schema_class(), "sensor");
+        assert_eq!(message.get("device"), Some("door"));
+        assert!(message.is_for("eventgh-ghost.default"));
+        assert_eq!(message.to_string(), text);
+        assert_eq!(
+            message.to_json(),
+            json!({
+                "type": "xpl-trig", "hop": 1, "source": "acme-sensor.hall", "target": "*",
+                "schema": "sensor.basic", "body": {"device": ["door", "window"], "current": "open"},
+            })
+        );
+
+        let crlf = text.replace('\n', "\r\n");
+        assert_eq!(XplMessage::parse(&crlf).unwrap(), message);
+        assert!(XplMessage::parse("xpl-nope\n{\n}\n").is_err());
+        assert!(XplMessage::parse("xpl-cmnd\n{\nhop=1\nsource=a-b.c\ntarget=*\n}\nx10.basic\n{\n").is_err());
+    }
+
+    #[test]
+    fn test_line_breaks_are_rejected() {
+        let message = XplMessage::new(MessageType::Command, "x10.basic").with_value("device", "a1");
+        assert!(message.check_lines().is_ok());
+
+        let injected = message.clone().with_value("command", "on\n}\nx10.basic\n{\ncommand=off");
+        let err = injected.check_lines().unwrap_err();
+        assert!(err.to_string().contains("command"), "{}", err);
+        assert!(message.clone().with_target("*\r\nhop=9").check_lines().is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! xPL home automation protocol over UDP
+//!
+//! Every xPL message seen on the network becomes an event named
+//! `<prefix>.<type>.<schema>`, e.g. `xPL.trig.sensor.basic`, whose payload
+//! holds the header and body (see [`XplMessage::to_json`]). Heartbeats of
+//! other devices are only raised if `heartbeat_events` is set.
+//!
+//! As the spec asks of every device, the gateway listens on a port of its
+//! own behind the local hub, announces it with `hbeat.app` heartbeats, and
+//! says goodbye with `hbeat.end`. Until the hub echoes the first heartbeat
+//! it beats every 3 seconds, after two minutes every 30 seconds; once
+//! confirmed, every `interval_minutes`. `hbeat.request` is answered after
+//! a random 2 to 6 seconds.
+
+pub mod gateway;
+pub mod message;
+
+pub use gateway::XplGateway;
+pub use message::{MessageType, XplMessage};
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::Error;
+use crate::eg::action::base::ActionError;
+use crate::eg::action::item::ActionItem;
+use crate::plugins::template;
+
+/// Port hubs listen on
+pub const XPL_PORT: u16 = 3865;
+/// Vendor and device part of our source address
+pub const VENDOR_DEVICE: &str = "eventgh-ghost";
+/// Largest message the spec allows
+pub const MAX_MESSAGE_SIZE: usize = 1500;
+
+/// Error type for the xPL plugin
+#[derive(Debug, thiserror::Error)]
+pub enum XplError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed message: {0}")]
+    Malformed(String),
+    #[error("Invalid settings: {0}")]
+    Settings(String),
+    #[error("The xPL gateway is not running")]
+    NotRunning,
+}
+
+impl From<XplError> for Error {
+    fn from(e: XplError) -> Self {
+        Error::Other(e.to_string())
+    }
+}
+
+/// Settings of the `xPL` plugin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct XplSettings {
+    /// Instance part of our source address, up to 16 of `a-z0-9-`
+    pub instance: String,
+    /// Address to listen on
+    pub address: IpAddr,
+    /// Port to listen on; 0 picks a free one behind the hub
+    pub port: u16,
+    /// Where messages are sent, usually the broadcast address
+    pub broadcast: IpAddr,
+    /// Port of the hub
+    pub hub_port: u16,
+    /// Minutes between heartbeats once the hub answered, 5 to 9 recommended
+    pub interval_minutes: u8,
+    /// Prefix of event names; empty for none
+    pub prefix: String,
+    /// Raise events for other devices' heartbeats too
+    pub heartbeat_events: bool,
+    /// Only raise events for messages sent to us or everyone
+    pub only_targeted: bool,
+}
+
+impl Default for XplSettings {
+    fn default() -> Self {
+        Self {
+            instance: "default".to_string(),
+            address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            broadcast: IpAddr::V4(Ipv4Addr::BROADCAST),
+            hub_port: XPL_PORT,
+            interval_minutes: 5,
+            prefix: XplGateway::NAME.to_string(),
+            heartbeat_events: false,
+            only_targeted: false,
+        }
+    }
+}
+
+impl XplSettings {
+    pub fn validate(&self) -> Result<(), XplError> {
+        let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if self.instance.is_empty() || self.instance.len() > 16 || !self.instance.chars().all(valid) {
+            return Err(XplError::Settings(format!("instance '{}' must be 1 to 16 of a-z, 0-9 and -", self.instance)));
+        }
+        if !(1..=30).contains(&self.interval_minutes) {
+            return Err(XplError::Settings("interval_minutes must be between 1 and 30".into()));
+        }
+        Ok(())
+    }
+
+    /// Our `vendor-device.instance` address
+    pub fn source(&self) -> String {
+        format!("{}.{}", VENDOR_DEVICE, self.instance)
+    }
+}
+
+struct Link {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    source: String,
+}
+
+/// Sends messages from the gateway's socket; cheap to clone
+#[derive(Clone, Default)]
+pub struct XplSender(Arc<Mutex<Option<Link>>>);
+
+impl XplSender {
+    /// Send `message` with our source address and a hop count of 1
+    pub fn send(&self, mut message: XplMessage) -> Result<(), XplError> {
+        let link = self.0.lock().map_err(|_| XplError::NotRunning)?;
+        let link = link.as_ref().ok_or(XplError::NotRunning)?;
+        message.source = link.source.clone();
+        message.hop = 1;
+        message.check_lines()?;
+        let text = message.to_string();
+        if text.len() > MAX_MESSAGE_SIZE {
+            return Err(XplError::Malformed(format!("{} bytes is over the limit of {}", text.len(), MAX_MESSAGE_SIZE)));
+        }
+        link.socket.send_to(text.as_bytes(), link.destination)?;
+        Ok(())
+    }
+
+    fn set(&self, link: Option<Link>) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = link;
+        }
+    }
+}
+
+/// What a send action sends; every field is a [template](crate::plugins::template)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendMessage {
+    #[serde(rename = "type")]
+    pub kind: MessageType,
+    #[serde(default = "everyone")]
+    pub target: String,
+    /// `class.type`, e.g. `x10.basic`
+    pub schema: String,
+    /// `name=value` lines, e.g. `device={payload.device}`
+    #[serde(default)]
+    pub body: Vec<String>,
+}
+
+fn everyone() -> String {
+    "*".to_string()
+}
+
+/// Creates an action that sends `message` through `sender`
+pub fn send_action(
+    name: &str,
+    description: &str,
+    plugin_id: Uuid,
+    sender: XplSender,
+    message: SendMessage,
+) -> ActionItem {
+    ActionItem::new(
+        name,
+        description,
+        plugin_id,
+        move |event| {
+            let mut outgoing = XplMessage::new(message.kind, &template::render(&message.schema, event))
+                .with_target(&template::render(&message.target, event));
+            for line in &message.body {
+                let line = template::render(line, event);
+                let (name, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| ActionError::ExecutionFailed(format!("body line '{}' has no '='", line)))?;
+                outgoing = outgoing.with_value(name.trim(), value);
+            }
+            sender.send(outgoing).map_err(|e| ActionError::ExecutionFailed(e.to_string()))?;
+            Ok(())
+        },
+    )
+}
@@ -36,6 +36,12 @@ impl PluginTestFixture {
 
 }
 
+impl Default for PluginTestFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Test fixture for configuration tests
 pub struct ConfigTestFixture {
     pub store: MockConfigStore,
@@ -62,6 +68,12 @@ impl ConfigTestFixture {
     }
 }
 
+impl Default for ConfigTestFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 /// Test fixture for integration tests
 pub struct IntegrationTestFixture {
@@ -91,4 +103,10 @@ impl IntegrationTestFixture {
         // TODO: Implement state assertion
         unimplemented!()
     }
-} 
\ No newline at end of file
+} 
+
+impl Default for IntegrationTestFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
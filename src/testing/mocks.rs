@@ -1,12 +1,16 @@
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 use crate::core::{
     Plugin, PluginInfo, Event,
-    Error, Config, ConfigStore, ConfigError,
+    Error, Config, ConfigStore, ConfigError, ConfigManager,
 };
+use crate::core::config::{Keystore, MasterKey, SecretString};
 use crate::core::event::EventHandler;
+#[cfg(feature = "gui")]
 use gtk::prelude::*;
+#[cfg(feature = "gui")]
 use gtk::{self, Window};
 use std::any::Any;
 use chrono::{DateTime, Local};
@@ -18,6 +22,7 @@ use async_trait::async_trait;
 pub struct MockPlugin {
     info: PluginInfo,
     state: PluginState,
+    config: Option<Config>,
 }
 
 impl MockPlugin {
@@ -34,10 +39,17 @@ impl MockPlugin {
                 platforms: vec!["all".to_string()],
             },
             state: PluginState::Stopped,
+            config: None,
         }
     }
 }
 
+impl Default for MockPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl Plugin for MockPlugin {
     fn get_info(&self) -> PluginInfo {
@@ -71,10 +83,11 @@ impl Plugin for MockPlugin {
     }
     
     fn get_config(&self) -> Option<&Config> {
-        None
+        self.config.as_ref()
     }
     
-    async fn update_config(&mut self, _config: Config) -> Result<(), Error> {
+    async fn update_config(&mut self, config: Config) -> Result<(), Error> {
+        self.config = Some(config);
         Ok(())
     }
     
@@ -174,6 +187,12 @@ impl MockEventHandler {
     }
 }
 
+impl Default for MockEventHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventHandler for MockEventHandler {
     fn handle_event(&mut self, event: &dyn Event) -> Result<(), Error> {
         self.handled_events.push(event.get_type());
@@ -192,22 +211,44 @@ pub struct MockConfigStore {
     config: Arc<Mutex<Config>>,
 }
 
+impl MockConfigStore {
+    pub fn new(config: Config) -> Self {
+        Self { config: Arc::new(Mutex::new(config)) }
+    }
+}
 
 impl ConfigStore for MockConfigStore {
     fn load(&self) -> Result<Config, ConfigError> {
-        todo!("Implement mock config loading")
+        Ok(self.config.try_lock().map_err(|_| ConfigError::from("Mock config store is busy"))?.clone())
     }
 
-    fn save(&self, _config: &Config) -> Result<(), ConfigError> {
-        todo!("Implement mock config saving")
+    fn save(&self, config: &Config) -> Result<(), ConfigError> {
+        *self.config.try_lock().map_err(|_| ConfigError::from("Mock config store is busy"))? = config.clone();
+        Ok(())
     }
 }
 
+/// Config manager with a keystore in `dir` holding `secrets` as name and
+/// clear text pairs
+pub fn mock_secrets(dir: &Path, secrets: &[(&str, &str)]) -> Arc<ConfigManager> {
+    let mut keystore = Keystore::open(dir.join("secrets.json"), MasterKey::KeyFile(dir.join("master.key")))
+        .expect("keystore in a temporary directory");
+    for (name, value) in secrets {
+        keystore.store(name, &SecretString::from(*value)).expect("secret stored");
+    }
+    let manager = ConfigManager::new(Box::new(MockConfigStore::new(Config::default())))
+        .and_then(|manager| manager.with_keystore(keystore))
+        .expect("config manager");
+    Arc::new(manager)
+}
+
 /// Mock window for testing
+#[cfg(feature = "gui")]
 pub struct MockWindow {
     pub widget: Window,
 }
 
+#[cfg(feature = "gui")]
 impl MockWindow {
     pub fn new() -> Self {
         let widget = Window::new();
@@ -228,6 +269,7 @@ mod tests {
         assert_eq!(plugin.get_version(), "1.0.0");
     }
     
+    #[cfg(feature = "gui")]
     #[test]
     fn test_mock_window() {
         gtk::init().expect("Failed to initialize GTK");
@@ -9,14 +9,14 @@ pub mod fixtures;
 pub mod helpers;
 pub mod mocks;
 
+// Re-export common testing utilities
+pub use mocks::*;
+pub use helpers::*;
+pub use fixtures::*;
+
 #[cfg(test)]
 pub mod test {
     pub use super::fixtures::*;
     pub use super::helpers::*;
     pub use super::mocks::*;
-}
-
-// Re-export common testing utilities
-pub use mocks::*;
-pub use helpers::*;
-pub use fixtures::*; 
\ No newline at end of file
+} 
\ No newline at end of file